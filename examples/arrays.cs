@@ -0,0 +1,95 @@
+using System;
+
+struct Point {
+  public int X, Y;
+}
+
+class Animal {}
+class Dog : Animal {}
+class Cat : Animal {}
+
+public class Program {
+  public static void Main() {
+    sbyte[] sbytes = new sbyte[1];
+    sbytes[0] = -1;
+    Console.WriteLine(sbytes[0]);
+    byte[] bytes = new byte[1];
+    bytes[0] = 255;
+    Console.WriteLine(bytes[0]);
+    short[] shorts = new short[1];
+    shorts[0] = -5;
+    Console.WriteLine(shorts[0]);
+    ushort[] ushorts = new ushort[1];
+    ushorts[0] = 65535;
+    Console.WriteLine(ushorts[0]);
+    uint[] uints = new uint[1];
+    uints[0] = uint.MaxValue;
+    Console.WriteLine(uints[0]);
+    long[] longs = new long[3];
+    longs[2] = long.MaxValue;
+    Console.WriteLine(longs[2] + longs[0]);
+    float[] floats = new float[1];
+    floats[0] = 0.25f;
+    Console.WriteLine(floats[0]);
+    double[] doubles = new double[2];
+    doubles[0] = 0.5;
+    doubles[1] = 1.5;
+    Console.WriteLine(doubles[0] + doubles[1]);
+    char[] chars = new char[2];
+    chars[0] = 'o';
+    chars[1] = 'k';
+    Console.Write(chars[0]);
+    Console.WriteLine(chars[1]);
+    string[] strings = new string[2];
+    strings[0] = "ya";
+    strings[1] = "cht";
+    Console.WriteLine(strings[0] + strings[1]);
+
+    Point[] points = new Point[2];
+    Point p = new Point();
+    p.X = 3;
+    p.Y = 4;
+    points[1] = p;
+    Point q = points[1];
+    Console.WriteLine(q.X + q.Y);
+    Console.WriteLine(points[0].X);
+
+    Animal[] animals = new Dog[2];
+    animals[0] = new Dog();
+    try {
+      animals[1] = new Cat();
+    } catch (ArrayTypeMismatchException e) {
+      Console.WriteLine(e.Message);
+    }
+    animals[1] = null;
+    Console.WriteLine("{0}", animals[1] == null);
+    object[] objects = new string[1];
+    try {
+      objects[0] = 1;
+    } catch (ArrayTypeMismatchException) {
+      Console.WriteLine("mismatch");
+    }
+    objects[0] = "fine";
+    Console.WriteLine((string)objects[0]);
+    object[][] jagged = new string[1][];
+    try {
+      jagged[0] = new object[1];
+    } catch (ArrayTypeMismatchException) {
+      Console.WriteLine("jagged mismatch");
+    }
+    jagged[0] = new string[2];
+    Console.WriteLine(jagged[0].Length);
+    int length = -1;
+    try {
+      Console.WriteLine(new int[length].Length);
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    long longLength = 0x100000001;
+    try {
+      Console.WriteLine(new int[longLength].Length);
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+  }
+}
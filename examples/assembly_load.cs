@@ -0,0 +1,30 @@
+using System;
+using System.IO;
+using System.Reflection;
+
+// Build with /resource:payload.dll, where payload.dll is a library (version 2.1.0.0) with the
+// resource greeting.txt: "Hello from payload"
+public class Program {
+  public static void Main() {
+    Stream stream = Assembly.GetExecutingAssembly().GetManifestResourceStream("payload.dll");
+    byte[] bytes = new byte[stream.Length];
+    stream.Read(bytes, 0, bytes.Length);
+    stream.Close();
+
+    // Only the metadata and the resources of a loaded assembly are available
+    Assembly payload = Assembly.Load(bytes);
+    Console.WriteLine(payload.FullName);
+    Console.WriteLine(new StreamReader(payload.GetManifestResourceStream("greeting.txt")).ReadToEnd());
+
+    try {
+      Assembly.Load(new byte[] { 0x4d, 0x5a, 0x90, 0x00 });
+    } catch (BadImageFormatException e) {
+      Console.WriteLine(e.Message);
+    }
+    try {
+      Assembly.Load((byte[])null);
+    } catch (ArgumentNullException e) {
+      Console.WriteLine(e.Message);
+    }
+  }
+}
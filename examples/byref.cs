@@ -0,0 +1,84 @@
+using System;
+using System.Threading;
+
+struct Counter {
+  public int Value;
+  public void Increment() { Value++; }
+}
+
+class Holder {
+  public int Field;
+  public long Wide;
+}
+
+public class Program {
+  static void Swap(ref int a, ref int b) {
+    int t = a;
+    a = b;
+    b = t;
+  }
+
+  static bool TryParseDigit(char c, out int digit) {
+    if (c >= '0' && c <= '9') {
+      digit = c - '0';
+      return true;
+    }
+    digit = 0;
+    return false;
+  }
+
+  static void AddTo(ref long target, long n) { target += n; }
+
+  static void Replace(ref string s) { s = "replaced"; }
+
+  static void Increment(ref int x) { x++; }
+
+  static int IncrementCopy(int v) {
+    Increment(ref v);
+    return v;
+  }
+
+  public static void Main() {
+    int x = 1, y = 2;
+    Swap(ref x, ref y);
+    Console.WriteLine(x);
+    Console.WriteLine(y);
+
+    int d;
+    if (TryParseDigit('7', out d)) Console.WriteLine(d);
+    Console.WriteLine(TryParseDigit('x', out d) ? 1 : 0);
+
+    Counter c = new Counter();
+    c.Increment();
+    c.Increment();
+    Console.WriteLine(c.Value);
+
+    Holder h = new Holder();
+    Swap(ref h.Field, ref x);
+    Console.WriteLine(h.Field);
+    AddTo(ref h.Wide, 5000000000);
+    Console.WriteLine(h.Wide);
+
+    int[] arr = new int[3];
+    arr[0] = 10;
+    arr[2] = 30;
+    Swap(ref arr[0], ref arr[2]);
+    Console.WriteLine(arr[0]);
+    Console.WriteLine(arr[2]);
+
+    Counter[] counters = new Counter[2];
+    counters[1].Increment();
+    Console.WriteLine(counters[1].Value);
+
+    string s = "original";
+    Replace(ref s);
+    Console.WriteLine(s);
+
+    Console.WriteLine(IncrementCopy(41));
+
+    int shared = 0;
+    Interlocked.Increment(ref shared);
+    Interlocked.Add(ref shared, 10);
+    Console.WriteLine(shared);
+  }
+}
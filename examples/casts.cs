@@ -0,0 +1,73 @@
+using System;
+
+interface IShape {
+  int Area();
+}
+
+class Shape : IShape {
+  public virtual int Area() { return 0; }
+}
+
+class Square : Shape {
+  int side;
+
+  public Square(int side) { this.side = side; }
+
+  public override int Area() { return side * side; }
+}
+
+class Circle : Shape {}
+
+public class Program {
+  static string Describe(object o) {
+    if (o is Square) return "Square";
+    if (o is IShape) return "IShape";
+    if (o is int) return "int";
+    if (o is string) return "string";
+    if (o is string[]) return "string[]";
+    if (o is object[]) return "object[]";
+    if (o is int[]) return "int[]";
+    if (o is int[,]) return "int[,]";
+    if (o is Array) return "Array";
+    return "object";
+  }
+
+  public static void Main() {
+    object[] values = {
+      new Square(2), new Circle(), 42, "yacht", new string[1], new Shape[2],
+      new int[3], new int[2, 3], new double[1], new Program()
+    };
+    for (int i = 0; i < values.Length; i++)
+      Console.WriteLine(Describe(values[i]));
+
+    Shape s = values[0] as Shape;
+    Console.WriteLine(s.Area());
+    Console.WriteLine("{0}", values[1] as Square == null);
+    try {
+      Square bad = (Square)values[1];
+    } catch (InvalidCastException e) {
+      Console.WriteLine(e.Message);
+    }
+    Console.WriteLine((int)values[2]);
+    IShape shape = (IShape)values[0];
+    Console.WriteLine(shape.Area());
+
+    object squares = new Square[] { new Square(3) };
+    Shape[] shapes = (Shape[])squares;
+    Console.WriteLine(shapes[0].Area());
+    IShape[] ishapes = squares as IShape[];
+    Console.WriteLine(ishapes[0].Area());
+    Console.WriteLine("{0}", squares as Circle[] == null);
+    try {
+      Circle[] circles = (Circle[])squares;
+    } catch (InvalidCastException e) {
+      Console.WriteLine(e.Message);
+    }
+    // Arrays of value types are not covariant
+    Console.WriteLine("{0}", values[6] as object[] == null);
+    int[,] grid = (int[,])values[7];
+    Console.WriteLine(grid.GetLength(1));
+    object nothing = null;
+    Console.WriteLine("{0}", (string)nothing == null);
+  }
+}
@@ -0,0 +1,95 @@
+using System;
+
+public class Program {
+  public static void Main() {
+    int max = int.MaxValue;
+    int min = int.MinValue;
+    uint umax = uint.MaxValue;
+    uint one = 1;
+    ulong big = 1UL << 32;
+    long minusOne = -1;
+    double huge = 3e10;
+    int n200 = 200;
+    int n65536 = 65536;
+    try {
+      Console.WriteLine(checked(max - 1 + 1));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      Console.WriteLine(unchecked(max + 1));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      Console.WriteLine(checked(max + 1));
+    } catch (OverflowException e) {
+      Console.WriteLine(e.Message);
+    }
+    try {
+      Console.WriteLine(checked(min - 1));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      Console.WriteLine(checked(n65536 * n65536));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      Console.WriteLine(checked(umax + 1));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      Console.WriteLine(checked(one - 2));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      Console.WriteLine(checked(big * big));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      Console.WriteLine(checked(minusOne * 3));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      Console.WriteLine(checked((sbyte)n200));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      Console.WriteLine(checked((byte)n200));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      Console.WriteLine(checked((byte)minusOne));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      Console.WriteLine(checked((int)huge));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      Console.WriteLine(checked((int)umax));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      Console.WriteLine(checked((ulong)minusOne));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      Console.WriteLine(checked((uint)(big - 1)));
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+  }
+}
@@ -0,0 +1,66 @@
+using System;
+
+delegate int BinaryOp(int a, int b);
+delegate void Notify(string message);
+
+class Counter {
+  int count;
+
+  public Counter(int start) { count = start; }
+
+  public int Add(int a, int b) {
+    count += a + b;
+    return count;
+  }
+}
+
+class Greeter {
+  public virtual void Greet(string name) { Console.WriteLine("Hello, " + name); }
+}
+
+class LoudGreeter : Greeter {
+  public override void Greet(string name) { Console.WriteLine("HELLO, " + name); }
+}
+
+public class Program {
+  static int Add(int a, int b) { return a + b; }
+
+  static int Mul(int a, int b) { return a * b; }
+
+  static void Log(string message) { Console.WriteLine("log: " + message); }
+
+  public static unsafe void Main() {
+    BinaryOp op = Add;
+    Console.WriteLine(op(2, 3));
+    op = Mul;
+    Console.WriteLine(op(2, 3));
+
+    Counter counter = new Counter(10);
+    BinaryOp acc = counter.Add;
+    acc(1, 2);
+    Console.WriteLine(acc(3, 4));
+
+    Greeter greeter = new LoudGreeter();
+    Notify greet = greeter.Greet;
+    greet("world");
+
+    int factor = 3;
+    BinaryOp scaled = (a, b) => (a + b) * factor;
+    Console.WriteLine(scaled(1, 2));
+    factor = 4;
+    Console.WriteLine(scaled(1, 2));
+
+    Notify all = Log;
+    all += greeter.Greet;
+    all += Log;
+    all("combined");
+    all -= Log;
+    all("removed");
+    all -= greet;
+    all -= Log;
+    Console.WriteLine("{0}", all == null);
+
+    delegate*<int, int, int> fp = &Mul;
+    Console.WriteLine(fp(4, 5));
+  }
+}
@@ -0,0 +1,81 @@
+using System;
+
+class MyException : Exception {
+  public MyException(string message) : base(message) {}
+}
+
+class Failure<T> : Exception {
+  public Failure() : base("generic failure") {}
+}
+
+public class Sample {
+  static int Thrower(bool doThrow) {
+    if (doThrow) throw new MyException("thrown from callee");
+    return 42;
+  }
+
+  public static void Main() {
+    try {
+      throw new Exception("boom");
+    } catch (Exception e) {
+      Console.WriteLine(e.Message);
+    }
+
+    try {
+      Console.WriteLine(Thrower(false));
+    } finally {
+      Console.WriteLine("finally");
+    }
+
+    try {
+      Console.WriteLine(Thrower(true));
+    } catch (MyException e) {
+      Console.WriteLine(e.Message);
+    }
+
+    try {
+      try {
+        throw new Exception("inner");
+      } finally {
+        Console.WriteLine("inner finally");
+      }
+    } catch (Exception) {
+      Console.WriteLine("outer catch");
+    }
+
+    try {
+      throw new MyException("filtered");
+    } catch (Exception e) when (e is MyException) {
+      Console.WriteLine(e.Message);
+    }
+
+    try {
+      try {
+        throw new Exception("rethrown");
+      } catch (Exception) {
+        throw;
+      }
+    } catch (Exception e) {
+      Console.WriteLine(e.Message);
+    }
+
+    int sum = 0;
+    for (int i = 0; i < 5; i++) {
+      try {
+        if (i == 3) continue;
+        sum += i;
+      } finally {
+        sum += 10;
+      }
+    }
+    Console.WriteLine(sum);
+
+    try {
+      throw new Failure<int>();
+    } catch (Failure<string>) {
+      Console.WriteLine("wrong failure");
+    } catch (Failure<int> e) {
+      Console.WriteLine(e.Message);
+    }
+  }
+}
@@ -0,0 +1,94 @@
+using System;
+
+class Stack<T> {
+  T[] items = new T[8];
+  int count;
+
+  public void Push(T item) {
+    items[count++] = item;
+  }
+
+  public T Pop() {
+    return items[--count];
+  }
+
+  public int Count {
+    get { return count; }
+  }
+}
+
+struct Pair<T> {
+  public T First;
+  public T Second;
+
+  public Pair(T first, T second) {
+    First = first;
+    Second = second;
+  }
+
+  public void Swap() {
+    T t = First;
+    First = Second;
+    Second = t;
+  }
+}
+
+class Printer {
+  public virtual string Describe<T>(T value) {
+    return "Printer " + value;
+  }
+}
+
+class LoudPrinter : Printer {
+  public override string Describe<T>(T value) {
+    return "LoudPrinter " + value + "!";
+  }
+}
+
+class Wrapper<T> : Printer {
+  public override string Describe<U>(U value) {
+    return "Wrapper " + value;
+  }
+}
+
+public class Sample {
+  static T Identity<T>(T value) {
+    return value;
+  }
+
+  public static void Main() {
+    Stack<int> ints = new Stack<int>();
+    ints.Push(1);
+    ints.Push(2);
+    ints.Push(3);
+    Console.WriteLine(ints.Count);
+    Console.WriteLine(ints.Pop() + ints.Pop());
+    Stack<string> strings = new Stack<string>();
+    strings.Push("hello");
+    Console.WriteLine(strings.Pop());
+
+    Console.WriteLine(Identity(42));
+    Console.WriteLine(Identity("generic"));
+
+    // Generic structs are copied by value
+    Pair<int> pair = new Pair<int>(1, 2);
+    Pair<int> copy = pair;
+    copy.Swap();
+    Console.WriteLine(pair.First);
+    Console.WriteLine(copy.First);
+    object boxed = pair;
+    Pair<int> unboxed = (Pair<int>)boxed;
+    Console.WriteLine(unboxed.Second);
+
+    // Generic virtual methods dispatch on the class of the object
+    Printer printer = new Printer();
+    Console.WriteLine(printer.Describe(1));
+    printer = new LoudPrinter();
+    Console.WriteLine(printer.Describe(2));
+    Console.WriteLine(printer.Describe("three"));
+
+    // Classes instantiated after the first call override the method as well
+    printer = new Wrapper<int>();
+    Console.WriteLine(printer.Describe(4));
+  }
+}
@@ -0,0 +1,62 @@
+using System;
+
+interface IShape {
+  int Area();
+  string Name();
+}
+
+interface IPrinter {
+  void Print(int x);
+  void Print(string s);
+}
+
+interface IGetter<T> {
+  T Get();
+}
+
+class Square : IShape {
+  int side;
+  public Square(int side) { this.side = side; }
+  public int Area() { return side * side; }
+  public string Name() { return "square"; }
+}
+
+class Hidden : IShape {
+  int IShape.Area() { return 7; }
+  string IShape.Name() { return "hidden"; }
+  public int Area() { return -1; }
+}
+
+class Printer : IPrinter {
+  public void Print(int x) { Console.WriteLine(x + 1); }
+  public void Print(string s) { Console.WriteLine("string " + s); }
+}
+
+class Both : IGetter<int>, IGetter<string> {
+  int IGetter<int>.Get() { return 5; }
+  string IGetter<string>.Get() { return "five"; }
+}
+
+public class Sample {
+  static void Show(IShape shape) {
+    Console.WriteLine(shape.Name());
+    Console.WriteLine(shape.Area());
+  }
+
+  public static void Main() {
+    Show(new Square(3));
+    Hidden hidden = new Hidden();
+    Show(hidden);
+    Console.WriteLine(hidden.Area());
+
+    IPrinter printer = new Printer();
+    printer.Print(41);
+    printer.Print("hi");
+
+    Both both = new Both();
+    IGetter<int> i = both;
+    IGetter<string> s = both;
+    Console.WriteLine(i.Get());
+    Console.WriteLine(s.Get());
+  }
+}
@@ -0,0 +1,67 @@
+using System;
+
+public class Program {
+  static int Sum(int[,] grid) {
+    int sum = 0;
+    for (int i = 0; i < grid.GetLength(0); i++)
+      for (int j = 0; j < grid.GetLength(1); j++)
+        sum += grid[i, j];
+    return sum;
+  }
+
+  public static void Main() {
+    int[,] grid = new int[3, 4];
+    for (int i = 0; i < 3; i++)
+      for (int j = 0; j < 4; j++)
+        grid[i, j] = i * 10 + j;
+    Console.WriteLine(grid[2, 3]);
+    Console.WriteLine(Sum(grid));
+    Console.WriteLine(grid.Length);
+    Console.WriteLine(grid.Rank);
+    grid[1, 1]++;
+    Console.WriteLine(grid[1, 1]);
+    try {
+      grid[3, 0] = 1;
+    } catch (IndexOutOfRangeException e) {
+      Console.WriteLine(e.Message);
+    }
+
+    string[,,] cube = new string[2, 2, 2];
+    cube[1, 0, 1] = "yacht";
+    Console.WriteLine(cube[1, 0, 1]);
+    Console.WriteLine("{0}", cube[0, 0, 0] == null);
+    Console.WriteLine(cube.GetUpperBound(2));
+
+    // newobj instance void float64[,]::.ctor(int32, int32, int32, int32), which makes the lower
+    // bounds 1 and 5
+    double[,] shifted =
+        (double[,])Array.CreateInstance(typeof(double), new[] { 2, 2 }, new[] { 1, 5 });
+    shifted[1, 5] = 0.5;
+    shifted[2, 6] = 1.5;
+    Console.WriteLine(shifted[1, 5] + shifted[2, 6]);
+    Console.WriteLine(shifted.GetLowerBound(1));
+    Console.WriteLine(shifted.GetUpperBound(0));
+    try {
+      Console.WriteLine(shifted[0, 5]);
+    } catch (IndexOutOfRangeException) {
+      Console.WriteLine("out of range");
+    }
+
+    // Negative lengths overflow, and the size of the elements is computed without wrapping around
+    try {
+      grid = new int[-1, 2];
+    } catch (OverflowException) {
+      Console.WriteLine("overflow");
+    }
+    try {
+      grid = new int[0x40000000, 0x40000000];
+    } catch (OutOfMemoryException) {
+      Console.WriteLine("out of memory");
+    }
+    try {
+      cube = new string[0x40000000, 0x40000000, 0x40000000];
+    } catch (OutOfMemoryException) {
+      Console.WriteLine("out of memory");
+    }
+  }
+}
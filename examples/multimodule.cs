@@ -0,0 +1,26 @@
+using System;
+using Shapes;
+
+// Build multimodule_shapes.cs with /target:module first, then this with
+// /addmodule:multimodule_shapes.netmodule. Types of the other module are referred to through
+// ModuleRefs.
+public class Square : Shape {
+  public override int Area() {
+    return 9;
+  }
+}
+
+public class Program {
+  public static void Main() {
+    Console.WriteLine(Util.Add(40, 2));
+    Shape shape = new Shape();
+    Console.WriteLine(shape.Area());
+    shape = new Square();
+    Console.WriteLine(shape.Area());
+
+    // Instances of generic virtual methods are compiled in the module defining them
+    Console.WriteLine(shape.Describe(1));
+    shape = new Circle();
+    Console.WriteLine(shape.Describe(2));
+  }
+}
@@ -0,0 +1,23 @@
+namespace Shapes {
+  public class Shape {
+    public virtual int Area() {
+      return 1;
+    }
+
+    public virtual string Describe<T>(T value) {
+      return "Shape " + value;
+    }
+  }
+
+  public class Util {
+    public static int Add(int x, int y) {
+      return x + y;
+    }
+  }
+
+  public class Circle : Shape {
+    public override string Describe<T>(T value) {
+      return "Circle " + value;
+    }
+  }
+}
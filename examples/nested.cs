@@ -0,0 +1,13 @@
+using System;
+using Lib;
+
+// Build with /reference:nested_lib.dll. Types nested in another assembly are referred to through
+// the types enclosing them.
+public class Program {
+  public static void Main() {
+    Console.WriteLine(Outer.Name());
+    Outer.Inner inner = new Outer.Inner(21);
+    Console.WriteLine(inner.Twice());
+    Console.WriteLine(Outer.Inner.Deepest.Name());
+  }
+}
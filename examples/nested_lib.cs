@@ -0,0 +1,25 @@
+namespace Lib {
+  public class Outer {
+    public static string Name() {
+      return "Outer";
+    }
+
+    public class Inner {
+      public int value;
+
+      public Inner(int value) {
+        this.value = value;
+      }
+
+      public int Twice() {
+        return value * 2;
+      }
+
+      public class Deepest {
+        public static string Name() {
+          return "Deepest";
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,84 @@
+using System;
+using System.Runtime.CompilerServices;
+
+// Opcodes that C# emits only for particular constructs (or never). The comments show the IL of
+// the lines that C# cannot express.
+public class Program {
+  static int Sum(__arglist) {
+    ArgIterator args = new ArgIterator(__arglist);
+    int sum = 0;
+    while (args.GetRemainingCount() > 0)
+      sum += __refvalue(args.GetNextArg(), int);
+    args.End();
+    return sum;
+  }
+
+  static int Twice(int x) {
+    return x * 2;
+  }
+
+  static int JumpToTwice(int x) {
+    // jmp int32 Program::Twice(int32)
+    return Twice(x);
+  }
+
+  static void Greet(string name) {
+    Console.WriteLine("Hello, " + name);
+  }
+
+  static int JumpToGreet(int x) {
+    // jmp void Program::Greet(string), whose signature differs
+    throw new InvalidProgramException();
+  }
+
+  public static unsafe void Main() {
+    Console.WriteLine(typeof(int).FullName);
+    Console.WriteLine(typeof(Program).Name);
+    Console.WriteLine("{0}", typeof(Program) == typeof(Program));
+    Console.WriteLine("{0}", typeof(string) != typeof(object));
+
+    int[] primes = { 2, 3, 5, 7, 11 };
+    int total = 0;
+    for (int i = 0; i < primes.Length; i++)
+      total += primes[i];
+    Console.WriteLine(total);
+    // no. 2 ldelem.i4 (skips the range check)
+    Console.WriteLine(primes[4]);
+    char[] name = { 'y', 'a', 'c', 'h', 't' };
+    for (int i = 0; i < name.Length; i++)
+      Console.Write(name[i]);
+    Console.WriteLine();
+
+    int* p = stackalloc int[4];
+    Console.WriteLine(*p);
+    *p = 42;
+    Console.WriteLine(*p);
+
+    byte* src = stackalloc byte[8];
+    byte* dst = stackalloc byte[8];
+    Unsafe.InitBlock(src, 7, 8); // initblk
+    Unsafe.CopyBlock(dst, src, 8); // cpblk
+    Console.WriteLine(*(long*)dst);
+
+    int n = 5;
+    TypedReference r = __makeref(n);
+    Console.WriteLine("{0}", __reftype(r) == typeof(int));
+    __refvalue(r, int) = 6;
+    Console.WriteLine(n);
+    try {
+      Console.WriteLine(__refvalue(r, string));
+    } catch (InvalidCastException e) {
+      Console.WriteLine(e.Message);
+    }
+
+    Console.WriteLine(Sum(__arglist(1, 2, 3)));
+    Console.WriteLine(Sum(__arglist()));
+
+    Console.WriteLine(JumpToTwice(21));
+    try {
+      JumpToGreet(1);
+    } catch (InvalidProgramException e) {
+      Console.WriteLine(e.Message);
+    }
+  }
+}
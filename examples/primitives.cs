@@ -0,0 +1,56 @@
+using System;
+
+public class Program {
+  static byte Average(byte a, byte b) { return (byte)((a + b) / 2); }
+
+  static float Scale(float x, short factor) { return x * factor; }
+
+  public static void Main() {
+    sbyte sb = -128;
+    sb--;
+    Console.WriteLine(sb);
+    byte b = 255;
+    b++;
+    Console.WriteLine(b);
+    short s = 32767;
+    s++;
+    Console.WriteLine(s);
+    ushort us = 0;
+    us--;
+    Console.WriteLine(us);
+    uint ui = 0;
+    ui--;
+    Console.WriteLine(ui);
+    ulong ul = ulong.MaxValue;
+    Console.WriteLine(ul);
+    Console.WriteLine(ul / 3);
+    Console.WriteLine(ul % 10);
+
+    Console.WriteLine(Average(200, 250));
+    Console.WriteLine(Scale(1.5f, -4));
+    Console.WriteLine(1.0f / 3);
+
+    int big = 300;
+    Console.WriteLine((byte)big);
+    Console.WriteLine((sbyte)200);
+    Console.WriteLine((short)70000);
+    Console.WriteLine((char)(65 + 1));
+    double d = -3.9;
+    Console.WriteLine((int)d);
+    Console.WriteLine((long)3.9);
+    long minusOne = -1;
+    Console.WriteLine((uint)minusOne);
+    Console.WriteLine((double)ui);
+    Console.WriteLine((float)(ul >> 40));
+
+    IntPtr p = (IntPtr)42;
+    Console.WriteLine((long)p + 1);
+    UIntPtr up = (UIntPtr)ui;
+    Console.WriteLine((ulong)up);
+
+    uint one = 1;
+    Console.WriteLine(one < ui ? "unsigned" : "signed");
+    Console.WriteLine(0x80000000u >> 4);
+    Console.WriteLine(int.MinValue >> 4);
+  }
+}
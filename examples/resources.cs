@@ -0,0 +1,45 @@
+using System;
+using System.IO;
+using System.Reflection;
+
+// Build with /resource:greeting.txt, where greeting.txt is "Hello, resources\nbye"
+public class Program {
+  public static void Main() {
+    Assembly asm = typeof(Program).Assembly;
+    Console.WriteLine(asm.FullName);
+    Console.WriteLine(typeof(string).Assembly.FullName);
+
+    Stream stream = asm.GetManifestResourceStream("greeting.txt");
+    StreamReader reader = new StreamReader(stream);
+    Console.WriteLine(reader.ReadLine());
+    reader.Close();
+    try {
+      stream.ReadByte();
+    } catch (ObjectDisposedException e) {
+      Console.WriteLine(e.Message);
+    }
+
+    // Left unclosed for the garbage collector to free
+    stream = asm.GetManifestResourceStream("greeting.txt");
+    byte[] buffer = new byte[4];
+    try {
+      stream.Read(buffer, -1, 2);
+    } catch (ArgumentOutOfRangeException e) {
+      Console.WriteLine(e.Message);
+    }
+    try {
+      stream.Read(buffer, 2, 3);
+    } catch (ArgumentException e) {
+      Console.WriteLine(e.Message);
+    }
+    Console.WriteLine(stream.Read(buffer, 1, 3));
+    Console.WriteLine(buffer[1]);
+
+    try {
+      asm.GetManifestResourceStream(null);
+    } catch (ArgumentNullException e) {
+      Console.WriteLine(e.Message);
+    }
+    Console.WriteLine("{0}", asm.GetManifestResourceStream("missing.txt") == null);
+  }
+}
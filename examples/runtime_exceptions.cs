@@ -0,0 +1,63 @@
+using System;
+
+class Node {
+  public int Value;
+  public Node Next;
+}
+
+public class Sample {
+  static int Divide(int a, int b) {
+    return a / b;
+  }
+
+  static int Remainder(int a, int b) {
+    return a % b;
+  }
+
+  static long DivideLong(long a, long b) {
+    return a / b;
+  }
+
+  public static void Main() {
+    Node node = new Node();
+    try {
+      Console.WriteLine(node.Next.Value);
+    } catch (NullReferenceException) {
+      Console.WriteLine("NullReferenceException");
+    }
+
+    int[] array = new int[3];
+    try {
+      array[3] = 1;
+    } catch (IndexOutOfRangeException) {
+      Console.WriteLine("IndexOutOfRangeException");
+    }
+    try {
+      Console.WriteLine(array[-1]);
+    } catch (IndexOutOfRangeException) {
+      Console.WriteLine("IndexOutOfRangeException");
+    }
+
+    try {
+      Console.WriteLine(Divide(1, 0));
+    } catch (DivideByZeroException) {
+      Console.WriteLine("DivideByZeroException");
+    }
+
+    try {
+      Console.WriteLine(Divide(int.MinValue, -1));
+    } catch (OverflowException) {
+      Console.WriteLine("OverflowException");
+    }
+    try {
+      Console.WriteLine(DivideLong(long.MinValue, -1));
+    } catch (OverflowException) {
+      Console.WriteLine("OverflowException");
+    }
+
+    Console.WriteLine(Remainder(int.MinValue, -1));
+    Console.WriteLine(Remainder(7, -1));
+    Console.WriteLine(Divide(-7, 2));
+    Console.WriteLine(Remainder(-7, 2));
+  }
+}
@@ -0,0 +1,47 @@
+using System;
+
+class Counter {
+  public static int Count;
+  public static void Increment() { Count++; }
+}
+
+class Precise {
+  public static int Value;
+
+  static Precise() {
+    Console.WriteLine("Precise initialized");
+    Value = 10;
+  }
+
+  public static int Get() { return Value; }
+}
+
+class Table {
+  public static readonly int[] Squares = MakeSquares();
+
+  static int[] MakeSquares() {
+    int[] squares = new int[5];
+    for (int i = 0; i < squares.Length; i++) squares[i] = i * i;
+    return squares;
+  }
+}
+
+public class Sample {
+  static void Bump(ref int x) { x += 40; }
+
+  public static void Main() {
+    Counter.Increment();
+    Counter.Increment();
+    Console.WriteLine(Counter.Count);
+
+    Console.WriteLine("before Precise");
+    Console.WriteLine(Precise.Get());
+    Console.WriteLine(Precise.Get() + 1);
+
+    Console.WriteLine(Table.Squares[3]);
+    Console.WriteLine(Table.Squares.Length);
+
+    Bump(ref Counter.Count);
+    Console.WriteLine(Counter.Count);
+  }
+}
@@ -0,0 +1,71 @@
+using System;
+
+struct Vector3 {
+  public double X, Y, Z;
+
+  public Vector3(double x, double y, double z) {
+    X = x;
+    Y = y;
+    Z = z;
+  }
+
+  public Vector3 Add(Vector3 o) { return new Vector3(X + o.X, Y + o.Y, Z + o.Z); }
+
+  public double Dot(Vector3 o) { return X * o.X + Y * o.Y + Z * o.Z; }
+
+  public override string ToString() { return "(" + X + ", " + Y + ", " + Z + ")"; }
+}
+
+class Body {
+  public Vector3 Position;
+}
+
+public class Program {
+  static void Scale(Vector3 v) { v.X *= 100; }
+
+  // IL: cpobj Vector3
+  static void Copy(ref Vector3 dst, ref Vector3 src) { dst = src; }
+
+  static Vector3 Read(ref Vector3 v) { return v; }
+
+  public static unsafe void Main() {
+    Vector3 a = new Vector3(1, 2, 3);
+    Vector3 b = a;
+    b.X = 10;
+    Console.WriteLine(a.X);
+    Console.WriteLine(b.X);
+
+    Vector3 c = a.Add(b);
+    Console.WriteLine(c.X);
+    Console.WriteLine(c.Z);
+    Console.WriteLine(a.Dot(b));
+
+    Scale(a);
+    Console.WriteLine(a.X);
+
+    Body body = new Body();
+    body.Position = c;
+    body.Position.Y = 7;
+    Console.WriteLine(body.Position.Y);
+    Console.WriteLine(c.Y);
+
+    Vector3[] points = new Vector3[2];
+    points[1] = a;
+    points[1].Z = 9;
+    Console.WriteLine(points[1].Z);
+    Console.WriteLine(a.Z);
+    Console.WriteLine(points[0].X);
+
+    object boxed = a;
+    a.X = 5;
+    Vector3 unboxed = (Vector3)boxed;
+    Console.WriteLine(unboxed.X);
+    Console.WriteLine(boxed.ToString());
+    Console.WriteLine(a.ToString());
+
+    Vector3 d = default(Vector3);
+    Copy(ref d, ref a);
+    Console.WriteLine(Read(ref d).X);
+    Console.WriteLine(sizeof(Vector3));
+  }
+}
@@ -0,0 +1,13 @@
+using System;
+
+// Compiled with -platform:x64, which makes a PE32+ image
+public class X64 {
+  static int Square(int x) {
+    return x * x;
+  }
+
+  public static void Main() {
+    Console.WriteLine("Hello from PE32+");
+    Console.WriteLine(Square(7));
+  }
+}
@@ -19,22 +19,38 @@ impl<'a> BytesToInstructions<'a> {
     pub fn convert(&mut self) -> Option<Vec<Instruction>> {
         let mut iseq = vec![];
 
-        self.make_target_map();
+        self.make_target_map()?;
 
         while let Some((i, byte)) = self.iter.next() {
             match *byte {
+                il_instr::NOP => iseq.push(Instruction::Nop),
+                il_instr::BREAK => iseq.push(Instruction::Break),
                 il_instr::LDNULL => iseq.push(Instruction::Ldnull),
                 il_instr::LDSTR => {
                     let token = self.read_u32()?;
-                    assert!(token & 0xff00_0000 == 0x7000_0000);
+                    if token & 0xff00_0000 != 0x7000_0000 {
+                        return None;
+                    }
                     let us_offset = token & 0x00ff_ffff;
                     iseq.push(Instruction::Ldstr(us_offset))
                 }
+                il_instr::JMP => iseq.push(Instruction::Jmp(Token(self.read_u32()?))),
                 il_instr::CALL => iseq.push(Instruction::Call(Token(self.read_u32()?))),
                 il_instr::CALLVIRT => iseq.push(Instruction::CallVirt(Token(self.read_u32()?))),
+                il_instr::CALLI => iseq.push(Instruction::Calli(Token(self.read_u32()?))),
                 il_instr::BOX => iseq.push(Instruction::Box(Token(self.read_u32()?))),
+                il_instr::UNBOX => iseq.push(Instruction::Unbox(Token(self.read_u32()?))),
+                il_instr::UNBOX_ANY => iseq.push(Instruction::Unbox_any(Token(self.read_u32()?))),
+                il_instr::LDOBJ => iseq.push(Instruction::Ldobj(Token(self.read_u32()?))),
+                il_instr::STOBJ => iseq.push(Instruction::Stobj(Token(self.read_u32()?))),
+                il_instr::CPOBJ => iseq.push(Instruction::Cpobj(Token(self.read_u32()?))),
+                il_instr::CASTCLASS => iseq.push(Instruction::Castclass(Token(self.read_u32()?))),
+                il_instr::ISINST => iseq.push(Instruction::Isinst(Token(self.read_u32()?))),
                 il_instr::NEWOBJ => iseq.push(Instruction::Newobj(Token(self.read_u32()?))),
                 il_instr::NEWARR => iseq.push(Instruction::Newarr(Token(self.read_u32()?))),
+                il_instr::LDTOKEN => iseq.push(Instruction::Ldtoken(Token(self.read_u32()?))),
+                il_instr::MKREFANY => iseq.push(Instruction::Mkrefany(Token(self.read_u32()?))),
+                il_instr::REFANYVAL => iseq.push(Instruction::Refanyval(Token(self.read_u32()?))),
                 il_instr::LDC_I4_M1 => iseq.push(Instruction::Ldc_I4_M1),
                 il_instr::LDC_I4_0 => iseq.push(Instruction::Ldc_I4_0),
                 il_instr::LDC_I4_1 => iseq.push(Instruction::Ldc_I4_1),
@@ -45,8 +61,14 @@ impl<'a> BytesToInstructions<'a> {
                 il_instr::LDC_I4_6 => iseq.push(Instruction::Ldc_I4_6),
                 il_instr::LDC_I4_7 => iseq.push(Instruction::Ldc_I4_7),
                 il_instr::LDC_I4_8 => iseq.push(Instruction::Ldc_I4_8),
-                il_instr::LDC_I4_S => iseq.push(Instruction::Ldc_I4_S(self.read_u8()? as i32)),
+                il_instr::LDC_I4_S => {
+                    iseq.push(Instruction::Ldc_I4_S(self.read_u8()? as i8 as i32))
+                }
                 il_instr::LDC_I4 => iseq.push(Instruction::Ldc_I4(self.read_u32()? as i32)),
+                il_instr::LDC_I8 => iseq.push(Instruction::Ldc_I8(self.read_u64()? as i64)),
+                il_instr::LDC_R4 => iseq.push(Instruction::Ldc_R4(unsafe {
+                    transmute::<u32, f32>(self.read_u32()?)
+                })),
                 il_instr::LDC_R8 => iseq.push(Instruction::Ldc_R8(unsafe {
                     transmute::<u64, f64>(self.read_u64()?)
                 })),
@@ -55,129 +77,277 @@ impl<'a> BytesToInstructions<'a> {
                 il_instr::LDARG_2 => iseq.push(Instruction::Ldarg_2),
                 il_instr::LDARG_3 => iseq.push(Instruction::Ldarg_3),
                 il_instr::LDARG_S => iseq.push(Instruction::Ldarg_S(self.read_u8()? as i32)),
+                il_instr::LDARGA_S => iseq.push(Instruction::Ldarga_S(self.read_u8()?)),
                 il_instr::LDLOC_0 => iseq.push(Instruction::Ldloc_0),
                 il_instr::LDLOC_1 => iseq.push(Instruction::Ldloc_1),
                 il_instr::LDLOC_2 => iseq.push(Instruction::Ldloc_2),
                 il_instr::LDLOC_3 => iseq.push(Instruction::Ldloc_3),
                 il_instr::LDLOC_S => iseq.push(Instruction::Ldloc_S(self.read_u8()?)),
+                il_instr::LDLOCA_S => iseq.push(Instruction::Ldloca_S(self.read_u8()?)),
                 il_instr::LDFLD => iseq.push(Instruction::Ldfld(Token(self.read_u32()?))),
+                il_instr::LDFLDA => iseq.push(Instruction::Ldflda(Token(self.read_u32()?))),
+                il_instr::LDSFLD => iseq.push(Instruction::Ldsfld(Token(self.read_u32()?))),
+                il_instr::LDSFLDA => iseq.push(Instruction::Ldsflda(Token(self.read_u32()?))),
                 il_instr::LDELEM_U1 => iseq.push(Instruction::Ldelem_U1),
                 il_instr::LDELEM_I1 => iseq.push(Instruction::Ldelem_I1),
+                il_instr::LDELEM_I2 => iseq.push(Instruction::Ldelem_I2),
+                il_instr::LDELEM_U2 => iseq.push(Instruction::Ldelem_U2),
                 il_instr::LDELEM_I4 => iseq.push(Instruction::Ldelem_I4),
+                il_instr::LDELEM_U4 => iseq.push(Instruction::Ldelem_U4),
+                il_instr::LDELEM_I8 => iseq.push(Instruction::Ldelem_I8),
+                il_instr::LDELEM_I => iseq.push(Instruction::Ldelem_I),
+                il_instr::LDELEM_R4 => iseq.push(Instruction::Ldelem_R4),
+                il_instr::LDELEM_R8 => iseq.push(Instruction::Ldelem_R8),
                 il_instr::LDELEM_REF => iseq.push(Instruction::Ldelem_ref),
+                il_instr::LDELEM => iseq.push(Instruction::Ldelem(Token(self.read_u32()?))),
+                il_instr::LDELEMA => iseq.push(Instruction::Ldelema(Token(self.read_u32()?))),
+                il_instr::LDIND_I1 => iseq.push(Instruction::Ldind_I1),
+                il_instr::LDIND_U1 => iseq.push(Instruction::Ldind_U1),
+                il_instr::LDIND_I2 => iseq.push(Instruction::Ldind_I2),
+                il_instr::LDIND_U2 => iseq.push(Instruction::Ldind_U2),
+                il_instr::LDIND_I4 => iseq.push(Instruction::Ldind_I4),
+                il_instr::LDIND_U4 => iseq.push(Instruction::Ldind_U4),
+                il_instr::LDIND_I8 => iseq.push(Instruction::Ldind_I8),
+                il_instr::LDIND_I => iseq.push(Instruction::Ldind_I),
+                il_instr::LDIND_R4 => iseq.push(Instruction::Ldind_R4),
+                il_instr::LDIND_R8 => iseq.push(Instruction::Ldind_R8),
+                il_instr::LDIND_REF => iseq.push(Instruction::Ldind_ref),
                 il_instr::STLOC_0 => iseq.push(Instruction::Stloc_0),
                 il_instr::STLOC_1 => iseq.push(Instruction::Stloc_1),
                 il_instr::STLOC_2 => iseq.push(Instruction::Stloc_2),
                 il_instr::STLOC_3 => iseq.push(Instruction::Stloc_3),
                 il_instr::STLOC_S => iseq.push(Instruction::Stloc_S(self.read_u8()?)),
                 il_instr::STFLD => iseq.push(Instruction::Stfld(Token(self.read_u32()?))),
+                il_instr::STSFLD => iseq.push(Instruction::Stsfld(Token(self.read_u32()?))),
+                il_instr::STELEM_I => iseq.push(Instruction::Stelem_I),
                 il_instr::STELEM_I1 => iseq.push(Instruction::Stelem_I1),
+                il_instr::STELEM_I2 => iseq.push(Instruction::Stelem_I2),
                 il_instr::STELEM_I4 => iseq.push(Instruction::Stelem_I4),
+                il_instr::STELEM_I8 => iseq.push(Instruction::Stelem_I8),
+                il_instr::STELEM_R4 => iseq.push(Instruction::Stelem_R4),
+                il_instr::STELEM_R8 => iseq.push(Instruction::Stelem_R8),
                 il_instr::STELEM_REF => iseq.push(Instruction::Stelem_ref),
+                il_instr::STELEM => iseq.push(Instruction::Stelem(Token(self.read_u32()?))),
+                il_instr::STIND_REF => iseq.push(Instruction::Stind_ref),
+                il_instr::STIND_I1 => iseq.push(Instruction::Stind_I1),
+                il_instr::STIND_I2 => iseq.push(Instruction::Stind_I2),
+                il_instr::STIND_I4 => iseq.push(Instruction::Stind_I4),
+                il_instr::STIND_I8 => iseq.push(Instruction::Stind_I8),
+                il_instr::STIND_I => iseq.push(Instruction::Stind_I),
+                il_instr::STIND_R4 => iseq.push(Instruction::Stind_R4),
+                il_instr::STIND_R8 => iseq.push(Instruction::Stind_R8),
                 il_instr::STARG_S => iseq.push(Instruction::Starg_S(self.read_u8()?)),
                 il_instr::LDLEN => iseq.push(Instruction::Ldlen),
+                il_instr::CONV_I1 => iseq.push(Instruction::Conv_I1),
+                il_instr::CONV_I2 => iseq.push(Instruction::Conv_I2),
                 il_instr::CONV_I4 => iseq.push(Instruction::Conv_I4),
                 il_instr::CONV_I8 => iseq.push(Instruction::Conv_I8),
+                il_instr::CONV_I => iseq.push(Instruction::Conv_I),
+                il_instr::CONV_U1 => iseq.push(Instruction::Conv_U1),
+                il_instr::CONV_U2 => iseq.push(Instruction::Conv_U2),
+                il_instr::CONV_U4 => iseq.push(Instruction::Conv_U4),
+                il_instr::CONV_U8 => iseq.push(Instruction::Conv_U8),
+                il_instr::CONV_U => iseq.push(Instruction::Conv_U),
+                il_instr::CONV_R4 => iseq.push(Instruction::Conv_R4),
                 il_instr::CONV_R8 => iseq.push(Instruction::Conv_R8),
                 il_instr::CONV_R_UN => iseq.push(Instruction::Conv_R_un),
+                il_instr::CONV_OVF_I1 => iseq.push(Instruction::Conv_ovf_I1),
+                il_instr::CONV_OVF_I2 => iseq.push(Instruction::Conv_ovf_I2),
+                il_instr::CONV_OVF_I4 => iseq.push(Instruction::Conv_ovf_I4),
+                il_instr::CONV_OVF_I8 => iseq.push(Instruction::Conv_ovf_I8),
+                il_instr::CONV_OVF_I => iseq.push(Instruction::Conv_ovf_I),
+                il_instr::CONV_OVF_U1 => iseq.push(Instruction::Conv_ovf_U1),
+                il_instr::CONV_OVF_U2 => iseq.push(Instruction::Conv_ovf_U2),
+                il_instr::CONV_OVF_U4 => iseq.push(Instruction::Conv_ovf_U4),
+                il_instr::CONV_OVF_U8 => iseq.push(Instruction::Conv_ovf_U8),
+                il_instr::CONV_OVF_U => iseq.push(Instruction::Conv_ovf_U),
+                il_instr::CONV_OVF_I1_UN => iseq.push(Instruction::Conv_ovf_I1_un),
+                il_instr::CONV_OVF_I2_UN => iseq.push(Instruction::Conv_ovf_I2_un),
+                il_instr::CONV_OVF_I4_UN => iseq.push(Instruction::Conv_ovf_I4_un),
+                il_instr::CONV_OVF_I8_UN => iseq.push(Instruction::Conv_ovf_I8_un),
+                il_instr::CONV_OVF_I_UN => iseq.push(Instruction::Conv_ovf_I_un),
+                il_instr::CONV_OVF_U1_UN => iseq.push(Instruction::Conv_ovf_U1_un),
+                il_instr::CONV_OVF_U2_UN => iseq.push(Instruction::Conv_ovf_U2_un),
+                il_instr::CONV_OVF_U4_UN => iseq.push(Instruction::Conv_ovf_U4_un),
+                il_instr::CONV_OVF_U8_UN => iseq.push(Instruction::Conv_ovf_U8_un),
+                il_instr::CONV_OVF_U_UN => iseq.push(Instruction::Conv_ovf_U_un),
                 il_instr::POP => iseq.push(Instruction::Pop),
                 il_instr::DUP => iseq.push(Instruction::Dup),
-                il_instr::BGE => iseq.push(Instruction::Bge({
-                    let target = self.read_u32()? as i32;
-                    *self.target_map.get(&(i as i32 + 1 + 4 + target)).unwrap()
-                })),
-                il_instr::BGE_UN => iseq.push(Instruction::Bge_un({
-                    let target = self.read_u32()? as i32;
-                    *self.target_map.get(&(i as i32 + 1 + 4 + target)).unwrap()
-                })),
-                il_instr::BGT => iseq.push(Instruction::Bgt({
-                    let target = self.read_u32()? as i32;
-                    *self.target_map.get(&(i as i32 + 1 + 4 + target)).unwrap()
-                })),
-                il_instr::BLT => iseq.push(Instruction::Blt({
-                    let target = self.read_u32()? as i32;
-                    *self.target_map.get(&(i as i32 + 1 + 4 + target)).unwrap()
-                })),
-                il_instr::BLE => iseq.push(Instruction::Ble({
-                    let target = self.read_u32()? as i32;
-                    *self.target_map.get(&(i as i32 + 1 + 4 + target)).unwrap()
-                })),
-                il_instr::BLE_UN => iseq.push(Instruction::Ble_un({
-                    let target = self.read_u32()? as i32;
-                    *self.target_map.get(&(i as i32 + 1 + 4 + target)).unwrap()
-                })),
-                il_instr::BEQ => iseq.push(Instruction::Beq({
-                    let target = self.read_u32()? as i32;
-                    *self.target_map.get(&(i as i32 + 1 + 4 + target)).unwrap()
-                })),
-                il_instr::BNE_UN => iseq.push(Instruction::Bne_un({
-                    let target = self.read_u32()? as i32;
-                    *self.target_map.get(&(i as i32 + 1 + 4 + target)).unwrap()
-                })),
-                il_instr::BRFALSE => iseq.push(Instruction::Brfalse({
-                    let target = self.read_u32()? as i32;
-                    *self.target_map.get(&(i as i32 + 1 + 4 + target)).unwrap()
-                })),
-                il_instr::BRTRUE => iseq.push(Instruction::Brtrue({
-                    let target = self.read_u32()? as i32;
-                    *self.target_map.get(&(i as i32 + 1 + 4 + target)).unwrap()
-                })),
-                il_instr::BR => iseq.push(Instruction::Br({
-                    let target = self.read_u32()? as i32;
-                    *self.target_map.get(&(i as i32 + 1 + 4 + target)).unwrap()
-                })),
+                il_instr::BGE => iseq.push(Instruction::Bge(self.read_target(i)?)),
+                il_instr::BGE_UN => iseq.push(Instruction::Bge_un(self.read_target(i)?)),
+                il_instr::BGT => iseq.push(Instruction::Bgt(self.read_target(i)?)),
+                il_instr::BLT => iseq.push(Instruction::Blt(self.read_target(i)?)),
+                il_instr::BLE => iseq.push(Instruction::Ble(self.read_target(i)?)),
+                il_instr::BLE_UN => iseq.push(Instruction::Ble_un(self.read_target(i)?)),
+                il_instr::BEQ => iseq.push(Instruction::Beq(self.read_target(i)?)),
+                il_instr::BNE_UN => iseq.push(Instruction::Bne_un(self.read_target(i)?)),
+                il_instr::BRFALSE => iseq.push(Instruction::Brfalse(self.read_target(i)?)),
+                il_instr::BRTRUE => iseq.push(Instruction::Brtrue(self.read_target(i)?)),
+                il_instr::BR => iseq.push(Instruction::Br(self.read_target(i)?)),
+                il_instr::LEAVE => iseq.push(Instruction::Leave(self.read_target(i)?)),
+                il_instr::BGT_UN => iseq.push(Instruction::Bgt_un(self.read_target(i)?)),
+                il_instr::BLT_UN => iseq.push(Instruction::Blt_un(self.read_target(i)?)),
+                il_instr::LEAVE_S => iseq.push(Instruction::Leave(self.read_short_target(i)?)),
+                il_instr::BR_S => iseq.push(Instruction::Br(self.read_short_target(i)?)),
+                il_instr::BRFALSE_S => iseq.push(Instruction::Brfalse(self.read_short_target(i)?)),
+                il_instr::BRTRUE_S => iseq.push(Instruction::Brtrue(self.read_short_target(i)?)),
+                il_instr::BEQ_S => iseq.push(Instruction::Beq(self.read_short_target(i)?)),
+                il_instr::BGE_S => iseq.push(Instruction::Bge(self.read_short_target(i)?)),
+                il_instr::BGT_S => iseq.push(Instruction::Bgt(self.read_short_target(i)?)),
+                il_instr::BLE_S => iseq.push(Instruction::Ble(self.read_short_target(i)?)),
+                il_instr::BLT_S => iseq.push(Instruction::Blt(self.read_short_target(i)?)),
+                il_instr::BNE_UN_S => iseq.push(Instruction::Bne_un(self.read_short_target(i)?)),
+                il_instr::BGE_UN_S => iseq.push(Instruction::Bge_un(self.read_short_target(i)?)),
+                il_instr::BGT_UN_S => iseq.push(Instruction::Bgt_un(self.read_short_target(i)?)),
+                il_instr::BLE_UN_S => iseq.push(Instruction::Ble_un(self.read_short_target(i)?)),
+                il_instr::BLT_UN_S => iseq.push(Instruction::Blt_un(self.read_short_target(i)?)),
+                il_instr::SWITCH => {
+                    let n = self.read_u32()? as usize;
+                    let offsets = (0..n)
+                        .map(|_| self.read_u32().map(|offset| offset as i32))
+                        .collect::<Option<Vec<i32>>>()?;
+                    // Offsets are relative to the end of the whole instruction
+                    let next = (i + 1 + 4 + 4 * n) as i32;
+                    let targets = offsets
+                        .into_iter()
+                        .map(|offset| self.target_map.get(&next.wrapping_add(offset)).map(|t| *t))
+                        .collect::<Option<Vec<usize>>>()?;
+                    iseq.push(Instruction::Switch(targets))
+                }
+                il_instr::THROW => iseq.push(Instruction::Throw),
+                il_instr::ENDFINALLY => iseq.push(Instruction::Endfinally),
                 0xfe => match self.iter.next()?.1 {
                     &il_instr::CLT => iseq.push(Instruction::Clt),
+                    &il_instr::CLT_UN => iseq.push(Instruction::Clt_un),
                     &il_instr::CEQ => iseq.push(Instruction::Ceq),
                     &il_instr::CGT => iseq.push(Instruction::Cgt),
-                    _ => unimplemented!(),
+                    &il_instr::CGT_UN => iseq.push(Instruction::Cgt_un),
+                    &il_instr::LDARG => iseq.push(Instruction::Ldarg(self.read_u16()?)),
+                    &il_instr::LDARGA => iseq.push(Instruction::Ldarga(self.read_u16()?)),
+                    &il_instr::STARG => iseq.push(Instruction::Starg(self.read_u16()?)),
+                    &il_instr::LDLOC => iseq.push(Instruction::Ldloc(self.read_u16()?)),
+                    &il_instr::LDLOCA => iseq.push(Instruction::Ldloca(self.read_u16()?)),
+                    &il_instr::STLOC => iseq.push(Instruction::Stloc(self.read_u16()?)),
+                    &il_instr::UNALIGNED => iseq.push(Instruction::Unaligned(self.read_u8()?)),
+                    &il_instr::VOLATILE => iseq.push(Instruction::Volatile),
+                    &il_instr::TAIL => iseq.push(Instruction::Tail),
+                    &il_instr::READONLY => iseq.push(Instruction::Readonly),
+                    &il_instr::NO => iseq.push(Instruction::No(self.read_u8()?)),
+                    &il_instr::CONSTRAINED => {
+                        iseq.push(Instruction::Constrained(Token(self.read_u32()?)))
+                    }
+                    &il_instr::INITOBJ => iseq.push(Instruction::Initobj(Token(self.read_u32()?))),
+                    &il_instr::SIZEOF => iseq.push(Instruction::Sizeof(Token(self.read_u32()?))),
+                    &il_instr::LDFTN => iseq.push(Instruction::Ldftn(Token(self.read_u32()?))),
+                    &il_instr::LDVIRTFTN => {
+                        iseq.push(Instruction::Ldvirtftn(Token(self.read_u32()?)))
+                    }
+                    &il_instr::LOCALLOC => iseq.push(Instruction::Localloc),
+                    &il_instr::CPBLK => iseq.push(Instruction::Cpblk),
+                    &il_instr::INITBLK => iseq.push(Instruction::Initblk),
+                    &il_instr::ARGLIST => iseq.push(Instruction::Arglist),
+                    &il_instr::REFANYTYPE => iseq.push(Instruction::Refanytype),
+                    &il_instr::RETHROW => iseq.push(Instruction::Rethrow),
+                    &il_instr::ENDFILTER => iseq.push(Instruction::Endfilter),
+                    // Unknown or unsupported instruction
+                    _ => return None,
                 },
                 il_instr::ADD => iseq.push(Instruction::Add),
                 il_instr::SUB => iseq.push(Instruction::Sub),
                 il_instr::MUL => iseq.push(Instruction::Mul),
                 il_instr::DIV => iseq.push(Instruction::Div),
+                il_instr::DIV_UN => iseq.push(Instruction::Div_un),
                 il_instr::REM => iseq.push(Instruction::Rem),
                 il_instr::REM_UN => iseq.push(Instruction::Rem_un),
+                il_instr::AND => iseq.push(Instruction::And),
+                il_instr::OR => iseq.push(Instruction::Or),
                 il_instr::XOR => iseq.push(Instruction::Xor),
                 il_instr::SHL => iseq.push(Instruction::Shl),
                 il_instr::SHR => iseq.push(Instruction::Shr),
                 il_instr::SHR_UN => iseq.push(Instruction::Shr_un),
+                il_instr::ADD_OVF => iseq.push(Instruction::Add_ovf),
+                il_instr::ADD_OVF_UN => iseq.push(Instruction::Add_ovf_un),
+                il_instr::SUB_OVF => iseq.push(Instruction::Sub_ovf),
+                il_instr::SUB_OVF_UN => iseq.push(Instruction::Sub_ovf_un),
+                il_instr::MUL_OVF => iseq.push(Instruction::Mul_ovf),
+                il_instr::MUL_OVF_UN => iseq.push(Instruction::Mul_ovf_un),
                 il_instr::NEG => iseq.push(Instruction::Neg),
+                il_instr::NOT => iseq.push(Instruction::Not),
+                il_instr::CKFINITE => iseq.push(Instruction::Ckfinite),
                 il_instr::RET => iseq.push(Instruction::Ret),
-                e => unimplemented!("{:?}", e),
+                _ => return None,
             }
         }
 
         Some(iseq)
     }
 
-    fn make_target_map(&mut self) {
+    fn make_target_map(&mut self) -> Option<()> {
         let mut iter = self.iter.clone();
         let mut iseq_size = 0;
         while let Some((i, byte)) = iter.next() {
             self.target_map.insert(i as i32, iseq_size);
-            if *byte == 0xfe {
-                // 2 bytes instruction
-                let byte = iter.next().unwrap().1;
-                for _ in 0..il_instr::get_instr2_size(*byte) - 2 {
-                    iter.next();
+            let operand_size = if *byte == il_instr::SWITCH {
+                // switch is followed by the number of targets and the targets
+                let mut n = 0;
+                for k in 0..4 {
+                    n += (*iter.next()?.1 as usize) << (k * 8);
                 }
+                4 * n
+            } else if *byte == 0xfe {
+                // 2 bytes instruction
+                let byte = iter.next()?.1;
+                il_instr::get_instr2_size(*byte)? - 2
             } else {
-                for _ in 0..il_instr::get_instr_size(*byte) - 1 {
-                    iter.next();
-                }
+                il_instr::get_instr_size(*byte)? - 1
+            };
+            if operand_size > iter.len() {
+                return None;
+            }
+            if operand_size > 0 {
+                iter.nth(operand_size - 1);
             }
             iseq_size += 1;
         }
+        // Exception clauses may refer to the end of the method body
+        self.target_map.insert(self.iter.len() as i32, iseq_size);
+        Some(())
+    }
+
+    /// Converts a byte offset in the method body into an index of the converted instructions.
+    pub fn get_instr_index(&self, offset: u32) -> Option<usize> {
+        self.target_map.get(&(offset as i32)).map(|i| *i)
     }
 }
 
 impl<'a> BytesToInstructions<'a> {
+    /// Reads the 4-byte branch target of the instruction at ``pc``
+    fn read_target(&mut self, pc: usize) -> Option<usize> {
+        let target = self.read_u32()? as i32;
+        self.target_map
+            .get(&(pc as i32 + 1 + 4).wrapping_add(target))
+            .map(|t| *t)
+    }
+
+    /// Reads the 1-byte branch target of the instruction at ``pc``
+    fn read_short_target(&mut self, pc: usize) -> Option<usize> {
+        let target = self.read_u8()? as i8 as i32;
+        self.target_map
+            .get(&(pc as i32 + 1 + 1).wrapping_add(target))
+            .map(|t| *t)
+    }
+
     fn read_u8(&mut self) -> Option<u8> {
         let x = *self.iter.next()?.1;
         Some(x)
     }
 
+    fn read_u16(&mut self) -> Option<u16> {
+        let x = *self.iter.next()?.1 as u16;
+        let y = *self.iter.next()?.1 as u16;
+        Some((y << 8) + x)
+    }
+
     fn read_u32(&mut self) -> Option<u32> {
         let x = *self.iter.next()?.1 as u32;
         let y = *self.iter.next()?.1 as u32;
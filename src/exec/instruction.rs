@@ -1,8 +1,10 @@
 use crate::metadata::token::*;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 #[allow(non_camel_case_types)]
 pub enum Instruction {
+    Nop,
+    Break,
     Ldnull,
     Ldstr(u32),
     Ldc_I4_M1,
@@ -17,37 +19,114 @@ pub enum Instruction {
     Ldc_I4_8,
     Ldc_I4_S(i32),
     Ldc_I4(i32),
+    Ldc_I8(i64),
+    Ldc_R4(f32),
     Ldc_R8(f64),
     Ldarg_0,
     Ldarg_1,
     Ldarg_2,
     Ldarg_3,
     Ldarg_S(i32),
+    Ldarg(u16),
+    Ldarga_S(u8),
+    Ldarga(u16),
     Ldloc_0,
     Ldloc_1,
     Ldloc_2,
     Ldloc_3,
     Ldloc_S(u8),
+    Ldloc(u16),
+    Ldloca_S(u8),
+    Ldloca(u16),
     Ldfld(Token),
+    Ldflda(Token),
+    Ldsfld(Token),
+    Ldsflda(Token),
     Ldelem_I1,
     Ldelem_U1,
+    Ldelem_I2,
+    Ldelem_U2,
     Ldelem_I4,
+    Ldelem_U4,
+    Ldelem_I8,
+    Ldelem_I,
+    Ldelem_R4,
+    Ldelem_R8,
     Ldelem_ref,
+    Ldelem(Token),
+    Ldelema(Token),
+    Ldind_I1,
+    Ldind_U1,
+    Ldind_I2,
+    Ldind_U2,
+    Ldind_I4,
+    Ldind_U4,
+    Ldind_I8,
+    Ldind_I,
+    Ldind_R4,
+    Ldind_R8,
+    Ldind_ref,
     Stloc_0,
     Stloc_1,
     Stloc_2,
     Stloc_3,
     Stloc_S(u8),
+    Stloc(u16),
     Stfld(Token),
+    Stsfld(Token),
+    Stelem_I,
     Stelem_I1,
+    Stelem_I2,
     Stelem_I4,
+    Stelem_I8,
+    Stelem_R4,
+    Stelem_R8,
     Stelem_ref,
+    Stelem(Token),
+    Stind_ref,
+    Stind_I1,
+    Stind_I2,
+    Stind_I4,
+    Stind_I8,
+    Stind_I,
+    Stind_R4,
+    Stind_R8,
     Starg_S(u8),
+    Starg(u16),
     Ldlen,
+    Conv_I1,
+    Conv_I2,
     Conv_I4,
     Conv_I8,
+    Conv_I,
+    Conv_U1,
+    Conv_U2,
+    Conv_U4,
+    Conv_U8,
+    Conv_U,
+    Conv_R4,
     Conv_R8,
     Conv_R_un,
+    Conv_ovf_I1,
+    Conv_ovf_I2,
+    Conv_ovf_I4,
+    Conv_ovf_I8,
+    Conv_ovf_I,
+    Conv_ovf_U1,
+    Conv_ovf_U2,
+    Conv_ovf_U4,
+    Conv_ovf_U8,
+    Conv_ovf_U,
+    Conv_ovf_I1_un,
+    Conv_ovf_I2_un,
+    Conv_ovf_I4_un,
+    Conv_ovf_I8_un,
+    Conv_ovf_I_un,
+    Conv_ovf_U1_un,
+    Conv_ovf_U2_un,
+    Conv_ovf_U4_un,
+    Conv_ovf_U8_un,
+    Conv_ovf_U_un,
     Dup,
     Pop,
     Beq(usize),
@@ -55,40 +134,95 @@ pub enum Instruction {
     Bge(usize),
     Bge_un(usize),
     Bgt(usize),
+    Bgt_un(usize),
     Blt(usize),
+    Blt_un(usize),
     Ble(usize),
     Ble_un(usize),
     Brfalse(usize),
     Brtrue(usize),
     Br(usize),
+    Switch(Vec<usize>),
     Cgt,
+    Cgt_un,
     Clt,
+    Clt_un,
     Ceq,
+    Ckfinite,
     Add,
     Sub,
     Mul,
     Div,
+    Div_un,
     Rem,
     Rem_un,
+    And,
+    Or,
     Xor,
     Shl,
     Shr,
     Shr_un,
+    Add_ovf,
+    Add_ovf_un,
+    Sub_ovf,
+    Sub_ovf_un,
+    Mul_ovf,
+    Mul_ovf_un,
     Neg,
+    Not,
+    Jmp(Token),
     Call(Token),
     CallVirt(Token),
+    Calli(Token),
+    Ldftn(Token),
+    Ldvirtftn(Token),
     Box(Token),
+    Unbox(Token),
+    Unbox_any(Token),
+    Initobj(Token),
+    Ldobj(Token),
+    Stobj(Token),
+    Cpobj(Token),
+    Sizeof(Token),
+    Castclass(Token),
+    Isinst(Token),
     Newobj(Token),
     Newarr(Token),
+    Ldtoken(Token),
+    Localloc,
+    Cpblk,
+    Initblk,
+    Arglist,
+    Mkrefany(Token),
+    Refanyval(Token),
+    Refanytype,
+    Throw,
+    Rethrow,
+    Leave(usize),
+    Endfinally,
+    Endfilter,
     Ret,
+    // Prefixes
+    Unaligned(u8),
+    Volatile,
+    Tail,
+    Readonly,
+    Constrained(Token),
+    No(u8),
 }
 
 #[rustfmt::skip]
 pub mod il_instr {
+    pub const NOP        : u8 = 0x00;
+    pub const BREAK      : u8 = 0x01;
     pub const LDNULL     : u8 = 0x14;
     pub const LDSTR      : u8 = 0x72;
+    pub const JMP        : u8 = 0x27;
     pub const CALL       : u8 = 0x28;
     pub const CALLVIRT   : u8 = 0x6f;
+    pub const CALLI      : u8 = 0x29;
+    pub const LDFTN      : u8 = 0x06; // 0xfe leads
+    pub const LDVIRTFTN  : u8 = 0x07; // 0xfe leads
     pub const LDC_I4_M1  : u8 = 0x15;
     pub const LDC_I4_0   : u8 = 0x16;
     pub const LDC_I4_1   : u8 = 0x17;
@@ -101,103 +235,258 @@ pub mod il_instr {
     pub const LDC_I4_8   : u8 = 0x1e;
     pub const LDC_I4_S   : u8 = 0x1f;
     pub const LDC_I4     : u8 = 0x20;
+    pub const LDC_I8     : u8 = 0x21;
+    pub const LDC_R4     : u8 = 0x22;
     pub const LDC_R8     : u8 = 0x23;
     pub const LDARG_0    : u8 = 0x02;
     pub const LDARG_1    : u8 = 0x03;
     pub const LDARG_2    : u8 = 0x04;
     pub const LDARG_3    : u8 = 0x05;
     pub const LDARG_S    : u8 = 0x0e;
+    pub const LDARG      : u8 = 0x09; // 0xfe leads
+    pub const LDARGA_S   : u8 = 0x0f;
+    pub const LDARGA     : u8 = 0x0a; // 0xfe leads
     pub const LDLOC_0    : u8 = 0x06;
     pub const LDLOC_1    : u8 = 0x07;
     pub const LDLOC_2    : u8 = 0x08;
     pub const LDLOC_3    : u8 = 0x09;
     pub const LDLOC_S    : u8 = 0x11;
+    pub const LDLOC      : u8 = 0x0c; // 0xfe leads
+    pub const LDLOCA_S   : u8 = 0x12;
+    pub const LDLOCA     : u8 = 0x0d; // 0xfe leads
     pub const LDFLD      : u8 = 0x7b;
+    pub const LDFLDA     : u8 = 0x7c;
+    pub const LDSFLD     : u8 = 0x7e;
+    pub const LDSFLDA    : u8 = 0x7f;
     pub const LDELEM_I1  : u8 = 0x90;
     pub const LDELEM_U1  : u8 = 0x91;
+    pub const LDELEM_I2  : u8 = 0x92;
+    pub const LDELEM_U2  : u8 = 0x93;
     pub const LDELEM_I4  : u8 = 0x94;
+    pub const LDELEM_U4  : u8 = 0x95;
+    pub const LDELEM_I8  : u8 = 0x96;
+    pub const LDELEM_I   : u8 = 0x97;
+    pub const LDELEM_R4  : u8 = 0x98;
+    pub const LDELEM_R8  : u8 = 0x99;
     pub const LDELEM_REF : u8 = 0x9a;
+    pub const LDELEM     : u8 = 0xa3;
+    pub const LDELEMA    : u8 = 0x8f;
+    pub const LDIND_I1   : u8 = 0x46;
+    pub const LDIND_U1   : u8 = 0x47;
+    pub const LDIND_I2   : u8 = 0x48;
+    pub const LDIND_U2   : u8 = 0x49;
+    pub const LDIND_I4   : u8 = 0x4a;
+    pub const LDIND_U4   : u8 = 0x4b;
+    pub const LDIND_I8   : u8 = 0x4c;
+    pub const LDIND_I    : u8 = 0x4d;
+    pub const LDIND_R4   : u8 = 0x4e;
+    pub const LDIND_R8   : u8 = 0x4f;
+    pub const LDIND_REF  : u8 = 0x50;
     pub const STLOC_0    : u8 = 0x0a;
     pub const STLOC_1    : u8 = 0x0b;
     pub const STLOC_2    : u8 = 0x0c;
     pub const STLOC_3    : u8 = 0x0d;
     pub const STLOC_S    : u8 = 0x13;
+    pub const STLOC      : u8 = 0x0e; // 0xfe leads
     pub const STFLD      : u8 = 0x7d;
+    pub const STSFLD     : u8 = 0x80;
+    pub const STELEM_I   : u8 = 0x9b;
     pub const STELEM_I1  : u8 = 0x9c;
+    pub const STELEM_I2  : u8 = 0x9d;
     pub const STELEM_I4  : u8 = 0x9e;
+    pub const STELEM_I8  : u8 = 0x9f;
+    pub const STELEM_R4  : u8 = 0xa0;
+    pub const STELEM_R8  : u8 = 0xa1;
     pub const STELEM_REF : u8 = 0xa2;
+    pub const STELEM     : u8 = 0xa4;
+    pub const STIND_REF  : u8 = 0x51;
+    pub const STIND_I1   : u8 = 0x52;
+    pub const STIND_I2   : u8 = 0x53;
+    pub const STIND_I4   : u8 = 0x54;
+    pub const STIND_I8   : u8 = 0x55;
+    pub const STIND_R4   : u8 = 0x56;
+    pub const STIND_R8   : u8 = 0x57;
+    pub const STIND_I    : u8 = 0xdf;
     pub const STARG_S    : u8 = 0x10;
+    pub const STARG      : u8 = 0x0b; // 0xfe leads
     pub const LDLEN      : u8 = 0x8e;
+    pub const CONV_I1    : u8 = 0x67;
+    pub const CONV_I2    : u8 = 0x68;
     pub const CONV_I4    : u8 = 0x69;
     pub const CONV_I8    : u8 = 0x6a;
+    pub const CONV_I     : u8 = 0xd3;
+    pub const CONV_U1    : u8 = 0xd2;
+    pub const CONV_U2    : u8 = 0xd1;
+    pub const CONV_U4    : u8 = 0x6d;
+    pub const CONV_U8    : u8 = 0x6e;
+    pub const CONV_U     : u8 = 0xe0;
+    pub const CONV_R4    : u8 = 0x6b;
     pub const CONV_R8    : u8 = 0x6c;
     pub const CONV_R_UN  : u8 = 0x76;
+    pub const CONV_OVF_I1: u8 = 0xb3;
+    pub const CONV_OVF_I2: u8 = 0xb5;
+    pub const CONV_OVF_I4: u8 = 0xb7;
+    pub const CONV_OVF_I8: u8 = 0xb9;
+    pub const CONV_OVF_I : u8 = 0xd4;
+    pub const CONV_OVF_U1: u8 = 0xb4;
+    pub const CONV_OVF_U2: u8 = 0xb6;
+    pub const CONV_OVF_U4: u8 = 0xb8;
+    pub const CONV_OVF_U8: u8 = 0xba;
+    pub const CONV_OVF_U : u8 = 0xd5;
+    pub const CONV_OVF_I1_UN: u8 = 0x82;
+    pub const CONV_OVF_I2_UN: u8 = 0x83;
+    pub const CONV_OVF_I4_UN: u8 = 0x84;
+    pub const CONV_OVF_I8_UN: u8 = 0x85;
+    pub const CONV_OVF_I_UN : u8 = 0x8a;
+    pub const CONV_OVF_U1_UN: u8 = 0x86;
+    pub const CONV_OVF_U2_UN: u8 = 0x87;
+    pub const CONV_OVF_U4_UN: u8 = 0x88;
+    pub const CONV_OVF_U8_UN: u8 = 0x89;
+    pub const CONV_OVF_U_UN : u8 = 0x8b;
     pub const DUP        : u8 = 0x25;
     pub const POP        : u8 = 0x26;
+    pub const BR_S       : u8 = 0x2b;
+    pub const BRFALSE_S  : u8 = 0x2c;
+    pub const BRTRUE_S   : u8 = 0x2d;
+    pub const BEQ_S      : u8 = 0x2e;
+    pub const BGE_S      : u8 = 0x2f;
+    pub const BGT_S      : u8 = 0x30;
+    pub const BLE_S      : u8 = 0x31;
+    pub const BLT_S      : u8 = 0x32;
+    pub const BNE_UN_S   : u8 = 0x33;
+    pub const BGE_UN_S   : u8 = 0x34;
+    pub const BGT_UN_S   : u8 = 0x35;
+    pub const BLE_UN_S   : u8 = 0x36;
+    pub const BLT_UN_S   : u8 = 0x37;
     pub const BR         : u8 = 0x38;
     pub const BGE        : u8 = 0x3c;
     pub const BGE_UN     : u8 = 0x41;
     pub const BGT        : u8 = 0x3d;
+    pub const BGT_UN     : u8 = 0x42;
     pub const BLE        : u8 = 0x3e;
     pub const BLE_UN     : u8 = 0x43;
     pub const BLT        : u8 = 0x3f;
+    pub const BLT_UN     : u8 = 0x44;
     pub const BEQ        : u8 = 0x3b;
     pub const BNE_UN     : u8 = 0x40;
     pub const BRFALSE    : u8 = 0x39;
     pub const BRTRUE     : u8 = 0x3a;
+    pub const SWITCH     : u8 = 0x45;
     pub const CGT        : u8 = 0x02; // 0xfe leads
+    pub const CGT_UN     : u8 = 0x03; // 0xfe leads
     pub const CLT        : u8 = 0x04; // 0xfe leads
+    pub const CLT_UN     : u8 = 0x05; // 0xfe leads
     pub const CEQ        : u8 = 0x01; // 0xfe leads
+    pub const CKFINITE   : u8 = 0xc3;
     pub const ADD        : u8 = 0x58;
     pub const SUB        : u8 = 0x59;
     pub const MUL        : u8 = 0x5a;
     pub const DIV        : u8 = 0x5b;
+    pub const DIV_UN     : u8 = 0x5c;
     pub const REM        : u8 = 0x5d;
     pub const REM_UN     : u8 = 0x5e;
+    pub const AND        : u8 = 0x5f;
+    pub const OR         : u8 = 0x60;
     pub const XOR        : u8 = 0x61;
     pub const SHL        : u8 = 0x62;
     pub const SHR        : u8 = 0x63;
     pub const SHR_UN     : u8 = 0x64;
+    pub const ADD_OVF    : u8 = 0xd6;
+    pub const ADD_OVF_UN : u8 = 0xd7;
+    pub const SUB_OVF    : u8 = 0xda;
+    pub const SUB_OVF_UN : u8 = 0xdb;
+    pub const MUL_OVF    : u8 = 0xd8;
+    pub const MUL_OVF_UN : u8 = 0xd9;
     pub const NEG        : u8 = 0x65;
+    pub const NOT        : u8 = 0x66;
     pub const BOX        : u8 = 0x8c;
+    pub const UNBOX      : u8 = 0x79;
+    pub const UNBOX_ANY  : u8 = 0xa5;
+    pub const INITOBJ    : u8 = 0x15; // 0xfe leads
+    pub const LDOBJ      : u8 = 0x71;
+    pub const STOBJ      : u8 = 0x81;
+    pub const CPOBJ      : u8 = 0x70;
+    pub const SIZEOF     : u8 = 0x1c; // 0xfe leads
+    pub const CASTCLASS  : u8 = 0x74;
+    pub const ISINST     : u8 = 0x75;
     pub const NEWOBJ     : u8 = 0x73;
     pub const NEWARR     : u8 = 0x8d;
+    pub const LDTOKEN    : u8 = 0xd0;
+    pub const LOCALLOC   : u8 = 0x0f; // 0xfe leads
+    pub const CPBLK      : u8 = 0x17; // 0xfe leads
+    pub const INITBLK    : u8 = 0x18; // 0xfe leads
+    pub const ARGLIST    : u8 = 0x00; // 0xfe leads
+    pub const MKREFANY   : u8 = 0xc6;
+    pub const REFANYVAL  : u8 = 0xc2;
+    pub const REFANYTYPE : u8 = 0x1d; // 0xfe leads
+    pub const THROW      : u8 = 0x7a;
+    pub const RETHROW    : u8 = 0x1a; // 0xfe leads
+    pub const LEAVE      : u8 = 0xdd;
+    pub const LEAVE_S    : u8 = 0xde;
+    pub const ENDFINALLY : u8 = 0xdc;
+    pub const ENDFILTER  : u8 = 0x11; // 0xfe leads
     pub const RET        : u8 = 0x2a;
+    pub const UNALIGNED  : u8 = 0x12; // 0xfe leads
+    pub const VOLATILE   : u8 = 0x13; // 0xfe leads
+    pub const TAIL       : u8 = 0x14; // 0xfe leads
+    pub const READONLY   : u8 = 0x1e; // 0xfe leads
+    pub const CONSTRAINED: u8 = 0x16; // 0xfe leads
+    pub const NO         : u8 = 0x19; // 0xfe leads
 
-    pub fn get_instr_size<'a>(instr: u8) -> usize {
-        match instr {
-            LDC_R8 => 9,
-            LDNULL | LDSTR | 
-            CALL | CALLVIRT |
-            NEWOBJ | NEWARR | BOX |
-            STFLD | LDFLD |
+    pub fn get_instr_size<'a>(instr: u8) -> Option<usize> {
+        Some(match instr {
+            LDC_R8 | LDC_I8 => 9,
+            LDSTR | LDC_R4 |
+            JMP | CALL | CALLVIRT | CALLI |
+            NEWOBJ | NEWARR | BOX | UNBOX | UNBOX_ANY | LDOBJ | STOBJ | CPOBJ |
+            CASTCLASS | ISINST | LDTOKEN | MKREFANY | REFANYVAL |
+            STFLD | LDFLD | LDFLDA | LDELEMA | LDELEM | STELEM | STSFLD | LDSFLD | LDSFLDA |
             BGE | BGE_UN | BR | BLT | BNE_UN | BRFALSE | BGT
-             | BRTRUE | BLE | BLE_UN | BEQ |
+             | BRTRUE | BLE | BLE_UN | BEQ | LEAVE | BGT_UN | BLT_UN |
             LDC_I4 => 5, 
             LDC_I4_M1 | LDC_I4_0 | LDC_I4_1 | LDC_I4_2 | LDC_I4_3 
              | LDC_I4_4 | LDC_I4_5 | LDC_I4_6 
              | LDC_I4_7 | LDC_I4_8 |
             LDARG_0 | LDARG_1 | LDARG_2 | LDARG_3 | 
             LDLOC_0 | LDLOC_1 | LDLOC_2 | LDLOC_3 |
-            LDELEM_I4 | LDELEM_I1 | LDELEM_U1 | LDELEM_REF |
+            LDELEM_I1 | LDELEM_U1 | LDELEM_I2 | LDELEM_U2 | LDELEM_I4 | LDELEM_U4 | LDELEM_I8
+             | LDELEM_I | LDELEM_R4 | LDELEM_R8 | LDELEM_REF |
             STLOC_0 | STLOC_1 | STLOC_2 | STLOC_3 |
-            STELEM_I4 | STELEM_I1 | STELEM_REF |
-            ADD | SUB | MUL | DIV | REM | REM_UN | XOR 
-            | SHL | SHR | SHR_UN | NEG | 
-            RET | POP | DUP |
-            CONV_I4 | CONV_I8 | CONV_R8 | CONV_R_UN |
+            STELEM_I | STELEM_I1 | STELEM_I2 | STELEM_I4 | STELEM_I8 | STELEM_R4 | STELEM_R8
+             | STELEM_REF |
+            LDIND_I1 | LDIND_U1 | LDIND_I2 | LDIND_U2 | LDIND_I4 | LDIND_U4 | LDIND_I8 | LDIND_I 
+             | LDIND_R4 | LDIND_R8 | LDIND_REF |
+            STIND_REF | STIND_I1 | STIND_I2 | STIND_I4 | STIND_I8 | STIND_R4 | STIND_R8 | STIND_I |
+            ADD | SUB | MUL | DIV | DIV_UN | REM | REM_UN | AND | OR | XOR 
+            | SHL | SHR | SHR_UN | NEG | NOT | CKFINITE |
+            ADD_OVF | ADD_OVF_UN | SUB_OVF | SUB_OVF_UN | MUL_OVF | MUL_OVF_UN |
+            NOP | BREAK | RET | POP | DUP | THROW | ENDFINALLY | LDNULL |
+            CONV_I1 | CONV_I2 | CONV_I4 | CONV_I8 | CONV_I 
+             | CONV_U1 | CONV_U2 | CONV_U4 | CONV_U8 | CONV_U 
+             | CONV_R4 | CONV_R8 | CONV_R_UN |
+            CONV_OVF_I1 | CONV_OVF_I2 | CONV_OVF_I4 | CONV_OVF_I8 | CONV_OVF_I
+             | CONV_OVF_U1 | CONV_OVF_U2 | CONV_OVF_U4 | CONV_OVF_U8 | CONV_OVF_U
+             | CONV_OVF_I1_UN | CONV_OVF_I2_UN | CONV_OVF_I4_UN | CONV_OVF_I8_UN | CONV_OVF_I_UN
+             | CONV_OVF_U1_UN | CONV_OVF_U2_UN | CONV_OVF_U4_UN | CONV_OVF_U8_UN | CONV_OVF_U_UN |
             LDLEN => 1,
             LDLOC_S |
             STLOC_S |
-            STARG_S | LDARG_S | LDC_I4_S => 2,
-            e => panic!("Not an instruction: {}", e),
-        }
+            STARG_S | LDARG_S | LDARGA_S | LDLOCA_S | LDC_I4_S |
+            LEAVE_S | BR_S | BRFALSE_S | BRTRUE_S | BEQ_S | BGE_S | BGT_S | BLE_S 
+             | BLT_S | BNE_UN_S | BGE_UN_S | BGT_UN_S | BLE_UN_S | BLT_UN_S => 2,
+            _ => return None,
+        })
     }
 
-    pub fn get_instr2_size<'a>(instr: u8) -> usize {
-        match instr {
-            CGT | CLT | CEQ => 2,
-            e => panic!("2 bytes inst: Not an instruction: {}", e),
-        }
+    pub fn get_instr2_size<'a>(instr: u8) -> Option<usize> {
+        Some(match instr {
+            CGT | CGT_UN | CLT | CLT_UN | CEQ | RETHROW | ENDFILTER |
+            VOLATILE | TAIL | READONLY |
+            LOCALLOC | CPBLK | INITBLK | ARGLIST | REFANYTYPE => 2,
+            UNALIGNED | NO => 3,
+            LDARG | LDARGA | STARG | LDLOC | LDLOCA | STLOC => 4,
+            INITOBJ | SIZEOF | CONSTRAINED | LDFTN | LDVIRTFTN => 6,
+            _ => return None,
+        })
     }
 }
@@ -1,16 +1,137 @@
 use crate::{
     exec::jit::jit::*,
-    metadata::signature::*,
+    metadata::{
+        assembly::*,
+        image::{
+            mscorlib_system_class, mscorlib_system_delegate, mscorlib_system_io_stream,
+            mscorlib_system_reflection_assembly, mscorlib_system_type, ManifestResource,
+        },
+        signature::*,
+    },
     util::{name_path::*, resolver::*},
 };
 use llvm::{core::*, prelude::*};
 use rustc_hash::FxHashMap;
 use std::{
+    cell::{Cell, RefCell},
     char::decode_utf16,
     ffi::{c_void, CString},
-    mem, ptr,
+    fmt,
+    io::{self, Cursor, Read, Write},
+    mem, process, ptr,
+    sync::atomic::{AtomicI32, AtomicPtr, Ordering},
 };
 
+thread_local! {
+    /// The exception being thrown. Null if there is no pending exception.
+    pub static PENDING_EXCEPTION: Cell<*mut u8> = Cell::new(ptr::null_mut());
+
+    /// Information about the class each method table belongs to.
+    pub static METHOD_TABLE_INFO: RefCell<FxHashMap<usize, MethodTableInfo>> = {
+        RefCell::new(FxHashMap::default())
+    };
+
+    /// Ranks and element classes of the array types that identify the elements of arrays of
+    /// arrays, by their descriptors (see ``array_type_descriptor``)
+    static ARRAY_TYPES: RefCell<FxHashMap<usize, (i32, usize)>> = {
+        RefCell::new(FxHashMap::default())
+    };
+
+    /// Assemblies loaded by ``System.Reflection.Assembly.Load`` and the executing ones. An
+    /// Assembly object holds the index in this list.
+    pub static LOADED_ASSEMBLIES: RefCell<Vec<RuntimeAssembly>> = RefCell::new(vec![]);
+
+    /// Handle of mscorlib in ``LOADED_ASSEMBLIES``. None until guest code asks for it.
+    static MSCORLIB_ASSEMBLY: Cell<Option<usize>> = Cell::new(None);
+
+    /// Finds the dependencies of the assemblies loaded by ``System.Reflection.Assembly.Load``
+    pub static GUEST_ASSEMBLY_RESOLVER: RefCell<Option<AssemblyResolver>> = {
+        RefCell::new(None)
+    };
+
+    /// Type objects of the method tables. A type has only one Type object, which lives as long as
+    /// the method table does.
+    static TYPE_OBJECTS: RefCell<FxHashMap<usize, usize>> = RefCell::new(FxHashMap::default());
+
+    /// What ``System.Console`` writes goes here instead of stdout while this is ``Some``
+    static CONSOLE_OUTPUT: RefCell<Option<String>> = RefCell::new(None);
+}
+
+macro_rules! console_print { ($($arg:tt)*) => { write_console(format_args!($($arg)*)) }; }
+
+#[rustfmt::skip]
+macro_rules! console_println {
+    () => { write_console(format_args!("\n")) };
+    ($($arg:tt)*) => { write_console(format_args!("{}\n", format_args!($($arg)*))) };
+}
+
+fn write_console(args: fmt::Arguments) {
+    CONSOLE_OUTPUT.with(|output| match *output.borrow_mut() {
+        Some(ref mut output) => fmt::Write::write_fmt(output, args).unwrap(),
+        None => io::stdout().write_fmt(args).unwrap(),
+    })
+}
+
+/// Makes ``System.Console`` write to a buffer instead of stdout on this thread
+pub fn start_capturing_console_output() {
+    CONSOLE_OUTPUT.with(|output| *output.borrow_mut() = Some(String::new()))
+}
+
+/// Returns what ``System.Console`` wrote since ``start_capturing_console_output`` and makes it
+/// write to stdout again
+pub fn stop_capturing_console_output() -> String {
+    CONSOLE_OUTPUT.with(|output| output.borrow_mut().take().unwrap_or_default())
+}
+
+#[derive(Clone, Debug)]
+pub struct MethodTableInfo {
+    /// Method table of the parent class
+    pub parent: Option<usize>,
+
+    /// Method tables of the implemented interfaces and the slots of the method table that
+    /// implement their methods (see ``InterfaceMap``)
+    pub interfaces: Vec<(usize, Vec<usize>)>,
+
+    /// Full name of the class (e.g. System.Exception)
+    pub name: String,
+
+    /// True if the class is a value type, whose arrays are not covariant
+    pub is_valuetype: bool,
+
+    /// Handle of the assembly defining the class in ``LOADED_ASSEMBLIES``. None for mscorlib.
+    pub assembly: Option<usize>,
+
+    /// Instances of generic virtual methods that the class implements, which have no slot in
+    /// the method table. Searchable with the ids given by the JIT.
+    pub generic_virtual_methods: FxHashMap<usize, usize>,
+}
+
+/// What guest code sees of an assembly through ``System.Reflection.Assembly``
+#[derive(Clone, Debug)]
+pub struct RuntimeAssembly {
+    pub full_name: String,
+    /// None if the manifest resources are broken. Guest code reading them gets
+    /// BadImageFormatException.
+    pub resources: Option<Vec<ManifestResource>>,
+}
+
+impl RuntimeAssembly {
+    pub fn new(asm: &Assembly) -> Self {
+        Self {
+            full_name: asm.full_name(),
+            resources: asm.image.manifest_resources().ok(),
+        }
+    }
+
+    /// The built-in mscorlib, which has no resources
+    pub fn mscorlib() -> Self {
+        Self {
+            full_name: "mscorlib, Version=4.0.0.0, Culture=neutral".to_string(),
+            resources: Some(vec![]),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Function {
     pub ty: Type,
@@ -44,6 +165,41 @@ impl BuiltinFunctions {
                                 1, 0))
                     }
                 );
+                macro_rules! helper {
+                    ($name:expr, $f:expr, $ret_ty:expr, [ $($param_ty:expr),* ]) => {{
+                        let mut params_ty = vec![$($param_ty),*];
+                        map.insert($name.to_string(), Function {
+                            ty: Type::void_ty(),
+                            function: $f as *mut c_void,
+                            llvm_function: LLVMAddFunction(
+                                module,
+                                CString::new($name).unwrap().as_ptr(),
+                                LLVMFunctionType($ret_ty, params_ty.as_mut_ptr(), params_ty.len() as u32, 0))
+                        });
+                    }}
+                }
+                let ptr_ty = LLVMPointerType(LLVMInt8TypeInContext(ctx), 0);
+                helper!("throw_exception",         throw_exception,         LLVMVoidTypeInContext(ctx),  [ptr_ty]);
+                helper!("exception_pending",       exception_pending,       LLVMInt32TypeInContext(ctx), []);
+                helper!("take_exception",          take_exception,          ptr_ty,                      []);
+                helper!("get_exception",           get_exception,           ptr_ty,                      []);
+                helper!("is_instance",             is_instance,             LLVMInt32TypeInContext(ctx), [ptr_ty, ptr_ty]);
+                helper!("is_array_instance",       is_array_instance,       LLVMInt32TypeInContext(ctx), [ptr_ty, LLVMInt32TypeInContext(ctx), ptr_ty, LLVMInt32TypeInContext(ctx)]);
+                helper!("get_interface_method",    get_interface_method,    ptr_ty,                      [ptr_ty, ptr_ty, LLVMInt32TypeInContext(ctx)]);
+                helper!("get_generic_virtual_method",      get_generic_virtual_method,      ptr_ty,                     [ptr_ty, LLVMInt32TypeInContext(ctx)]);
+                helper!("register_generic_virtual_method", register_generic_virtual_method, LLVMVoidTypeInContext(ctx), [ptr_ty, LLVMInt32TypeInContext(ctx), ptr_ty]);
+                helper!("unhandled_exception",     unhandled_exception,     LLVMVoidTypeInContext(ctx),  []);
+                helper!("register_static_storage", register_static_storage, LLVMVoidTypeInContext(ctx),  [ptr_ty, LLVMInt32TypeInContext(ctx)]);
+                helper!("delegate_ctor",           delegate_ctor,           LLVMVoidTypeInContext(ctx),  [ptr_ty, ptr_ty, ptr_ty]);
+                helper!("delegate_target",         delegate_target,         ptr_ty,                      [ptr_ty]);
+                helper!("delegate_method",         delegate_method,         ptr_ty,                      [ptr_ty]);
+                helper!("delegate_invocation_count", delegate_invocation_count, LLVMInt32TypeInContext(ctx), [ptr_ty]);
+                helper!("delegate_invocation_item",  delegate_invocation_item,  ptr_ty,                      [ptr_ty, LLVMInt32TypeInContext(ctx)]);
+                helper!("new_system_assembly",     new_system_assembly,     ptr_ty,                      [LLVMInt32TypeInContext(ctx)]);
+                helper!("new_array",               new_array,               ptr_ty,                      [LLVMInt32TypeInContext(ctx), LLVMInt32TypeInContext(ctx), LLVMInt64TypeInContext(ctx), ptr_ty]);
+                helper!("memory_copy",             memory_copy,             LLVMVoidTypeInContext(ctx),  [ptr_ty, ptr_ty, LLVMInt64TypeInContext(ctx)]);
+                helper!("memory_set",              memory_set,              LLVMVoidTypeInContext(ctx),  [ptr_ty, LLVMInt32TypeInContext(ctx), LLVMInt64TypeInContext(ctx)]);
+                helper!("initialize_array",        initialize_array,        LLVMInt32TypeInContext(ctx), [ptr_ty, LLVMInt32TypeInContext(ctx), ptr_ty, LLVMInt32TypeInContext(ctx)]);
                 map.insert(
                     "new_szarray".to_string(),
                     Function {
@@ -65,23 +221,51 @@ impl BuiltinFunctions {
             map: {
                 macro_rules! parse_llvm_ty {
                     (void) => { LLVMVoidTypeInContext(ctx) };
+                    (bool) => { LLVMInt8TypeInContext(ctx) };
                     (i4  ) => { LLVMInt32TypeInContext(ctx) };
+                    (u4  ) => { LLVMInt32TypeInContext(ctx) };
+                    (i8  ) => { LLVMInt64TypeInContext(ctx) };
+                    (u8  ) => { LLVMInt64TypeInContext(ctx) };
+                    (r4  ) => { LLVMFloatTypeInContext(ctx) };
                     (r8  ) => { LLVMDoubleTypeInContext(ctx) };
                     (char) => { LLVMInt32TypeInContext(ctx) };
                     (str ) => { LLVMPointerType(LLVMInt8TypeInContext(ctx), 0) };
                     (obj ) => { LLVMPointerType(LLVMInt8TypeInContext(ctx), 0) };
                     (obja) => { LLVMPointerType(LLVMInt8TypeInContext(ctx), 0) };
                     (ptr ) => { LLVMPointerType(LLVMInt8TypeInContext(ctx), 0) };
+                    (i4r ) => { LLVMPointerType(LLVMInt32TypeInContext(ctx), 0) };
+                    (dlg ) => { LLVMPointerType(LLVMInt8TypeInContext(ctx), 0) };
+                    (m0  ) => { LLVMPointerType(LLVMInt8TypeInContext(ctx), 0) };
+                    (m0r ) => { LLVMPointerType(LLVMPointerType(LLVMInt8TypeInContext(ctx), 0), 0) };
+                    (u1a ) => { LLVMPointerType(LLVMInt8TypeInContext(ctx), 0) };
+                    (asm ) => { LLVMPointerType(LLVMInt8TypeInContext(ctx), 0) };
+                    (strm) => { LLVMPointerType(LLVMInt8TypeInContext(ctx), 0) };
+                    (typ ) => { LLVMPointerType(LLVMInt8TypeInContext(ctx), 0) };
+                    (rth ) => { LLVMInt64TypeInContext(ctx) };
                 }
 
                 macro_rules! parse_ty {
                     (void)  => { Type::void_ty() };
+                    (bool)  => { Type::boolean_ty() };
                     (i4  )  => { Type::i4_ty() };
+                    (u4  )  => { Type::u4_ty() };
+                    (i8  )  => { Type::i8_ty() };
+                    (u8  )  => { Type::u8_ty() };
+                    (r4  )  => { Type::r4_ty() };
                     (r8  )  => { Type::r8_ty() };
                     (char)  => { Type::char_ty() };
                     (obj )  => { Type::object_ty() };
                     (obja)  => { Type::object_szarr_ty() };
                     (str )  => { Type::string_ty() };
+                    (i4r )  => { Type::byref_ty(Type::i4_ty()) };
+                    (dlg )  => { Type::class_ty(mscorlib_system_delegate()) };
+                    (m0  )  => { Type::new(ElementType::MVar(0)) };
+                    (m0r )  => { Type::byref_ty(Type::new(ElementType::MVar(0))) };
+                    (u1a )  => { Type::szarr_ty(Type::u1_ty()) };
+                    (asm )  => { Type::class_ty(mscorlib_system_reflection_assembly()) };
+                    (strm)  => { Type::class_ty(mscorlib_system_io_stream()) };
+                    (typ )  => { Type::class_ty(mscorlib_system_type()) };
+                    (rth )  => { Type::from_class(mscorlib_system_class("RuntimeTypeHandle")) };
                 }
 
                 macro_rules! def_func {
@@ -119,8 +303,13 @@ impl BuiltinFunctions {
                     def_func!(        r8,   [r8, r8],   0,                     "llvm.pow.f64")
                 ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
                 let write_line = vec![
+                    def_func!(        void, [],         write_line,            "[mscorlib]System::Console.WriteLine()"),
                     def_func!(        void, [str ],     write_line_string,     "[mscorlib]System::Console.WriteLine(String)"),
                     def_func!(        void, [i4  ],     write_line_i4,         "[mscorlib]System::Console.WriteLine(int32)"),
+                    def_func!(        void, [u4  ],     write_line_u4,         "[mscorlib]System::Console.WriteLine(uint32)"),
+                    def_func!(        void, [i8  ],     write_line_i8,         "[mscorlib]System::Console.WriteLine(int64)"),
+                    def_func!(        void, [u8  ],     write_line_u8,         "[mscorlib]System::Console.WriteLine(uint64)"),
+                    def_func!(        void, [r4  ],     write_line_r4,         "[mscorlib]System::Console.WriteLine(float32)"),
                     def_func!(        void, [r8  ],     write_line_r8,         "[mscorlib]System::Console.WriteLine(float64)"),
                     def_func!(        void, [char],     write_line_char,       "[mscorlib]System::Console.WriteLine(char)"),
                     def_func!(        void, [str, obj], write_line_string_obj, "[mscorlib]System::Console.WriteLine(String, Object)"),
@@ -128,6 +317,10 @@ impl BuiltinFunctions {
                 let write = vec![
                     def_func!(        void, [str ],     write_string,          "[mscorlib]System::Console.Write(String)"),
                     def_func!(        void, [i4  ],     write_i4,              "[mscorlib]System::Console.Write(int32)"),
+                    def_func!(        void, [u4  ],     write_u4,              "[mscorlib]System::Console.Write(uint32)"),
+                    def_func!(        void, [i8  ],     write_i8,              "[mscorlib]System::Console.Write(int64)"),
+                    def_func!(        void, [u8  ],     write_u8,              "[mscorlib]System::Console.Write(uint64)"),
+                    def_func!(        void, [r4  ],     write_r4,              "[mscorlib]System::Console.Write(float32)"),
                     def_func!(        void, [r8  ],     write_r8,              "[mscorlib]System::Console.Write(float64)"),
                     def_func!(        void, [char],     write_char,            "[mscorlib]System::Console.Write(char)"),
                 ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
@@ -146,12 +339,148 @@ impl BuiltinFunctions {
                 let int32_to_string = vec![
                     def_func!([0x20], str,  [],         int_to_string,         "[mscorlib]System::Int32.ToString()")
                 ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let int64_to_string: Vec<Function> = vec![
+                    def_func!([0x20], str,  [],         long_to_string,        "[mscorlib]System::Int64.ToString()")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let double_to_string = vec![
+                    def_func!([0x20], str,  [],         double_to_string,      "[mscorlib]System::Double.ToString()")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let sbyte_to_string = vec![
+                    def_func!([0x20], str,  [],         sbyte_to_string,       "[mscorlib]System::SByte.ToString()")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let byte_to_string = vec![
+                    def_func!([0x20], str,  [],         byte_to_string,        "[mscorlib]System::Byte.ToString()")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let int16_to_string = vec![
+                    def_func!([0x20], str,  [],         short_to_string,       "[mscorlib]System::Int16.ToString()")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let uint16_to_string = vec![
+                    def_func!([0x20], str,  [],         ushort_to_string,      "[mscorlib]System::UInt16.ToString()")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let uint32_to_string = vec![
+                    def_func!([0x20], str,  [],         uint_to_string,        "[mscorlib]System::UInt32.ToString()")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let uint64_to_string: Vec<Function> = vec![
+                    def_func!([0x20], str,  [],         ulong_to_string,       "[mscorlib]System::UInt64.ToString()")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let single_to_string = vec![
+                    def_func!([0x20], str,  [],         float_to_string,       "[mscorlib]System::Single.ToString()")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let boolean_to_string = vec![
+                    def_func!([0x20], str,  [],         bool_to_string,        "[mscorlib]System::Boolean.ToString()")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let char_to_string = vec![
+                    def_func!([0x20], str,  [],         char_to_string,        "[mscorlib]System::Char.ToString()")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let valuetype_to_string = vec![
+                    def_func!([0x20], str,  [],         valuetype_to_string,   "[mscorlib]System::ValueType.ToString()")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let enum_to_string = vec![
+                    def_func!([0x20], str,  [],         enum_to_string,        "[mscorlib]System::Enum.ToString()")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
                 let obj_to_string = vec![
                     def_func!([0x20], str,  [],         object_to_string,      "[mscorlib]System::Object.ToString()"),
                 ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let exception_ctor: Vec<Function> = vec![
+                    def_func!([0x20], void, [],         exception_ctor,        "[mscorlib]System::Exception..ctor()"),
+                    def_func!([0x20], void, [str],      exception_ctor_string, "[mscorlib]System::Exception..ctor(String)"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let exception_get_message = vec![
+                    def_func!([0x20], str,  [],         exception_get_message, "[mscorlib]System::Exception.get_Message()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let exception_to_string = vec![
+                    def_func!([0x20], str,  [],         exception_to_string,   "[mscorlib]System::Exception.ToString()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let delegate_combine = vec![
+                    def_func!(        dlg,  [dlg, dlg], delegate_combine,      "[mscorlib]System::Delegate.Combine(Delegate, Delegate)"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let delegate_remove = vec![
+                    def_func!(        dlg,  [dlg, dlg], delegate_remove,       "[mscorlib]System::Delegate.Remove(Delegate, Delegate)"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let type_get_type_from_handle = vec![
+                    def_func!(        typ,  [rth],      type_get_type_from_handle, "[mscorlib]System::Type.GetTypeFromHandle(RuntimeTypeHandle)"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let type_get_full_name: Vec<Function> = vec![
+                    def_func!([0x20], str,  [],         type_get_full_name,    "[mscorlib]System::Type.get_FullName()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let type_get_name = vec![
+                    def_func!([0x20], str,  [],         type_get_name,         "[mscorlib]System::Type.get_Name()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let type_get_assembly = vec![
+                    def_func!([0x20], asm,  [],         type_get_assembly,     "[mscorlib]System::Type.get_Assembly()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let type_op_equality = vec![
+                    def_func!(        bool, [typ, typ], type_op_equality,      "[mscorlib]System::Type.op_Equality(Type, Type)"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let type_op_inequality = vec![
+                    def_func!(        bool, [typ, typ], type_op_inequality,    "[mscorlib]System::Type.op_Inequality(Type, Type)"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
                 let string_to_string = vec![
                     def_func!([0x20], str,  [],         string_to_string,      "[mscorlib]System::String.ToString()"),
                 ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let assembly_load = vec![
+                    def_func!(        asm,  [u1a],      assembly_load,         "[mscorlib]System.Reflection::Assembly.Load(uint8[])"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let assembly_get_full_name: Vec<Function> = vec![
+                    def_func!([0x20], str,  [],         assembly_get_full_name, "[mscorlib]System.Reflection::Assembly.get_FullName()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let assembly_get_manifest_resource_stream = vec![
+                    def_func!([0x20], strm, [str],      assembly_get_manifest_resource_stream, "[mscorlib]System.Reflection::Assembly.GetManifestResourceStream(string)"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let stream_get_length = vec![
+                    def_func!([0x20], i8,   [],         stream_get_length,     "[mscorlib]System.IO::Stream.get_Length()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let stream_get_position = vec![
+                    def_func!([0x20], i8,   [],         stream_get_position,   "[mscorlib]System.IO::Stream.get_Position()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let stream_read_byte = vec![
+                    def_func!([0x20], i4,   [],         stream_read_byte,      "[mscorlib]System.IO::Stream.ReadByte()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let stream_read = vec![
+                    def_func!([0x20], i4,   [u1a, i4, i4], stream_read,        "[mscorlib]System.IO::Stream.Read(uint8[], int32, int32)"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let stream_close: Vec<Function> = vec![
+                    def_func!([0x20], void, [],         stream_close,          "[mscorlib]System.IO::Stream.Close()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let stream_reader_ctor = vec![
+                    def_func!([0x20], void, [strm],     stream_reader_ctor,    "[mscorlib]System.IO::StreamReader..ctor(Stream)"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let stream_reader_peek: Vec<Function> = vec![
+                    def_func!([0x20], i4,   [],         stream_reader_peek,    "[mscorlib]System.IO::StreamReader.Peek()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let stream_reader_read: Vec<Function> = vec![
+                    def_func!([0x20], i4,   [],         stream_reader_read,    "[mscorlib]System.IO::StreamReader.Read()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let stream_reader_read_line: Vec<Function> = vec![
+                    def_func!([0x20], str,  [],         stream_reader_read_line, "[mscorlib]System.IO::StreamReader.ReadLine()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let stream_reader_read_to_end: Vec<Function> = vec![
+                    def_func!([0x20], str,  [],         stream_reader_read_to_end, "[mscorlib]System.IO::StreamReader.ReadToEnd()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let stream_reader_close: Vec<Function> = vec![
+                    def_func!([0x20], void, [],         stream_reader_close,   "[mscorlib]System.IO::StreamReader.Close()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let stream_reader_get_end_of_stream = vec![
+                    def_func!([0x20], bool, [],         stream_reader_get_end_of_stream, "[mscorlib]System.IO::StreamReader.get_EndOfStream()"),
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+
+                let interlocked_increment = vec![
+                    def_func!(        i4,   [i4r],      interlocked_increment, "[mscorlib]System.Threading::Interlocked.Increment(int32&)")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let interlocked_decrement = vec![
+                    def_func!(        i4,   [i4r],      interlocked_decrement, "[mscorlib]System.Threading::Interlocked.Decrement(int32&)")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let interlocked_add = vec![
+                    def_func!(        i4,   [i4r, i4],  interlocked_add,       "[mscorlib]System.Threading::Interlocked.Add(int32&, int32)")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let interlocked_exchange = vec![
+                    def_func!(        i4,   [i4r, i4],  interlocked_exchange,  "[mscorlib]System.Threading::Interlocked.Exchange(int32&, int32)")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
+                let interlocked_compare_exchange = vec![
+                    def_func!(        i4,   [i4r, i4, i4], interlocked_compare_exchange, "[mscorlib]System.Threading::Interlocked.CompareExchange(int32&, int32, int32)"),
+                    // Only for reference types
+                    def_func!([0x10], m0,   [m0r, m0, m0], interlocked_compare_exchange_ref, "[mscorlib]System.Threading::Interlocked.CompareExchange<T>(!!0&, !!0, !!0)")
+                ].into_iter().map(|(ty, function, llvm_function)| Function { ty, function, llvm_function }).collect();
 
                 let mut resolver = NameResolver::new();
 
@@ -159,15 +488,77 @@ impl BuiltinFunctions {
                 resolver.add(MethodPath(vec!["mscorlib", "System", "Console", "Write"     ]), write           );
                 resolver.add(MethodPath(vec!["mscorlib", "System", "Object",  "ToString"  ]), obj_to_string   );
                 resolver.add(MethodPath(vec!["mscorlib", "System", "Int32",   "ToString"  ]), int32_to_string );
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Int64",   "ToString"  ]), int64_to_string.clone());
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Double",  "ToString"  ]), double_to_string);
+                resolver.add(MethodPath(vec!["mscorlib", "System", "SByte",   "ToString"  ]), sbyte_to_string );
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Byte",    "ToString"  ]), byte_to_string  );
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Int16",   "ToString"  ]), int16_to_string );
+                resolver.add(MethodPath(vec!["mscorlib", "System", "UInt16",  "ToString"  ]), uint16_to_string);
+                resolver.add(MethodPath(vec!["mscorlib", "System", "UInt32",  "ToString"  ]), uint32_to_string);
+                resolver.add(MethodPath(vec!["mscorlib", "System", "UInt64",  "ToString"  ]), uint64_to_string.clone());
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Single",  "ToString"  ]), single_to_string);
+                resolver.add(MethodPath(vec!["mscorlib", "System", "IntPtr",  "ToString"  ]), int64_to_string.clone());
+                resolver.add(MethodPath(vec!["mscorlib", "System", "UIntPtr", "ToString"  ]), uint64_to_string);
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Boolean", "ToString"  ]), boolean_to_string);
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Char",    "ToString"  ]), char_to_string  );
+                resolver.add(MethodPath(vec!["mscorlib", "System", "ValueType", "ToString"]), valuetype_to_string);
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Enum",    "ToString"  ]), enum_to_string  );
                 resolver.add(MethodPath(vec!["mscorlib", "System", "String",  "get_Chars" ]), get_chars       );
                 resolver.add(MethodPath(vec!["mscorlib", "System", "String",  "get_Length"]), get_length      );
                 resolver.add(MethodPath(vec!["mscorlib", "System", "String",  "ToString"  ]), string_to_string);
                 resolver.add(MethodPath(vec!["mscorlib", "System", "String",  "Concat"    ]), concat          );
+                for name in &["SystemException", "NullReferenceException", "IndexOutOfRangeException",
+                              "ArithmeticException", "DivideByZeroException", "OverflowException",
+                              "InvalidCastException", "ArrayTypeMismatchException", "ArgumentException",
+                              "InvalidProgramException", "InvalidOperationException",
+                              "ArgumentNullException", "BadImageFormatException",
+                              "ArgumentOutOfRangeException", "ObjectDisposedException",
+                              "MissingMethodException", "OutOfMemoryException"] {
+                    resolver.add(MethodPath(vec!["mscorlib", "System", name, ".ctor"]), exception_ctor.clone());
+                }
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Exception", ".ctor"      ]), exception_ctor       );
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Exception", "get_Message"]), exception_get_message);
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Exception", "ToString"   ]), exception_to_string  );
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Type", "GetTypeFromHandle"]), type_get_type_from_handle);
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Type", "get_FullName"     ]), type_get_full_name.clone());
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Type", "ToString"         ]), type_get_full_name       );
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Type", "get_Name"         ]), type_get_name            );
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Type", "get_Assembly"     ]), type_get_assembly        );
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Type", "op_Equality"      ]), type_op_equality         );
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Type", "op_Inequality"    ]), type_op_inequality       );
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Delegate", "Combine"  ]), delegate_combine);
+                resolver.add(MethodPath(vec!["mscorlib", "System", "Delegate", "Remove"   ]), delegate_remove );
                 resolver.add(MethodPath(vec!["mscorlib", "System", "Math",    "Sqrt"      ]), sqrt            );
                 resolver.add(MethodPath(vec!["mscorlib", "System", "Math",    "Sin"       ]), sin             );
                 resolver.add(MethodPath(vec!["mscorlib", "System", "Math",    "Cos"       ]), cos             );
                 resolver.add(MethodPath(vec!["mscorlib", "System", "Math",    "Abs"       ]), abs             );
                 resolver.add(MethodPath(vec!["mscorlib", "System", "Math",    "Pow"       ]), pow             );
+                resolver.add(MethodPath(vec!["mscorlib", "System.Reflection", "Assembly", "Load"        ]), assembly_load                 );
+                resolver.add(MethodPath(vec!["mscorlib", "System.Reflection", "Assembly", "get_FullName"]), assembly_get_full_name.clone());
+                resolver.add(MethodPath(vec!["mscorlib", "System.Reflection", "Assembly", "ToString"    ]), assembly_get_full_name        );
+                resolver.add(MethodPath(vec!["mscorlib", "System.Reflection", "Assembly", "GetManifestResourceStream"]), assembly_get_manifest_resource_stream);
+                resolver.add(MethodPath(vec!["mscorlib", "System.IO", "Stream", "get_Length"  ]), stream_get_length    );
+                resolver.add(MethodPath(vec!["mscorlib", "System.IO", "Stream", "get_Position"]), stream_get_position  );
+                resolver.add(MethodPath(vec!["mscorlib", "System.IO", "Stream", "ReadByte"    ]), stream_read_byte     );
+                resolver.add(MethodPath(vec!["mscorlib", "System.IO", "Stream", "Read"        ]), stream_read          );
+                resolver.add(MethodPath(vec!["mscorlib", "System.IO", "Stream", "Close"       ]), stream_close.clone() );
+                resolver.add(MethodPath(vec!["mscorlib", "System.IO", "Stream", "Dispose"     ]), stream_close         );
+                // The methods of TextReader are called through StreamReader, the only reader
+                for class in &["TextReader", "StreamReader"] {
+                    resolver.add(MethodPath(vec!["mscorlib", "System.IO", class, "Peek"     ]), stream_reader_peek.clone()       );
+                    resolver.add(MethodPath(vec!["mscorlib", "System.IO", class, "Read"     ]), stream_reader_read.clone()       );
+                    resolver.add(MethodPath(vec!["mscorlib", "System.IO", class, "ReadLine" ]), stream_reader_read_line.clone()  );
+                    resolver.add(MethodPath(vec!["mscorlib", "System.IO", class, "ReadToEnd"]), stream_reader_read_to_end.clone());
+                    resolver.add(MethodPath(vec!["mscorlib", "System.IO", class, "Close"    ]), stream_reader_close.clone()      );
+                    resolver.add(MethodPath(vec!["mscorlib", "System.IO", class, "Dispose"  ]), stream_reader_close.clone()      );
+                }
+                resolver.add(MethodPath(vec!["mscorlib", "System.IO", "StreamReader", ".ctor"          ]), stream_reader_ctor             );
+                resolver.add(MethodPath(vec!["mscorlib", "System.IO", "StreamReader", "get_EndOfStream"]), stream_reader_get_end_of_stream);
+                resolver.add(MethodPath(vec!["mscorlib", "System.Threading", "Interlocked", "Increment"      ]), interlocked_increment       );
+                resolver.add(MethodPath(vec!["mscorlib", "System.Threading", "Interlocked", "Decrement"      ]), interlocked_decrement       );
+                resolver.add(MethodPath(vec!["mscorlib", "System.Threading", "Interlocked", "Add"            ]), interlocked_add             );
+                resolver.add(MethodPath(vec!["mscorlib", "System.Threading", "Interlocked", "Exchange"       ]), interlocked_exchange        );
+                resolver.add(MethodPath(vec!["mscorlib", "System.Threading", "Interlocked", "CompareExchange"]), interlocked_compare_exchange);
 
                 resolver
             },
@@ -193,26 +584,51 @@ impl BuiltinFunctions {
 
 #[no_mangle]
 pub fn write_line_i4(n: i32) {
-    println!("{}", n);
+    console_println!("{}", n);
+}
+
+#[no_mangle]
+pub fn write_line_u4(n: u32) {
+    console_println!("{}", n);
+}
+
+#[no_mangle]
+pub fn write_line_i8(n: i64) {
+    console_println!("{}", n);
+}
+
+#[no_mangle]
+pub fn write_line_u8(n: u64) {
+    console_println!("{}", n);
+}
+
+#[no_mangle]
+pub fn write_line_r4(n: f32) {
+    console_println!("{}", n);
 }
 
 #[no_mangle]
 pub fn write_line_r8(n: f64) {
-    println!("{}", n);
+    console_println!("{}", n);
 }
 
 #[no_mangle]
 pub fn write_line_char(c: u16) {
-    println!(
+    console_println!(
         "{}",
         decode_utf16([c].iter().cloned()).next().unwrap().unwrap()
     );
 }
 
+#[no_mangle]
+pub fn write_line() {
+    console_println!()
+}
+
 #[no_mangle]
 pub fn write_line_string(system_string: *mut u64) {
     let utf16_string_ptr = unsafe { retrieve_utf16_string_from_system_string(system_string) };
-    println!(
+    console_println!(
         "{}",
         String::from_utf16_lossy(unsafe { &*utf16_string_ptr })
     );
@@ -261,7 +677,7 @@ unsafe fn concat_str_str(s1: *mut u64, s2: *mut u64) -> *mut u64 {
 #[no_mangle]
 unsafe fn concat_obj_arr(objs: *mut u64) -> *mut u64 {
     let len = *objs;
-    let objs = objs.add(1) as *mut u64;
+    let objs = objs.add(2) as *mut u64;
     let mut res = "".to_string();
     for i in 0..len {
         let system_object = *objs.add(i as usize) as *mut u64;
@@ -299,26 +715,46 @@ pub unsafe fn write_line_string_obj(system_string: *mut u64, system_int32: *mut
     )));
     for (i, s) in string.split("{0}").enumerate() {
         if i > 0 {
-            print!("{}", string2)
+            console_print!("{}", string2)
         }
-        print!("{}", s);
+        console_print!("{}", s);
     }
-    println!();
+    console_println!();
 }
 
 #[no_mangle]
 pub fn write_i4(n: i32) {
-    print!("{}", n);
+    console_print!("{}", n);
+}
+
+#[no_mangle]
+pub fn write_u4(n: u32) {
+    console_print!("{}", n);
+}
+
+#[no_mangle]
+pub fn write_i8(n: i64) {
+    console_print!("{}", n);
+}
+
+#[no_mangle]
+pub fn write_u8(n: u64) {
+    console_print!("{}", n);
+}
+
+#[no_mangle]
+pub fn write_r4(n: f32) {
+    console_print!("{}", n);
 }
 
 #[no_mangle]
 pub fn write_r8(n: f64) {
-    print!("{}", n);
+    console_print!("{}", n);
 }
 
 #[no_mangle]
 pub fn write_char(c: u16) {
-    print!(
+    console_print!(
         "{}",
         decode_utf16([c].iter().cloned()).next().unwrap().unwrap()
     );
@@ -327,7 +763,7 @@ pub fn write_char(c: u16) {
 #[no_mangle]
 pub fn write_string(system_string: *mut u64) {
     let utf16_string_ptr = unsafe { retrieve_utf16_string_from_system_string(system_string) };
-    print!(
+    console_print!(
         "{}",
         String::from_utf16_lossy(unsafe { &*utf16_string_ptr })
     );
@@ -358,21 +794,752 @@ pub fn int_to_string(system_int32: *mut u64) -> *mut u64 {
     }
 }
 
+#[no_mangle]
+pub fn long_to_string(system_int64: *mut u64) -> *mut u64 {
+    unsafe {
+        let value = *system_int64.offset(1) as i64;
+        new_system_string(format!("{}", value))
+    }
+}
+
+#[no_mangle]
+pub fn double_to_string(system_double: *mut u64) -> *mut u64 {
+    unsafe {
+        let value = f64::from_bits(*system_double.offset(1));
+        new_system_string(format!("{}", value))
+    }
+}
+
+#[no_mangle]
+pub fn sbyte_to_string(system_sbyte: *mut u64) -> *mut u64 {
+    unsafe {
+        let value = *(system_sbyte.offset(1) as *mut i8);
+        new_system_string(format!("{}", value))
+    }
+}
+
+#[no_mangle]
+pub fn byte_to_string(system_byte: *mut u64) -> *mut u64 {
+    unsafe {
+        let value = *(system_byte.offset(1) as *mut u8);
+        new_system_string(format!("{}", value))
+    }
+}
+
+#[no_mangle]
+pub fn short_to_string(system_int16: *mut u64) -> *mut u64 {
+    unsafe {
+        let value = *(system_int16.offset(1) as *mut i16);
+        new_system_string(format!("{}", value))
+    }
+}
+
+#[no_mangle]
+pub fn ushort_to_string(system_uint16: *mut u64) -> *mut u64 {
+    unsafe {
+        let value = *(system_uint16.offset(1) as *mut u16);
+        new_system_string(format!("{}", value))
+    }
+}
+
+#[no_mangle]
+pub fn uint_to_string(system_uint32: *mut u64) -> *mut u64 {
+    unsafe {
+        let value = *(system_uint32.offset(1) as *mut u32);
+        new_system_string(format!("{}", value))
+    }
+}
+
+#[no_mangle]
+pub fn ulong_to_string(system_uint64: *mut u64) -> *mut u64 {
+    unsafe {
+        let value = *system_uint64.offset(1);
+        new_system_string(format!("{}", value))
+    }
+}
+
+#[no_mangle]
+pub fn float_to_string(system_single: *mut u64) -> *mut u64 {
+    unsafe {
+        let value = *(system_single.offset(1) as *mut f32);
+        new_system_string(format!("{}", value))
+    }
+}
+
+#[no_mangle]
+pub fn bool_to_string(system_boolean: *mut u64) -> *mut u64 {
+    unsafe {
+        let value = *(system_boolean.offset(1) as *mut u8) != 0;
+        new_system_string(if value { "True" } else { "False" }.to_string())
+    }
+}
+
+#[no_mangle]
+pub fn char_to_string(system_char: *mut u64) -> *mut u64 {
+    unsafe {
+        let c = *(system_char.offset(1) as *mut u16);
+        new_system_string(
+            decode_utf16([c].iter().cloned())
+                .next()
+                .unwrap()
+                .unwrap()
+                .to_string(),
+        )
+    }
+}
+
+/// Boxed value types that do not override ToString are shown by their class names
+#[no_mangle]
+pub unsafe fn valuetype_to_string(obj: *mut u8) -> *mut u64 {
+    new_system_string(get_class_name(obj))
+}
+
+/// Enums are shown by their underlying values since the names of the members are not kept at
+/// runtime
+#[no_mangle]
+pub fn enum_to_string(system_enum: *mut u64) -> *mut u64 {
+    unsafe {
+        let value = *(system_enum.offset(1) as *mut i32);
+        new_system_string(format!("{}", value))
+    }
+}
+
 #[no_mangle]
 pub fn string_to_string(string: *mut u8) -> *mut u8 {
     string
 }
 
+#[no_mangle]
+pub unsafe fn exception_ctor(exception: *mut u64) {
+    *exception.offset(1) = 0;
+}
+
+#[no_mangle]
+pub unsafe fn exception_ctor_string(exception: *mut u64, message: *mut u64) {
+    *exception.offset(1) = message as u64;
+}
+
+#[no_mangle]
+pub unsafe fn exception_get_message(exception: *mut u64) -> *mut u64 {
+    let message = *exception.offset(1) as *mut u64;
+    if !message.is_null() {
+        return message;
+    }
+    let name = get_class_name(exception as *mut u8);
+    new_system_string(match name.as_str() {
+        "System.NullReferenceException" => {
+            "Object reference not set to an instance of an object.".to_string()
+        }
+        "System.IndexOutOfRangeException" => {
+            "Index was outside the bounds of the array.".to_string()
+        }
+        "System.ArithmeticException" => {
+            "Overflow or underflow in the arithmetic operation.".to_string()
+        }
+        "System.DivideByZeroException" => "Attempted to divide by zero.".to_string(),
+        "System.OverflowException" => "Arithmetic operation resulted in an overflow.".to_string(),
+        "System.InvalidCastException" => "Specified cast is not valid.".to_string(),
+        "System.ArrayTypeMismatchException" => {
+            "Attempted to access an element as a type incompatible with the array.".to_string()
+        }
+        "System.ArgumentException" => "Value does not fall within the expected range.".to_string(),
+        "System.ArgumentNullException" => "Value cannot be null.".to_string(),
+        "System.ArgumentOutOfRangeException" => {
+            "Specified argument was out of the range of valid values.".to_string()
+        }
+        "System.ObjectDisposedException" => "Cannot access a disposed object.".to_string(),
+        "System.MissingMethodException" => "Method not found.".to_string(),
+        "System.OutOfMemoryException" => {
+            "Insufficient memory to continue the execution of the program.".to_string()
+        }
+        "System.BadImageFormatException" => {
+            "Format of the executable (.exe) or library (.dll) is invalid.".to_string()
+        }
+        "System.InvalidProgramException" => {
+            "Common Language Runtime detected an invalid program.".to_string()
+        }
+        _ => format!("Exception of type '{}' was thrown.", name),
+    })
+}
+
+#[no_mangle]
+pub unsafe fn exception_to_string(exception: *mut u64) -> *mut u64 {
+    let message = exception_get_message(exception);
+    let mut s = format!("{}: ", get_class_name(exception as *mut u8))
+        .encode_utf16()
+        .collect::<Vec<u16>>();
+    s.extend(&*retrieve_utf16_string_from_system_string(message));
+    new_system_string_from_utf16(s)
+}
+
+/// Throws a new exception of the class ``name`` in System, which must be registered in
+/// ``EXCEPTION_METHOD_TABLE_PTRS``. The default message of the class is used if ``message`` is
+/// None.
+unsafe fn throw_system_exception(name: &str, message: Option<String>) {
+    let exception = memory_alloc(16) as *mut u64;
+    *(exception.offset(0) as *mut MethodTablePtrTy) =
+        EXCEPTION_METHOD_TABLE_PTRS.with(|emp| emp.borrow()[name]);
+    *exception.offset(1) = message.map_or(0, |message| new_system_string(message) as u64);
+    throw_exception(exception as *mut u8)
+}
+
+/// Loads an assembly from the bytes of ``System.Byte[]``. Throws ArgumentNullException if
+/// ``bytes`` is null and BadImageFormatException if they are not a valid assembly.
+///
+/// Only the metadata and the resources of the assembly are available to guest code. Compiling
+/// its methods, and so using its types, is out of the scope of this function: the JIT works on
+/// one assembly at a time, and the loaded one is not linked to it.
+#[no_mangle]
+pub unsafe fn assembly_load(bytes: *mut u8) -> *mut u64 {
+    if bytes.is_null() {
+        throw_system_exception("ArgumentNullException", None);
+        return ptr::null_mut();
+    }
+    let len = *(bytes as *mut u64) as usize;
+    let bytes = ::std::slice::from_raw_parts(bytes.add(SZARRAY_HEADER_SIZE as usize), len);
+    let asm = match GUEST_ASSEMBLY_RESOLVER.with(|r| r.borrow().clone()) {
+        Some(resolver) => Assembly::load_from_bytes_with_resolver(bytes, resolver),
+        None => Assembly::load_from_bytes(bytes),
+    };
+    let asm = match asm {
+        Ok(asm) => asm,
+        Err(err) => {
+            throw_system_exception("BadImageFormatException", Some(err.to_string()));
+            return ptr::null_mut();
+        }
+    };
+    let handle = register_runtime_assembly(RuntimeAssembly::new(&asm.borrow()));
+    new_system_assembly(handle as u32)
+}
+
+/// Adds ``asm`` to ``LOADED_ASSEMBLIES`` and returns its handle
+pub fn register_runtime_assembly(asm: RuntimeAssembly) -> usize {
+    LOADED_ASSEMBLIES.with(|asms| {
+        let mut asms = asms.borrow_mut();
+        asms.push(asm);
+        asms.len() - 1
+    })
+}
+
+/// Returns a new Assembly object of the assembly ``handle`` in ``LOADED_ASSEMBLIES``
+#[no_mangle]
+pub unsafe fn new_system_assembly(handle: u32) -> *mut u64 {
+    let system_assembly = memory_alloc(16) as *mut u64;
+    *(system_assembly.offset(0) as *mut MethodTablePtrTy) =
+        ASSEMBLY_METHOD_TABLE_PTR.with(|amp| amp.borrow().unwrap());
+    *system_assembly.offset(1) = handle as u64;
+    system_assembly
+}
+
+#[no_mangle]
+pub unsafe fn assembly_get_full_name(system_assembly: *mut u64) -> *mut u64 {
+    let handle = *system_assembly.offset(1) as usize;
+    let name = LOADED_ASSEMBLIES.with(|asms| asms.borrow()[handle].full_name.clone());
+    new_system_string(name)
+}
+
+/// Returns a new stream of the resource ``name``, or null if the assembly has no such resource.
+/// Throws ArgumentNullException if ``name`` is null and BadImageFormatException if the resources
+/// of the assembly are broken.
+#[no_mangle]
+pub unsafe fn assembly_get_manifest_resource_stream(
+    system_assembly: *mut u64,
+    name: *mut u64,
+) -> *mut u64 {
+    if name.is_null() {
+        throw_system_exception("ArgumentNullException", None);
+        return ptr::null_mut();
+    }
+    let handle = *system_assembly.offset(1) as usize;
+    let name = String::from_utf16_lossy(&*retrieve_utf16_string_from_system_string(name));
+    let bytes = LOADED_ASSEMBLIES.with(|asms| {
+        asms.borrow()[handle].resources.as_ref().map(|resources| {
+            resources
+                .iter()
+                .find(|res| res.name == name)
+                .map(|res| res.bytes.clone())
+        })
+    });
+    match bytes {
+        Some(Some(bytes)) => new_system_stream(bytes),
+        Some(None) => ptr::null_mut(),
+        None => {
+            throw_system_exception("BadImageFormatException", None);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// A Stream object holds the bytes and the position until it is closed or collected
+unsafe fn new_system_stream(bytes: Vec<u8>) -> *mut u64 {
+    let stream = memory_alloc(16) as *mut u64;
+    *(stream.offset(0) as *mut MethodTablePtrTy) =
+        STREAM_METHOD_TABLE_PTR.with(|smp| smp.borrow().unwrap());
+    *(stream.offset(1) as *mut *mut Cursor<Vec<u8>>) = Box::into_raw(Box::new(Cursor::new(bytes)));
+    GC_register_finalizer(
+        stream as *mut u8,
+        finalizer_stream as *mut u8,
+        ptr::null_mut(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+    );
+    stream
+}
+
+#[no_mangle]
+fn finalizer_stream(obj: *mut u64, _cd: *mut u8) {
+    unsafe { stream_close(obj) }
+}
+
+/// Returns None if ``stream`` is null or closed. Throws ObjectDisposedException if it is closed.
+unsafe fn retrieve_cursor_from_system_stream<'a>(
+    stream: *mut u64,
+) -> Option<&'a mut Cursor<Vec<u8>>> {
+    if stream.is_null() {
+        return None;
+    }
+    let cursor = (*(stream.offset(1) as *mut *mut Cursor<Vec<u8>>)).as_mut();
+    if cursor.is_none() {
+        throw_system_exception("ObjectDisposedException", None);
+    }
+    cursor
+}
+
+#[no_mangle]
+pub unsafe fn stream_get_length(stream: *mut u64) -> i64 {
+    retrieve_cursor_from_system_stream(stream).map_or(0, |cursor| cursor.get_ref().len() as i64)
+}
+
+#[no_mangle]
+pub unsafe fn stream_get_position(stream: *mut u64) -> i64 {
+    retrieve_cursor_from_system_stream(stream).map_or(0, |cursor| cursor.position() as i64)
+}
+
+/// Returns -1 at the end of the stream
+#[no_mangle]
+pub unsafe fn stream_read_byte(stream: *mut u64) -> i32 {
+    let mut buf = [0u8];
+    match retrieve_cursor_from_system_stream(stream).map(|cursor| cursor.read(&mut buf)) {
+        Some(Ok(1)) => buf[0] as i32,
+        _ => -1,
+    }
+}
+
+/// Reads up to ``count`` bytes into ``buffer`` from ``offset`` and returns the number of bytes
+/// read. Throws ArgumentNullException if ``buffer`` is null, ArgumentOutOfRangeException if
+/// ``offset`` or ``count`` is negative and ArgumentException if they exceed ``buffer``.
+#[no_mangle]
+pub unsafe fn stream_read(stream: *mut u64, buffer: *mut u8, offset: i32, count: i32) -> i32 {
+    if buffer.is_null() {
+        throw_system_exception("ArgumentNullException", None);
+        return 0;
+    }
+    if offset < 0 || count < 0 {
+        throw_system_exception("ArgumentOutOfRangeException", None);
+        return 0;
+    }
+    let len = *(buffer as *mut u64) as usize;
+    let (offset, count) = (offset as usize, count as usize);
+    if offset + count > len {
+        throw_system_exception("ArgumentException", None);
+        return 0;
+    }
+    let buffer = ::std::slice::from_raw_parts_mut(
+        buffer.add(SZARRAY_HEADER_SIZE as usize + offset),
+        count,
+    );
+    match retrieve_cursor_from_system_stream(stream).map(|cursor| cursor.read(buffer)) {
+        Some(Ok(read)) => read as i32,
+        _ => 0,
+    }
+}
+
+/// Closing a closed stream does nothing
+#[no_mangle]
+pub unsafe fn stream_close(stream: *mut u64) {
+    if stream.is_null() {
+        return;
+    }
+    let cursor = stream.offset(1) as *mut *mut Cursor<Vec<u8>>;
+    if !(*cursor).is_null() {
+        drop(Box::from_raw(*cursor));
+        *cursor = ptr::null_mut();
+    }
+}
+
+/// A StreamReader object holds the stream. A UTF-8 byte order mark at the beginning of the
+/// stream is skipped. Throws ArgumentNullException if ``stream`` is null.
+#[no_mangle]
+pub unsafe fn stream_reader_ctor(stream_reader: *mut u64, stream: *mut u64) {
+    if stream.is_null() {
+        throw_system_exception("ArgumentNullException", None);
+        return;
+    }
+    *stream_reader.offset(1) = stream as u64;
+    if let Some(cursor) = retrieve_cursor_from_system_stream(stream) {
+        if cursor.position() == 0 && cursor.get_ref().starts_with(&[0xef, 0xbb, 0xbf]) {
+            cursor.set_position(3);
+        }
+    }
+}
+
+unsafe fn retrieve_cursor_from_system_stream_reader<'a>(
+    stream_reader: *mut u64,
+) -> Option<&'a mut Cursor<Vec<u8>>> {
+    retrieve_cursor_from_system_stream(*stream_reader.offset(1) as *mut u64)
+}
+
+/// Returns the next character and its length in bytes. Invalid bytes are read as U+FFFD one by
+/// one. Characters out of the BMP are not supported.
+fn peek_utf8_char(cursor: &Cursor<Vec<u8>>) -> Option<(u16, usize)> {
+    let rest = &cursor.get_ref()[(cursor.position() as usize).min(cursor.get_ref().len())..];
+    let len = match *rest.first()? {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => return Some((0xfffd, 1)),
+    };
+    match rest
+        .get(..len)
+        .and_then(|bytes| std::str::from_utf8(bytes).ok())
+    {
+        Some(c) => Some((c.encode_utf16().next().unwrap(), len)),
+        None => Some((0xfffd, 1)),
+    }
+}
+
+/// Returns -1 at the end of the stream
+#[no_mangle]
+pub unsafe fn stream_reader_peek(stream_reader: *mut u64) -> i32 {
+    retrieve_cursor_from_system_stream_reader(stream_reader)
+        .and_then(|cursor| peek_utf8_char(cursor))
+        .map_or(-1, |(c, _)| c as i32)
+}
+
+/// Returns -1 at the end of the stream
+#[no_mangle]
+pub unsafe fn stream_reader_read(stream_reader: *mut u64) -> i32 {
+    let cursor = match retrieve_cursor_from_system_stream_reader(stream_reader) {
+        Some(cursor) => cursor,
+        None => return -1,
+    };
+    match peek_utf8_char(cursor) {
+        Some((c, len)) => {
+            cursor.set_position(cursor.position() + len as u64);
+            c as i32
+        }
+        None => -1,
+    }
+}
+
+/// Returns null at the end of the stream. Lines end with ``\n``, ``\r`` or ``\r\n``.
+#[no_mangle]
+pub unsafe fn stream_reader_read_line(stream_reader: *mut u64) -> *mut u64 {
+    let cursor = match retrieve_cursor_from_system_stream_reader(stream_reader) {
+        Some(cursor) => cursor,
+        None => return ptr::null_mut(),
+    };
+    let bytes = cursor.get_ref();
+    let start = (cursor.position() as usize).min(bytes.len());
+    if start == bytes.len() {
+        return ptr::null_mut();
+    }
+    let end = bytes[start..]
+        .iter()
+        .position(|&b| b == b'\n' || b == b'\r')
+        .map_or(bytes.len(), |i| start + i);
+    let line = String::from_utf8_lossy(&bytes[start..end]).into_owned();
+    let next = match bytes.get(end..end + 2) {
+        Some(b"\r\n") => end + 2,
+        _ => (end + 1).min(bytes.len()),
+    };
+    cursor.set_position(next as u64);
+    new_system_string(line)
+}
+
+#[no_mangle]
+pub unsafe fn stream_reader_read_to_end(stream_reader: *mut u64) -> *mut u64 {
+    let mut bytes = vec![];
+    if let Some(cursor) = retrieve_cursor_from_system_stream_reader(stream_reader) {
+        cursor.read_to_end(&mut bytes).ok();
+    }
+    new_system_string(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[no_mangle]
+pub unsafe fn stream_reader_get_end_of_stream(stream_reader: *mut u64) -> u8 {
+    match retrieve_cursor_from_system_stream_reader(stream_reader) {
+        Some(cursor) => (cursor.position() >= cursor.get_ref().len() as u64) as u8,
+        None => 1,
+    }
+}
+
+/// Closes the stream too
+#[no_mangle]
+pub unsafe fn stream_reader_close(stream_reader: *mut u64) {
+    let stream = *stream_reader.offset(1) as *mut u64;
+    if !stream.is_null() {
+        stream_close(stream);
+    }
+}
+
+#[no_mangle]
+pub fn throw_exception(exception: *mut u8) {
+    PENDING_EXCEPTION.with(|e| e.set(exception))
+}
+
+#[no_mangle]
+pub fn exception_pending() -> i32 {
+    PENDING_EXCEPTION.with(|e| !e.get().is_null()) as i32
+}
+
+#[no_mangle]
+pub fn get_exception() -> *mut u8 {
+    PENDING_EXCEPTION.with(|e| e.get())
+}
+
+#[no_mangle]
+pub fn take_exception() -> *mut u8 {
+    PENDING_EXCEPTION.with(|e| e.replace(ptr::null_mut()))
+}
+
+#[no_mangle]
+pub unsafe fn unhandled_exception() {
+    let exception = take_exception() as *mut u64;
+    eprintln!(
+        "Unhandled Exception: {}",
+        convert_object_to_string(exception)
+    );
+    process::exit(1)
+}
+
+pub fn register_method_table(
+    method_table: MethodTablePtrTy,
+    parent: Option<MethodTablePtrTy>,
+    interfaces: Vec<(MethodTablePtrTy, Vec<usize>)>,
+    name: String,
+    is_valuetype: bool,
+    assembly: Option<usize>,
+) {
+    METHOD_TABLE_INFO.with(|map| {
+        map.borrow_mut().insert(
+            method_table as usize,
+            MethodTableInfo {
+                parent: parent.map(|parent| parent as usize),
+                interfaces: interfaces
+                    .into_iter()
+                    .map(|(interface, slots)| (interface as usize, slots))
+                    .collect(),
+                name,
+                is_valuetype,
+                assembly,
+                generic_virtual_methods: FxHashMap::default(),
+            },
+        )
+    });
+}
+
+/// Returns the full name of the class of ``obj``
+pub unsafe fn get_class_name(obj: *mut u8) -> String {
+    let method_table = *(obj as *mut usize);
+    METHOD_TABLE_INFO.with(|map| {
+        map.borrow()
+            .get(&method_table)
+            .map_or_else(|| "System.Object".to_string(), |info| info.name.clone())
+    })
+}
+
+/// Returns 1 if the class of ``obj`` is the class whose method table is ``method_table``, derives
+/// from it or implements it. ``method_table`` may also be the descriptor of an array type (see
+/// ``array_type_descriptor``).
+#[no_mangle]
+pub unsafe fn is_instance(obj: *mut u8, method_table: *mut u8) -> i32 {
+    let array_ty = ARRAY_TYPES.with(|tys| tys.borrow().get(&(method_table as usize)).cloned());
+    if let Some((rank, elem_method_table)) = array_ty {
+        return is_array_instance(obj, rank, elem_method_table as *mut u8, 1);
+    }
+    if obj.is_null() {
+        return 0;
+    }
+    let cur = *(obj as *mut usize);
+    METHOD_TABLE_INFO.with(|map| derives_from(&map.borrow(), cur, method_table as usize) as i32)
+}
+
+/// Returns true if the class whose method table is ``cur`` is the one of ``method_table``, derives
+/// from it or implements it
+fn derives_from(
+    map: &FxHashMap<usize, MethodTableInfo>,
+    mut cur: usize,
+    method_table: usize,
+) -> bool {
+    loop {
+        if cur == method_table {
+            return true;
+        }
+        let info = match map.get(&cur) {
+            Some(info) => info,
+            None => return false,
+        };
+        if info
+            .interfaces
+            .iter()
+            .any(|(interface, _)| *interface == method_table)
+        {
+            return true;
+        }
+        match info.parent {
+            Some(parent) => cur = parent,
+            None => return false,
+        }
+    }
+}
+
+/// Returns 1 if ``obj`` is an array of ``rank`` dimensions (0 for single-dimensional arrays, -1
+/// for any array) whose elements are of the class that ``elem_method_table`` identifies (see
+/// ``new_szarray``). Arrays of reference types are covariant (``is_covariant``), so the arrays of
+/// the classes deriving from the element class are also its instances.
+#[no_mangle]
+pub unsafe fn is_array_instance(
+    obj: *mut u8,
+    rank: i32,
+    elem_method_table: *mut u8,
+    is_covariant: i32,
+) -> i32 {
+    if obj.is_null() {
+        return 0;
+    }
+    let header = obj as *mut u64;
+    METHOD_TABLE_INFO.with(|map| {
+        let map = map.borrow();
+        // Objects begin with their method tables, single-dimensional arrays with their lengths
+        // and multidimensional arrays with their complemented ranks
+        if map.contains_key(&(*header as usize)) {
+            return 0;
+        }
+        let has_rank = match rank {
+            -1 => return 1,
+            0 => *header >> 32 == 0,
+            rank => *header == !(rank as u64),
+        };
+        if !has_rank {
+            return 0;
+        }
+        let elem = *header.add(1) as usize;
+        (elem == elem_method_table as usize
+            || is_covariant != 0 && is_covariant_element(&map, elem, elem_method_table as usize))
+            as i32
+    })
+}
+
+/// Returns true if the arrays whose elements ``elem`` identifies (see ``new_szarray``) are
+/// instances of the arrays whose elements ``target`` identifies, which holds if the elements are
+/// references assignable to the elements of ``target``
+fn is_covariant_element(
+    map: &FxHashMap<usize, MethodTableInfo>,
+    elem: usize,
+    target: usize,
+) -> bool {
+    if elem == target {
+        return true;
+    }
+    // Arrays are references, assignable to the arrays of the same rank whose elements they are
+    // covariant with
+    if let Some((rank, elem_elem)) = ARRAY_TYPES.with(|tys| tys.borrow().get(&elem).cloned()) {
+        return match ARRAY_TYPES.with(|tys| tys.borrow().get(&target).cloned()) {
+            Some((target_rank, target_elem)) => {
+                rank == target_rank && is_covariant_element(map, elem_elem, target_elem)
+            }
+            None => target == 0,
+        };
+    }
+    // Null is the element class of the arrays of any references (e.g. object[])
+    match map.get(&elem) {
+        Some(info) if !info.is_valuetype => target == 0 || derives_from(map, elem, target),
+        _ => false,
+    }
+}
+
+/// Returns the descriptor that identifies the arrays of ``rank`` dimensions (0 for
+/// single-dimensional arrays) whose elements ``elem_method_table`` identifies, in place of the
+/// method table of the element class for arrays of such arrays (see ``new_szarray``)
+pub fn array_type_descriptor(rank: i32, elem_method_table: *mut u8) -> *mut u8 {
+    ARRAY_TYPES.with(|tys| {
+        let mut tys = tys.borrow_mut();
+        let ty = (rank, elem_method_table as usize);
+        if let Some((descriptor, _)) = tys.iter().find(|(_, t)| **t == ty) {
+            return *descriptor as *mut u8;
+        }
+        // Only the address matters
+        let descriptor = Box::into_raw(Box::new(0u64)) as usize;
+        tys.insert(descriptor, ty);
+        descriptor as *mut u8
+    })
+}
+
+/// Returns the method of ``obj`` that implements the ``slot``-th method of the interface whose
+/// method table is ``interface``
+#[no_mangle]
+pub unsafe fn get_interface_method(obj: *mut u8, interface: *mut u8, slot: i32) -> *mut u8 {
+    let method_table = *(obj as *mut *mut *mut u8);
+    METHOD_TABLE_INFO.with(|map| {
+        let map = map.borrow();
+        let (_, slots) = map
+            .get(&(method_table as usize))
+            .and_then(|info| {
+                info.interfaces
+                    .iter()
+                    .find(|(i, _)| *i == interface as usize)
+            })
+            .unwrap();
+        *method_table.add(slots[slot as usize])
+    })
+}
+
+/// Makes the objects of ``method_table`` call ``func`` for the instance of a generic virtual
+/// method identified by ``id``
+#[no_mangle]
+pub unsafe fn register_generic_virtual_method(method_table: *mut u8, id: i32, func: *mut u8) {
+    METHOD_TABLE_INFO.with(|map| {
+        if let Some(info) = map.borrow_mut().get_mut(&(method_table as usize)) {
+            info.generic_virtual_methods.insert(id as usize, func as usize);
+        }
+    })
+}
+
+/// Returns the function that ``obj`` calls for the instance of a generic virtual method
+/// identified by ``id``, searching from the class of ``obj`` toward its base classes. Throws
+/// MissingMethodException and returns null if no class has the instance (e.g. the method is
+/// abstract).
+#[no_mangle]
+pub unsafe fn get_generic_virtual_method(obj: *mut u8, id: i32) -> *mut u8 {
+    let mut method_table = Some(*(obj as *mut usize));
+    let func = METHOD_TABLE_INFO.with(|map| {
+        let map = map.borrow();
+        while let Some(info) = method_table.and_then(|method_table| map.get(&method_table)) {
+            if let Some(func) = info.generic_virtual_methods.get(&(id as usize)) {
+                return Some(*func as *mut u8);
+            }
+            method_table = info.parent;
+        }
+        None
+    });
+    func.unwrap_or_else(|| {
+        throw_system_exception("MissingMethodException", None);
+        ptr::null_mut()
+    })
+}
+
 // TODO: Currently using boehm-gc. Replace with better way in the future.
 #[link(name = "gc")]
 extern "C" {
-    fn GC_malloc(len: u32) -> *mut u8;
+    fn GC_malloc(len: usize) -> *mut u8;
     fn GC_register_finalizer(obj: *mut u8, f: *mut u8, cd: *mut u8, ofn: *mut u8, ocd: *mut u8);
+    fn GC_add_roots(low: *mut u8, high_plus_one: *mut u8);
 }
 
 unsafe fn new_utf16_string(s: String) -> *mut Vec<u16> {
     let utf16 = s.encode_utf16().collect::<Vec<u16>>();
-    let ptr = GC_malloc(mem::size_of::<Vec<u16>>() as u32);
+    let ptr = GC_malloc(mem::size_of::<Vec<u16>>());
     ptr::copy_nonoverlapping(&utf16 as *const Vec<u16>, ptr as *mut Vec<u16>, 1);
     mem::forget(utf16);
     GC_register_finalizer(
@@ -386,7 +1553,7 @@ unsafe fn new_utf16_string(s: String) -> *mut Vec<u16> {
 }
 
 pub unsafe fn new_utf16_string_from_vec_u16(utf16: Vec<u16>) -> *mut Vec<u16> {
-    let ptr = GC_malloc(mem::size_of::<Vec<u16>>() as u32);
+    let ptr = GC_malloc(mem::size_of::<Vec<u16>>());
     ptr::copy_nonoverlapping(&utf16 as *const Vec<u16>, ptr as *mut Vec<u16>, 1);
     mem::forget(utf16);
     GC_register_finalizer(
@@ -406,12 +1573,398 @@ fn finalizer_string(obj: *mut Vec<u16>, _cd: *mut u8) {
 
 #[no_mangle]
 pub fn memory_alloc(len: u32) -> *mut u8 {
-    unsafe { GC_malloc(len) }
+    unsafe { GC_malloc(len as usize) }
+}
+
+#[no_mangle]
+pub unsafe fn interlocked_increment(location: *mut i32) -> i32 {
+    interlocked_add(location, 1)
+}
+
+#[no_mangle]
+pub unsafe fn interlocked_decrement(location: *mut i32) -> i32 {
+    interlocked_add(location, -1)
+}
+
+/// Returns the new value
+#[no_mangle]
+pub unsafe fn interlocked_add(location: *mut i32, value: i32) -> i32 {
+    let location = &*(location as *const AtomicI32);
+    location
+        .fetch_add(value, Ordering::SeqCst)
+        .wrapping_add(value)
+}
+
+/// Returns the original value
+#[no_mangle]
+pub unsafe fn interlocked_exchange(location: *mut i32, value: i32) -> i32 {
+    let location = &*(location as *const AtomicI32);
+    location.swap(value, Ordering::SeqCst)
+}
+
+/// Stores ``value`` if the current value equals ``comparand``. Returns the original value.
+#[no_mangle]
+pub unsafe fn interlocked_compare_exchange(location: *mut i32, value: i32, comparand: i32) -> i32 {
+    let location = &*(location as *const AtomicI32);
+    match location.compare_exchange(comparand, value, Ordering::SeqCst, Ordering::SeqCst) {
+        Ok(original) | Err(original) => original,
+    }
 }
 
+/// Returns the original value
+#[no_mangle]
+pub unsafe fn interlocked_compare_exchange_ref(
+    location: *mut *mut u8,
+    value: *mut u8,
+    comparand: *mut u8,
+) -> *mut u8 {
+    let location = &*(location as *const AtomicPtr<u8>);
+    match location.compare_exchange(comparand, value, Ordering::SeqCst, Ordering::SeqCst) {
+        Ok(original) | Err(original) => original,
+    }
+}
+
+/// Makes the garbage collector scan ``len`` bytes from ``storage``, which holds static fields
+#[no_mangle]
+pub fn register_static_storage(storage: *mut u8, len: u32) {
+    unsafe { GC_add_roots(storage, storage.add(len as usize)) }
+}
+
+/// Allocates a single-dimensional array. The elements follow the length and the method table of
+/// the element class, which the JIT sets for type tests and to check covariant stores (null for
+/// the elements of any reference type, and ``array_type_descriptor`` for arrays). Throws
+/// OutOfMemoryException and returns null if the array is too large.
 #[no_mangle]
 pub fn new_szarray(elem_sz: u32, len: u32) -> *mut u8 {
-    let ptr = unsafe { GC_malloc(elem_sz * len + 8) };
+    let ptr = (len as usize)
+        .checked_mul(elem_sz as usize)
+        .and_then(|size| size.checked_add(SZARRAY_HEADER_SIZE as usize))
+        .map_or(ptr::null_mut(), |size| unsafe { GC_malloc(size) });
+    if ptr.is_null() {
+        unsafe { throw_system_exception("OutOfMemoryException", None) };
+        return ptr::null_mut();
+    }
     unsafe { *(ptr as *mut u64) = len as u64 };
     ptr
 }
+
+pub const SZARRAY_HEADER_SIZE: u32 = 16;
+
+/// Allocates a multidimensional array of ``len`` elements in total. The complemented rank (which
+/// tells the array from the other objects), the method table of the element class (see
+/// ``new_szarray``), the lengths and then the lower bounds of the ``rank`` dimensions precede the
+/// elements. The caller sets the lengths and the lower bounds. Throws OutOfMemoryException and
+/// returns null if the array is too large.
+#[no_mangle]
+pub unsafe fn new_array(elem_sz: u32, rank: u32, len: u64, elem_method_table: *mut u8) -> *mut u8 {
+    let ptr = (len as usize)
+        .checked_mul(elem_sz as usize)
+        .and_then(|size| size.checked_add(16 * (rank as usize + 1)))
+        .map_or(ptr::null_mut(), |size| GC_malloc(size)) as *mut u64;
+    if ptr.is_null() {
+        throw_system_exception("OutOfMemoryException", None);
+        return ptr::null_mut();
+    }
+    *ptr = !(rank as u64);
+    *ptr.add(1) = elem_method_table as u64;
+    ptr as *mut u8
+}
+
+#[no_mangle]
+pub unsafe fn memory_copy(dst: *mut u8, src: *mut u8, len: u64) {
+    ptr::copy(src, dst, len as usize)
+}
+
+#[no_mangle]
+pub unsafe fn memory_set(dst: *mut u8, value: i32, len: u64) {
+    ptr::write_bytes(dst, value as u8, len as usize)
+}
+
+/// Copies ``data`` made by ``ldtoken`` (its length followed by the bytes) to the elements of
+/// ``array``. An element of the data may be smaller than the one of the array (e.g. ``char``),
+/// in which case it is zero-extended. Returns 0 if the data is too small or null (the field has
+/// no initial data).
+#[no_mangle]
+pub unsafe fn initialize_array(
+    array: *mut u8,
+    elem_sz: u32,
+    data: *mut u8,
+    data_elem_sz: u32,
+) -> i32 {
+    let len = *(array as *mut u64) as usize;
+    let (elem_sz, data_elem_sz) = (elem_sz as usize, data_elem_sz as usize);
+    if data.is_null() || (*(data as *mut u64) as usize) < len * data_elem_sz {
+        return 0;
+    }
+    let elems = array.add(SZARRAY_HEADER_SIZE as usize);
+    let data = data.add(8);
+    if elem_sz == data_elem_sz {
+        ptr::copy_nonoverlapping(data, elems, len * elem_sz);
+        return 1;
+    }
+    ptr::write_bytes(elems, 0, len * elem_sz);
+    for i in 0..len {
+        ptr::copy_nonoverlapping(
+            data.add(i * data_elem_sz),
+            elems.add(i * elem_sz),
+            data_elem_sz.min(elem_sz),
+        );
+    }
+    1
+}
+
+/// Returns the Type object of the method table ``handle`` (see ``RuntimeTypeHandle``)
+#[no_mangle]
+pub unsafe fn type_get_type_from_handle(handle: u64) -> *mut u64 {
+    if handle == 0 {
+        return ptr::null_mut();
+    }
+    TYPE_OBJECTS.with(|objs| {
+        *objs.borrow_mut().entry(handle as usize).or_insert_with(|| {
+            let method_table = TYPE_METHOD_TABLE_PTR.with(|tmp| tmp.borrow().unwrap());
+            Box::into_raw(Box::new([method_table as u64, handle])) as usize
+        }) as *mut u64
+    })
+}
+
+/// Types not defined in any assembly (e.g. arrays) belong to mscorlib
+#[no_mangle]
+pub unsafe fn type_get_assembly(system_type: *mut u64) -> *mut u64 {
+    let method_table = *system_type.offset(1) as usize;
+    let assembly = METHOD_TABLE_INFO.with(|map| {
+        map.borrow()
+            .get(&method_table)
+            .and_then(|info| info.assembly)
+    });
+    let handle = assembly.unwrap_or_else(|| {
+        MSCORLIB_ASSEMBLY.with(|mscorlib| {
+            let handle = mscorlib
+                .get()
+                .unwrap_or_else(|| register_runtime_assembly(RuntimeAssembly::mscorlib()));
+            mscorlib.set(Some(handle));
+            handle
+        })
+    });
+    new_system_assembly(handle as u32)
+}
+
+#[no_mangle]
+pub unsafe fn type_get_full_name(system_type: *mut u64) -> *mut u64 {
+    let method_table = *system_type.offset(1) as usize;
+    new_system_string(METHOD_TABLE_INFO.with(|map| {
+        map.borrow()
+            .get(&method_table)
+            .map_or_else(|| "System.Object".to_string(), |info| info.name.clone())
+    }))
+}
+
+#[no_mangle]
+pub unsafe fn type_get_name(system_type: *mut u64) -> *mut u64 {
+    let full_name = String::from_utf16_lossy(&*retrieve_utf16_string_from_system_string(
+        type_get_full_name(system_type),
+    ));
+    new_system_string(full_name.rsplit('.').next().unwrap().to_string())
+}
+
+/// Type objects are compared by reference since a type has only one
+#[no_mangle]
+pub fn type_op_equality(a: *mut u64, b: *mut u64) -> u8 {
+    (a == b) as u8
+}
+
+#[no_mangle]
+pub fn type_op_inequality(a: *mut u64, b: *mut u64) -> u8 {
+    (a != b) as u8
+}
+
+// Delegates are laid out as {method table, target, method, invocation list} (see
+// System.Delegate). The invocation list of a delegate made by Delegate.Combine is an array of the
+// combined delegates in the order of invocation. Otherwise the list is null and the delegate
+// itself is invoked.
+
+#[no_mangle]
+pub unsafe fn delegate_ctor(delegate: *mut u64, target: *mut u8, method: *mut u8) {
+    *delegate.add(1) = target as u64;
+    *delegate.add(2) = method as u64;
+    *delegate.add(3) = 0;
+}
+
+/// Returns the target object, which is null if the method is static
+#[no_mangle]
+pub unsafe fn delegate_target(delegate: *mut u64) -> *mut u8 {
+    *delegate.add(1) as *mut u8
+}
+
+#[no_mangle]
+pub unsafe fn delegate_method(delegate: *mut u64) -> *mut u8 {
+    *delegate.add(2) as *mut u8
+}
+
+#[no_mangle]
+pub unsafe fn delegate_invocation_count(delegate: *mut u64) -> i32 {
+    let list = *delegate.add(3) as *mut u64;
+    if list.is_null() {
+        1
+    } else {
+        *list as i32
+    }
+}
+
+#[no_mangle]
+pub unsafe fn delegate_invocation_item(delegate: *mut u64, i: i32) -> *mut u64 {
+    let list = *delegate.add(3) as *mut u64;
+    if list.is_null() {
+        delegate
+    } else {
+        *list.add(i as usize + 2) as *mut u64
+    }
+}
+
+unsafe fn get_invocation_list(delegate: *mut u64) -> Vec<*mut u64> {
+    (0..delegate_invocation_count(delegate))
+        .map(|i| delegate_invocation_item(delegate, i))
+        .collect()
+}
+
+/// Two delegates are equal if they call the same method on the same target
+unsafe fn delegate_equals(d1: *mut u64, d2: *mut u64) -> bool {
+    d1 == d2
+        || (delegate_target(d1) == delegate_target(d2)
+            && delegate_method(d1) == delegate_method(d2))
+}
+
+/// Returns a delegate of the same type as ``delegate`` that invokes ``list``
+unsafe fn new_multicast_delegate(delegate: *mut u64, list: Vec<*mut u64>) -> *mut u64 {
+    match list.len() {
+        0 => ptr::null_mut(),
+        1 => list[0],
+        len => {
+            let array = new_szarray(8, len as u32) as *mut u64;
+            for (i, item) in list.iter().enumerate() {
+                *array.add(i + 2) = *item as u64;
+            }
+            let last = list[len - 1];
+            let multicast = memory_alloc(32) as *mut u64;
+            *multicast = *delegate;
+            *multicast.add(1) = *last.add(1);
+            *multicast.add(2) = *last.add(2);
+            *multicast.add(3) = array as u64;
+            multicast
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe fn delegate_combine(a: *mut u64, b: *mut u64) -> *mut u64 {
+    if a.is_null() {
+        return b;
+    }
+    if b.is_null() {
+        return a;
+    }
+    let mut list = get_invocation_list(a);
+    list.extend(get_invocation_list(b));
+    new_multicast_delegate(a, list)
+}
+
+/// Removes the last occurrence of the invocation list of ``value`` from that of ``source``
+#[no_mangle]
+pub unsafe fn delegate_remove(source: *mut u64, value: *mut u64) -> *mut u64 {
+    if source.is_null() || value.is_null() {
+        return source;
+    }
+    let mut list = get_invocation_list(source);
+    let sub = get_invocation_list(value);
+    if list.len() < sub.len() {
+        return source;
+    }
+    let found = (0..=list.len() - sub.len()).rev().find(|&i| {
+        list[i..i + sub.len()]
+            .iter()
+            .zip(&sub)
+            .all(|(&d1, &d2)| delegate_equals(d1, d2))
+    });
+    match found {
+        Some(i) => {
+            list.drain(i..i + sub.len());
+            new_multicast_delegate(source, list)
+        }
+        None => source,
+    }
+}
+
+#[test]
+fn test_system_stream() {
+    // The method tables of Stream and the exceptions are set up along with mscorlib
+    let asm = Assembly::load(std::path::PathBuf::from("./examples/hello.exe")).unwrap();
+    let mut shared_env = SharedEnvironment::new();
+    unsafe { JITCompiler::new(&mut asm.borrow_mut(), &mut shared_env) };
+    let exception_name = || {
+        let exception = take_exception();
+        assert!(!exception.is_null());
+        unsafe { get_class_name(exception) }
+    };
+
+    unsafe {
+        let stream = new_system_stream(b"yacht".to_vec());
+        let buffer = new_szarray(1, 4);
+        stream_read(stream, ptr::null_mut(), 0, 1);
+        assert_eq!(exception_name(), "System.ArgumentNullException");
+        stream_read(stream, buffer, 0, -1);
+        assert_eq!(exception_name(), "System.ArgumentOutOfRangeException");
+        stream_read(stream, buffer, 3, 2);
+        assert_eq!(exception_name(), "System.ArgumentException");
+        assert_eq!(stream_read(stream, buffer, 1, 3), 3);
+        assert_eq!(*buffer.add(SZARRAY_HEADER_SIZE as usize + 1), b'y');
+        assert!(take_exception().is_null());
+
+        stream_close(stream);
+        stream_close(stream);
+        stream_close(ptr::null_mut());
+        assert!(take_exception().is_null());
+        assert_eq!(stream_read_byte(stream), -1);
+        assert_eq!(exception_name(), "System.ObjectDisposedException");
+        stream_get_length(stream);
+        assert_eq!(exception_name(), "System.ObjectDisposedException");
+
+        // Streams that are not closed are closed when collected
+        let stream = new_system_stream(b"yacht".to_vec());
+        finalizer_stream(stream, ptr::null_mut());
+        assert!((*(stream.offset(1) as *mut *mut Cursor<Vec<u8>>)).is_null());
+    }
+}
+
+#[test]
+fn test_type_get_assembly() {
+    let asm = Assembly::load(std::path::PathBuf::from("./examples/nested.exe")).unwrap();
+    let method = asm.borrow_mut().image.get_entry_method().unwrap();
+    let mut shared_env = SharedEnvironment::new();
+    unsafe {
+        let mut asm = asm.borrow_mut();
+        JITCompiler::new(&mut asm, &mut shared_env)
+            .generate_method_as_main(&method)
+            .unwrap();
+    }
+
+    // Classes know the assemblies defining them. Those of mscorlib do not.
+    let expected = [
+        ("Lib.Outer", "nested_lib, Version=1.0.0.0, Culture=neutral"),
+        ("Inner", "nested_lib, Version=1.0.0.0, Culture=neutral"),
+        ("System.Object", "mscorlib, Version=4.0.0.0, Culture=neutral"),
+    ];
+    for &(class, full_name) in &expected {
+        let method_table = METHOD_TABLE_INFO.with(|map| {
+            *map.borrow()
+                .iter()
+                .find(|(_, info)| info.name == class)
+                .unwrap()
+                .0
+        });
+        unsafe {
+            let system_type = type_get_type_from_handle(method_table as u64);
+            let name = assembly_get_full_name(type_get_assembly(system_type));
+            let name = String::from_utf16_lossy(&*retrieve_utf16_string_from_system_string(name));
+            assert_eq!(name, full_name);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::exec::instruction::*;
+use crate::{exec::instruction::*, metadata::method::*};
 use std::collections::BTreeMap;
 
 #[derive(Debug, Clone)]
@@ -13,6 +13,7 @@ pub enum BrKind {
     ConditionalJmp { destinations: Vec<usize> },
     UnconditionalJmp { destination: usize },
     ImplicitJmp { destination: usize },
+    Terminated, // ends with an instruction that never falls through (e.g. throw)
     BlockStart,
 }
 
@@ -26,7 +27,11 @@ impl CFGMaker {
 }
 
 impl CFGMaker {
-    pub fn make_basic_blocks(&mut self, code: &[Instruction]) -> Vec<BasicBlock> {
+    pub fn make_basic_blocks(
+        &mut self,
+        code: &[Instruction],
+        clauses: &[ExceptionClause],
+    ) -> Vec<BasicBlock> {
         let mut map = BTreeMap::new();
 
         macro_rules! jmp_at {
@@ -48,9 +53,11 @@ impl CFGMaker {
                 Instruction::Bge(target)
                 | Instruction::Bge_un(target)
                 | Instruction::Bgt(target)
+                | Instruction::Bgt_un(target)
                 | Instruction::Ble(target)
                 | Instruction::Ble_un(target)
                 | Instruction::Blt(target)
+                | Instruction::Blt_un(target)
                 | Instruction::Beq(target)
                 | Instruction::Bne_un(target)
                 | Instruction::Brfalse(target)
@@ -64,7 +71,16 @@ impl CFGMaker {
                     new_block_starts_at!(*target);
                     new_block_starts_at!(pc + 1);
                 }
-                Instruction::Br(target) => {
+                Instruction::Switch(targets) => {
+                    // The last destination is taken when the value is out of range
+                    let mut destinations = targets.clone();
+                    destinations.push(pc + 1);
+                    for target in &destinations {
+                        new_block_starts_at!(*target);
+                    }
+                    jmp_at!(pc, BrKind::ConditionalJmp { destinations });
+                }
+                Instruction::Br(target) | Instruction::Leave(target) => {
                     jmp_at!(
                         pc,
                         BrKind::UnconditionalJmp {
@@ -73,10 +89,27 @@ impl CFGMaker {
                     );
                     new_block_starts_at!(*target);
                 }
+                Instruction::Jmp(_)
+                | Instruction::Throw
+                | Instruction::Rethrow
+                | Instruction::Endfinally
+                | Instruction::Endfilter => {
+                    jmp_at!(pc, BrKind::Terminated);
+                    new_block_starts_at!(pc + 1);
+                }
                 _ => {}
             }
         }
 
+        // Handlers (and filters) are entered only by exceptions
+        for clause in clauses {
+            new_block_starts_at!(clause.try_offset);
+            new_block_starts_at!(clause.handler_offset);
+            if let ExceptionClauseKind::Filter { filter_offset } = clause.kind {
+                new_block_starts_at!(filter_offset);
+            }
+        }
+
         let mut start = Some(0);
         let mut blocks = vec![];
 
@@ -90,7 +123,9 @@ impl CFGMaker {
             }};
         }
 
-        for (key, kind_list) in map {
+        for (key, mut kind_list) in map {
+            // A block must start before the instruction at ``key`` ends it
+            kind_list.sort_by_key(|kind| *kind != BrKind::BlockStart);
             for kind in kind_list {
                 match kind {
                     BrKind::BlockStart => {
@@ -101,7 +136,9 @@ impl CFGMaker {
                         }
                         start = Some(key)
                     }
-                    BrKind::ConditionalJmp { .. } | BrKind::UnconditionalJmp { .. } => {
+                    BrKind::ConditionalJmp { .. }
+                    | BrKind::UnconditionalJmp { .. }
+                    | BrKind::Terminated => {
                         if let Some(start) = start {
                             if start <= key {
                                 create_block!(start..key + 1, kind)
@@ -115,7 +152,9 @@ impl CFGMaker {
         }
 
         if let Some(start) = start {
-            create_block!(start..code.len(), BrKind::BlockStart);
+            if start < code.len() {
+                create_block!(start..code.len(), BrKind::BlockStart);
+            }
         }
 
         blocks
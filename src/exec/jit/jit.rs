@@ -10,15 +10,33 @@ use id_arena;
 use llvm;
 use llvm::{core::*, prelude::*};
 use rustc_hash::FxHashMap;
-use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::{cell::RefCell, rc::Rc};
 use std::ffi::CString;
+use std::fmt;
 use std::ptr;
 
 thread_local! {
     pub static STRING_METHOD_TABLE_PTR: RefCell<Option<MethodTablePtrTy>> = {
         RefCell::new(None)
-    }
+    };
+
+    pub static ASSEMBLY_METHOD_TABLE_PTR: RefCell<Option<MethodTablePtrTy>> = {
+        RefCell::new(None)
+    };
+
+    pub static STREAM_METHOD_TABLE_PTR: RefCell<Option<MethodTablePtrTy>> = {
+        RefCell::new(None)
+    };
+
+    pub static TYPE_METHOD_TABLE_PTR: RefCell<Option<MethodTablePtrTy>> = {
+        RefCell::new(None)
+    };
+
+    /// Method tables of the exceptions that builtin functions throw, by class name
+    pub static EXCEPTION_METHOD_TABLE_PTRS: RefCell<FxHashMap<String, MethodTablePtrTy>> = {
+        RefCell::new(FxHashMap::default())
+    };
 }
 
 macro_rules! cstr0 {
@@ -27,8 +45,18 @@ macro_rules! cstr0 {
     };
 }
 
+/// Stops compiling the method, for IL that this JIT doesn't support
+macro_rules! unsupported {
+    ($($arg:tt)*) => {
+        return Err(Error::CouldntCompile(format!($($arg)*)))
+    };
+}
+
 fn alloc_raw_method_table(len: usize) -> MethodTablePtrTy {
-    Box::into_raw(vec![0 as *mut ::std::ffi::c_void; len].into_boxed_slice()) as MethodTablePtrTy
+    // Allocate at least one slot so that the method tables of interfaces, which have no methods,
+    // still have distinct addresses
+    Box::into_raw(vec![0 as *mut ::std::ffi::c_void; len.max(1)].into_boxed_slice())
+        as MethodTablePtrTy
 }
 
 type TypeId = id_arena::Id<Type>;
@@ -39,10 +67,19 @@ pub type CResult<T> = Result<T, Error>;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Error {
-    CouldntCompile,
+    CouldntCompile(String),
     General,
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::CouldntCompile(reason) => write!(f, "couldn't compile: {}", reason),
+            Error::General => write!(f, "couldn't compile"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum BasicBlockInfo {
     Positioned(LLVMBasicBlockRef),
@@ -69,9 +106,51 @@ pub struct SharedEnvironment {
     /// All classes. Searchable with ``TypePath``.
     pub class_types: ClassTypesNameResolver,
 
+    /// Storage (a global variable) for the static fields of each class. Searchable with
+    /// ``TypePath``.
+    pub static_storages: NameResolver<LLVMValueRef>,
+
+    /// Unboxed layouts (structures of the instance fields) of value types. Searchable with
+    /// ``TypePath``.
+    pub value_types: NameResolver<LLVMTypeRef>,
+
     /// All method tables. Searchable with their pointers.
     pub method_table_map: FxHashMap<MethodTablePtrTy, (LLVMValueRef, Vec<LLVMValueRef>)>,
 
+    /// Classes whose method tables are built so far, with the method tables
+    pub method_table_classes: Vec<(MethodTablePtrTy, ClassInfo)>,
+
+    /// Ids of the instances of generic virtual methods, which have no slot in method tables.
+    /// Searchable with the method table of the declaring class, the slot of the generic method
+    /// definition and the type arguments.
+    pub generic_virtual_method_ids: FxHashMap<(MethodTablePtrTy, usize, String), usize>,
+
+    /// Declaring classes, slots and type arguments of the instances of generic virtual methods by
+    /// their ids. Classes whose method tables are built after an id is made compile it as well.
+    pub generic_virtual_method_instances: Vec<(ClassInfo, usize, Vec<Type>)>,
+
+    /// Functions of the instances of generic virtual methods with the method tables of the
+    /// classes calling them and their ids (see ``get_generic_virtual_method``)
+    pub generic_virtual_methods: Vec<(MethodTablePtrTy, usize, LLVMValueRef)>,
+
+    /// Instances of generic virtual methods defined in another image than the one that asked for
+    /// them, with their definitions and the method tables and ids to register them for. Their
+    /// bodies refer to the metadata of those images, so the images compile them (see
+    /// ``generate_other_image_methods``).
+    pub other_image_generic_virtual_methods:
+        Vec<(MethodTablePtrTy, usize, MethodInfoRef, MethodDefInfo)>,
+
+    /// Environments of the other assemblies and modules by their names, kept to compile the
+    /// methods that the others ask for
+    pub asm_envs: FxHashMap<String, AssemblyUniqueEnvironment>,
+
+    /// ``Invoke`` of each delegate type, which is provided by the runtime. Searchable with
+    /// ``TypePath``.
+    pub delegate_invokes: NameResolver<LLVMValueRef>,
+
+    /// Handles of the assemblies in ``LOADED_ASSEMBLIES`` by their names
+    pub runtime_assemblies: FxHashMap<String, usize>,
+
     /// LLVM Context
     pub context: LLVMContextRef,
 
@@ -91,6 +170,9 @@ pub struct SharedEnvironment {
 #[derive(Clone)]
 pub struct AssemblyUniqueEnvironment {
     pub generated: FxHashMap<RVA, LLVMValueRef>,
+    /// Instances of generic methods (and methods of generic classes). Searchable with the RVA
+    /// and the name of the instance.
+    pub generated_instances: FxHashMap<(RVA, String), LLVMValueRef>,
     pub compile_queue: VecDeque<(LLVMValueRef, MethodInfoRef)>,
 }
 
@@ -108,6 +190,52 @@ pub struct JITCompiler<'a> {
 pub struct CodeEnvironment {
     pub arguments: FxHashMap<usize, TypedValue>,
     pub locals: FxHashMap<usize, TypedValue>,
+
+    /// Program counter of the instruction being compiled
+    pub pc: usize,
+
+    /// True if compiling the entry method
+    pub is_main: bool,
+
+    /// Exception handling clauses of the method being compiled
+    pub exception_clauses: Vec<ExceptionClause>,
+
+    /// (selector, exception) slots for each clause. The selector tells ``endfinally`` where to
+    /// continue, and the exception slot holds the exception caught by the clause.
+    pub clause_slots: FxHashMap<usize, (LLVMValueRef, LLVMValueRef)>,
+
+    /// Classes of the exceptions that each catch clause catches
+    pub catch_classes: FxHashMap<usize, ClassInfoRef>,
+
+    /// Blocks dispatching a thrown exception to the handlers of the clauses (in this order)
+    pub dispatch_blocks: FxHashMap<Vec<usize>, LLVMBasicBlockRef>,
+
+    /// Continuations of each finally clause. The i-th one is selected by the selector ``i + 1``.
+    pub finally_continuations: FxHashMap<usize, Vec<FinallyContinuation>>,
+
+    /// Where each ``leave`` (by its pc) goes first
+    pub leave_continuations: FxHashMap<usize, FinallyContinuation>,
+
+    /// Type arguments of the method being compiled (and its class)
+    pub generic_context: GenericContext,
+
+    /// Type given by the ``constrained.`` prefix to the next ``callvirt``
+    pub constrained: Option<Type>,
+
+    /// Type of the method being compiled
+    pub method_ty: Option<Type>,
+
+    /// Block of the arguments passed after the sentinel if the method being compiled is vararg
+    /// (see ``gen_vararg_call``)
+    pub arglist: Option<LLVMValueRef>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FinallyContinuation {
+    /// Run the finally handler of ``clause`` with its selector set to ``selector``
+    Finally { clause: usize, selector: u64 },
+    /// Jump to the target of ``leave``
+    Leave { target: usize },
 }
 
 #[derive(Debug, Clone)]
@@ -180,17 +308,22 @@ impl<'a> JITCompiler<'a> {
         llvm::execution_engine::LLVMRunFunction(ee, method, 0, vec![].as_mut_ptr());
     }
 
-    pub unsafe fn generate_method_as_main(&mut self, method_ref: &MethodInfoRef) -> LLVMValueRef {
-        self.generate_all_class_and_method();
+    pub unsafe fn generate_method_as_main(
+        &mut self,
+        method_ref: &MethodInfoRef,
+    ) -> CResult<LLVMValueRef> {
+        self.generate_all_class_and_method()?;
 
         self.basic_blocks.clear();
         self.phi_stack.clear();
         self.env = CodeEnvironment::new();
+        self.env.is_main = true;
 
         let method_info = method_ref.borrow();
         let method = method_info.as_mdef();
 
-        let basic_blocks = CFGMaker::new().make_basic_blocks(&method.body);
+        let basic_blocks =
+            CFGMaker::new().make_basic_blocks(&method.body, &method.exception_clauses);
         let (ret_ty, mut params_ty): (LLVMTypeRef, Vec<LLVMTypeRef>) =
             { (Type::new(ElementType::Void).to_llvmty(self), vec![]) };
         let func_ty = LLVMFunctionType(ret_ty, params_ty.as_mut_ptr(), params_ty.len() as u32, 0);
@@ -222,6 +355,21 @@ impl<'a> JITCompiler<'a> {
             self.get_local(i, Some(&ty));
         }
 
+        self.setup_exception_handling(method)?;
+
+        // The type initializer of the class of the entry method runs before the entry method
+        // unless the class is marked beforefieldinit
+        {
+            let class = method.class.borrow();
+            if !class.is_before_field_init() {
+                LLVMPositionBuilderAtEnd(self.shared_env.builder, bb_entry);
+                self.build_type_initialization(&class);
+                let bb_start = LLVMGetInsertBlock(self.shared_env.builder);
+                self.basic_blocks
+                    .insert(0, BasicBlockInfo::Unpositioned(bb_start));
+            }
+        }
+
         for block in &basic_blocks {
             // Exclude entry block (whose .start == 0) since it's already inserted
             if block.start > 0 {
@@ -235,10 +383,11 @@ impl<'a> JITCompiler<'a> {
         // Compile all the basic blocks
 
         for i in 0..basic_blocks.len() {
-            self.compile_block(&basic_blocks, i, &vec![]).unwrap();
+            self.compile_block(&basic_blocks, i, &vec![])?;
         }
 
-        self.generate_queued_methods();
+        self.generate_queued_methods()?;
+        self.generate_other_image_methods()?;
 
         // Set all the class methods to the appropriate method_table
 
@@ -259,6 +408,25 @@ impl<'a> JITCompiler<'a> {
             }
         }
 
+        for (method_table, id, func) in self.shared_env.generic_virtual_methods.clone() {
+            self.call_helper(
+                "register_generic_virtual_method",
+                vec![
+                    self.llvm_ptr(method_table as *mut u8),
+                    self.llvm_int32(id as u64),
+                    func,
+                ],
+            );
+        }
+
+        // Static fields may hold the only references to objects, so the garbage collector must
+        // scan them
+
+        for storage in self.shared_env.static_storages.collect_values() {
+            let size = self.get_size_of_llvm_class_type(LLVMTypeOf(storage));
+            self.call_helper("register_static_storage", vec![storage, size]);
+        }
+
         LLVMBuildBr(self.shared_env.builder, bb_entry);
 
         // Append ``ret void`` to the incomplete basic blocks
@@ -283,17 +451,24 @@ impl<'a> JITCompiler<'a> {
 
         LLVMRunPassManager(self.shared_env.pass_mgr, self.shared_env.module);
 
-        func
+        Ok(func)
     }
 
-    unsafe fn generate_func(&mut self, func: LLVMValueRef, method_ref: &MethodInfoRef) {
+    unsafe fn generate_func(
+        &mut self,
+        func: LLVMValueRef,
+        method_ref: &MethodInfoRef,
+    ) -> CResult<()> {
         self.generating = Some(func);
         self.env = CodeEnvironment::new();
 
         let method_info = method_ref.borrow();
         let method = method_info.as_mdef();
         let method_ty = method.ty.as_fnptr().unwrap();
-        let basic_blocks = CFGMaker::new().make_basic_blocks(&method.body);
+        self.env.generic_context = method.get_generic_context();
+        self.env.method_ty = Some(method.ty.clone());
+        let basic_blocks =
+            CFGMaker::new().make_basic_blocks(&method.body, &method.exception_clauses);
         let ret_ty = LLVMGetElementType(LLVMGetReturnType(LLVMTypeOf(func)));
         let bb_entry = LLVMAppendBasicBlockInContext(
             self.shared_env.context,
@@ -305,14 +480,11 @@ impl<'a> JITCompiler<'a> {
             .insert(0, BasicBlockInfo::Unpositioned(bb_entry));
         LLVMPositionBuilderAtEnd(self.shared_env.builder, bb_entry);
 
-        let shift = if method_ty.has_this() {
+        let shift = if method_ty.has_implicit_this() {
             LLVMBuildStore(
                 self.shared_env.builder,
                 LLVMGetParam(func, 0),
-                self.get_argument(
-                    0,
-                    Some(&Type::new(ElementType::Class(method.class.clone()))),
-                ),
+                self.get_argument(0, Some(&get_this_ty(&method.class))),
             );
             1
         } else {
@@ -327,11 +499,17 @@ impl<'a> JITCompiler<'a> {
             );
         }
 
+        if method_ty.is_vararg() {
+            self.env.arglist = Some(LLVMGetParam(func, (method_ty.params.len() + shift) as u32));
+        }
+
         // Declare locals
         for (i, ty) in method.locals_ty.iter().enumerate() {
             self.get_local(i, Some(&ty));
         }
 
+        self.setup_exception_handling(method)?;
+
         for block in &basic_blocks {
             if block.start > 0 {
                 // Exclude entry block (whose .start == 0) since it's already inserted
@@ -343,7 +521,7 @@ impl<'a> JITCompiler<'a> {
         }
 
         for i in 0..basic_blocks.len() {
-            self.compile_block(&basic_blocks, i, &vec![]).unwrap();
+            self.compile_block(&basic_blocks, i, &vec![])?;
         }
 
         let last_block = basic_blocks.last().unwrap();
@@ -373,6 +551,8 @@ impl<'a> JITCompiler<'a> {
 
         self.basic_blocks.clear();
         self.phi_stack.clear();
+
+        Ok(())
     }
 
     unsafe fn setup_mscorlib_system(&mut self) {
@@ -380,14 +560,50 @@ impl<'a> JITCompiler<'a> {
             mscorlib_system_object(),
             mscorlib_system_int32(),
             mscorlib_system_string(),
+            mscorlib_system_exception(),
+            mscorlib_system_reflection_assembly(),
+            mscorlib_system_io_stream(),
+            mscorlib_system_type(),
         ] {
             let class = class_ref.borrow();
             self.get_llvm_class_type(&class);
             let (method_table_ptr, _) = self.ensure_all_class_methods_compiled(&class);
-            if class.name == "String" {
-                STRING_METHOD_TABLE_PTR.with(|smp| *smp.borrow_mut() = Some(method_table_ptr));
+            match class.name.as_str() {
+                "String" => STRING_METHOD_TABLE_PTR
+                    .with(|smp| *smp.borrow_mut() = Some(method_table_ptr)),
+                "Assembly" => ASSEMBLY_METHOD_TABLE_PTR
+                    .with(|amp| *amp.borrow_mut() = Some(method_table_ptr)),
+                "Stream" => STREAM_METHOD_TABLE_PTR
+                    .with(|smp| *smp.borrow_mut() = Some(method_table_ptr)),
+                "Type" => TYPE_METHOD_TABLE_PTR
+                    .with(|tmp| *tmp.borrow_mut() = Some(method_table_ptr)),
+                _ => {}
             }
         }
+
+        for class_ref in &[
+            mscorlib_system_argument_exception(),
+            mscorlib_system_argument_null_exception(),
+            mscorlib_system_bad_image_format_exception(),
+            mscorlib_system_argument_out_of_range_exception(),
+            mscorlib_system_object_disposed_exception(),
+            mscorlib_system_missing_method_exception(),
+            mscorlib_system_out_of_memory_exception(),
+        ] {
+            let class = class_ref.borrow();
+            self.get_llvm_class_type(&class);
+            let (method_table_ptr, _) = self.ensure_all_class_methods_compiled(&class);
+            EXCEPTION_METHOD_TABLE_PTRS
+                .with(|emp| emp.borrow_mut().insert(class.name.clone(), method_table_ptr));
+        }
+
+        // Assemblies loaded by guest code find their dependencies in the same way as this one.
+        // They have no file, so the directory of this one is probed first instead.
+        let mut guest_resolver = self.assembly.image.resolver.clone();
+        if let Some(dir) = self.assembly.image.filename.parent() {
+            guest_resolver.probing_paths.insert(0, dir.to_path_buf());
+        }
+        GUEST_ASSEMBLY_RESOLVER.with(|r| *r.borrow_mut() = Some(guest_resolver));
     }
 
     pub unsafe fn define_all_class(&mut self) {
@@ -396,11 +612,14 @@ impl<'a> JITCompiler<'a> {
             .image
             .class_cache
             .iter()
-            .filter_map(|(_tok, class)| {
+            .filter_map(|(tok, class)| {
                 let class = class.borrow();
-                // ``class_cache`` may contain classes belonging to another assembly. Here exclude
-                // them.
+                // ``class_cache`` may contain classes belonging to another assembly or another
+                // module of this assembly. Here exclude them.
                 match class.resolution_scope {
+                    _ if decode_token(*tok).0 != TableKind::TypeDef.into() => None,
+                    // Generic types are compiled when they are instantiated
+                    _ if class.is_generic_def() => None,
                     // TODO: Support all possible ResolutionScope
                     ResolutionScope::AssemblyRef { ref name } if name == &self.assembly.name => {
                         Some(class.clone())
@@ -422,11 +641,17 @@ impl<'a> JITCompiler<'a> {
             .image
             .method_cache
             .iter()
-            .map(|(_, minforef)| minforef.borrow().clone().into_mdef())
-            .collect::<Vec<MethodDefInfo>>();
+            .filter(|(_, minforef)| {
+                let minfo = minforef.borrow();
+                let m = minfo.as_mdef();
+                m.has_body() && !m.is_open()
+            })
+            .map(|(_, minforef)| minforef.clone())
+            .collect::<Vec<MethodInfoRef>>();
 
-        for m in methods {
-            let llvm_function = self.get_function_by_rva(m.rva);
+        for m_ref in methods {
+            let llvm_function = self.get_function_by_method(&m_ref);
+            let m = m_ref.borrow().clone().into_mdef();
             let class = m.class.borrow();
             let method_path = ((&*class).into(): TypePath).with_method_name(m.name.as_str());
             self.shared_env.methods.map.add(
@@ -440,7 +665,7 @@ impl<'a> JITCompiler<'a> {
         }
     }
 
-    pub unsafe fn generate_all_class_and_method(&mut self) {
+    pub unsafe fn generate_all_class_and_method(&mut self) -> CResult<()> {
         let mut asm_envs = vec![];
         let mut asms = FxHashMap::default();
 
@@ -448,27 +673,117 @@ impl<'a> JITCompiler<'a> {
             .image
             .collect_all_reachable_assemblies(&mut asms);
 
+        // ``Type.get_Assembly`` finds the assemblies of classes by their names. The modules of an
+        // assembly share the name of the one with the manifest.
+        self.shared_env.runtime_assembly(self.assembly);
+        for asm in asms.values() {
+            let asm = asm.borrow();
+            if asm.image.parent_assembly.is_none() {
+                self.shared_env.runtime_assembly(&asm);
+            }
+        }
+
+        // Methods are registered before any class is defined so that the classes inheriting
+        // methods from other assemblies or modules use the functions of their owners
         for (_name, asmref) in &asms {
             let mut asm = asmref.borrow_mut();
             let mut compiler =
                 JITCompiler::new_without_mscorlib_init(&mut *asm, &mut self.shared_env);
-            compiler.define_all_class();
             compiler.define_all_method();
             asm_envs.push(compiler.asm_env);
         }
 
-        for (asm_env, (_name, asmref)) in asm_envs.into_iter().zip(asms.iter()) {
+        // The other modules of this assembly call the methods of this module through
+        // ``shared_env``
+        let has_modules = !self.assembly.image.modules.is_empty();
+        if has_modules {
+            self.define_all_method();
+        }
+
+        for (asm_env, (_name, asmref)) in asm_envs.iter_mut().zip(asms.iter()) {
+            let mut asm = asmref.borrow_mut();
+            let mut compiler =
+                JITCompiler::new_without_mscorlib_init(&mut *asm, &mut self.shared_env)
+                    .with_asm_env(::std::mem::replace(asm_env, AssemblyUniqueEnvironment::new()));
+            compiler.define_all_class();
+            *asm_env = compiler.asm_env;
+        }
+
+        if has_modules {
+            self.define_all_class();
+        }
+
+        for (asm_env, (name, asmref)) in asm_envs.into_iter().zip(asms.iter()) {
             let mut asmref = asmref.borrow_mut();
-            JITCompiler::new_without_mscorlib_init(&mut *asmref, &mut self.shared_env)
-                .with_asm_env(asm_env)
-                .generate_queued_methods();
+            let mut compiler =
+                JITCompiler::new_without_mscorlib_init(&mut *asmref, &mut self.shared_env)
+                    .with_asm_env(asm_env);
+            compiler.generate_queued_methods()?;
+            let asm_env = compiler.asm_env;
+            self.shared_env.asm_envs.insert(name.clone(), asm_env);
+        }
+        Ok(())
+    }
+
+    /// Compiles the instances of generic virtual methods that the images defining them are asked
+    /// for by the others, until no image asks for more
+    unsafe fn generate_other_image_methods(&mut self) -> CResult<()> {
+        let mut asms = FxHashMap::default();
+        self.assembly
+            .image
+            .collect_all_reachable_assemblies(&mut asms);
+
+        loop {
+            let requests =
+                ::std::mem::take(&mut self.shared_env.other_image_generic_virtual_methods);
+            if requests.is_empty() {
+                break;
+            }
+            for (method_table, id, def_ref, instance) in requests {
+                let instance_ref = Rc::new(RefCell::new(MethodInfo::MDef(instance)));
+                if self.assembly.image.defines_method(&def_ref) {
+                    let func = self.get_function_by_method(&instance_ref);
+                    self.shared_env
+                        .generic_virtual_methods
+                        .push((method_table, id, func));
+                    self.generate_queued_methods()?;
+                    continue;
+                }
+                let (name, asmref) = match asms
+                    .iter()
+                    .find(|(_, asm)| asm.borrow().image.defines_method(&def_ref))
+                {
+                    Some(asm) => asm,
+                    // Left to ``get_generic_virtual_method``, which throws MissingMethodException
+                    None => continue,
+                };
+                let asm_env = self
+                    .shared_env
+                    .asm_envs
+                    .remove(name)
+                    .unwrap_or_else(AssemblyUniqueEnvironment::new);
+                let mut asm = asmref.borrow_mut();
+                let mut compiler =
+                    JITCompiler::new_without_mscorlib_init(&mut asm, self.shared_env)
+                        .with_asm_env(asm_env);
+                let func = compiler.get_function_by_method(&instance_ref);
+                compiler
+                    .shared_env
+                    .generic_virtual_methods
+                    .push((method_table, id, func));
+                compiler.generate_queued_methods()?;
+                let asm_env = compiler.asm_env;
+                self.shared_env.asm_envs.insert(name.clone(), asm_env);
+            }
         }
+        Ok(())
     }
 
-    pub unsafe fn generate_queued_methods(&mut self) {
+    pub unsafe fn generate_queued_methods(&mut self) -> CResult<()> {
         while let Some((func, method)) = self.asm_env.compile_queue.pop_front() {
-            self.generate_func(func, &method);
+            self.generate_func(func, &method)?;
         }
+        Ok(())
     }
 
     unsafe fn get_local_ty(&mut self, id: usize) -> TypeId {
@@ -484,20 +799,10 @@ impl<'a> JITCompiler<'a> {
             return v.val;
         }
 
-        let func = self.generating.unwrap();
-        let builder = LLVMCreateBuilderInContext(self.shared_env.context);
-        let entry_bb = LLVMGetEntryBasicBlock(func);
-        let first_inst = LLVMGetFirstInstruction(entry_bb);
-
-        // A variable must be declared at the first point of entry block
-        if first_inst == ptr::null_mut() {
-            LLVMPositionBuilderAtEnd(builder, entry_bb);
-        } else {
-            LLVMPositionBuilderBefore(builder, first_inst);
-        }
-
-        let var = LLVMBuildAlloca(builder, ty.unwrap().to_llvmty(self), cstr0!());
-        let ty_id = self.shared_env.type_id(ty.unwrap());
+        let ty = self.instantiate_ty(ty.unwrap());
+        let llvm_ty = ty.to_llvmty(self);
+        let var = self.build_entry_alloca(llvm_ty);
+        let ty_id = self.shared_env.type_id(&ty);
 
         self.env.locals.insert(id, TypedValue::new(ty_id, var));
 
@@ -509,6 +814,17 @@ impl<'a> JITCompiler<'a> {
             return v.val;
         }
 
+        let ty = self.instantiate_ty(ty.unwrap());
+        let llvm_ty = ty.to_llvmty(self);
+        let var = self.build_entry_alloca(llvm_ty);
+        let ty_id = self.shared_env.type_id(&ty);
+
+        self.env.arguments.insert(id, TypedValue::new(ty_id, var));
+
+        var
+    }
+
+    unsafe fn build_entry_alloca(&mut self, ty: LLVMTypeRef) -> LLVMValueRef {
         let func = self.generating.unwrap();
         let builder = LLVMCreateBuilderInContext(self.shared_env.context);
         let entry_bb = LLVMGetEntryBasicBlock(func);
@@ -521,12 +837,7 @@ impl<'a> JITCompiler<'a> {
             LLVMPositionBuilderBefore(builder, first_inst);
         }
 
-        let var = LLVMBuildAlloca(builder, ty.unwrap().to_llvmty(self), cstr0!());
-        let ty_id = self.shared_env.type_id(ty.unwrap());
-
-        self.env.arguments.insert(id, TypedValue::new(ty_id, var));
-
-        var
+        LLVMBuildAlloca(builder, ty, cstr0!())
     }
 
     // Returns destination
@@ -556,7 +867,10 @@ impl<'a> JITCompiler<'a> {
 
         LLVMPositionBuilderAtEnd(self.shared_env.builder, bb.set_positioned().retrieve());
 
-        let phi_stack = self.build_phi_stack(cur_block!().start, init_stack);
+        let mut phi_stack = self.build_phi_stack(cur_block!().start, init_stack);
+        if let Some(exception) = self.get_caught_exception(cur_block!().start) {
+            phi_stack.push(exception);
+        }
         let stack = self.compile_bytecode(cur_block!(), phi_stack)?;
 
         match &cur_block!().kind {
@@ -570,7 +884,8 @@ impl<'a> JITCompiler<'a> {
                 Ok(d)
             }
             BrKind::UnconditionalJmp { destination } => {
-                let src_bb = self.get_basic_block(cur_block!().start).retrieve();
+                // Exception checks after calls may have split the block
+                let src_bb = LLVMGetInsertBlock(self.shared_env.builder);
                 self.phi_stack
                     .entry(*destination)
                     .or_insert(vec![])
@@ -578,7 +893,7 @@ impl<'a> JITCompiler<'a> {
                 Ok(*destination)
             }
             BrKind::ImplicitJmp { destination } => {
-                let src_bb = self.get_basic_block(cur_block!().start).retrieve();
+                let src_bb = LLVMGetInsertBlock(self.shared_env.builder);
                 self.phi_stack
                     .entry(*destination)
                     .or_insert(vec![])
@@ -639,7 +954,7 @@ impl<'a> JITCompiler<'a> {
             } else if ty.is_float() {
                 TypedValue::new(val1.ty, concat_idents!(LLVMBuild, $fop)(self.shared_env.builder,
                                 val1.val, val2.val, cstr0!()))
-            } else { unimplemented!("{:?}", ty) })
+            } else { unsupported!("{:?}", ty) })
         }}}
         #[rustfmt::skip]
         macro_rules! unaryop { ($iop:ident, $fop:ident) => {{
@@ -649,7 +964,7 @@ impl<'a> JITCompiler<'a> {
                 TypedValue::new(val.ty, concat_idents!(LLVMBuild, $iop)(self.shared_env.builder, val.val, cstr0!()))
             } else if ty.is_float() {
                 TypedValue::new(val.ty, concat_idents!(LLVMBuild, $fop)(self.shared_env.builder, val.val, cstr0!()))
-            } else { unimplemented!("{:?}", ty) });
+            } else { unsupported!("{:?}", ty) });
         }}}
         #[rustfmt::skip]
         macro_rules! push_i4 { ($n:expr) => {
@@ -664,12 +979,12 @@ impl<'a> JITCompiler<'a> {
             ))
         }}
         #[rustfmt::skip]
-        macro_rules! ldloc { ($n:expr) => {
-            stack.push(TypedValue::new(
-                self.get_local_ty($n),
-                LLVMBuildLoad(self.shared_env.builder, self.get_local($n, None), cstr0!()),
-            ))
-        }}
+        macro_rules! ldloc { ($n:expr) => {{
+            let ty_id = self.get_local_ty($n);
+            let ty = self.shared_env.ty_arena[ty_id].clone();
+            let val = LLVMBuildLoad(self.shared_env.builder, self.get_local($n, None), cstr0!());
+            stack.push(self.to_stack_value(val, &ty))
+        }}}
         #[rustfmt::skip]
         macro_rules! stloc { ($n:expr) => {{
             let val = self.get_local($n, None);
@@ -678,12 +993,12 @@ impl<'a> JITCompiler<'a> {
                     LLVMGetElementType(LLVMTypeOf(val))), val);
         }}; }
         #[rustfmt::skip]
-        macro_rules! ldarg { ($n:expr) => {
-            stack.push(TypedValue::new(
-                self.get_argument_ty($n),
-                LLVMBuildLoad(self.shared_env.builder, self.get_argument($n, None), cstr0!()),
-            ))
-        }}
+        macro_rules! ldarg { ($n:expr) => {{
+            let ty_id = self.get_argument_ty($n);
+            let ty = self.shared_env.ty_arena[ty_id].clone();
+            let val = LLVMBuildLoad(self.shared_env.builder, self.get_argument($n, None), cstr0!());
+            stack.push(self.to_stack_value(val, &ty))
+        }}}
         #[rustfmt::skip]
         macro_rules! starg { ($n:expr) => {{
             let val = self.get_argument($n, None);
@@ -692,25 +1007,58 @@ impl<'a> JITCompiler<'a> {
                     LLVMGetElementType(LLVMTypeOf(val))), val);
         }}; }
         #[rustfmt::skip]
-        macro_rules! conv { ($ty:expr) => {{
-            let val = stack.pop().unwrap().val;
+        macro_rules! ldloca { ($n:expr) => {{
+            let ty_id = self.get_local_ty($n);
+            let ty = self.shared_env.ty_arena[ty_id].clone();
             stack.push(TypedValue::new(
-                self.shared_env.type_id(&$ty),
-                {
-                    let llvm_ty = $ty.to_llvmty(self);
-                    self.typecast(val, llvm_ty)
-                }
+                self.shared_env.type_id(&Type::byref_ty(ty)), self.get_local($n, None),
             ))
-        }}; }
+        }}}
+        #[rustfmt::skip]
+        macro_rules! ldarga { ($n:expr) => {{
+            let ty_id = self.get_argument_ty($n);
+            let ty = self.shared_env.ty_arena[ty_id].clone();
+            stack.push(TypedValue::new(
+                self.shared_env.type_id(&Type::byref_ty(ty)), self.get_argument($n, None),
+            ))
+        }}}
+        #[rustfmt::skip]
+        macro_rules! ldind { ($llvm_ty:ident, $signed:expr, $ty:expr) => {
+            self.gen_instr_ldind(&mut stack, $llvm_ty(self.shared_env.context), $signed, $ty)
+        }}
+        #[rustfmt::skip]
+        macro_rules! stind { ($llvm_ty:ident) => {
+            self.gen_instr_stind(&mut stack, Some($llvm_ty(self.shared_env.context)))
+        }}
+        #[rustfmt::skip]
+        macro_rules! conv { ($ty:expr, $signed:expr) => {
+            self.gen_instr_conv(&mut stack, $ty, $signed)
+        }}
+        #[rustfmt::skip]
+        macro_rules! conv_ovf { ($ty:expr, $signed:expr, $signed_src:expr) => {
+            self.gen_instr_conv_ovf(&mut stack, $ty, $signed, $signed_src)
+        }}
 
         let code = &block.code;
 
-        for instr in code {
+        for (i, instr) in code.iter().enumerate() {
+            self.env.pc = block.start + i;
             match instr {
+                // Prefixes are only hints for this JIT
+                Instruction::Nop
+                | Instruction::Break
+                | Instruction::Unaligned(_)
+                | Instruction::Volatile
+                | Instruction::Tail
+                | Instruction::Readonly
+                | Instruction::No(_) => {}
+                Instruction::Constrained(token) => {
+                    self.env.constrained = Some(self.get_type_by_token(*token)?)
+                }
                 Instruction::Ldnull => push_i4!(0),
                 Instruction::Ldstr(us_offset) => self.create_new_string(
                     &mut stack,
-                    self.assembly.image.get_user_string(*us_offset).clone(),
+                    self.assembly.image.get_user_string(*us_offset).unwrap(),
                 ),
                 Instruction::Ldc_I4_M1 => push_i4!(0 - 1),
                 Instruction::Ldc_I4_0 => push_i4!(0),
@@ -724,69 +1072,204 @@ impl<'a> JITCompiler<'a> {
                 Instruction::Ldc_I4_8 => push_i4!(8),
                 Instruction::Ldc_I4_S(n) => push_i4!(*n),
                 Instruction::Ldc_I4(n) => push_i4!(*n),
+                Instruction::Ldc_I8(n) => stack.push(TypedValue::new(
+                    self.shared_env.type_id(&Type::i8_ty()),
+                    LLVMConstInt(LLVMInt64TypeInContext(self.shared_env.context), *n as u64, 1),
+                )),
+                // Floating-point values on the evaluation stack are always of type F
+                Instruction::Ldc_R4(f) => push_r8!(*f as f64),
                 Instruction::Ldc_R8(f) => push_r8!(*f),
                 Instruction::Ldloc_0 => ldloc!(0),
                 Instruction::Ldloc_1 => ldloc!(1),
                 Instruction::Ldloc_2 => ldloc!(2),
                 Instruction::Ldloc_3 => ldloc!(3),
                 Instruction::Ldloc_S(n) => ldloc!(*n as usize),
-                Instruction::Ldfld(token) => self.gen_instr_ldfld(&mut stack, *token),
-                Instruction::Ldelem_U1 => self.gen_instr_ldelem_i1(&mut stack),
-                Instruction::Ldelem_I1 => self.gen_instr_ldelem_i1(&mut stack),
-                Instruction::Ldelem_I4 => self.gen_instr_ldelem_i4(&mut stack),
-                Instruction::Ldelem_ref => self.gen_instr_ldelem_ref(&mut stack),
+                Instruction::Ldloc(n) => ldloc!(*n as usize),
+                Instruction::Ldloca_S(n) => ldloca!(*n as usize),
+                Instruction::Ldloca(n) => ldloca!(*n as usize),
+                Instruction::Ldfld(token) => self.gen_instr_ldfld(&mut stack, *token)?,
+                Instruction::Ldflda(token) => self.gen_instr_ldflda(&mut stack, *token)?,
+                Instruction::Ldsfld(token) => self.gen_instr_ldsfld(&mut stack, *token)?,
+                Instruction::Ldsflda(token) => self.gen_instr_ldsflda(&mut stack, *token)?,
+                // The element type is taken from the type of the array
+                Instruction::Ldelem_I1
+                | Instruction::Ldelem_U1
+                | Instruction::Ldelem_I2
+                | Instruction::Ldelem_U2
+                | Instruction::Ldelem_I4
+                | Instruction::Ldelem_U4
+                | Instruction::Ldelem_I8
+                | Instruction::Ldelem_I
+                | Instruction::Ldelem_R4
+                | Instruction::Ldelem_R8
+                | Instruction::Ldelem_ref
+                | Instruction::Ldelem(_) => self.gen_instr_ldelem(&mut stack),
+                Instruction::Ldelema(_) => self.gen_instr_ldelema(&mut stack),
+                Instruction::Ldind_I1 => ldind!(LLVMInt8TypeInContext, true, Type::i4_ty()),
+                Instruction::Ldind_U1 => ldind!(LLVMInt8TypeInContext, false, Type::i4_ty()),
+                Instruction::Ldind_I2 => ldind!(LLVMInt16TypeInContext, true, Type::i4_ty()),
+                Instruction::Ldind_U2 => ldind!(LLVMInt16TypeInContext, false, Type::i4_ty()),
+                Instruction::Ldind_I4 => ldind!(LLVMInt32TypeInContext, true, Type::i4_ty()),
+                Instruction::Ldind_U4 => ldind!(LLVMInt32TypeInContext, false, Type::i4_ty()),
+                Instruction::Ldind_I8 => ldind!(LLVMInt64TypeInContext, true, Type::i8_ty()),
+                Instruction::Ldind_I => ldind!(LLVMInt64TypeInContext, true, Type::i_ty()),
+                Instruction::Ldind_R4 => ldind!(LLVMFloatTypeInContext, true, Type::r8_ty()),
+                Instruction::Ldind_R8 => ldind!(LLVMDoubleTypeInContext, true, Type::r8_ty()),
+                Instruction::Ldind_ref => self.gen_instr_ldind_ref(&mut stack),
                 Instruction::Stloc_0 => stloc!(0),
                 Instruction::Stloc_1 => stloc!(1),
                 Instruction::Stloc_2 => stloc!(2),
                 Instruction::Stloc_3 => stloc!(3),
                 Instruction::Stloc_S(n) => stloc!(*n as usize),
-                Instruction::Stfld(token) => self.gen_instr_stfld(&mut stack, *token),
-                Instruction::Stelem_I1 => self.gen_instr_stelem_i1(&mut stack),
-                Instruction::Stelem_I4 => self.gen_instr_stelem_i4(&mut stack),
-                Instruction::Stelem_ref => self.gen_instr_stelem_ref(&mut stack),
+                Instruction::Stloc(n) => stloc!(*n as usize),
+                Instruction::Stfld(token) => self.gen_instr_stfld(&mut stack, *token)?,
+                Instruction::Stsfld(token) => self.gen_instr_stsfld(&mut stack, *token)?,
+                Instruction::Stelem_I
+                | Instruction::Stelem_I1
+                | Instruction::Stelem_I2
+                | Instruction::Stelem_I4
+                | Instruction::Stelem_I8
+                | Instruction::Stelem_R4
+                | Instruction::Stelem_R8 => self.gen_instr_stelem(&mut stack, false),
+                Instruction::Stelem_ref => self.gen_instr_stelem(&mut stack, true),
+                Instruction::Stelem(token) => {
+                    let is_ref = !self.get_type_by_token(*token)?.is_valuetype();
+                    self.gen_instr_stelem(&mut stack, is_ref)
+                }
+                Instruction::Stind_I1 => stind!(LLVMInt8TypeInContext),
+                Instruction::Stind_I2 => stind!(LLVMInt16TypeInContext),
+                Instruction::Stind_I4 => stind!(LLVMInt32TypeInContext),
+                Instruction::Stind_I8 => stind!(LLVMInt64TypeInContext),
+                Instruction::Stind_I => stind!(LLVMInt64TypeInContext),
+                Instruction::Stind_R4 => stind!(LLVMFloatTypeInContext),
+                Instruction::Stind_R8 => stind!(LLVMDoubleTypeInContext),
+                Instruction::Stind_ref => self.gen_instr_stind(&mut stack, None),
                 Instruction::Starg_S(n) => starg!(*n as usize),
+                Instruction::Starg(n) => starg!(*n as usize),
                 Instruction::Ldarg_0 => ldarg!(0),
                 Instruction::Ldarg_1 => ldarg!(1),
                 Instruction::Ldarg_2 => ldarg!(2),
                 Instruction::Ldarg_3 => ldarg!(3),
                 Instruction::Ldarg_S(n) => ldarg!(*n as usize),
+                Instruction::Ldarg(n) => ldarg!(*n as usize),
+                Instruction::Ldarga_S(n) => ldarga!(*n as usize),
+                Instruction::Ldarga(n) => ldarga!(*n as usize),
                 Instruction::Ldlen => self.gen_instr_ldlen(&mut stack),
-                Instruction::Conv_I4 => conv!(Type::i4_ty()),
-                Instruction::Conv_I8 => conv!(Type::i8_ty()),
-                Instruction::Conv_R8 => conv!(Type::r8_ty()),
+                Instruction::Conv_I1 => conv!(Type::i1_ty(), true),
+                Instruction::Conv_I2 => conv!(Type::i2_ty(), true),
+                Instruction::Conv_I4 => conv!(Type::i4_ty(), true),
+                Instruction::Conv_I8 => conv!(Type::i8_ty(), true),
+                Instruction::Conv_I => conv!(Type::i_ty(), true),
+                Instruction::Conv_U1 => conv!(Type::u1_ty(), false),
+                Instruction::Conv_U2 => conv!(Type::u2_ty(), false),
+                Instruction::Conv_U4 => conv!(Type::u4_ty(), false),
+                Instruction::Conv_U8 => conv!(Type::u8_ty(), false),
+                Instruction::Conv_U => conv!(Type::u_ty(), false),
+                Instruction::Conv_R4 => conv!(Type::r4_ty(), true),
+                Instruction::Conv_R8 => conv!(Type::r8_ty(), true),
                 Instruction::Conv_R_un => self.gen_instr_conv_r_un(&mut stack),
+                Instruction::Conv_ovf_I1 => conv_ovf!(Type::i1_ty(), true, true),
+                Instruction::Conv_ovf_I2 => conv_ovf!(Type::i2_ty(), true, true),
+                Instruction::Conv_ovf_I4 => conv_ovf!(Type::i4_ty(), true, true),
+                Instruction::Conv_ovf_I8 => conv_ovf!(Type::i8_ty(), true, true),
+                Instruction::Conv_ovf_I => conv_ovf!(Type::i_ty(), true, true),
+                Instruction::Conv_ovf_U1 => conv_ovf!(Type::u1_ty(), false, true),
+                Instruction::Conv_ovf_U2 => conv_ovf!(Type::u2_ty(), false, true),
+                Instruction::Conv_ovf_U4 => conv_ovf!(Type::u4_ty(), false, true),
+                Instruction::Conv_ovf_U8 => conv_ovf!(Type::u8_ty(), false, true),
+                Instruction::Conv_ovf_U => conv_ovf!(Type::u_ty(), false, true),
+                Instruction::Conv_ovf_I1_un => conv_ovf!(Type::i1_ty(), true, false),
+                Instruction::Conv_ovf_I2_un => conv_ovf!(Type::i2_ty(), true, false),
+                Instruction::Conv_ovf_I4_un => conv_ovf!(Type::i4_ty(), true, false),
+                Instruction::Conv_ovf_I8_un => conv_ovf!(Type::i8_ty(), true, false),
+                Instruction::Conv_ovf_I_un => conv_ovf!(Type::i_ty(), true, false),
+                Instruction::Conv_ovf_U1_un => conv_ovf!(Type::u1_ty(), false, false),
+                Instruction::Conv_ovf_U2_un => conv_ovf!(Type::u2_ty(), false, false),
+                Instruction::Conv_ovf_U4_un => conv_ovf!(Type::u4_ty(), false, false),
+                Instruction::Conv_ovf_U8_un => conv_ovf!(Type::u8_ty(), false, false),
+                Instruction::Conv_ovf_U_un => conv_ovf!(Type::u_ty(), false, false),
                 Instruction::Pop => {
                     stack.pop();
                 }
                 Instruction::Dup => {
                     stack.push(*stack.last().unwrap());
                 }
-                Instruction::Call(token) => self.gen_instr_call(&mut stack, *token),
-                Instruction::CallVirt(token) => self.gen_instr_callvirt(&mut stack, *token),
-                Instruction::Box(token) => self.gen_instr_box(&mut stack, *token),
-                Instruction::Newobj(token) => self.gen_instr_newobj(&mut stack, *token),
-                Instruction::Newarr(token) => self.gen_instr_newarr(&mut stack, *token),
+                Instruction::Call(token) => self.gen_instr_call(&mut stack, *token)?,
+                Instruction::CallVirt(token) => self.gen_instr_callvirt(&mut stack, *token)?,
+                Instruction::Calli(token) => self.gen_instr_calli(&mut stack, *token)?,
+                Instruction::Ldftn(token) => self.gen_instr_ldftn(&mut stack, *token)?,
+                Instruction::Ldvirtftn(token) => self.gen_instr_ldvirtftn(&mut stack, *token)?,
+                Instruction::Box(token) => self.gen_instr_box(&mut stack, *token)?,
+                Instruction::Unbox(token) => self.gen_instr_unbox(&mut stack, *token)?,
+                Instruction::Unbox_any(token) => self.gen_instr_unbox_any(&mut stack, *token)?,
+                Instruction::Initobj(token) => self.gen_instr_initobj(&mut stack, *token)?,
+                Instruction::Ldobj(token) => self.gen_instr_ldobj(&mut stack, *token)?,
+                Instruction::Stobj(token) => self.gen_instr_stobj(&mut stack, *token)?,
+                Instruction::Cpobj(token) => self.gen_instr_cpobj(&mut stack, *token)?,
+                Instruction::Sizeof(token) => self.gen_instr_sizeof(&mut stack, *token)?,
+                Instruction::Castclass(token) => self.gen_instr_castclass(&mut stack, *token)?,
+                Instruction::Isinst(token) => self.gen_instr_isinst(&mut stack, *token)?,
+                Instruction::Newobj(token) => self.gen_instr_newobj(&mut stack, *token)?,
+                Instruction::Newarr(token) => self.gen_instr_newarr(&mut stack, *token)?,
+                Instruction::Ldtoken(token) => self.gen_instr_ldtoken(&mut stack, *token)?,
+                Instruction::Localloc => self.gen_instr_localloc(&mut stack),
+                Instruction::Cpblk => self.gen_instr_cpblk(&mut stack),
+                Instruction::Initblk => self.gen_instr_initblk(&mut stack),
+                Instruction::Arglist => {
+                    let arglist = self.env.arglist.unwrap();
+                    stack.push(self.build_runtime_handle("RuntimeArgumentHandle", arglist))
+                }
+                Instruction::Mkrefany(token) => self.gen_instr_mkrefany(&mut stack, *token)?,
+                Instruction::Refanyval(token) => self.gen_instr_refanyval(&mut stack, *token)?,
+                Instruction::Refanytype => {
+                    let typed_ref = stack.pop().unwrap().val;
+                    let method_table =
+                        LLVMBuildExtractValue(self.shared_env.builder, typed_ref, 1, cstr0!());
+                    stack.push(self.build_runtime_handle("RuntimeTypeHandle", method_table))
+                }
                 Instruction::Add => binop!(Add, FAdd),
                 Instruction::Sub => binop!(Sub, FSub),
                 Instruction::Mul => binop!(Mul, FMul),
-                Instruction::Div => binop!(SDiv, FDiv),
-                Instruction::Rem => binop!(SRem, FRem),
-                Instruction::Rem_un => binop!(URem, FRem),
+                Instruction::Div => {
+                    self.build_divisor_check(stack.last().unwrap().val);
+                    let len = stack.len();
+                    self.build_division_overflow_check(stack[len - 2].val, stack[len - 1].val);
+                    binop!(SDiv, FDiv)
+                }
+                Instruction::Div_un => {
+                    self.build_divisor_check(stack.last().unwrap().val);
+                    binop!(UDiv, FDiv)
+                }
+                Instruction::Rem => {
+                    self.build_divisor_check(stack.last().unwrap().val);
+                    let divisor = stack.last_mut().unwrap();
+                    divisor.val = self.replace_minus_one_divisor(divisor.val);
+                    binop!(SRem, FRem)
+                }
+                Instruction::Rem_un => {
+                    self.build_divisor_check(stack.last().unwrap().val);
+                    binop!(URem, FRem)
+                }
+                Instruction::Add_ovf => self.gen_instr_ovf_binop(&mut stack, "add", true)?,
+                Instruction::Add_ovf_un => self.gen_instr_ovf_binop(&mut stack, "add", false)?,
+                Instruction::Sub_ovf => self.gen_instr_ovf_binop(&mut stack, "sub", true)?,
+                Instruction::Sub_ovf_un => self.gen_instr_ovf_binop(&mut stack, "sub", false)?,
+                Instruction::Mul_ovf => self.gen_instr_ovf_binop(&mut stack, "mul", true)?,
+                Instruction::Mul_ovf_un => self.gen_instr_ovf_binop(&mut stack, "mul", false)?,
+                Instruction::And => binop!(And, And),
+                Instruction::Or => binop!(Or, Or),
                 Instruction::Xor => binop!(Xor, Xor),
                 Instruction::Shl => binop!(Shl, Shl),
                 Instruction::Shr => binop!(AShr, AShr),
                 Instruction::Shr_un => binop!(LShr, LShr),
                 Instruction::Neg => unaryop!(Neg, FNeg),
-                Instruction::Ret => {
-                    let ret_ty =
-                        LLVMGetElementType(LLVMGetReturnType(LLVMTypeOf(self.generating.unwrap())));
-                    if LLVMGetTypeKind(ret_ty) == llvm::LLVMTypeKind::LLVMVoidTypeKind {
-                        LLVMBuildRetVoid(self.shared_env.builder);
-                    } else {
-                        let val = stack.pop().unwrap().val;
-                        LLVMBuildRet(self.shared_env.builder, self.typecast(val, ret_ty));
-                    }
+                Instruction::Not => unaryop!(Not, Not),
+                Instruction::Ckfinite => {
+                    let val = stack.last().unwrap().val;
+                    self.build_finite_check(val);
                 }
+                Instruction::Ret => self.gen_instr_ret(&mut stack),
+                Instruction::Jmp(token) => self.gen_instr_jmp(*token)?,
                 Instruction::Brfalse { .. } | Instruction::Brtrue { .. } => {
                     let val1 = stack.pop().unwrap();
                     let cond_val = LLVMBuildICmp(
@@ -808,11 +1291,13 @@ impl<'a> JITCompiler<'a> {
                 Instruction::Bge { .. }
                 | Instruction::Bge_un { .. }
                 | Instruction::Blt { .. }
+                | Instruction::Blt_un { .. }
                 | Instruction::Ble { .. }
                 | Instruction::Ble_un { .. }
                 | Instruction::Beq { .. }
                 | Instruction::Bne_un { .. }
-                | Instruction::Bgt { .. } => {
+                | Instruction::Bgt { .. }
+                | Instruction::Bgt_un { .. } => {
                     let val2 = stack.pop().unwrap();
                     let val1 = stack.pop().unwrap();
                     let ty = &self.shared_env.ty_arena[val1.ty];
@@ -823,9 +1308,11 @@ impl<'a> JITCompiler<'a> {
                                 Instruction::Bge { .. } => llvm::LLVMIntPredicate::LLVMIntSGE,
                                 Instruction::Bge_un { .. } => llvm::LLVMIntPredicate::LLVMIntUGE,
                                 Instruction::Blt { .. } => llvm::LLVMIntPredicate::LLVMIntSLT,
+                                Instruction::Blt_un { .. } => llvm::LLVMIntPredicate::LLVMIntULT,
                                 Instruction::Ble { .. } => llvm::LLVMIntPredicate::LLVMIntSLE,
                                 Instruction::Ble_un { .. } => llvm::LLVMIntPredicate::LLVMIntULE,
                                 Instruction::Bgt { .. } => llvm::LLVMIntPredicate::LLVMIntSGT,
+                                Instruction::Bgt_un { .. } => llvm::LLVMIntPredicate::LLVMIntUGT,
                                 Instruction::Beq { .. } => llvm::LLVMIntPredicate::LLVMIntEQ,
                                 Instruction::Bne_un { .. } => llvm::LLVMIntPredicate::LLVMIntNE,
                                 _ => unreachable!(),
@@ -838,14 +1325,17 @@ impl<'a> JITCompiler<'a> {
                         LLVMBuildFCmp(
                             self.shared_env.builder,
                             match instr {
+                                // *.un branches are taken if the values are unordered (NaN)
                                 Instruction::Bge { .. } => llvm::LLVMRealPredicate::LLVMRealOGE,
-                                Instruction::Bge_un { .. } => llvm::LLVMRealPredicate::LLVMRealOGE,
+                                Instruction::Bge_un { .. } => llvm::LLVMRealPredicate::LLVMRealUGE,
                                 Instruction::Blt { .. } => llvm::LLVMRealPredicate::LLVMRealOLT,
+                                Instruction::Blt_un { .. } => llvm::LLVMRealPredicate::LLVMRealULT,
                                 Instruction::Ble { .. } => llvm::LLVMRealPredicate::LLVMRealOLE,
-                                Instruction::Ble_un { .. } => llvm::LLVMRealPredicate::LLVMRealOLE,
+                                Instruction::Ble_un { .. } => llvm::LLVMRealPredicate::LLVMRealULE,
                                 Instruction::Bgt { .. } => llvm::LLVMRealPredicate::LLVMRealOGT,
+                                Instruction::Bgt_un { .. } => llvm::LLVMRealPredicate::LLVMRealUGT,
                                 Instruction::Beq { .. } => llvm::LLVMRealPredicate::LLVMRealOEQ,
-                                Instruction::Bne_un { .. } => llvm::LLVMRealPredicate::LLVMRealONE,
+                                Instruction::Bne_un { .. } => llvm::LLVMRealPredicate::LLVMRealUNE,
                                 _ => unreachable!(),
                             },
                             val1.val,
@@ -853,13 +1343,32 @@ impl<'a> JITCompiler<'a> {
                             cstr0!(),
                         )
                     } else {
-                        unimplemented!("{:?}", ty)
+                        unsupported!("{:?}", ty)
                     };
                     let destinations = block.kind.get_conditional_jump_destinations();
                     let bb_then = self.get_basic_block(destinations[0]).retrieve();
                     let bb_else = self.get_basic_block(destinations[1]).retrieve();
                     LLVMBuildCondBr(self.shared_env.builder, cond_val, bb_then, bb_else);
                 }
+                Instruction::Switch(_) => {
+                    let val = stack.pop().unwrap().val;
+                    let destinations = block.kind.get_conditional_jump_destinations();
+                    let (default, targets) = destinations.split_last().unwrap();
+                    let bb_default = self.get_basic_block(*default).retrieve();
+                    let switch = LLVMBuildSwitch(
+                        self.shared_env.builder,
+                        val,
+                        bb_default,
+                        targets.len() as u32,
+                    );
+                    for (i, target) in targets.iter().enumerate() {
+                        LLVMAddCase(
+                            switch,
+                            LLVMConstInt(LLVMTypeOf(val), i as u64, 0),
+                            self.get_basic_block(*target).retrieve(),
+                        );
+                    }
+                }
                 Instruction::Br { .. } => {
                     let destination = block.kind.get_unconditional_jump_destination();
                     let bb_br = self.get_basic_block(destination).retrieve();
@@ -867,24 +1376,77 @@ impl<'a> JITCompiler<'a> {
                         LLVMBuildBr(self.shared_env.builder, bb_br);
                     }
                 }
-                Instruction::Clt | Instruction::Cgt | Instruction::Ceq => {
+                Instruction::Leave(target) => {
+                    // ``leave`` empties the evaluation stack
+                    stack.clear();
+                    let continuation = self
+                        .env
+                        .leave_continuations
+                        .get(&self.env.pc)
+                        .cloned()
+                        .unwrap_or(FinallyContinuation::Leave { target: *target });
+                    self.build_finally_continuation(continuation);
+                }
+                Instruction::Throw => {
+                    let exception = stack.pop().unwrap().val;
+                    self.build_throw(exception);
+                }
+                Instruction::Rethrow => {
+                    let pc = self.env.pc;
+                    let clause = self.find_innermost_clause(|c| {
+                        !c.is_finally_or_fault() && c.handler_contains(pc)
+                    });
+                    let (_, exception_slot) = self.env.clause_slots[&clause];
+                    let exception =
+                        LLVMBuildLoad(self.shared_env.builder, exception_slot, cstr0!());
+                    self.build_throw(exception);
+                }
+                Instruction::Endfinally => self.gen_instr_endfinally(),
+                Instruction::Endfilter => {
+                    let val = stack.pop().unwrap().val;
+                    self.gen_instr_endfilter(val)
+                }
+                Instruction::Clt
+                | Instruction::Clt_un
+                | Instruction::Cgt
+                | Instruction::Cgt_un
+                | Instruction::Ceq => {
                     let val2 = stack.pop().unwrap();
                     let val1 = stack.pop().unwrap();
-                    let cond_val = self.typecast(
+                    let ty = &self.shared_env.ty_arena[val1.ty];
+                    let cond_val = if ty.is_float() {
+                        LLVMBuildFCmp(
+                            self.shared_env.builder,
+                            match instr {
+                                Instruction::Cgt { .. } => llvm::LLVMRealPredicate::LLVMRealOGT,
+                                Instruction::Cgt_un { .. } => llvm::LLVMRealPredicate::LLVMRealUGT,
+                                Instruction::Clt { .. } => llvm::LLVMRealPredicate::LLVMRealOLT,
+                                Instruction::Clt_un { .. } => llvm::LLVMRealPredicate::LLVMRealULT,
+                                Instruction::Ceq { .. } => llvm::LLVMRealPredicate::LLVMRealOEQ,
+                                _ => unreachable!(),
+                            },
+                            val1.val,
+                            val2.val,
+                            cstr0!(),
+                        )
+                    } else {
                         LLVMBuildICmp(
                             self.shared_env.builder,
                             match instr {
                                 Instruction::Cgt { .. } => llvm::LLVMIntPredicate::LLVMIntSGT,
+                                Instruction::Cgt_un { .. } => llvm::LLVMIntPredicate::LLVMIntUGT,
                                 Instruction::Clt { .. } => llvm::LLVMIntPredicate::LLVMIntSLT,
+                                Instruction::Clt_un { .. } => llvm::LLVMIntPredicate::LLVMIntULT,
                                 Instruction::Ceq { .. } => llvm::LLVMIntPredicate::LLVMIntEQ,
                                 _ => unreachable!(),
                             },
                             val1.val,
-                            val2.val,
+                            self.typecast(val2.val, LLVMTypeOf(val1.val)),
                             cstr0!(),
-                        ),
-                        LLVMInt32TypeInContext(self.shared_env.context),
-                    );
+                        )
+                    };
+                    let cond_val =
+                        self.typecast(cond_val, LLVMInt32TypeInContext(self.shared_env.context));
                     stack.push(TypedValue::new(
                         self.shared_env.type_id(&Type::i4_ty()),
                         cond_val,
@@ -896,6 +1458,16 @@ impl<'a> JITCompiler<'a> {
         Ok(stack)
     }
 
+    unsafe fn gen_instr_ret(&mut self, stack: &mut Vec<TypedValue>) {
+        let ret_ty = LLVMGetElementType(LLVMGetReturnType(LLVMTypeOf(self.generating.unwrap())));
+        if LLVMGetTypeKind(ret_ty) == llvm::LLVMTypeKind::LLVMVoidTypeKind {
+            LLVMBuildRetVoid(self.shared_env.builder);
+        } else {
+            let val = stack.pop().unwrap().val;
+            LLVMBuildRet(self.shared_env.builder, self.typecast(val, ret_ty));
+        }
+    }
+
     unsafe fn create_new_string(&mut self, stack: &mut Vec<TypedValue>, s: Vec<u16>) {
         let class_system_string_ref = mscorlib_system_string();
         let class_system_string = class_system_string_ref.borrow();
@@ -925,43 +1497,110 @@ impl<'a> JITCompiler<'a> {
         ));
     }
 
-    unsafe fn gen_instr_call(&mut self, stack: &mut Vec<TypedValue>, token: Token) {
+    unsafe fn gen_instr_call(&mut self, stack: &mut Vec<TypedValue>, token: Token) -> CResult<()> {
         self.gen_instr_general_call(stack, token, false)
     }
 
-    unsafe fn gen_instr_callvirt(&mut self, stack: &mut Vec<TypedValue>, token: Token) {
+    unsafe fn gen_instr_callvirt(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
         self.gen_instr_general_call(stack, token, true)
     }
 
-    unsafe fn get_function_by_rva(&mut self, rva: u32) -> LLVMValueRef {
-        if let Some(f) = self.asm_env.generated.get(&rva) {
-            return *f;
+    /// Returns the llvm function that calls ``method_ref``, which may also be a method whose
+    /// code the runtime provides (see ``get_runtime_method``)
+    unsafe fn get_function_to_call(&mut self, method_ref: &MethodInfoRef) -> CResult<LLVMValueRef> {
+        if !method_ref.borrow().as_mdef().is_runtime_impl() {
+            return Ok(self.get_function_by_method(method_ref));
         }
+        match self.get_runtime_method(method_ref) {
+            Some(func) => Ok(func),
+            None => unsupported!("runtime method: {}", method_ref.borrow().get_name()),
+        }
+    }
 
-        let method_ref = self.assembly.image.get_method_by_rva(rva).unwrap();
-        let method_info = method_ref.borrow();
-        let method = method_info.as_mdef();
-        let method_sig = method.ty.as_fnptr().unwrap();
+    /// Returns the llvm function for ``method_ref``, which may be an instance of a generic method
+    /// or a method of a generic class instance. The function is declared and queued to be
+    /// compiled at the first request.
+    unsafe fn get_function_by_method(&mut self, method_ref: &MethodInfoRef) -> LLVMValueRef {
+        let (rva, instance_name) = {
+            let method_info = method_ref.borrow();
+            let method = method_info.as_mdef();
+            let class = method.class.borrow();
+            if class.generic_args.is_empty() && method.generic_args.is_empty() {
+                (method.rva, None)
+            } else {
+                let name = format!(
+                    "{}<{}>",
+                    class.get_full_name(),
+                    type_list_name(&method.generic_args)
+                );
+                (method.rva, Some(name))
+            }
+        };
 
-        let ret_ty = method_sig.ret.to_llvmty(self);
-        let mut params_ty = method_sig
-            .params
-            .iter()
-            .map(|ty| ty.to_llvmty(self))
-            .collect::<Vec<LLVMTypeRef>>();
+        match instance_name {
+            None => {
+                if let Some(f) = self.get_function_of_other_image(method_ref) {
+                    return f;
+                }
+                if let Some(f) = self.asm_env.generated.get(&rva) {
+                    return *f;
+                }
+                let func = self.declare_function(method_ref);
+                self.asm_env.generated.insert(rva, func);
+                func
+            }
+            Some(name) => {
+                let key = (rva, name);
+                if let Some(f) = self.asm_env.generated_instances.get(&key) {
+                    return *f;
+                }
+                let func = self.declare_function(method_ref);
+                self.asm_env.generated_instances.insert(key, func);
+                func
+            }
+        }
+    }
 
-        if method_sig.has_this() {
-            params_ty.insert(0, self.get_llvm_class_type(&method.class.borrow()))
+    /// Returns the function registered in ``shared_env`` for ``method_ref`` if it is defined in
+    /// another assembly or module. Its body refers to the metadata of that image, so it must not
+    /// be compiled here.
+    unsafe fn get_function_of_other_image(
+        &mut self,
+        method_ref: &MethodInfoRef,
+    ) -> Option<LLVMValueRef> {
+        if self.assembly.image.defines_method(method_ref) {
+            return None;
         }
+        let method_info = method_ref.borrow();
+        let method = method_info.as_mdef();
+        let class = method.class.borrow();
+        let method_path = ((&*class).into(): TypePath).with_method_name(method.name.as_str());
+        let f = self.shared_env.methods.get_method(method_path, &method.ty)?;
+        Some(f.llvm_function)
+    }
 
-        let func_ty = LLVMFunctionType(ret_ty, params_ty.as_mut_ptr(), params_ty.len() as u32, 0);
+    /// Adds the llvm function for ``method_ref`` and queues it to be compiled
+    unsafe fn declare_function(&mut self, method_ref: &MethodInfoRef) -> LLVMValueRef {
+        let method_info = method_ref.borrow();
+        let method = method_info.as_mdef();
+        let method_sig = method.ty.as_fnptr().unwrap();
+
+        let this_ty = if method_sig.has_implicit_this() {
+            Some(get_this_ty(&method.class).to_llvmty(self))
+        } else {
+            None
+        };
+        let func_ty = self.get_llvm_function_type(method_sig, this_ty);
         let func = LLVMAddFunction(
             self.shared_env.module,
             CString::new(method.name.as_str()).unwrap().as_ptr(),
             func_ty,
         );
 
-        self.asm_env.generated.insert(rva, func);
         self.asm_env
             .compile_queue
             .push_back((func, method_ref.clone()));
@@ -969,37 +1608,201 @@ impl<'a> JITCompiler<'a> {
         func
     }
 
-    unsafe fn gen_instr_general_call(
-        &mut self,
-        stack: &mut Vec<TypedValue>,
-        token: Token,
-        is_virtual: bool,
-    ) {
-        unsafe fn call(
-            compiler: &mut JITCompiler,
-            stack: &mut Vec<TypedValue>,
-            func: LLVMValueRef,
-            msig: &MethodSignature,
-        ) {
-            let (_, args) = get_arg_vals_from_stack(stack, msig.params.len(), msig.has_this());
-            let ret = compiler.call_function(func, args);
-            if !msig.ret.is_void() {
-                stack.push(TypedValue::new(compiler.shared_env.type_id(&msig.ret), ret));
-            }
-        };
+    /// Returns the function provided by the runtime for ``method_ref``, or None if the method is
+    /// not supported (e.g. ``BeginInvoke`` of delegates)
+    unsafe fn get_runtime_method(&mut self, method_ref: &MethodInfoRef) -> Option<LLVMValueRef> {
+        let name = method_ref.borrow().get_name().to_string();
+        match name.as_str() {
+            ".ctor" => Some(
+                self.shared_env
+                    .methods
+                    .get_helper_function("delegate_ctor")
+                    .unwrap()
+                    .llvm_function,
+            ),
+            "Invoke" => Some(self.get_delegate_invoke(method_ref)),
+            _ => None,
+        }
+    }
 
-        unsafe fn callvirt(
-            compiler: &mut JITCompiler,
-            stack: &mut Vec<TypedValue>,
-            method_idx: usize,
-            method_sig: &MethodSignature,
-            method_ty: LLVMTypeRef,
+    /// Returns ``Invoke`` of a delegate type. It calls the methods in the invocation list in
+    /// order, and returns the result of the last one.
+    unsafe fn get_delegate_invoke(&mut self, method_ref: &MethodInfoRef) -> LLVMValueRef {
+        let method_info = method_ref.borrow();
+        let method = method_info.as_mdef();
+        let method_sig = method.ty.as_fnptr().unwrap();
+        let class_ref = method.class.borrow();
+        let class = &*class_ref;
+        if let Some(func) = self.shared_env.delegate_invokes.get(class.into(): TypePath) {
+            return *func;
+        }
+
+        let this_ty = self.get_llvm_class_type(class);
+        let func_ty = self.get_llvm_function_type(method_sig, Some(this_ty));
+        let func = LLVMAddFunction(
+            self.shared_env.module,
+            CString::new(format!("{}.Invoke", class.get_full_name()))
+                .unwrap()
+                .as_ptr(),
+            func_ty,
+        );
+        self.shared_env
+            .delegate_invokes
+            .add(class.into(): TypePath, func);
+
+        // Borrow the global builder and give it back at the end
+        let ctx = self.shared_env.context;
+        let builder = self.shared_env.builder;
+        let cur_bb = LLVMGetInsertBlock(builder);
+        let bb_entry = LLVMAppendBasicBlockInContext(ctx, func, cstr0!());
+        let bb_loop = LLVMAppendBasicBlockInContext(ctx, func, cstr0!());
+        let bb_static = LLVMAppendBasicBlockInContext(ctx, func, cstr0!());
+        let bb_instance = LLVMAppendBasicBlockInContext(ctx, func, cstr0!());
+        let bb_next = LLVMAppendBasicBlockInContext(ctx, func, cstr0!());
+        let bb_exit = LLVMAppendBasicBlockInContext(ctx, func, cstr0!());
+
+        let this = LLVMGetParam(func, 0);
+        let args = (1..LLVMCountParams(func))
+            .map(|i| LLVMGetParam(func, i))
+            .collect::<Vec<LLVMValueRef>>();
+
+        LLVMPositionBuilderAtEnd(builder, bb_entry);
+        let count = self.call_helper("delegate_invocation_count", vec![this]);
+        LLVMBuildBr(builder, bb_loop);
+
+        LLVMPositionBuilderAtEnd(builder, bb_loop);
+        let i = LLVMBuildPhi(builder, LLVMInt32TypeInContext(ctx), cstr0!());
+        let item = self.call_helper("delegate_invocation_item", vec![this, i]);
+        let target = self.call_helper("delegate_target", vec![item]);
+        let raw_method = self.call_helper("delegate_method", vec![item]);
+        let is_static = LLVMBuildIsNull(builder, target, cstr0!());
+        LLVMBuildCondBr(builder, is_static, bb_static, bb_instance);
+
+        LLVMPositionBuilderAtEnd(builder, bb_static);
+        let static_ty = self.get_llvm_function_type(method_sig, None);
+        let static_method = self.typecast(raw_method, LLVMPointerType(static_ty, 0));
+        let static_ret = self.call_function(static_method, args.clone());
+        LLVMBuildBr(builder, bb_next);
+
+        LLVMPositionBuilderAtEnd(builder, bb_instance);
+        let instance_ty = self.get_llvm_function_type(method_sig, Some(LLVMTypeOf(target)));
+        let instance_method = self.typecast(raw_method, LLVMPointerType(instance_ty, 0));
+        let mut instance_args = args;
+        instance_args.insert(0, target);
+        let instance_ret = self.call_function(instance_method, instance_args);
+        LLVMBuildBr(builder, bb_next);
+
+        // Stop at the first exception
+        LLVMPositionBuilderAtEnd(builder, bb_next);
+        let ret = if method_sig.ret.is_void() {
+            None
+        } else {
+            let ret = LLVMBuildPhi(builder, LLVMTypeOf(static_ret), cstr0!());
+            LLVMAddIncoming(
+                ret,
+                vec![static_ret, instance_ret].as_mut_ptr(),
+                vec![bb_static, bb_instance].as_mut_ptr(),
+                2,
+            );
+            Some(ret)
+        };
+        let next_i = LLVMBuildAdd(builder, i, self.llvm_int32(1), cstr0!());
+        LLVMAddIncoming(
+            i,
+            vec![self.llvm_int32(0), next_i].as_mut_ptr(),
+            vec![bb_entry, bb_next].as_mut_ptr(),
+            2,
+        );
+        let pending = self.call_helper("exception_pending", vec![]);
+        let no_exception = LLVMBuildICmp(
+            builder,
+            llvm::LLVMIntPredicate::LLVMIntEQ,
+            pending,
+            LLVMConstNull(LLVMTypeOf(pending)),
+            cstr0!(),
+        );
+        let has_next = LLVMBuildICmp(
+            builder,
+            llvm::LLVMIntPredicate::LLVMIntSLT,
+            next_i,
+            count,
+            cstr0!(),
+        );
+        let cond = LLVMBuildAnd(builder, no_exception, has_next, cstr0!());
+        LLVMBuildCondBr(builder, cond, bb_loop, bb_exit);
+
+        LLVMPositionBuilderAtEnd(builder, bb_exit);
+        match ret {
+            Some(ret) => LLVMBuildRet(builder, ret),
+            None => LLVMBuildRetVoid(builder),
+        };
+
+        if !cur_bb.is_null() {
+            LLVMPositionBuilderAtEnd(builder, cur_bb);
+        }
+
+        func
+    }
+
+    unsafe fn get_llvm_function_type(
+        &mut self,
+        method_sig: &MethodSignature,
+        this_ty: Option<LLVMTypeRef>,
+    ) -> LLVMTypeRef {
+        let ret_ty = method_sig.ret.to_llvmty(self);
+        let mut params_ty = method_sig
+            .params
+            .iter()
+            .map(|ty| ty.to_llvmty(self))
+            .collect::<Vec<LLVMTypeRef>>();
+
+        if let Some(this_ty) = this_ty {
+            params_ty.insert(0, this_ty)
+        }
+
+        // The arguments passed after the sentinel
+        if method_sig.is_vararg() {
+            params_ty.push(LLVMPointerType(LLVMInt8TypeInContext(self.shared_env.context), 0))
+        }
+
+        LLVMFunctionType(ret_ty, params_ty.as_mut_ptr(), params_ty.len() as u32, 0)
+    }
+
+    unsafe fn gen_instr_general_call(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+        is_virtual: bool,
+    ) -> CResult<()> {
+        unsafe fn call(
+            compiler: &mut JITCompiler,
+            stack: &mut Vec<TypedValue>,
+            func: LLVMValueRef,
+            msig: &MethodSignature,
+        ) {
+            let (_, args) =
+                get_arg_vals_from_stack(stack, msig.params.len(), msig.has_implicit_this());
+            let ret = compiler.call_function(func, args);
+            compiler.build_exception_check();
+            if !msig.ret.is_void() {
+                let ret_ty = compiler.instantiate_ty(&msig.ret);
+                stack.push(compiler.to_stack_value(ret, &ret_ty));
+            }
+        };
+
+        unsafe fn callvirt(
+            compiler: &mut JITCompiler,
+            stack: &mut Vec<TypedValue>,
+            method_idx: usize,
+            method_sig: &MethodSignature,
+            method_ty: LLVMTypeRef,
         ) {
             let (obj_ty_id, args) = get_arg_vals_from_stack(stack, method_sig.params.len(), true);
             let obj_llvm_ty = compiler.shared_env.ty_arena[obj_ty_id.unwrap()]
                 .clone()
                 .to_llvmty(compiler);
             let obj = compiler.typecast(args[0], obj_llvm_ty);
+            compiler.build_null_check(obj);
 
             let method_table =
                 compiler.load_element(obj, vec![compiler.llvm_int32(0), compiler.llvm_int32(0)]);
@@ -1008,17 +1811,98 @@ impl<'a> JITCompiler<'a> {
             let vmethod = compiler.typecast(raw_vmethod, method_ty);
 
             let ret = compiler.call_function(vmethod, args);
+            compiler.build_exception_check();
             if !method_sig.ret.is_void() {
-                stack.push(TypedValue::new(
-                    compiler.shared_env.type_id(&method_sig.ret),
-                    ret,
-                ));
+                let ret_ty = compiler.instantiate_ty(&method_sig.ret);
+                stack.push(compiler.to_stack_value(ret, &ret_ty));
+            }
+        };
+
+        unsafe fn callvirt_interface(
+            compiler: &mut JITCompiler,
+            stack: &mut Vec<TypedValue>,
+            interface: &ClassInfo,
+            name: &str,
+            ty: &Type,
+        ) {
+            let method_sig = ty.as_fnptr().unwrap();
+            let (_, args) = get_arg_vals_from_stack(stack, method_sig.params.len(), true);
+            compiler.build_null_check(args[0]);
+
+            let interface_ty = compiler.get_llvm_class_type(interface);
+            let (_, interface_method_table) = compiler.ensure_all_class_methods_compiled(interface);
+            let slot = interface.get_interface_method_index(name, ty).unwrap();
+            let raw_method = compiler.call_helper(
+                "get_interface_method",
+                vec![args[0], interface_method_table, compiler.llvm_int32(slot as u64)],
+            );
+            let method_ty = compiler.get_llvm_function_type(method_sig, Some(interface_ty));
+            let method = compiler.typecast(raw_method, LLVMPointerType(method_ty, 0));
+
+            let ret = compiler.call_function(method, args);
+            compiler.build_exception_check();
+            if !method_sig.ret.is_void() {
+                let ret_ty = compiler.instantiate_ty(&method_sig.ret);
+                stack.push(compiler.to_stack_value(ret, &ret_ty));
+            }
+        };
+
+        unsafe fn call_method(
+            compiler: &mut JITCompiler,
+            stack: &mut Vec<TypedValue>,
+            method_ref: &MethodInfoRef,
+            is_virtual: bool,
+        ) -> CResult<()> {
+            let method_info = method_ref.borrow();
+            let method = method_info.as_mdef();
+            let method_sig = method.ty.as_fnptr().unwrap();
+            let class = method.class.borrow();
+            if is_virtual && class.is_interface() {
+                callvirt_interface(compiler, stack, &class, method.name.as_str(), &method.ty);
+            // Instances of generic methods have no slot in method tables
+            } else if is_virtual && method.is_virtual() && !method.generic_args.is_empty() {
+                let this_ty = compiler.get_llvm_class_type(&class);
+                let method_ty = compiler.get_llvm_function_type(method_sig, Some(this_ty));
+                let this = stack[stack.len() - method_sig.params.len() - 1].val;
+                compiler.build_null_check(this);
+                let id = compiler.ensure_generic_virtual_method_compiled(method);
+                let raw_method = compiler.call_helper(
+                    "get_generic_virtual_method",
+                    vec![this, compiler.llvm_int32(id as u64)],
+                );
+                compiler.build_exception_check();
+                let func = compiler.typecast(raw_method, LLVMPointerType(method_ty, 0));
+                call(compiler, stack, func, method_sig);
+            } else if is_virtual && method.generic_args.is_empty() {
+                // The callee may be abstract, so the type is made from the signature
+                let this_ty = compiler.get_llvm_class_type(&class);
+                let method_ty = compiler.get_llvm_function_type(method_sig, Some(this_ty));
+                callvirt(
+                    compiler,
+                    stack,
+                    class.get_method_index(&method.name, &method.ty).unwrap(),
+                    method_sig,
+                    LLVMPointerType(method_ty, 0),
+                );
+            } else {
+                if method.is_static() && !class.is_before_field_init() {
+                    compiler.build_type_initialization(&class);
+                }
+                let func = compiler.get_function_to_call(method_ref)?;
+                call(compiler, stack, func, method_sig);
             }
+            Ok(())
         };
 
+        if let Some(ty) = self.env.constrained.take() {
+            if let Some(method_ref) = self.resolve_constrained_callvirt(stack, ty, token)? {
+                return call_method(self, stack, &method_ref, false);
+            }
+        }
+
         match self.assembly.image.metadata.get_table_entry(token).unwrap() {
             Table::MemberRef(mrt) => {
-                let class_token = mrt.class2token();
+                let class_token = mrt.class2token().unwrap();
                 let class = &self
                     .assembly
                     .image
@@ -1026,378 +1910,2822 @@ impl<'a> JITCompiler<'a> {
                     .get_table_entry(class_token)
                     .unwrap();
                 match class {
+                    Table::TypeRef(_)
+                        if is_virtual
+                            && self
+                                .assembly
+                                .image
+                                .get_class(class_token)
+                                .map_or(false, |class| class.borrow().is_interface()) =>
+                    {
+                        let interface = self.assembly.image.get_class(class_token).unwrap().clone();
+                        let name = self.assembly.image.get_string(mrt.name).unwrap().to_string();
+                        let ty = self
+                            .assembly
+                            .image
+                            .get_method_ref_type_from_signature(mrt.signature)
+                            .unwrap();
+                        callvirt_interface(self, stack, &interface.borrow(), &name, &ty);
+                        Ok(())
+                    }
                     Table::TypeRef(trt) => {
-                        let type_path = self.assembly.image.get_path_from_type_ref_table(trt);
-                        let name = self.assembly.image.get_string(mrt.name);
+                        let type_path =
+                            self.assembly.image.get_path_from_type_ref_table(trt).unwrap();
+                        let name = self.assembly.image.get_string(mrt.name).unwrap();
                         let ty = self
                             .assembly
                             .image
-                            .get_method_ref_type_from_signature(mrt.signature);
-                        let f = match self
+                            .get_method_ref_type_from_signature(mrt.signature)
+                            .unwrap();
+                        if type_path.0.as_slice() == ["mscorlib", "System", "Array"] {
+                            let name = name.to_string();
+                            let params_len = ty.as_fnptr().unwrap().params.len();
+                            return self.gen_system_array_method(stack, name.as_str(), params_len);
+                        }
+                        if type_path.0.as_slice() == ["mscorlib", "System.Reflection", "Assembly"]
+                            && name == "GetExecutingAssembly"
+                        {
+                            self.gen_get_executing_assembly(stack);
+                            return Ok(());
+                        }
+                        if type_path.0.as_slice()
+                            == ["mscorlib", "System.Runtime.CompilerServices", "RuntimeHelpers"]
+                            && name == "InitializeArray"
+                        {
+                            self.gen_initialize_array(stack);
+                            return Ok(());
+                        }
+                        if type_path.0.as_slice() == ["mscorlib", "System", "ArgIterator"] {
+                            let name = name.to_string();
+                            return self.gen_arg_iterator_method(stack, name.as_str());
+                        }
+                        let runtime_method = self
+                            .assembly
+                            .image
+                            .get_class(class_token)
+                            .and_then(|class| find_runtime_method(&class.borrow(), name, &ty));
+                        if let Some(method_ref) = runtime_method {
+                            return call_method(self, stack, &method_ref, is_virtual);
+                        }
+                        let llvm_f = match self
                             .shared_env
                             .methods
                             .get_method(type_path.with_method_name(&name), &ty)
                         {
-                            Some(f) => f,
-                            None => return,
+                            Some(f) => f.llvm_function,
+                            None => return Ok(()),
                         };
+                        let midx = self
+                            .assembly
+                            .image
+                            .get_class(class_token)
+                            .and_then(|class| class.borrow().get_method_index(name, &ty));
                         let method_sig = ty.as_fnptr().unwrap();
+                        if method_sig.has_implicit_this() {
+                            self.box_this_by_ref(stack, method_sig.params.len())?;
+                        }
                         if is_virtual {
-                            let llvm_f_ty = LLVMTypeOf(f.llvm_function);
-                            let midx =
-                                (self.assembly.image.get_class(class_token).unwrap().borrow())
-                                    .get_method_index(name)
-                                    .unwrap();
-                            callvirt(self, stack, midx, method_sig, llvm_f_ty);
+                            let llvm_f_ty = LLVMTypeOf(llvm_f);
+                            callvirt(self, stack, midx.unwrap(), method_sig, llvm_f_ty);
                         } else {
-                            let llvm_f = f.llvm_function;
                             call(self, stack, llvm_f, method_sig);
                         }
+                        Ok(())
                     }
-                    _ => unimplemented!(),
-                }
-            }
-            Table::MethodDef(mdt) => {
-                let func = self.get_function_by_rva(mdt.rva);
-                let method_ref = self.assembly.image.get_method_by_rva(mdt.rva).unwrap();
-                let method = method_ref.borrow();
-                let method_sig = method.as_mdef().ty.as_fnptr().unwrap();
-                if is_virtual {
-                    callvirt(
-                        self,
-                        stack,
-                        method
-                            .get_class()
-                            .borrow()
-                            .get_method_index(method.get_name())
-                            .unwrap(),
-                        method_sig,
-                        LLVMTypeOf(func),
-                    );
-                } else {
-                    call(self, stack, func, method_sig);
+                    Table::TypeSpec(tst) => {
+                        if let Some(arr_ty) = self.get_array_type_from_type_spec(&tst) {
+                            return self.gen_array_method(stack, arr_ty, &mrt);
+                        }
+                        let method_ref = self.get_generic_inst_method(&mrt, &tst);
+                        call_method(self, stack, &method_ref, is_virtual)
+                    }
+                    // Calls to vararg methods give the types of the arguments after the sentinel
+                    Table::MethodDef(_) => {
+                        let method_ref = self.assembly.image.get_method(class_token).unwrap();
+                        let ty = self
+                            .assembly
+                            .image
+                            .get_method_ref_type_from_signature(mrt.signature)
+                            .unwrap();
+                        self.gen_vararg_call(stack, &method_ref, ty.as_fnptr().unwrap())
+                    }
+                    e => unsupported!("{:?}", e),
+                }
+            }
+            Table::MethodDef(_) => {
+                let method_ref = self.assembly.image.get_method(token).unwrap();
+                let method_ty = method_ref.borrow().get_ty().clone();
+                if method_ty.as_fnptr().unwrap().is_vararg() {
+                    return self.gen_vararg_call(stack, &method_ref, method_ty.as_fnptr().unwrap());
                 }
+                call_method(self, stack, &method_ref, is_virtual)
             }
-            e => unimplemented!("{:?}", e),
+            Table::MethodSpec(mst) => {
+                if let Some((func, method_sig)) = self.get_builtin_method_spec_instance(&mst) {
+                    call(self, stack, func, &method_sig);
+                    return Ok(());
+                }
+                let method_ref = self.get_method_spec_instance(&mst)?;
+                call_method(self, stack, &method_ref, is_virtual)
+            }
+            e => unsupported!("{:?}", e),
         }
     }
 
-    unsafe fn gen_instr_stfld(&mut self, stack: &mut Vec<TypedValue>, token: Token) {
-        let val = stack.pop().unwrap();
+    /// Prepares ``this`` of ``callvirt token`` prefixed by ``constrained. ty``, which is the
+    /// address of a value of ``ty``. If the value type ``ty`` implements the method itself, the
+    /// method is returned to be called directly with the address. Otherwise ``this`` is replaced
+    /// with the boxed value (or the reference that the address holds) for a usual virtual call.
+    unsafe fn resolve_constrained_callvirt(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        ty: Type,
+        token: Token,
+    ) -> CResult<Option<MethodInfoRef>> {
+        let (name, method_ty, declaring_class) = self.get_method_outline(token)?;
+        let this_idx = stack.len() - method_ty.as_fnptr().unwrap().params.len() - 1;
+
+        if let Some(class_ref) = ty.as_valuetype() {
+            let class = class_ref.borrow();
+            let declaring_class = declaring_class.borrow();
+            let slot = if declaring_class.is_interface() {
+                class
+                    .interface_maps
+                    .iter()
+                    .find(|map| *map.interface.borrow() == *declaring_class)
+                    .and_then(|map| {
+                        let slot = declaring_class.get_interface_method_index(&name, &method_ty)?;
+                        Some(map.slots[slot])
+                    })
+            } else {
+                class.get_method_index(&name, &method_ty)
+            };
+            if let Some(method_ref) = slot.map(|slot| class.method_table[slot].clone()) {
+                let implemented = match &*method_ref.borrow() {
+                    MethodInfo::MDef(m) => *m.class.borrow() == *class,
+                    MethodInfo::MRef(_) => false,
+                };
+                if implemented {
+                    return Ok(Some(method_ref));
+                }
+            }
+        }
+
+        let addr = stack[this_idx].val;
+        let llvm_ty = ty.to_llvmty(self);
+        let ptr = LLVMBuildBitCast(
+            self.shared_env.builder,
+            addr,
+            LLVMPointerType(llvm_ty, 0),
+            cstr0!(),
+        );
+        let val = TypedValue::new(
+            self.shared_env.type_id(&ty),
+            LLVMBuildLoad(self.shared_env.builder, ptr, cstr0!()),
+        );
+        stack[this_idx] = self.box_value(val, &ty)?;
+        Ok(None)
+    }
+
+    /// Builtin methods of mscorlib value types (e.g. ``Int32.ToString``) take boxed ``this``.
+    /// Boxes ``this`` under the ``params_len`` arguments if it is the address of such a value.
+    unsafe fn box_this_by_ref(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        params_len: usize,
+    ) -> CResult<()> {
+        let this_idx = stack.len() - params_len - 1;
+        let this = stack[this_idx];
+        let ty = match self.shared_env.ty_arena[this.ty].as_pointee() {
+            Some(ty) => ty.clone(),
+            None => return Ok(()),
+        };
+        if get_boxed_class(&ty)?.is_none() {
+            return Ok(());
+        }
+        let val = TypedValue::new(
+            self.shared_env.type_id(&ty),
+            LLVMBuildLoad(self.shared_env.builder, this.val, cstr0!()),
+        );
+        stack[this_idx] = self.box_value(val, &ty)?;
+        Ok(())
+    }
+
+    /// Calls the vararg method ``method_ref`` with the arguments that ``site_sig`` gives. The
+    /// arguments after the fixed ones are passed as a block of their typed references preceded by
+    /// their count, which ``arglist`` gives to the callee.
+    unsafe fn gen_vararg_call(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        method_ref: &MethodInfoRef,
+        site_sig: &MethodSignature,
+    ) -> CResult<()> {
+        let method_info = method_ref.borrow();
+        let method = method_info.as_mdef();
+        let method_sig = method.ty.as_fnptr().unwrap();
+        let extra_tys = site_sig.params[method_sig.params.len()..].to_vec();
+        let extra_args = stack.split_off(stack.len() - extra_tys.len());
+
+        let ctx = self.shared_env.context;
+        let typed_ref_ty = Type::new(ElementType::TypedByRef).to_llvmty(self);
+        let mut block_elems_ty = [
+            LLVMInt64TypeInContext(ctx),
+            LLVMArrayType(typed_ref_ty, extra_tys.len() as u32),
+        ];
+        let block_ty = LLVMStructTypeInContext(ctx, block_elems_ty.as_mut_ptr(), 2, 0);
+        let block = self.build_entry_alloca(block_ty);
+        self.store2element(
+            block,
+            vec![self.llvm_int32(0), self.llvm_int32(0)],
+            LLVMConstInt(LLVMInt64TypeInContext(ctx), extra_tys.len() as u64, 0),
+        );
+        for (i, (arg, ty)) in extra_args.iter().zip(extra_tys.iter()).enumerate() {
+            let ty = self.instantiate_ty(ty);
+            let llvm_ty = ty.to_llvmty(self);
+            let slot = self.build_entry_alloca(llvm_ty);
+            LLVMBuildStore(self.shared_env.builder, self.typecast(arg.val, llvm_ty), slot);
+            let method_table = self.get_method_table_of_type(&ty)?;
+            let typed_ref = self.build_typed_ref(slot, method_table);
+            self.store2element(
+                block,
+                vec![self.llvm_int32(0), self.llvm_int32(1), self.llvm_int32(i as u64)],
+                typed_ref,
+            );
+        }
+
+        let class = method.class.borrow();
+        if method.is_static() && !class.is_before_field_init() {
+            self.build_type_initialization(&class);
+        }
+        let func = self.get_function_to_call(method_ref)?;
+        let (_, mut args) = get_arg_vals_from_stack(
+            stack,
+            method_sig.params.len(),
+            method_sig.has_implicit_this(),
+        );
+        args.push(block);
+        let ret = self.call_function(func, args);
+        self.build_exception_check();
+        if !method_sig.ret.is_void() {
+            let ret_ty = self.instantiate_ty(&method_sig.ret);
+            stack.push(self.to_stack_value(ret, &ret_ty));
+        }
+        Ok(())
+    }
+
+    /// ``jmp`` transfers the arguments of the current method to the method ``token`` as a tail
+    /// call. The signatures of the two methods must be the same; otherwise
+    /// ``InvalidProgramException`` is thrown. ``jmp`` is not allowed in protected blocks, so an
+    /// exception thrown by the callee simply goes to the caller of the current method.
+    unsafe fn gen_instr_jmp(&mut self, token: Token) -> CResult<()> {
+        let builder = self.shared_env.builder;
+        let (callee, callee_ty) = self.get_function_by_token(token)?;
+        let callee_sig = callee_ty.as_fnptr().unwrap();
+        let method_ty = self.env.method_ty.clone().unwrap();
+        let method_sig = method_ty.as_fnptr().unwrap();
+        let is_same_sig = method_sig.has_implicit_this() == callee_sig.has_implicit_this()
+            && method_sig.is_vararg() == callee_sig.is_vararg()
+            && self.instantiate_ty(&method_sig.ret) == self.instantiate_ty(&callee_sig.ret)
+            && method_sig.params.len() == callee_sig.params.len()
+            && method_sig
+                .params
+                .iter()
+                .zip(callee_sig.params.iter())
+                .all(|(p1, p2)| self.instantiate_ty(p1) == self.instantiate_ty(p2));
+        if !is_same_sig {
+            let always = LLVMConstInt(LLVMInt1TypeInContext(self.shared_env.context), 1, 0);
+            self.build_throw_if(always, mscorlib_system_invalid_program_exception());
+            LLVMBuildUnreachable(builder);
+            return Ok(());
+        }
+
+        let mut args = (0..self.env.arguments.len())
+            .map(|i| LLVMBuildLoad(builder, self.get_argument(i, None), cstr0!()))
+            .collect::<Vec<LLVMValueRef>>();
+        args.extend(self.env.arglist);
+        let ret = self.call_function(callee, args);
+        LLVMSetTailCall(ret, 1);
+        let ret_ty = LLVMGetElementType(LLVMGetReturnType(LLVMTypeOf(self.generating.unwrap())));
+        if LLVMGetTypeKind(ret_ty) == llvm::LLVMTypeKind::LLVMVoidTypeKind {
+            LLVMBuildRetVoid(builder);
+        } else {
+            LLVMBuildRet(builder, self.typecast(ret, ret_ty));
+        }
+        Ok(())
+    }
+
+    /// Returns the name, the signature and the declaring class of the method that ``token``
+    /// refers to. The signature of an instance of a generic method is the one of the definition.
+    unsafe fn get_method_outline(&mut self, token: Token) -> CResult<(String, Type, ClassInfoRef)> {
+        let method_ref = match self.assembly.image.metadata.get_table_entry(token).unwrap() {
+            Table::MemberRef(mrt) => {
+                let name = self.assembly.image.get_string(mrt.name).unwrap().to_string();
+                let ty = self
+                    .assembly
+                    .image
+                    .get_method_ref_type_from_signature(mrt.signature)
+                    .unwrap();
+                let class_token = mrt.class2token().unwrap();
+                let class = match self.assembly.image.metadata.get_table_entry(class_token) {
+                    Some(Table::TypeSpec(tst)) => self.get_class_from_type_spec(&tst),
+                    _ => self.assembly.image.get_class(class_token).unwrap().clone(),
+                };
+                let ctx = GenericContext {
+                    class_args: class.borrow().generic_args.clone(),
+                    method_args: vec![],
+                };
+                return Ok((name, ty.instantiate(&ctx), class));
+            }
+            Table::MethodDef(_) => self.assembly.image.get_method(token).unwrap(),
+            Table::MethodSpec(mst) => {
+                return self.get_method_outline(mst.method_decoded().unwrap().into())
+            }
+            e => unsupported!("{:?}", e),
+        };
+        let method_info = method_ref.borrow();
+        let method = method_info.as_mdef();
+        Ok((method.name.clone(), method.ty.clone(), method.class.clone()))
+    }
+
+    unsafe fn gen_instr_ldftn(&mut self, stack: &mut Vec<TypedValue>, token: Token) -> CResult<()> {
+        let (func, ty) = self.get_function_by_token(token)?;
+        stack.push(TypedValue::new(self.shared_env.type_id(&ty), func));
+        Ok(())
+    }
+
+    /// Compiles the instance of a generic virtual method for ``method.generic_args`` in every
+    /// class that derives from the declaring class of ``method``, which is an instance of the
+    /// method, and returns the id to look up the instance for an object at runtime. The classes
+    /// whose method tables are built later compile it when they are built.
+    unsafe fn ensure_generic_virtual_method_compiled(&mut self, method: &MethodDefInfo) -> usize {
+        let class = method.class.borrow();
+        let ctx = GenericContext {
+            class_args: vec![],
+            method_args: method.generic_args.clone(),
+        };
+        let slot = class
+            .method_table
+            .iter()
+            .position(|m| {
+                let m = m.borrow();
+                m.get_name() == method.name && m.get_ty().instantiate(&ctx) == method.ty
+            })
+            .unwrap();
+        self.get_llvm_class_type(&class);
+        let (method_table, _) = self.ensure_all_class_methods_compiled(&class);
+        let key = (method_table, slot, type_list_name(&method.generic_args));
+        if let Some(id) = self.shared_env.generic_virtual_method_ids.get(&key) {
+            return *id;
+        }
+        let id = self.shared_env.generic_virtual_method_instances.len();
+        self.shared_env.generic_virtual_method_ids.insert(key, id);
+        self.shared_env.generic_virtual_method_instances.push((
+            class.clone(),
+            slot,
+            method.generic_args.clone(),
+        ));
+
+        // Classes whose method tables are built from now on are not in ``method_table_classes``
+        // yet, and compile the instance themselves
+        for i in 0..self.shared_env.method_table_classes.len() {
+            let (method_table, derived) = self.shared_env.method_table_classes[i].clone();
+            self.compile_generic_virtual_method_instance(&derived, method_table, id);
+        }
+        id
+    }
+
+    /// Compiles the instance of the generic virtual method ``id`` that the objects of ``class``
+    /// call, if ``class`` derives from the declaring class of the method
+    unsafe fn compile_generic_virtual_method_instance(
+        &mut self,
+        class: &ClassInfo,
+        method_table: MethodTablePtrTy,
+        id: usize,
+    ) {
+        let (declaring, slot, args) = self.shared_env.generic_virtual_method_instances[id].clone();
+        if class.is_interface() || !class.is_assignable_to(&declaring) {
+            return;
+        }
+        let def_ref = class.method_table[slot].clone();
+        let instance = match &*def_ref.borrow() {
+            MethodInfo::MDef(m) if m.has_body() => m.instantiate(&args),
+            _ => return,
+        };
+        if !self.assembly.image.defines_method(&def_ref) {
+            return self
+                .shared_env
+                .other_image_generic_virtual_methods
+                .push((method_table, id, def_ref, instance));
+        }
+        let func = self.get_function_by_method(&Rc::new(RefCell::new(MethodInfo::MDef(instance))));
+        self.shared_env
+            .generic_virtual_methods
+            .push((method_table, id, func));
+    }
+
+    /// Pushes the function that ``callvirt token`` would call on the object on the stack
+    unsafe fn gen_instr_ldvirtftn(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
         let obj = stack.pop().unwrap();
-        match self.assembly.image.metadata.get_table_entry(token).unwrap() {
-            Table::Field(f) => {
-                let name = self.assembly.image.get_string(f.name);
-                let class = &self.shared_env.ty_arena[obj.ty]
-                    .as_class()
-                    .unwrap()
-                    .borrow();
-                let idx = class.get_field_index(name).unwrap() + /*method_table=*/1;
-                self.store2element(
-                    obj.val,
-                    vec![self.llvm_int32(0), self.llvm_int32(idx as u64)],
-                    val.val,
-                )
+        self.build_null_check(obj.val);
+
+        let (name, ty, class_ref) = self.get_method_outline(token)?;
+        let class = class_ref.borrow();
+        let is_method_spec = matches!(
+            self.assembly.image.metadata.get_table_entry(token),
+            Some(Table::MethodSpec(_))
+        );
+        // Instances of generic methods and non-virtual methods have no slot in method tables
+        let slot = if class.is_interface() {
+            class
+                .get_interface_method_index(&name, &ty)
+                .map(|slot| (slot, class.get_interface_methods()[slot].clone()))
+        } else if is_method_spec {
+            None
+        } else {
+            class
+                .get_method_index(&name, &ty)
+                .map(|slot| (slot, class.method_table[slot].clone()))
+        };
+        let (slot, method_ref) = match slot {
+            Some(slot) => slot,
+            None if is_method_spec => {
+                let method_ref = match self.assembly.image.metadata.get_table_entry(token) {
+                    Some(Table::MethodSpec(mst)) => self.get_method_spec_instance(&mst)?,
+                    _ => unreachable!(),
+                };
+                let method_info = method_ref.borrow();
+                let method = method_info.as_mdef();
+                if !method.is_virtual() {
+                    return self.gen_instr_ldftn(stack, token);
+                }
+                let id = self.ensure_generic_virtual_method_compiled(method);
+                let func = self.call_helper(
+                    "get_generic_virtual_method",
+                    vec![obj.val, self.llvm_int32(id as u64)],
+                );
+                self.build_exception_check();
+                let ty_id = self.shared_env.type_id(&method.ty);
+                stack.push(TypedValue::new(ty_id, func));
+                return Ok(());
             }
-            e => unimplemented!("{:?}", e),
+            None => return self.gen_instr_ldftn(stack, token),
+        };
+        let ty = match &*method_ref.borrow() {
+            MethodInfo::MDef(m) => m.ty.clone(),
+            MethodInfo::MRef(m) => m.ty.clone(),
+        };
+
+        let class_ty = self.get_llvm_class_type(&class);
+        let func = if class.is_interface() {
+            let (_, interface_method_table) = self.ensure_all_class_methods_compiled(&class);
+            self.call_helper(
+                "get_interface_method",
+                vec![obj.val, interface_method_table, self.llvm_int32(slot as u64)],
+            )
+        } else {
+            let obj = self.typecast(obj.val, class_ty);
+            let method_table = self.load_element(obj, vec![self.llvm_int32(0), self.llvm_int32(0)]);
+            self.load_element(method_table, vec![self.llvm_int32(slot as u64)])
+        };
+        stack.push(TypedValue::new(self.shared_env.type_id(&ty), func));
+        Ok(())
+    }
+
+    /// Calls the function on the stack with the signature that ``token`` (StandAloneSig) refers
+    /// to
+    unsafe fn gen_instr_calli(&mut self, stack: &mut Vec<TypedValue>, token: Token) -> CResult<()> {
+        let ty = match self.assembly.image.metadata.get_table_entry(token).unwrap() {
+            Table::StandAloneSig(sast) => self
+                .assembly
+                .image
+                .get_method_ref_type_from_signature(sast.signature)
+                .unwrap(),
+            e => unsupported!("{:?}", e),
+        };
+        let ty = self.instantiate_ty(&ty);
+        let method_sig = ty.as_fnptr().unwrap();
+
+        let func = stack.pop().unwrap();
+        let this = method_sig.has_implicit_this();
+        let (_, args) = get_arg_vals_from_stack(stack, method_sig.params.len(), this);
+        let func_ty = ty.to_llvmty(self);
+        let func = self.typecast(func.val, func_ty);
+        let ret = self.call_function(func, args);
+        self.build_exception_check();
+        if !method_sig.ret.is_void() {
+            stack.push(self.to_stack_value(ret, &method_sig.ret));
         }
+        Ok(())
     }
 
-    unsafe fn gen_instr_ldfld(&mut self, stack: &mut Vec<TypedValue>, token: Token) {
+    /// Returns the function of the method that ``token`` refers to and the type of the method
+    unsafe fn get_function_by_token(&mut self, token: Token) -> CResult<(LLVMValueRef, Type)> {
+        let method_ref = match self.assembly.image.metadata.get_table_entry(token).unwrap() {
+            Table::MethodDef(_) => self.assembly.image.get_method(token).unwrap(),
+            Table::MethodSpec(mst) => self.get_method_spec_instance(&mst)?,
+            Table::MemberRef(mrt) => {
+                let class_token = mrt.class2token().unwrap();
+                match self.assembly.image.metadata.get_table_entry(class_token) {
+                    Some(Table::TypeSpec(tst)) => self.get_generic_inst_method(&mrt, &tst),
+                    Some(Table::TypeRef(trt)) => {
+                        let type_path =
+                            self.assembly.image.get_path_from_type_ref_table(&trt).unwrap();
+                        let name = self.assembly.image.get_string(mrt.name).unwrap();
+                        let ty = self
+                            .assembly
+                            .image
+                            .get_method_ref_type_from_signature(mrt.signature)
+                            .unwrap();
+                        let func = self
+                            .shared_env
+                            .methods
+                            .get_method(type_path.with_method_name(name), &ty)
+                            .unwrap()
+                            .llvm_function;
+                        return Ok((func, ty));
+                    }
+                    e => unsupported!("{:?}", e),
+                }
+            }
+            e => unsupported!("{:?}", e),
+        };
+        let method_info = method_ref.borrow();
+        let method = method_info.as_mdef();
+        // Delegates of value types hold boxed values as the targets
+        let func = if !method.is_static() && method.class.borrow().is_valuetype() {
+            self.get_unboxing_stub(&method_ref)
+        } else {
+            self.get_function_to_call(&method_ref)?
+        };
+        Ok((func, method.ty.clone()))
+    }
+
+    unsafe fn gen_instr_stfld(&mut self, stack: &mut Vec<TypedValue>, token: Token) -> CResult<()> {
+        let val = stack.pop().unwrap();
         let obj = stack.pop().unwrap();
-        match self.assembly.image.metadata.get_table_entry(token).unwrap() {
-            Table::Field(f) => {
-                let name = self.assembly.image.get_string(f.name);
-                let (idx, ty) = self.shared_env.ty_arena[obj.ty]
-                    .as_class()
-                    .unwrap()
-                    .borrow()
-                    .fields
-                    .iter()
-                    .enumerate()
-                    .find(|(_, f)| &f.name == name)
-                    .map(|(i, f)| (i, f.ty.clone()))
-                    .unwrap();
-                stack.push(TypedValue::new(
-                    self.shared_env.type_id(&ty),
-                    self.load_element(
-                        obj.val,
-                        vec![
-                            self.llvm_int32(0),
-                            self.llvm_int32(idx as u64 + /*method_table=*/1),
-                        ],
-                    ),
-                ));
+        let (ptr, _) = self.get_instance_field(obj, token)?;
+        LLVMBuildStore(
+            self.shared_env.builder,
+            self.typecast(val.val, LLVMGetElementType(LLVMTypeOf(ptr))),
+            ptr,
+        );
+        Ok(())
+    }
+
+    unsafe fn gen_instr_ldfld(&mut self, stack: &mut Vec<TypedValue>, token: Token) -> CResult<()> {
+        let obj = stack.pop().unwrap();
+        let (ptr, ty) = self.get_instance_field(obj, token)?;
+        let val = LLVMBuildLoad(self.shared_env.builder, ptr, cstr0!());
+        stack.push(self.to_stack_value(val, &ty));
+        Ok(())
+    }
+
+    unsafe fn gen_instr_ldflda(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
+        let obj = stack.pop().unwrap();
+        let (ptr, ty) = self.get_instance_field(obj, token)?;
+        stack.push(TypedValue::new(
+            self.shared_env.type_id(&Type::byref_ty(ty)),
+            ptr,
+        ));
+        Ok(())
+    }
+
+    /// Returns the pointer to the instance field of ``obj`` that ``token`` refers to and the
+    /// type of the field
+    unsafe fn get_instance_field(
+        &mut self,
+        obj: TypedValue,
+        token: Token,
+    ) -> CResult<(LLVMValueRef, Type)> {
+        let name = match self.assembly.image.metadata.get_table_entry(token).unwrap() {
+            Table::Field(f) => self.assembly.image.get_string(f.name).unwrap().to_string(),
+            // Fields of generic classes
+            Table::MemberRef(mrt) => self.assembly.image.get_string(mrt.name).unwrap().to_string(),
+            e => unsupported!("{:?}", e),
+        };
+        let obj_ty = self.shared_env.ty_arena[obj.ty].clone();
+        // Fields of a value type are reached through the address of the value, which has no
+        // method table. A value on the stack is spilled to a temporary.
+        let (class, obj, first_field) = if let Some(class) = obj_ty.as_valuetype() {
+            let tmp = self.build_entry_alloca(LLVMTypeOf(obj.val));
+            LLVMBuildStore(self.shared_env.builder, obj.val, tmp);
+            (class.clone(), tmp, 0)
+        } else if let Some(class) = obj_ty.as_pointee().and_then(|ty| ty.as_valuetype()) {
+            self.build_null_check(obj.val);
+            let value_ty = self.get_llvm_value_type(&class.borrow());
+            let ptr = LLVMBuildBitCast(
+                self.shared_env.builder,
+                obj.val,
+                LLVMPointerType(value_ty, 0),
+                cstr0!(),
+            );
+            (class.clone(), ptr, 0)
+        } else {
+            self.build_null_check(obj.val);
+            (obj_ty.as_class().unwrap().clone(), obj.val, /*method_table=*/1)
+        };
+        let (idx, ty) = class
+            .borrow()
+            .collect_all_fields()
+            .into_iter()
+            .enumerate()
+            .rev()
+            .find(|(_, f)| f.name == name)
+            .map(|(i, f)| (i, f.ty))
+            .unwrap();
+        let ty = self.instantiate_ty(&ty);
+        let ptr = LLVMBuildGEP(
+            self.shared_env.builder,
+            obj,
+            vec![self.llvm_int32(0), self.llvm_int32(idx as u64 + first_field)]
+            .as_mut_ptr(),
+            2,
+            cstr0!(),
+        );
+        Ok((ptr, ty))
+    }
+
+    unsafe fn gen_instr_ldsfld(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
+        let (ptr, ty) = self.get_static_field(token)?;
+        let val = LLVMBuildLoad(self.shared_env.builder, ptr, cstr0!());
+        stack.push(self.to_stack_value(val, &ty));
+        Ok(())
+    }
+
+    unsafe fn gen_instr_ldsflda(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
+        let (ptr, ty) = self.get_static_field(token)?;
+        stack.push(TypedValue::new(
+            self.shared_env.type_id(&Type::byref_ty(ty)),
+            ptr,
+        ));
+        Ok(())
+    }
+
+    unsafe fn gen_instr_stsfld(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
+        let val = stack.pop().unwrap();
+        let (ptr, _) = self.get_static_field(token)?;
+        LLVMBuildStore(
+            self.shared_env.builder,
+            self.typecast(val.val, LLVMGetElementType(LLVMTypeOf(ptr))),
+            ptr,
+        );
+        Ok(())
+    }
+
+    /// Returns the pointer to the static field ``token`` refers to and the type of the field.
+    /// The type initializer of the class runs first if necessary.
+    unsafe fn get_static_field(&mut self, token: Token) -> CResult<(LLVMValueRef, Type)> {
+        let field = self.assembly.image.metadata.get_table_entry(token).unwrap();
+        let (class_ref, name) = match field {
+            Table::Field(ft) => (
+                self.assembly.image.get_field_owner(token).unwrap().clone(),
+                self.assembly.image.get_string(ft.name).unwrap().to_string(),
+            ),
+            Table::MemberRef(mrt) => {
+                let class_token = mrt.class2token().unwrap();
+                let class = self.assembly.image.metadata.get_table_entry(class_token).unwrap();
+                let class_ref = match class {
+                    // Static fields of generic classes
+                    Table::TypeSpec(tst) => self.get_class_from_type_spec(&tst),
+                    _ => self.assembly.image.get_class(class_token).unwrap().clone(),
+                };
+                (class_ref, self.assembly.image.get_string(mrt.name).unwrap().to_string())
+            }
+            e => unsupported!("{:?}", e),
+        };
+        let class = class_ref.borrow();
+
+        self.build_type_initialization(&class);
+
+        let idx = class.get_static_field_index(name.as_str()).unwrap();
+        let ty = self.instantiate_ty(&class.static_fields[idx].ty);
+        let storage = self.get_static_storage(&class);
+        let ptr = LLVMBuildGEP(
+            self.shared_env.builder,
+            storage,
+            vec![
+                self.llvm_int32(0),
+                self.llvm_int32(idx as u64 + /*initialized=*/1),
+            ]
+            .as_mut_ptr(),
+            2,
+            cstr0!(),
+        );
+        Ok((ptr, ty))
+    }
+
+    /// Returns the global variable that holds the static fields of ``class``. The first field of
+    /// it tells whether the type initializer has run.
+    unsafe fn get_static_storage(&mut self, class: &ClassInfo) -> LLVMValueRef {
+        if let Some(storage) = self.shared_env.static_storages.get(class.into(): TypePath) {
+            return *storage;
+        }
+
+        let name = CString::new(format!("{}.static", class.get_full_name())).unwrap();
+        let storage_ty = LLVMStructCreateNamed(self.shared_env.context, name.as_ptr());
+        let storage = LLVMAddGlobal(self.shared_env.module, storage_ty, name.as_ptr());
+
+        self.shared_env
+            .static_storages
+            .add(class.into(): TypePath, storage);
+
+        let mut fields_ty = class
+            .static_fields
+            .iter()
+            .map(|ClassField { ty, .. }| ty.to_llvmty(self))
+            .collect::<Vec<LLVMTypeRef>>();
+
+        // Whether the type initializer has run
+        fields_ty.insert(0, LLVMInt8TypeInContext(self.shared_env.context));
+
+        LLVMStructSetBody(storage_ty, fields_ty.as_mut_ptr(), fields_ty.len() as u32, 0);
+        LLVMSetInitializer(storage, LLVMConstNull(storage_ty));
+
+        storage
+    }
+
+    /// Runs the type initializer (.cctor) of ``class`` unless it has already run
+    unsafe fn build_type_initialization(&mut self, class: &ClassInfo) {
+        let cctor = match class.get_type_initializer() {
+            Some(cctor) => cctor,
+            None => return,
+        };
+        let func = self.generating.unwrap();
+        let builder = self.shared_env.builder;
+        let storage = self.get_static_storage(class);
+        let initialized = LLVMBuildGEP(
+            builder,
+            storage,
+            vec![self.llvm_int32(0), self.llvm_int32(0)].as_mut_ptr(),
+            2,
+            cstr0!(),
+        );
+        let cond = LLVMBuildICmp(
+            builder,
+            llvm::LLVMIntPredicate::LLVMIntEQ,
+            LLVMBuildLoad(builder, initialized, cstr0!()),
+            LLVMConstNull(LLVMInt8TypeInContext(self.shared_env.context)),
+            cstr0!(),
+        );
+        let bb_init = LLVMAppendBasicBlock(func, cstr0!());
+        let bb_continue = LLVMAppendBasicBlock(func, cstr0!());
+        LLVMBuildCondBr(builder, cond, bb_init, bb_continue);
+
+        LLVMPositionBuilderAtEnd(builder, bb_init);
+        // Mark as initialized first so that the accesses from the type initializer itself do
+        // not run it again
+        LLVMBuildStore(
+            builder,
+            LLVMConstInt(LLVMInt8TypeInContext(self.shared_env.context), 1, 0),
+            initialized,
+        );
+        let cctor = self.get_function_by_method(&cctor);
+        self.call_function(cctor, vec![]);
+        self.build_exception_check();
+        LLVMBuildBr(builder, bb_continue);
+
+        LLVMPositionBuilderAtEnd(builder, bb_continue);
+    }
+
+    unsafe fn gen_instr_ldelem(&mut self, stack: &mut Vec<TypedValue>) {
+        let index = stack.pop().unwrap().val;
+        let TypedValue {
+            ty: arr_ty,
+            val: array,
+        } = stack.pop().unwrap();
+        let elem_ty = self.shared_env.ty_arena[arr_ty]
+            .as_szarray()
+            .unwrap()
+            .elem_ty
+            .clone();
+        let ptr = self.build_szarray_element_ptr(array, index);
+        let elem = LLVMBuildLoad(self.shared_env.builder, ptr, cstr0!());
+        stack.push(self.to_stack_value(elem, &elem_ty));
+    }
+
+    /// Stores a value into an array element. Storing a reference (``check_covariance``) throws
+    /// ``ArrayTypeMismatchException`` if the object is not an instance of the element type that
+    /// the array was created with, which may be more derived than the type known here.
+    unsafe fn gen_instr_stelem(&mut self, stack: &mut Vec<TypedValue>, check_covariance: bool) {
+        let value = stack.pop().unwrap().val;
+        let index = stack.pop().unwrap().val;
+        let array = stack.pop().unwrap().val;
+        let ptr = self.build_szarray_element_ptr(array, index);
+        if check_covariance {
+            self.build_array_store_check(array, value);
+        }
+        let value = self.typecast(value, LLVMGetElementType(LLVMTypeOf(ptr)));
+        LLVMBuildStore(self.shared_env.builder, value, ptr);
+    }
+
+    unsafe fn build_array_store_check(&mut self, array: LLVMValueRef, obj: LLVMValueRef) {
+        let builder = self.shared_env.builder;
+        let ptr_ty = LLVMPointerType(LLVMInt8TypeInContext(self.shared_env.context), 0);
+        let header = self.typecast(array, LLVMPointerType(ptr_ty, 0));
+        let elem_method_table = self.load_element(header, vec![self.llvm_int32(1)]);
+        let obj = self.typecast(obj, ptr_ty);
+        let is_instance = self.call_helper("is_instance", vec![obj, elem_method_table]);
+        // Null is stored into any array, and any object into the arrays with no method table
+        let is_checked = LLVMBuildAnd(
+            builder,
+            LLVMBuildIsNotNull(builder, obj, cstr0!()),
+            LLVMBuildIsNotNull(builder, elem_method_table, cstr0!()),
+            cstr0!(),
+        );
+        let cond = LLVMBuildAnd(
+            builder,
+            is_checked,
+            LLVMBuildIsNull(builder, is_instance, cstr0!()),
+            cstr0!(),
+        );
+        self.build_throw_if(cond, mscorlib_system_array_type_mismatch_exception());
+    }
+
+    /// Returns the address of the element of the single-dimensional ``array`` at ``index``.
+    /// Throws ``IndexOutOfRangeException`` if ``index`` is out of range.
+    unsafe fn build_szarray_element_ptr(
+        &mut self,
+        array: LLVMValueRef,
+        index: LLVMValueRef,
+    ) -> LLVMValueRef {
+        self.build_bounds_check(array, index);
+        let builder = self.shared_env.builder;
+        let ptr_ty = LLVMPointerType(LLVMInt8TypeInContext(self.shared_env.context), 0);
+        let elems = LLVMBuildGEP(
+            builder,
+            LLVMBuildBitCast(builder, array, ptr_ty, cstr0!()),
+            vec![self.llvm_int32(SZARRAY_HEADER_SIZE as u64)].as_mut_ptr(),
+            1,
+            cstr0!(),
+        );
+        let elems = LLVMBuildBitCast(builder, elems, LLVMTypeOf(array), cstr0!());
+        LLVMBuildGEP(builder, elems, vec![index].as_mut_ptr(), 1, cstr0!())
+    }
+
+    unsafe fn gen_instr_ldelema(&mut self, stack: &mut Vec<TypedValue>) {
+        let index = stack.pop().unwrap().val;
+        let TypedValue {
+            ty: arr_ty,
+            val: array,
+        } = stack.pop().unwrap();
+        let elem_ty = self.shared_env.ty_arena[arr_ty]
+            .as_szarray()
+            .unwrap()
+            .elem_ty
+            .clone();
+        let ptr = self.build_szarray_element_ptr(array, index);
+        stack.push(TypedValue::new(
+            self.shared_env.type_id(&Type::byref_ty(elem_ty)),
+            ptr,
+        ));
+    }
+
+    /// Loads a value of ``llvm_ty`` through the address on the stack. The value is extended to
+    /// ``ty``, the type it has on the evaluation stack.
+    unsafe fn gen_instr_ldind(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        llvm_ty: LLVMTypeRef,
+        signed: bool,
+        ty: Type,
+    ) {
+        let addr = stack.pop().unwrap().val;
+        self.build_null_check(addr);
+        let builder = self.shared_env.builder;
+        let ptr = self.typecast(addr, LLVMPointerType(llvm_ty, 0));
+        let val = LLVMBuildLoad(builder, ptr, cstr0!());
+        let stack_llvm_ty = ty.to_llvmty(self);
+        let val = match LLVMGetTypeKind(llvm_ty) {
+            llvm::LLVMTypeKind::LLVMIntegerTypeKind
+                if LLVMGetIntTypeWidth(llvm_ty) < LLVMGetIntTypeWidth(stack_llvm_ty) =>
+            {
+                if signed {
+                    LLVMBuildSExt(builder, val, stack_llvm_ty, cstr0!())
+                } else {
+                    LLVMBuildZExt(builder, val, stack_llvm_ty, cstr0!())
+                }
+            }
+            llvm::LLVMTypeKind::LLVMFloatTypeKind => {
+                LLVMBuildFPExt(builder, val, stack_llvm_ty, cstr0!())
+            }
+            _ => val,
+        };
+        stack.push(TypedValue::new(self.shared_env.type_id(&ty), val));
+    }
+
+    unsafe fn gen_instr_ldind_ref(&mut self, stack: &mut Vec<TypedValue>) {
+        let addr = stack.pop().unwrap();
+        self.build_null_check(addr.val);
+        let ty = self.shared_env.ty_arena[addr.ty]
+            .as_pointee()
+            .cloned()
+            .unwrap_or_else(Type::object_ty);
+        stack.push(TypedValue::new(
+            self.shared_env.type_id(&ty),
+            LLVMBuildLoad(self.shared_env.builder, addr.val, cstr0!()),
+        ));
+    }
+
+    /// Stores the value on the stack through the address under it. ``llvm_ty`` is the type
+    /// of the location, or None if it is given by the type of the address (``stind.ref``).
+    unsafe fn gen_instr_stind(&mut self, stack: &mut Vec<TypedValue>, llvm_ty: Option<LLVMTypeRef>) {
+        let val = stack.pop().unwrap().val;
+        let addr = stack.pop().unwrap().val;
+        self.build_null_check(addr);
+        let ptr = match llvm_ty {
+            Some(llvm_ty) => self.typecast(addr, LLVMPointerType(llvm_ty, 0)),
+            None => addr,
+        };
+        LLVMBuildStore(
+            self.shared_env.builder,
+            self.typecast(val, LLVMGetElementType(LLVMTypeOf(ptr))),
+            ptr,
+        );
+    }
+
+    unsafe fn gen_instr_ldlen(&mut self, stack: &mut Vec<TypedValue>) {
+        let array = stack.pop().unwrap().val;
+        self.build_null_check(array);
+        let len = self.load_array_length(array);
+        stack.push(TypedValue::new(self.shared_env.type_id(&Type::i4_ty()), len));
+    }
+
+    /// Returns the multidimensional array type that ``tst`` refers to, if it does
+    unsafe fn get_array_type_from_type_spec(&self, tst: &TypeSpecTable) -> Option<Type> {
+        let ty = self.instantiate_ty(&self.assembly.image.get_type_from_type_spec(tst)?);
+        ty.as_array()?;
+        Some(ty)
+    }
+
+    /// Generates the methods that the runtime provides for multidimensional arrays (II.14.2):
+    /// ``.ctor``, ``Get``, ``Set`` and ``Address``
+    unsafe fn gen_array_method(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        arr_ty: Type,
+        mrt: &MemberRefTable,
+    ) -> CResult<()> {
+        let name = self.assembly.image.get_string(mrt.name).unwrap().to_string();
+        let params_len = self
+            .assembly
+            .image
+            .get_method_ref_type_from_signature(mrt.signature)
+            .unwrap()
+            .as_fnptr()
+            .unwrap()
+            .params
+            .len();
+        let arr = arr_ty.as_array().unwrap().clone();
+        let builder = self.shared_env.builder;
+        match name.as_str() {
+            ".ctor" => self.gen_new_array(stack, arr_ty, params_len),
+            "Get" => {
+                let (_, indices) = get_arg_vals_from_stack(stack, arr.rank, false);
+                let array = stack.pop().unwrap().val;
+                let ptr = self.build_array_element_ptr(array, &arr, indices);
+                let val = LLVMBuildLoad(builder, ptr, cstr0!());
+                stack.push(self.to_stack_value(val, &arr.elem_ty));
+            }
+            "Set" => {
+                let value = stack.pop().unwrap().val;
+                let (_, indices) = get_arg_vals_from_stack(stack, arr.rank, false);
+                let array = stack.pop().unwrap().val;
+                let ptr = self.build_array_element_ptr(array, &arr, indices);
+                let llvm_elem_ty = arr.elem_ty.to_llvmty(self);
+                LLVMBuildStore(builder, self.typecast(value, llvm_elem_ty), ptr);
+            }
+            "Address" => {
+                let (_, indices) = get_arg_vals_from_stack(stack, arr.rank, false);
+                let array = stack.pop().unwrap().val;
+                let ptr = self.build_array_element_ptr(array, &arr, indices);
+                stack.push(TypedValue::new(
+                    self.shared_env.type_id(&Type::byref_ty(arr.elem_ty)),
+                    ptr,
+                ));
+            }
+            _ => unsupported!("{}::{}", arr_ty.get_name(), name),
+        }
+        Ok(())
+    }
+
+    /// ``newobj`` of a multidimensional array. The arguments are the lengths of the dimensions,
+    /// or the pairs of the lower bound and the length of each dimension.
+    unsafe fn gen_new_array(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        arr_ty: Type,
+        params_len: usize,
+    ) {
+        let rank = arr_ty.as_array().unwrap().rank;
+        let builder = self.shared_env.builder;
+        let i64_ty = LLVMInt64TypeInContext(self.shared_env.context);
+        let (_, args) = get_arg_vals_from_stack(stack, params_len, false);
+        let args: Vec<LLVMValueRef> = args
+            .into_iter()
+            .map(|arg| self.build_int_cast(arg, i64_ty, true))
+            .collect();
+        let (lo_bounds, lengths): (Vec<LLVMValueRef>, Vec<LLVMValueRef>) = if params_len == rank {
+            (vec![LLVMConstInt(i64_ty, 0, 0); rank], args)
+        } else {
+            args.chunks(2).map(|pair| (pair[0], pair[1])).unzip()
+        };
+
+        let mul = self.get_overflow_intrinsic("llvm.umul.with.overflow.i64", i64_ty);
+        let mut total_len = LLVMConstInt(i64_ty, 1, 0);
+        for &len in &lengths {
+            let cond = LLVMBuildICmp(
+                builder,
+                llvm::LLVMIntPredicate::LLVMIntSLT,
+                len,
+                LLVMConstInt(i64_ty, 0, 0),
+                cstr0!(),
+            );
+            self.build_throw_if(cond, mscorlib_system_overflow_exception());
+            let result = self.call_function(mul, vec![total_len, len]);
+            let overflow = LLVMBuildExtractValue(builder, result, 1, cstr0!());
+            self.build_throw_if(overflow, mscorlib_system_out_of_memory_exception());
+            total_len = LLVMBuildExtractValue(builder, result, 0, cstr0!());
+        }
+
+        let elem_ty = arr_ty.as_array().unwrap().elem_ty.clone();
+        let llvm_elem_ty = elem_ty.to_llvmty(self);
+        let elem_sz = self.get_size_of_llvm_class_type(LLVMPointerType(llvm_elem_ty, 0));
+        let elem_method_table = self.get_element_method_table(&elem_ty);
+        let new_arr = self.call_helper(
+            "new_array",
+            vec![elem_sz, self.llvm_int32(rank as u64), total_len, elem_method_table],
+        );
+        self.build_exception_check();
+        let llvm_arr_ty = arr_ty.to_llvmty(self);
+        let new_arr = self.typecast(new_arr, llvm_arr_ty);
+        for (d, (len, lo_bound)) in lengths.into_iter().zip(lo_bounds).enumerate() {
+            self.store2element(new_arr, vec![self.llvm_int32((2 + d) as u64)], len);
+            self.store2element(new_arr, vec![self.llvm_int32((2 + rank + d) as u64)], lo_bound);
+        }
+        stack.push(TypedValue::new(self.shared_env.type_id(&arr_ty), new_arr));
+    }
+
+    /// Returns the address of the element of the multidimensional ``array`` at ``indices``.
+    /// Throws ``IndexOutOfRangeException`` if any index is out of its dimension.
+    unsafe fn build_array_element_ptr(
+        &mut self,
+        array: LLVMValueRef,
+        arr: &ArrayInfo,
+        indices: Vec<LLVMValueRef>,
+    ) -> LLVMValueRef {
+        self.build_null_check(array);
+        let builder = self.shared_env.builder;
+        let i64_ty = LLVMInt64TypeInContext(self.shared_env.context);
+        // Row-major order
+        let mut offset = LLVMConstInt(i64_ty, 0, 0);
+        for (d, index) in indices.into_iter().enumerate() {
+            let len = self.load_element(array, vec![self.llvm_int32((2 + d) as u64)]);
+            let lo_bound =
+                self.load_element(array, vec![self.llvm_int32((2 + arr.rank + d) as u64)]);
+            let index = LLVMBuildSub(
+                builder,
+                self.build_int_cast(index, i64_ty, true),
+                lo_bound,
+                cstr0!(),
+            );
+            // Indexes below the lower bound are also out of range when compared as unsigned
+            let cond =
+                LLVMBuildICmp(builder, llvm::LLVMIntPredicate::LLVMIntUGE, index, len, cstr0!());
+            self.build_throw_if(cond, mscorlib_system_index_out_of_range_exception());
+            offset = LLVMBuildAdd(
+                builder,
+                LLVMBuildMul(builder, offset, len, cstr0!()),
+                index,
+                cstr0!(),
+            );
+        }
+        let llvm_elem_ty = arr.elem_ty.to_llvmty(self);
+        let elems = LLVMBuildGEP(
+            builder,
+            array,
+            vec![self.llvm_int32(2 * (arr.rank + 1) as u64)].as_mut_ptr(),
+            1,
+            cstr0!(),
+        );
+        let elems = LLVMBuildBitCast(builder, elems, LLVMPointerType(llvm_elem_ty, 0), cstr0!());
+        LLVMBuildGEP(builder, elems, vec![offset].as_mut_ptr(), 1, cstr0!())
+    }
+
+    /// ``Assembly.GetExecutingAssembly()`` returns the assembly being compiled
+    unsafe fn gen_get_executing_assembly(&mut self, stack: &mut Vec<TypedValue>) {
+        let handle = self.shared_env.runtime_assembly(self.assembly);
+        let asm = self.call_helper("new_system_assembly", vec![self.llvm_int32(handle as u64)]);
+        let ty = Type::class_ty(mscorlib_system_reflection_assembly());
+        stack.push(TypedValue::new(self.shared_env.type_id(&ty), asm));
+    }
+
+    /// Generates the methods of ``System.Array`` called on arrays: ``get_Length``, ``get_Rank``,
+    /// ``GetLength``, ``GetLowerBound`` and ``GetUpperBound``. The shape of the array is taken
+    /// from its type on the stack.
+    unsafe fn gen_system_array_method(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        name: &str,
+        params_len: usize,
+    ) -> CResult<()> {
+        let (_, args) = get_arg_vals_from_stack(stack, params_len, false);
+        let TypedValue { ty, val: array } = stack.pop().unwrap();
+        let arr_ty = self.shared_env.ty_arena[ty].clone();
+        let rank = match (arr_ty.as_array(), arr_ty.as_szarray()) {
+            (Some(arr), _) => arr.rank,
+            (None, Some(_)) => 1,
+            _ => unsupported!("System.Array::{} on {:?}", name, arr_ty),
+        };
+        self.build_null_check(array);
+        let builder = self.shared_env.builder;
+        let i32_ty = LLVMInt32TypeInContext(self.shared_env.context);
+        let is_szarray = arr_ty.as_szarray().is_some();
+        let val = match name {
+            "get_Rank" => self.llvm_int32(rank as u64),
+            "get_Length" if is_szarray => self.load_array_length(array),
+            "get_Length" => {
+                let mut len = self.load_element(array, vec![self.llvm_int32(2)]);
+                for d in 1..rank {
+                    let len_d = self.load_element(array, vec![self.llvm_int32((2 + d) as u64)]);
+                    len = LLVMBuildMul(builder, len, len_d, cstr0!());
+                }
+                LLVMBuildTrunc(builder, len, i32_ty, cstr0!())
+            }
+            "GetLength" | "GetLowerBound" | "GetUpperBound" => {
+                let dim = args[0];
+                let cond = LLVMBuildICmp(
+                    builder,
+                    llvm::LLVMIntPredicate::LLVMIntUGE,
+                    dim,
+                    self.llvm_int32(rank as u64),
+                    cstr0!(),
+                );
+                self.build_throw_if(cond, mscorlib_system_index_out_of_range_exception());
+                let (len, lo_bound) = if is_szarray {
+                    (self.load_array_length(array), self.llvm_int32(0))
+                } else {
+                    let len_idx = LLVMBuildAdd(builder, dim, self.llvm_int32(2), cstr0!());
+                    let len = self.load_element(array, vec![len_idx]);
+                    let lo_bound_idx =
+                        LLVMBuildAdd(builder, len_idx, self.llvm_int32(rank as u64), cstr0!());
+                    let lo_bound = self.load_element(array, vec![lo_bound_idx]);
+                    (
+                        LLVMBuildTrunc(builder, len, i32_ty, cstr0!()),
+                        LLVMBuildTrunc(builder, lo_bound, i32_ty, cstr0!()),
+                    )
+                };
+                match name {
+                    "GetLength" => len,
+                    "GetLowerBound" => lo_bound,
+                    _ => LLVMBuildSub(
+                        builder,
+                        LLVMBuildAdd(builder, lo_bound, len, cstr0!()),
+                        self.llvm_int32(1),
+                        cstr0!(),
+                    ),
+                }
+            }
+            _ => unsupported!("System.Array::{}", name),
+        };
+        stack.push(TypedValue::new(self.shared_env.type_id(&Type::i4_ty()), val));
+        Ok(())
+    }
+
+    /// ``RuntimeHelpers.InitializeArray(array, field)`` copies the initial data of ``field`` (see
+    /// ``gen_instr_ldtoken``) to the elements of ``array``. ``char`` takes two bytes in the data
+    /// but four in arrays. Throws ``ArgumentException`` if the data is too small.
+    unsafe fn gen_initialize_array(&mut self, stack: &mut Vec<TypedValue>) {
+        let ctx = self.shared_env.context;
+        let field = stack.pop().unwrap().val;
+        let array = stack.pop().unwrap();
+        let elem_ty = self.shared_env.ty_arena[array.ty]
+            .as_szarray()
+            .unwrap()
+            .elem_ty
+            .clone();
+        let elem_llvm_ty = elem_ty.to_llvmty(self);
+        let elem_sz = LLVMConstTrunc(LLVMSizeOf(elem_llvm_ty), LLVMInt32TypeInContext(ctx));
+        let data_elem_sz = match elem_ty.base {
+            ElementType::Char => self.llvm_int32(2),
+            _ => elem_sz,
+        };
+        self.build_null_check(array.val);
+        let initialized = self.call_helper(
+            "initialize_array",
+            vec![array.val, elem_sz, field, data_elem_sz],
+        );
+        let cond = LLVMBuildIsNull(self.shared_env.builder, initialized, cstr0!());
+        self.build_throw_if(cond, mscorlib_system_argument_exception());
+    }
+
+    /// ``System.ArgIterator`` holds the block of the arguments that ``arglist`` gives (see
+    /// ``gen_vararg_call``) and the index of the next argument
+    unsafe fn gen_arg_iterator_method(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        name: &str,
+    ) -> CResult<()> {
+        let builder = self.shared_env.builder;
+        let ctx = self.shared_env.context;
+        let i64_ty = LLVMInt64TypeInContext(ctx);
+        let typed_ref_ty = Type::new(ElementType::TypedByRef).to_llvmty(self);
+        let mut block_elems_ty = [i64_ty, LLVMArrayType(typed_ref_ty, 0)];
+        let block_ty = LLVMStructTypeInContext(ctx, block_elems_ty.as_mut_ptr(), 2, 0);
+
+        if name == ".ctor" {
+            let args = stack.pop().unwrap().val;
+            let iter = self.typecast(stack.pop().unwrap().val, LLVMPointerType(i64_ty, 0));
+            self.store2element(iter, vec![self.llvm_int32(0)], args);
+            self.store2element(iter, vec![self.llvm_int32(1)], LLVMConstNull(i64_ty));
+            return Ok(());
+        }
+
+        let iter = self.typecast(stack.pop().unwrap().val, LLVMPointerType(i64_ty, 0));
+        let block = self.load_element(iter, vec![self.llvm_int32(0)]);
+        let block = self.typecast(block, LLVMPointerType(block_ty, 0));
+        let count = self.load_element(block, vec![self.llvm_int32(0), self.llvm_int32(0)]);
+        let next = self.load_element(iter, vec![self.llvm_int32(1)]);
+        match name {
+            "GetRemainingCount" => {
+                let remaining = LLVMBuildSub(builder, count, next, cstr0!());
+                stack.push(TypedValue::new(
+                    self.shared_env.type_id(&Type::i4_ty()),
+                    LLVMBuildTrunc(builder, remaining, LLVMInt32TypeInContext(ctx), cstr0!()),
+                ));
+            }
+            "GetNextArg" => {
+                let cond = LLVMBuildICmp(
+                    builder,
+                    llvm::LLVMIntPredicate::LLVMIntUGE,
+                    next,
+                    count,
+                    cstr0!(),
+                );
+                self.build_throw_if(cond, mscorlib_system_invalid_operation_exception());
+                let typed_ref =
+                    self.load_element(block, vec![self.llvm_int32(0), self.llvm_int32(1), next]);
+                let next = LLVMBuildAdd(builder, next, LLVMConstInt(i64_ty, 1, 0), cstr0!());
+                self.store2element(iter, vec![self.llvm_int32(1)], next);
+                stack.push(TypedValue::new(
+                    self.shared_env.type_id(&Type::new(ElementType::TypedByRef)),
+                    typed_ref,
+                ));
+            }
+            "End" => {}
+            _ => unsupported!("System.ArgIterator::{}", name),
+        }
+        Ok(())
+    }
+
+    unsafe fn load_array_length(&mut self, array: LLVMValueRef) -> LLVMValueRef {
+        let array = self.typecast(
+            array,
+            LLVMPointerType(LLVMInt32TypeInContext(self.shared_env.context), 0),
+        );
+        self.load_element(array, vec![self.llvm_int32(0)])
+    }
+
+    /// Converts the value on the stack to ``ty`` (``conv.*``). An integer is truncated, or
+    /// extended by ``signed``. A floating-point value is truncated toward zero.
+    unsafe fn gen_instr_conv(&mut self, stack: &mut Vec<TypedValue>, ty: Type, signed: bool) {
+        let val = stack.pop().unwrap();
+        let builder = self.shared_env.builder;
+        let llvm_ty = ty.to_llvmty(self);
+        let src_llvm_ty = LLVMTypeOf(val.val);
+        let to_float = ty.is_float();
+        let converted = match LLVMGetTypeKind(src_llvm_ty) {
+            llvm::LLVMTypeKind::LLVMDoubleTypeKind | llvm::LLVMTypeKind::LLVMFloatTypeKind => {
+                if to_float {
+                    LLVMBuildFPCast(builder, val.val, llvm_ty, cstr0!())
+                } else if signed {
+                    LLVMBuildFPToSI(builder, val.val, llvm_ty, cstr0!())
+                } else {
+                    LLVMBuildFPToUI(builder, val.val, llvm_ty, cstr0!())
+                }
+            }
+            // Managed and unmanaged pointers (e.g. ``conv.u`` of a pinned address)
+            llvm::LLVMTypeKind::LLVMPointerTypeKind => {
+                LLVMBuildPtrToInt(builder, val.val, llvm_ty, cstr0!())
+            }
+            _ if to_float => LLVMBuildSIToFP(builder, val.val, llvm_ty, cstr0!()),
+            _ => self.build_int_cast(val.val, llvm_ty, signed),
+        };
+        stack.push(self.to_stack_value(converted, &ty));
+    }
+
+    /// Converts the value on the stack to the integer type ``ty`` like ``conv.*``, but throws
+    /// ``OverflowException`` if the value does not fit in ``ty`` (``conv.ovf.*``). ``signed``
+    /// tells whether ``ty`` is signed, and ``signed_src`` is false for ``conv.ovf.*.un``.
+    unsafe fn gen_instr_conv_ovf(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        ty: Type,
+        signed: bool,
+        signed_src: bool,
+    ) {
+        let val = stack.pop().unwrap();
+        let builder = self.shared_env.builder;
+        let llvm_ty = ty.to_llvmty(self);
+        let (min, max) = int_range(LLVMGetIntTypeWidth(llvm_ty), signed);
+        let src_llvm_ty = LLVMTypeOf(val.val);
+        let converted = match LLVMGetTypeKind(src_llvm_ty) {
+            llvm::LLVMTypeKind::LLVMDoubleTypeKind | llvm::LLVMTypeKind::LLVMFloatTypeKind => {
+                // The fraction is truncated toward zero, so (min - 1, max + 1) fits. min - 1 is
+                // not representable for int64, where min itself is the lower bound.
+                let (lo_pred, lo) = if min as f64 - 1.0 == min as f64 {
+                    (llvm::LLVMRealPredicate::LLVMRealOGE, min as f64)
+                } else {
+                    (llvm::LLVMRealPredicate::LLVMRealOGT, min as f64 - 1.0)
+                };
+                let lo = LLVMConstReal(src_llvm_ty, lo);
+                let hi = LLVMConstReal(src_llvm_ty, max as f64 + 1.0);
+                // Ordered comparisons are false for NaN
+                let fits = LLVMBuildAnd(
+                    builder,
+                    LLVMBuildFCmp(builder, lo_pred, val.val, lo, cstr0!()),
+                    LLVMBuildFCmp(
+                        builder,
+                        llvm::LLVMRealPredicate::LLVMRealOLT,
+                        val.val,
+                        hi,
+                        cstr0!(),
+                    ),
+                    cstr0!(),
+                );
+                let cond = LLVMBuildNot(builder, fits, cstr0!());
+                self.build_throw_if(cond, mscorlib_system_overflow_exception());
+                if signed {
+                    LLVMBuildFPToSI(builder, val.val, llvm_ty, cstr0!())
+                } else {
+                    LLVMBuildFPToUI(builder, val.val, llvm_ty, cstr0!())
+                }
+            }
+            _ => {
+                let src = match LLVMGetTypeKind(src_llvm_ty) {
+                    llvm::LLVMTypeKind::LLVMPointerTypeKind => LLVMBuildPtrToInt(
+                        builder,
+                        val.val,
+                        LLVMInt64TypeInContext(self.shared_env.context),
+                        cstr0!(),
+                    ),
+                    _ => val.val,
+                };
+                let src_llvm_ty = LLVMTypeOf(src);
+                let (src_min, src_max) = int_range(LLVMGetIntTypeWidth(src_llvm_ty), signed_src);
+                if min > src_min {
+                    let cond = LLVMBuildICmp(
+                        builder,
+                        llvm::LLVMIntPredicate::LLVMIntSLT,
+                        src,
+                        LLVMConstInt(src_llvm_ty, min as u64, 1),
+                        cstr0!(),
+                    );
+                    self.build_throw_if(cond, mscorlib_system_overflow_exception());
+                }
+                if max < src_max {
+                    let pred = if signed_src {
+                        llvm::LLVMIntPredicate::LLVMIntSGT
+                    } else {
+                        llvm::LLVMIntPredicate::LLVMIntUGT
+                    };
+                    let cond = LLVMBuildICmp(
+                        builder,
+                        pred,
+                        src,
+                        LLVMConstInt(src_llvm_ty, max as u64, 0),
+                        cstr0!(),
+                    );
+                    self.build_throw_if(cond, mscorlib_system_overflow_exception());
+                }
+                self.build_int_cast(src, llvm_ty, signed_src)
+            }
+        };
+        stack.push(self.to_stack_value(converted, &ty));
+    }
+
+    /// Generates ``add.ovf``, ``sub.ovf`` or ``mul.ovf`` (``op``) with the overflow intrinsics
+    /// of LLVM. ``signed`` is false for the ``.un`` variants.
+    unsafe fn gen_instr_ovf_binop(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        op: &str,
+        signed: bool,
+    ) -> CResult<()> {
+        let val2 = stack.pop().unwrap();
+        let val1 = stack.pop().unwrap();
+        if !self.shared_env.ty_arena[val1.ty].is_int() {
+            unsupported!("{:?}", self.shared_env.ty_arena[val1.ty])
+        }
+        let builder = self.shared_env.builder;
+        // int32 and native int operands are computed as native int
+        let (ty, llvm_ty) = if LLVMGetIntTypeWidth(LLVMTypeOf(val1.val))
+            < LLVMGetIntTypeWidth(LLVMTypeOf(val2.val))
+        {
+            (val2.ty, LLVMTypeOf(val2.val))
+        } else {
+            (val1.ty, LLVMTypeOf(val1.val))
+        };
+        let lhs = self.build_int_cast(val1.val, llvm_ty, signed);
+        let rhs = self.build_int_cast(val2.val, llvm_ty, signed);
+        let name = format!(
+            "llvm.{}{}.with.overflow.i{}",
+            if signed { "s" } else { "u" },
+            op,
+            LLVMGetIntTypeWidth(llvm_ty)
+        );
+        let intrinsic = self.get_overflow_intrinsic(name.as_str(), llvm_ty);
+        let result = self.call_function(intrinsic, vec![lhs, rhs]);
+        let overflow = LLVMBuildExtractValue(builder, result, 1, cstr0!());
+        self.build_throw_if(overflow, mscorlib_system_overflow_exception());
+        stack.push(TypedValue::new(
+            ty,
+            LLVMBuildExtractValue(builder, result, 0, cstr0!()),
+        ));
+        Ok(())
+    }
+
+    /// Returns the intrinsic ``name`` (``llvm.*.with.overflow.*``) that takes two ``ty``
+    unsafe fn get_overflow_intrinsic(&self, name: &str, ty: LLVMTypeRef) -> LLVMValueRef {
+        let name = CString::new(name).unwrap();
+        let intrinsic = LLVMGetNamedFunction(self.shared_env.module, name.as_ptr());
+        if !intrinsic.is_null() {
+            return intrinsic;
+        }
+        let mut elems = [ty, LLVMInt1TypeInContext(self.shared_env.context)];
+        let ret_ty = LLVMStructTypeInContext(self.shared_env.context, elems.as_mut_ptr(), 2, 0);
+        let mut params = [ty, ty];
+        LLVMAddFunction(
+            self.shared_env.module,
+            name.as_ptr(),
+            LLVMFunctionType(ret_ty, params.as_mut_ptr(), 2, 0),
+        )
+    }
+
+    /// Truncates ``val`` to the integer type ``to``, or extends it by ``signed``
+    unsafe fn build_int_cast(
+        &self,
+        val: LLVMValueRef,
+        to: LLVMTypeRef,
+        signed: bool,
+    ) -> LLVMValueRef {
+        let builder = self.shared_env.builder;
+        let bw = LLVMGetIntTypeWidth(LLVMTypeOf(val));
+        let to_bw = LLVMGetIntTypeWidth(to);
+        if bw > to_bw {
+            LLVMBuildTrunc(builder, val, to, cstr0!())
+        } else if bw == to_bw {
+            val
+        } else if signed {
+            LLVMBuildSExt(builder, val, to, cstr0!())
+        } else {
+            LLVMBuildZExt(builder, val, to, cstr0!())
+        }
+    }
+
+    /// Returns ``val`` of ``ty`` as it is on the evaluation stack (III.1.1). Integers smaller
+    /// than int32 are extended by their signedness, and float32 is extended to F.
+    unsafe fn to_stack_value(&mut self, val: LLVMValueRef, ty: &Type) -> TypedValue {
+        let builder = self.shared_env.builder;
+        let ctx = self.shared_env.context;
+        let (val, ty) = match ty.base {
+            ElementType::I1 | ElementType::I2 => (
+                LLVMBuildSExt(builder, val, LLVMInt32TypeInContext(ctx), cstr0!()),
+                Type::i4_ty(),
+            ),
+            ElementType::U1 | ElementType::U2 => (
+                LLVMBuildZExt(builder, val, LLVMInt32TypeInContext(ctx), cstr0!()),
+                Type::i4_ty(),
+            ),
+            ElementType::R4 => (
+                LLVMBuildFPExt(builder, val, LLVMDoubleTypeInContext(ctx), cstr0!()),
+                Type::r8_ty(),
+            ),
+            _ => (val, ty.clone()),
+        };
+        TypedValue::new(self.shared_env.type_id(&ty), val)
+    }
+
+    unsafe fn gen_instr_conv_r_un(&mut self, stack: &mut Vec<TypedValue>) {
+        let value = stack.pop().unwrap().val;
+        stack.push(TypedValue::new(
+            self.shared_env.type_id(&Type::r8_ty()),
+            LLVMBuildUIToFP(
+                self.shared_env.builder,
+                value,
+                LLVMDoubleTypeInContext(self.shared_env.context),
+                cstr0!(),
+            ),
+        ))
+    }
+
+    unsafe fn gen_instr_box(&mut self, stack: &mut Vec<TypedValue>, token: Token) -> CResult<()> {
+        let val = stack.pop().unwrap();
+        let ty = self.get_type_by_token(token)?;
+        let obj = self.box_value(val, &ty)?;
+        stack.push(obj);
+        Ok(())
+    }
+
+    /// Returns a new object holding ``val`` of ``ty``. Boxing a reference type does nothing.
+    unsafe fn box_value(&mut self, val: TypedValue, ty: &Type) -> CResult<TypedValue> {
+        let class_ref = match get_boxed_class(ty)? {
+            Some(class_ref) => class_ref,
+            None => return Ok(val),
+        };
+        let class = class_ref.borrow();
+        let llvm_class = self.get_llvm_class_type(&class);
+        let new_obj = self.typecast(
+            self.call_memory_alloc(self.get_size_of_llvm_class_type(llvm_class)),
+            llvm_class,
+        );
+        let (_, method_table) = self.ensure_all_class_methods_compiled(&*class);
+        self.store2element(
+            new_obj,
+            vec![self.llvm_int32(0), self.llvm_int32(0)],
+            method_table,
+        );
+        let ptr = self.get_boxed_value_ptr(new_obj, &class, ty);
+        LLVMBuildStore(
+            self.shared_env.builder,
+            self.typecast(val.val, LLVMGetElementType(LLVMTypeOf(ptr))),
+            ptr,
+        );
+        Ok(TypedValue::new(self.shared_env.type_id(&Type::object_ty()), new_obj))
+    }
+
+    /// Returns the address of the value of ``ty`` that the boxed object ``obj`` of ``class``
+    /// holds. The value follows the method table.
+    unsafe fn get_boxed_value_ptr(
+        &mut self,
+        obj: LLVMValueRef,
+        class: &ClassInfo,
+        ty: &Type,
+    ) -> LLVMValueRef {
+        let llvm_class = self.get_llvm_class_type(class);
+        let llvm_ty = ty.to_llvmty(self);
+        let obj = self.typecast(obj, llvm_class);
+        let ptr = LLVMBuildGEP(
+            self.shared_env.builder,
+            obj,
+            vec![self.llvm_int32(0), self.llvm_int32(1)].as_mut_ptr(),
+            2,
+            cstr0!(),
+        );
+        LLVMBuildBitCast(
+            self.shared_env.builder,
+            ptr,
+            LLVMPointerType(llvm_ty, 0),
+            cstr0!(),
+        )
+    }
+
+    unsafe fn gen_instr_unbox(&mut self, stack: &mut Vec<TypedValue>, token: Token) -> CResult<()> {
+        let obj = stack.pop().unwrap().val;
+        let ty = self.get_type_by_token(token)?;
+        self.build_null_check(obj);
+        let class_ref = match get_boxed_class(&ty)? {
+            Some(class_ref) => class_ref,
+            None => unsupported!("unbox: {:?}", ty),
+        };
+        let class = class_ref.borrow();
+        let is_instance = self.build_is_instance(obj, &class);
+        self.build_throw_if(
+            LLVMBuildNot(self.shared_env.builder, is_instance, cstr0!()),
+            mscorlib_system_invalid_cast_exception(),
+        );
+        let ptr = self.get_boxed_value_ptr(obj, &class, &ty);
+        stack.push(TypedValue::new(
+            self.shared_env.type_id(&Type::byref_ty(ty)),
+            ptr,
+        ));
+        Ok(())
+    }
+
+    unsafe fn gen_instr_unbox_any(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
+        let ty = self.get_type_by_token(token)?;
+        if get_boxed_class(&ty)?.is_some() {
+            self.gen_instr_unbox(stack, token)?;
+            return self.gen_instr_ldobj(stack, token);
+        }
+        // unbox.any on a reference type works as castclass
+        self.gen_instr_castclass(stack, token)
+    }
+
+    unsafe fn gen_instr_castclass(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
+        let obj = stack.pop().unwrap();
+        let ty = self.get_type_by_token(token)?;
+        let result_ty = if ty.is_valuetype() { Type::object_ty() } else { ty.clone() };
+        let llvm_ty = result_ty.to_llvmty(self);
+        let result = self.typecast(obj.val, llvm_ty);
+        if !self.shared_env.ty_arena[obj.ty].is_assignable_to(&ty) {
+            let is_instance = self.build_is_instance_of_type(result, &ty)?;
+            // Casting null always succeeds
+            let is_not_null = LLVMBuildIsNotNull(self.shared_env.builder, result, cstr0!());
+            let is_invalid = LLVMBuildAnd(
+                self.shared_env.builder,
+                is_not_null,
+                LLVMBuildNot(self.shared_env.builder, is_instance, cstr0!()),
+                cstr0!(),
+            );
+            self.build_throw_if(is_invalid, mscorlib_system_invalid_cast_exception());
+        }
+        stack.push(TypedValue::new(
+            self.shared_env.type_id(&result_ty),
+            result,
+        ));
+        Ok(())
+    }
+
+    unsafe fn gen_instr_isinst(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
+        let obj = stack.pop().unwrap();
+        let ty = self.get_type_by_token(token)?;
+        let result_ty = if ty.is_valuetype() { Type::object_ty() } else { ty.clone() };
+        let llvm_ty = result_ty.to_llvmty(self);
+        let result = if self.shared_env.ty_arena[obj.ty].is_assignable_to(&ty) {
+            self.typecast(obj.val, llvm_ty)
+        } else {
+            let obj = self.typecast(obj.val, llvm_ty);
+            let is_instance = self.build_is_instance_of_type(obj, &ty)?;
+            LLVMBuildSelect(
+                self.shared_env.builder,
+                is_instance,
+                obj,
+                LLVMConstNull(llvm_ty),
+                cstr0!(),
+            )
+        };
+        stack.push(TypedValue::new(
+            self.shared_env.type_id(&result_ty),
+            result,
+        ));
+        Ok(())
+    }
+
+    /// Returns an i1 value that is true if ``obj`` is an instance of ``ty`` (or a boxed value of
+    /// ``ty``). Null is an instance of no type.
+    unsafe fn build_is_instance_of_type(
+        &mut self,
+        obj: LLVMValueRef,
+        ty: &Type,
+    ) -> CResult<LLVMValueRef> {
+        let (rank, elem_ty) = match ty.base {
+            ElementType::SzArray(ref szarr) => (0, szarr.elem_ty.clone()),
+            ElementType::Array(ref arr) => (arr.rank as i32, arr.elem_ty.clone()),
+            _ => match ty.get_class() {
+                Some(class_ref) if class_ref == mscorlib_system_class("Array") => {
+                    return Ok(self.build_is_array_instance(obj, -1, &Type::object_ty()));
+                }
+                Some(class_ref) => return Ok(self.build_is_instance(obj, &class_ref.borrow())),
+                None => unsupported!("type test against {:?}", ty),
+            },
+        };
+        Ok(self.build_is_array_instance(obj, rank, &elem_ty))
+    }
+
+    /// Returns an i1 value that is true if ``obj`` is an array of ``rank`` dimensions (0 for
+    /// single-dimensional arrays, -1 for any array) whose elements are of ``elem_ty`` or, if it
+    /// is a reference type, of a type assignable to it
+    unsafe fn build_is_array_instance(
+        &mut self,
+        obj: LLVMValueRef,
+        rank: i32,
+        elem_ty: &Type,
+    ) -> LLVMValueRef {
+        let ptr_ty = LLVMPointerType(LLVMInt8TypeInContext(self.shared_env.context), 0);
+        let obj = self.typecast(obj, ptr_ty);
+        let elem_method_table = self.get_element_method_table(elem_ty);
+        let is_covariant = self.llvm_int32(!elem_ty.is_valuetype() as u64);
+        let is_instance = self.call_helper(
+            "is_array_instance",
+            vec![obj, self.llvm_int32(rank as u64), elem_method_table, is_covariant],
+        );
+        LLVMBuildICmp(
+            self.shared_env.builder,
+            llvm::LLVMIntPredicate::LLVMIntNE,
+            is_instance,
+            LLVMConstNull(LLVMTypeOf(is_instance)),
+            cstr0!(),
+        )
+    }
+
+    /// Returns an i1 value that is true if the class of ``obj`` is ``class``, derives from it or
+    /// implements it
+    unsafe fn build_is_instance(&mut self, obj: LLVMValueRef, class: &ClassInfo) -> LLVMValueRef {
+        self.get_llvm_class_type(class);
+        let (_, method_table) = self.ensure_all_class_methods_compiled(class);
+        let is_instance = self.call_helper("is_instance", vec![obj, method_table]);
+        LLVMBuildICmp(
+            self.shared_env.builder,
+            llvm::LLVMIntPredicate::LLVMIntNE,
+            is_instance,
+            LLVMConstNull(LLVMTypeOf(is_instance)),
+            cstr0!(),
+        )
+    }
+
+    /// Returns ``addr`` as the pointer to the type that ``token`` refers to, and the type
+    unsafe fn get_typed_address(
+        &mut self,
+        addr: LLVMValueRef,
+        token: Token,
+    ) -> CResult<(LLVMValueRef, Type)> {
+        let ty = self.get_type_by_token(token)?;
+        let llvm_ty = ty.to_llvmty(self);
+        let ptr = self.typecast(addr, LLVMPointerType(llvm_ty, 0));
+        Ok((ptr, ty))
+    }
+
+    unsafe fn gen_instr_initobj(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
+        let addr = stack.pop().unwrap().val;
+        let (ptr, _) = self.get_typed_address(addr, token)?;
+        LLVMBuildStore(
+            self.shared_env.builder,
+            LLVMConstNull(LLVMGetElementType(LLVMTypeOf(ptr))),
+            ptr,
+        );
+        Ok(())
+    }
+
+    unsafe fn gen_instr_ldobj(&mut self, stack: &mut Vec<TypedValue>, token: Token) -> CResult<()> {
+        let addr = stack.pop().unwrap().val;
+        self.build_null_check(addr);
+        let (ptr, ty) = self.get_typed_address(addr, token)?;
+        let val = LLVMBuildLoad(self.shared_env.builder, ptr, cstr0!());
+        stack.push(self.to_stack_value(val, &ty));
+        Ok(())
+    }
+
+    unsafe fn gen_instr_stobj(&mut self, stack: &mut Vec<TypedValue>, token: Token) -> CResult<()> {
+        let val = stack.pop().unwrap().val;
+        let addr = stack.pop().unwrap().val;
+        self.build_null_check(addr);
+        let (ptr, _) = self.get_typed_address(addr, token)?;
+        LLVMBuildStore(
+            self.shared_env.builder,
+            self.typecast(val, LLVMGetElementType(LLVMTypeOf(ptr))),
+            ptr,
+        );
+        Ok(())
+    }
+
+    unsafe fn gen_instr_cpobj(&mut self, stack: &mut Vec<TypedValue>, token: Token) -> CResult<()> {
+        let src = stack.pop().unwrap().val;
+        let dst = stack.pop().unwrap().val;
+        self.build_null_check(src);
+        self.build_null_check(dst);
+        let (src, _) = self.get_typed_address(src, token)?;
+        let (dst, _) = self.get_typed_address(dst, token)?;
+        let val = LLVMBuildLoad(self.shared_env.builder, src, cstr0!());
+        LLVMBuildStore(self.shared_env.builder, val, dst);
+        Ok(())
+    }
+
+    unsafe fn gen_instr_sizeof(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
+        let ty = self.get_type_by_token(token)?;
+        let llvm_ty = ty.to_llvmty(self);
+        stack.push(TypedValue::new(
+            self.shared_env.type_id(&Type::i4_ty()),
+            LLVMConstTrunc(
+                LLVMSizeOf(llvm_ty),
+                LLVMInt32TypeInContext(self.shared_env.context),
+            ),
+        ));
+        Ok(())
+    }
+
+    /// Pushes the handle of the type or the field that ``token`` refers to. The handle of a
+    /// field points to its initial data (see ``gen_initialize_array``).
+    unsafe fn gen_instr_ldtoken(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
+        let handle = match self.assembly.image.metadata.get_table_entry(token).unwrap() {
+            Table::TypeDef(_) | Table::TypeRef(_) | Table::TypeSpec(_) => {
+                let ty = self.get_type_by_token(token)?;
+                let method_table = self.get_method_table_of_type(&ty)?;
+                self.build_runtime_handle("RuntimeTypeHandle", method_table)
+            }
+            Table::Field(_) => {
+                let data = self.get_field_data(token);
+                self.build_runtime_handle("RuntimeFieldHandle", data)
+            }
+            e => unsupported!("{:?}", e),
+        };
+        stack.push(handle);
+        Ok(())
+    }
+
+    /// Returns a constant of the initial data of the field ``token`` preceded by its length, or
+    /// null if the field has no such data
+    unsafe fn get_field_data(&mut self, token: Token) -> LLVMValueRef {
+        let ctx = self.shared_env.context;
+        let data = match self.assembly.image.get_field_rva_data(token) {
+            Some(data) => data,
+            None => return LLVMConstNull(LLVMPointerType(LLVMInt8TypeInContext(ctx), 0)),
+        };
+        let mut elems = [
+            LLVMConstInt(LLVMInt64TypeInContext(ctx), data.len() as u64, 0),
+            LLVMConstStringInContext(ctx, data.as_ptr() as *const i8, data.len() as u32, 1),
+        ];
+        let init = LLVMConstStructInContext(ctx, elems.as_mut_ptr(), 2, 0);
+        let global = LLVMAddGlobal(self.shared_env.module, LLVMTypeOf(init), cstr0!());
+        LLVMSetInitializer(global, init);
+        LLVMSetGlobalConstant(global, 1);
+        global
+    }
+
+    /// Returns a value of the handle type ``System.<name>`` holding ``ptr``
+    unsafe fn build_runtime_handle(&mut self, name: &str, ptr: LLVMValueRef) -> TypedValue {
+        let ty = Type::from_class(mscorlib_system_class(name));
+        let llvm_ty = ty.to_llvmty(self);
+        let val = LLVMBuildInsertValue(
+            self.shared_env.builder,
+            LLVMGetUndef(llvm_ty),
+            self.typecast(ptr, LLVMInt64TypeInContext(self.shared_env.context)),
+            0,
+            cstr0!(),
+        );
+        TypedValue::new(self.shared_env.type_id(&ty), val)
+    }
+
+    /// Returns the method table of ``ty``, which identifies the type at runtime
+    unsafe fn get_method_table_of_type(&mut self, ty: &Type) -> CResult<LLVMValueRef> {
+        let class_ref = match ty.get_class() {
+            Some(class_ref) => class_ref,
+            None => unsupported!("method table of {:?}", ty),
+        };
+        let class = class_ref.borrow();
+        self.get_llvm_class_type(&class);
+        let (_, method_table) = self.ensure_all_class_methods_compiled(&class);
+        Ok(method_table)
+    }
+
+    /// Allocates zeroed memory of the size on the stack, which is freed when the method returns.
+    /// The address is pushed as a native int.
+    unsafe fn gen_instr_localloc(&mut self, stack: &mut Vec<TypedValue>) {
+        let builder = self.shared_env.builder;
+        let ctx = self.shared_env.context;
+        let size = self.typecast(stack.pop().unwrap().val, LLVMInt64TypeInContext(ctx));
+        let ptr = LLVMBuildArrayAlloca(builder, LLVMInt8TypeInContext(ctx), size, cstr0!());
+        LLVMSetAlignment(ptr, 16);
+        self.call_helper("memory_set", vec![ptr, self.llvm_int32(0), size]);
+        stack.push(TypedValue::new(
+            self.shared_env.type_id(&Type::i_ty()),
+            LLVMBuildPtrToInt(builder, ptr, LLVMInt64TypeInContext(ctx), cstr0!()),
+        ));
+    }
+
+    unsafe fn gen_instr_cpblk(&mut self, stack: &mut Vec<TypedValue>) {
+        let size = stack.pop().unwrap().val;
+        let src = stack.pop().unwrap().val;
+        let dst = stack.pop().unwrap().val;
+        self.build_null_check(src);
+        self.build_null_check(dst);
+        self.call_helper("memory_copy", vec![dst, src, size]);
+    }
+
+    unsafe fn gen_instr_initblk(&mut self, stack: &mut Vec<TypedValue>) {
+        let size = stack.pop().unwrap().val;
+        let val = stack.pop().unwrap().val;
+        let dst = stack.pop().unwrap().val;
+        self.build_null_check(dst);
+        self.call_helper("memory_set", vec![dst, val, size]);
+    }
+
+    /// Returns a typed reference, which is the address of a value and the method table of its
+    /// type
+    unsafe fn build_typed_ref(
+        &mut self,
+        addr: LLVMValueRef,
+        method_table: LLVMValueRef,
+    ) -> LLVMValueRef {
+        let builder = self.shared_env.builder;
+        let ptr_ty = LLVMPointerType(LLVMInt8TypeInContext(self.shared_env.context), 0);
+        let typed_ref_ty = Type::new(ElementType::TypedByRef).to_llvmty(self);
+        let typed_ref = LLVMBuildInsertValue(
+            builder,
+            LLVMGetUndef(typed_ref_ty),
+            self.typecast(addr, ptr_ty),
+            0,
+            cstr0!(),
+        );
+        LLVMBuildInsertValue(
+            builder,
+            typed_ref,
+            self.typecast(method_table, ptr_ty),
+            1,
+            cstr0!(),
+        )
+    }
+
+    unsafe fn gen_instr_mkrefany(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
+        let addr = stack.pop().unwrap().val;
+        let ty = self.get_type_by_token(token)?;
+        let method_table = self.get_method_table_of_type(&ty)?;
+        let typed_ref = self.build_typed_ref(addr, method_table);
+        stack.push(TypedValue::new(
+            self.shared_env.type_id(&Type::new(ElementType::TypedByRef)),
+            typed_ref,
+        ));
+        Ok(())
+    }
+
+    /// Pushes the address in the typed reference on the stack. Throws ``InvalidCastException`` if
+    /// the type of the reference is not the one that ``token`` refers to.
+    unsafe fn gen_instr_refanyval(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
+        let builder = self.shared_env.builder;
+        let typed_ref = stack.pop().unwrap().val;
+        let ty = self.get_type_by_token(token)?;
+        let method_table = self.get_method_table_of_type(&ty)?;
+        let ref_method_table = LLVMBuildExtractValue(builder, typed_ref, 1, cstr0!());
+        let cond = LLVMBuildICmp(
+            builder,
+            llvm::LLVMIntPredicate::LLVMIntNE,
+            ref_method_table,
+            self.typecast(method_table, LLVMTypeOf(ref_method_table)),
+            cstr0!(),
+        );
+        self.build_throw_if(cond, mscorlib_system_invalid_cast_exception());
+        let addr = LLVMBuildExtractValue(builder, typed_ref, 0, cstr0!());
+        let llvm_ty = ty.to_llvmty(self);
+        stack.push(TypedValue::new(
+            self.shared_env.type_id(&Type::byref_ty(ty)),
+            self.typecast(addr, LLVMPointerType(llvm_ty, 0)),
+        ));
+        Ok(())
+    }
+
+    /// Returns the type that the TypeDef, TypeRef or TypeSpec ``token`` refers to
+    unsafe fn get_type_by_token(&mut self, token: Token) -> CResult<Type> {
+        match self.assembly.image.metadata.get_table_entry(token).unwrap() {
+            Table::TypeDef(_) | Table::TypeRef(_) => {
+                let class_ref = self.get_class_by_token(token)?;
+                let class = class_ref.borrow();
+                Ok(match ((&*class).into(): TypePath).0.as_slice() {
+                    ["mscorlib", "System", "Object"] => Type::object_ty(),
+                    ["mscorlib", "System", "String"] => Type::string_ty(),
+                    ["mscorlib", "System", "SByte"] => Type::i1_ty(),
+                    ["mscorlib", "System", "Byte"] => Type::u1_ty(),
+                    ["mscorlib", "System", "Int16"] => Type::i2_ty(),
+                    ["mscorlib", "System", "UInt16"] => Type::u2_ty(),
+                    ["mscorlib", "System", "Int32"] => Type::i4_ty(),
+                    ["mscorlib", "System", "UInt32"] => Type::u4_ty(),
+                    ["mscorlib", "System", "Int64"] => Type::i8_ty(),
+                    ["mscorlib", "System", "UInt64"] => Type::u8_ty(),
+                    ["mscorlib", "System", "IntPtr"] => Type::i_ty(),
+                    ["mscorlib", "System", "UIntPtr"] => Type::u_ty(),
+                    ["mscorlib", "System", "Single"] => Type::r4_ty(),
+                    ["mscorlib", "System", "Double"] => Type::r8_ty(),
+                    ["mscorlib", "System", "Boolean"] => Type::boolean_ty(),
+                    ["mscorlib", "System", "Char"] => Type::char_ty(),
+                    _ => Type::from_class(class_ref.clone()),
+                })
+            }
+            Table::TypeSpec(tst) => {
+                let ty = self
+                    .assembly
+                    .image
+                    .get_type_from_type_spec(&tst)
+                    .unwrap();
+                Ok(self.instantiate_ty(&ty))
+            }
+            e => unsupported!("{:?}", e),
+        }
+    }
+
+    unsafe fn gen_instr_newarr(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
+        let builder = self.shared_env.builder;
+        let i64_ty = LLVMInt64TypeInContext(self.shared_env.context);
+        // Negative lengths and native int ones beyond int32 overflow
+        let len = self.build_int_cast(stack.pop().unwrap().val, i64_ty, true);
+        let cond = LLVMBuildICmp(
+            builder,
+            llvm::LLVMIntPredicate::LLVMIntUGT,
+            len,
+            LLVMConstInt(i64_ty, i32::MAX as u64, 0),
+            cstr0!(),
+        );
+        self.build_throw_if(cond, mscorlib_system_overflow_exception());
+
+        let elem_ty = self.get_type_by_token(token)?;
+        let llvm_elem_ty = elem_ty.to_llvmty(self);
+        let szarr_ty = Type::szarr_ty(elem_ty.clone());
+        let llvm_szarr_ty = szarr_ty.to_llvmty(self);
+        let elem_sz = self.get_size_of_llvm_class_type(LLVMPointerType(llvm_elem_ty, 0));
+        let len = self.typecast(len, LLVMInt32TypeInContext(self.shared_env.context));
+        let new_arr = self.call_helper("new_szarray", vec![elem_sz, len]);
+        self.build_exception_check();
+
+        let elem_method_table = self.get_element_method_table(&elem_ty);
+        let header = self.typecast(new_arr, LLVMPointerType(LLVMTypeOf(elem_method_table), 0));
+        self.store2element(header, vec![self.llvm_int32(1)], elem_method_table);
+
+        stack.push(TypedValue::new(
+            self.shared_env.type_id(&szarr_ty),
+            self.typecast(new_arr, llvm_szarr_ty),
+        ));
+        Ok(())
+    }
+
+    /// Returns the method table that identifies the elements of the arrays of ``elem_ty`` at
+    /// runtime (see ``new_szarray``), or null if they are references of any type. Every object
+    /// can be stored into object[], so its stores need no checks.
+    unsafe fn get_element_method_table(&mut self, elem_ty: &Type) -> LLVMValueRef {
+        let method_table = self.get_element_method_table_ptr(elem_ty);
+        self.llvm_ptr(method_table)
+    }
+
+    /// Returns the pointer of ``get_element_method_table``. Arrays have no method tables, so the
+    /// descriptors of their types identify them.
+    unsafe fn get_element_method_table_ptr(&mut self, elem_ty: &Type) -> *mut u8 {
+        let (rank, elem_elem_ty) = match elem_ty.base {
+            ElementType::SzArray(ref szarr) => (0, &szarr.elem_ty),
+            ElementType::Array(ref arr) => (arr.rank as i32, &arr.elem_ty),
+            _ => {
+                return match elem_ty.get_class() {
+                    Some(class_ref) if elem_ty.base != ElementType::Object => {
+                        let class = class_ref.borrow();
+                        self.get_llvm_class_type(&class);
+                        self.ensure_all_class_methods_compiled(&class).0 as *mut u8
+                    }
+                    _ => ptr::null_mut(),
+                }
+            }
+        };
+        let elem_elem = self.get_element_method_table_ptr(elem_elem_ty);
+        array_type_descriptor(rank, elem_elem)
+    }
+
+    unsafe fn gen_instr_newobj(
+        &mut self,
+        stack: &mut Vec<TypedValue>,
+        token: Token,
+    ) -> CResult<()> {
+        unsafe fn newobj_method(
+            compiler: &mut JITCompiler,
+            stack: &mut Vec<TypedValue>,
+            method_ref: &MethodInfoRef,
+        ) -> CResult<()> {
+            let method_info = method_ref.borrow();
+            let method = method_info.as_mdef();
+            let method_sig = method.ty.as_fnptr().unwrap();
+            let class = method.class.borrow();
+
+            if !class.is_before_field_init() {
+                compiler.build_type_initialization(&class);
+            }
+
+            // A value type is constructed in a temporary and pushed as a value
+            if class.is_valuetype() {
+                let ty = Type::from_class(method.class.clone());
+                let llvm_ty = ty.to_llvmty(compiler);
+                let new_val = compiler.build_entry_alloca(llvm_ty);
+                LLVMBuildStore(compiler.shared_env.builder, LLVMConstNull(llvm_ty), new_val);
+                let (_, mut args) = get_arg_vals_from_stack(stack, method_sig.params.len(), false);
+                args.insert(0, new_val);
+
+                let func = compiler.get_function_to_call(method_ref)?;
+                compiler.call_function(func, args);
+                compiler.build_exception_check();
+
+                stack.push(TypedValue::new(
+                    compiler.shared_env.type_id(&ty),
+                    LLVMBuildLoad(compiler.shared_env.builder, new_val, cstr0!()),
+                ));
+                return Ok(());
+            }
+
+            let llvm_class_ty = compiler.get_llvm_class_type(&class);
+            let new_obj = compiler.typecast(
+                compiler.call_memory_alloc(compiler.get_size_of_llvm_class_type(llvm_class_ty)),
+                llvm_class_ty,
+            );
+            let (_, mut args) = get_arg_vals_from_stack(stack, method_sig.params.len(), false);
+            args.insert(0, new_obj);
+
+            let func = compiler.get_function_to_call(method_ref)?;
+            compiler.call_function(func, args);
+            compiler.build_exception_check();
+
+            let (_, method_table) = compiler.ensure_all_class_methods_compiled(&*class);
+
+            compiler.store2element(
+                new_obj,
+                vec![compiler.llvm_int32(0), compiler.llvm_int32(0)],
+                method_table,
+            );
+
+            stack.push(TypedValue::new(
+                compiler
+                    .shared_env
+                    .type_id(&Type::class_ty(method.class.clone())),
+                new_obj,
+            ));
+            Ok(())
+        }
+
+        match self.assembly.image.metadata.get_table_entry(token).unwrap() {
+            Table::MemberRef(mrt) => {
+                if let Some(Table::TypeSpec(tst)) =
+                    self.assembly.image.metadata.get_table_entry(mrt.class2token().unwrap())
+                {
+                    if let Some(arr_ty) = self.get_array_type_from_type_spec(&tst) {
+                        return self.gen_array_method(stack, arr_ty, &mrt);
+                    }
+                    let method_ref = self.get_generic_inst_method(&mrt, &tst);
+                    return newobj_method(self, stack, &method_ref);
+                }
+
+                let class = self.get_class_by_token(mrt.class2token().unwrap())?;
+                let class_borrowed = class.borrow();
+                let method_name = self.assembly.image.get_string(mrt.name).unwrap();
+                let type_path = (&*class_borrowed).into(): TypePath;
+                let method_ty = self
+                    .assembly
+                    .image
+                    .get_method_ref_type_from_signature(mrt.signature)
+                    .unwrap();
+                if let Some(method_ref) =
+                    find_runtime_method(&class_borrowed, method_name, &method_ty)
+                {
+                    return newobj_method(self, stack, &method_ref);
+                }
+                let (func, method_sig) = if let Some(f) = self
+                    .shared_env
+                    .methods
+                    .get_method(type_path.with_method_name(method_name), &method_ty)
+                {
+                    (f.llvm_function, f.ty.as_fnptr().unwrap().clone())
+                } else {
+                    unsupported!("{}::{}", class_borrowed.get_full_name(), method_name)
+                };
+                let llvm_class_ty = self.get_llvm_class_type(&*class_borrowed);
+                let new_obj = self.typecast(
+                    self.call_memory_alloc(self.get_size_of_llvm_class_type(llvm_class_ty)),
+                    llvm_class_ty,
+                );
+                let (_, mut args) = get_arg_vals_from_stack(stack, method_sig.params.len(), false);
+                args.insert(0, new_obj);
+
+                self.call_function(func, args);
+                self.build_exception_check();
+
+                let (_, method_table) = self.ensure_all_class_methods_compiled(&*class_borrowed);
+
+                self.store2element(
+                    new_obj,
+                    vec![self.llvm_int32(0), self.llvm_int32(0)],
+                    method_table,
+                );
+
+                stack.push(TypedValue::new(
+                    self.shared_env.type_id(&Type::class_ty(class.clone())),
+                    new_obj,
+                ));
+                Ok(())
+            } // TODO
+            Table::MethodDef(_) => {
+                let method_ref = self.assembly.image.get_method(token).unwrap();
+                newobj_method(self, stack, &method_ref)
+            }
+            e => unsupported!("call: {:?}", e),
+        }
+    }
+
+    /// Returns the method of a generic class instance that ``mrt`` refers to
+    unsafe fn get_generic_inst_method(
+        &mut self,
+        mrt: &MemberRefTable,
+        tst: &TypeSpecTable,
+    ) -> MethodInfoRef {
+        let class_ref = self.get_class_from_type_spec(tst);
+        let class = class_ref.borrow();
+        let name = self.assembly.image.get_string(mrt.name).unwrap();
+        // The signature is written in terms of the generic type definition
+        let ty = self
+            .assembly
+            .image
+            .get_method_ref_type_from_signature(mrt.signature)
+            .unwrap()
+            .instantiate(&GenericContext {
+                class_args: class.generic_args.clone(),
+                method_args: vec![],
+            });
+        class
+            .methods
+            .iter()
+            .find(|m| {
+                let m = m.borrow();
+                m.get_name() == name && m.as_mdef().ty == ty
+            })
+            .unwrap()
+            .clone()
+    }
+
+    /// Returns the instance of the generic method that ``mst`` refers to
+    unsafe fn get_method_spec_instance(&mut self, mst: &MethodSpecTable) -> CResult<MethodInfoRef> {
+        let args = self
+            .assembly
+            .image
+            .get_type_args_from_method_spec(mst)
+            .unwrap()
+            .iter()
+            .map(|ty| self.instantiate_ty(ty))
+            .collect::<Vec<Type>>();
+        let method_token = mst.method_decoded().unwrap();
+        let method_ref = match self
+            .assembly
+            .image
+            .metadata
+            .get_table_entry(method_token)
+            .unwrap()
+        {
+            Table::MethodDef(_) => self.assembly.image.get_method(method_token).unwrap(),
+            Table::MemberRef(mrt) => {
+                match self
+                    .assembly
+                    .image
+                    .metadata
+                    .get_table_entry(mrt.class2token().unwrap())
+                    .unwrap()
+                {
+                    Table::TypeSpec(tst) => self.get_generic_inst_method(&mrt, &tst),
+                    // Generic methods of the classes of other assemblies or modules
+                    Table::TypeDef(_) | Table::TypeRef(_) => {
+                        let class_ref = self
+                            .assembly
+                            .image
+                            .get_class(mrt.class2token().unwrap())
+                            .unwrap()
+                            .clone();
+                        let name = self.assembly.image.get_string(mrt.name).unwrap();
+                        let ty = self
+                            .assembly
+                            .image
+                            .get_method_ref_type_from_signature(mrt.signature)
+                            .unwrap();
+                        let class = class_ref.borrow();
+                        class
+                            .methods
+                            .iter()
+                            .find(|m| {
+                                let m = m.borrow();
+                                m.get_name() == name && m.as_mdef().ty == ty
+                            })
+                            .unwrap()
+                            .clone()
+                    }
+                    e => unsupported!("{:?}", e),
+                }
+            }
+            e => unsupported!("{:?}", e),
+        };
+        let instance = method_ref.borrow().as_mdef().instantiate(&args);
+        Ok(Rc::new(RefCell::new(MethodInfo::MDef(instance))))
+    }
+
+    /// Returns the builtin function and the instantiated signature of the generic mscorlib method
+    /// that ``mst`` refers to (e.g. ``Interlocked.CompareExchange<T>``)
+    unsafe fn get_builtin_method_spec_instance(
+        &mut self,
+        mst: &MethodSpecTable,
+    ) -> Option<(LLVMValueRef, MethodSignature)> {
+        let mrt = match self
+            .assembly
+            .image
+            .metadata
+            .get_table_entry(mst.method_decoded()?)?
+        {
+            Table::MemberRef(mrt) => mrt,
+            _ => return None,
+        };
+        let trt = match self.assembly.image.metadata.get_table_entry(mrt.class2token()?)? {
+            Table::TypeRef(trt) => trt,
+            _ => return None,
+        };
+        let type_path = self.assembly.image.get_path_from_type_ref_table(&trt)?;
+        let name = self.assembly.image.get_string(mrt.name)?;
+        let ty = self
+            .assembly
+            .image
+            .get_method_ref_type_from_signature(mrt.signature)?;
+        let func = self
+            .shared_env
+            .methods
+            .get_method(type_path.with_method_name(name), &ty)?
+            .llvm_function;
+        let args = self
+            .assembly
+            .image
+            .get_type_args_from_method_spec(mst)?
+            .iter()
+            .map(|ty| self.instantiate_ty(ty))
+            .collect::<Vec<Type>>();
+        let ty = ty.instantiate(&GenericContext {
+            class_args: vec![],
+            method_args: args,
+        });
+        Some((func, ty.as_fnptr().unwrap().clone()))
+    }
+
+    unsafe fn get_class_from_type_spec(&self, tst: &TypeSpecTable) -> ClassInfoRef {
+        let ty = self
+            .assembly
+            .image
+            .get_type_from_type_spec(tst)
+            .unwrap();
+        self.instantiate_ty(&ty).get_class().unwrap()
+    }
+
+    /// Replaces the type parameters in ``ty`` with the type arguments of the method being
+    /// compiled
+    fn instantiate_ty(&self, ty: &Type) -> Type {
+        ty.instantiate(&self.env.generic_context)
+    }
+
+    unsafe fn setup_exception_handling(&mut self, method: &MethodDefInfo) -> CResult<()> {
+        self.env.exception_clauses = method.exception_clauses.clone();
+
+        let ctx = self.shared_env.context;
+        for (i, clause) in method.exception_clauses.iter().enumerate() {
+            let selector = self.build_entry_alloca(LLVMInt32TypeInContext(ctx));
+            let exception =
+                self.build_entry_alloca(LLVMPointerType(LLVMInt8TypeInContext(ctx), 0));
+            self.env.clause_slots.insert(i, (selector, exception));
+            if let ExceptionClauseKind::Catch(token) = clause.kind {
+                let class_ref = self.get_class_by_token(token)?;
+                self.env.catch_classes.insert(i, class_ref);
+            }
+        }
+
+        // ``leave`` runs the finally handlers of the try blocks it exits, from the innermost one.
+        for (pc, instr) in method.body.iter().enumerate() {
+            let target = match instr {
+                Instruction::Leave(target) => *target,
+                _ => continue,
+            };
+            let mut continuation = FinallyContinuation::Leave { target };
+            for (i, clause) in method.exception_clauses.iter().enumerate().rev() {
+                if clause.kind != ExceptionClauseKind::Finally
+                    || !clause.try_contains(pc)
+                    || clause.try_contains(target)
+                {
+                    continue;
+                }
+                let continuations = self
+                    .env
+                    .finally_continuations
+                    .entry(i)
+                    .or_insert_with(|| vec![]);
+                continuations.push(continuation);
+                // Selector 0 means the handler is entered by an exception
+                continuation = FinallyContinuation::Finally {
+                    clause: i,
+                    selector: continuations.len() as u64,
+                };
+            }
+            self.env.leave_continuations.insert(pc, continuation);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the class that the TypeDef, TypeRef or TypeSpec ``token`` refers to
+    unsafe fn get_class_by_token(&self, token: Token) -> CResult<ClassInfoRef> {
+        let class_ref = match self.assembly.image.metadata.get_table_entry(token) {
+            Some(Table::TypeDef(_)) | Some(Table::TypeRef(_)) => {
+                self.assembly.image.get_class(token).cloned()
+            }
+            Some(Table::TypeSpec(tst)) => self
+                .assembly
+                .image
+                .get_type_from_type_spec(&tst)
+                .and_then(|ty| self.instantiate_ty(&ty).get_class()),
+            _ => None,
+        };
+        class_ref.ok_or_else(|| {
+            Error::CouldntCompile(format!("no class for the token {:#x}", token.0))
+        })
+    }
+
+    /// Returns the clauses whose try block contains ``pc``, from the innermost one
+    fn enclosing_clauses(&self, pc: usize) -> Vec<usize> {
+        (0..self.env.exception_clauses.len())
+            .filter(|&i| self.env.exception_clauses[i].try_contains(pc))
+            .collect()
+    }
+
+    /// Returns the clauses that an exception escaping from the handler of ``clause`` reaches
+    fn outer_clauses(&self, clause: usize) -> Vec<usize> {
+        let inner = &self.env.exception_clauses[clause];
+        (clause + 1..self.env.exception_clauses.len())
+            .filter(|&i| {
+                let outer = &self.env.exception_clauses[i];
+                outer.try_offset <= inner.try_offset
+                    && inner.try_offset + inner.try_length <= outer.try_offset + outer.try_length
+            })
+            .collect()
+    }
+
+    fn find_innermost_clause<F>(&self, f: F) -> usize
+    where
+        F: Fn(&ExceptionClause) -> bool,
+    {
+        self.env
+            .exception_clauses
+            .iter()
+            .enumerate()
+            .filter(|(_, clause)| f(clause))
+            .min_by_key(|(_, clause)| clause.handler_length)
+            .map(|(i, _)| i)
+            .unwrap()
+    }
+
+    /// Returns the exception that the catch handler or filter starting at ``start`` receives
+    unsafe fn get_caught_exception(&mut self, start: usize) -> Option<TypedValue> {
+        let (i, kind) = self
+            .env
+            .exception_clauses
+            .iter()
+            .enumerate()
+            .find(|(_, clause)| match clause.kind {
+                ExceptionClauseKind::Catch(_) => clause.handler_offset == start,
+                ExceptionClauseKind::Filter { filter_offset } => {
+                    clause.handler_offset == start || filter_offset == start
+                }
+                _ => false,
+            })
+            .map(|(i, clause)| (i, clause.kind.clone()))?;
+        let ty = match kind {
+            ExceptionClauseKind::Catch(_) => Type::class_ty(self.env.catch_classes[&i].clone()),
+            _ => Type::object_ty(),
+        };
+        let (_, exception_slot) = self.env.clause_slots[&i];
+        Some(TypedValue::new(
+            self.shared_env.type_id(&ty),
+            LLVMBuildLoad(self.shared_env.builder, exception_slot, cstr0!()),
+        ))
+    }
+
+    /// Returns the block that passes the pending exception to the handler of the first
+    /// matching clause in ``clauses``, or returns from the method if none of them matches.
+    unsafe fn get_dispatch_block(&mut self, clauses: Vec<usize>) -> LLVMBasicBlockRef {
+        if let Some(bb) = self.env.dispatch_blocks.get(&clauses) {
+            return *bb;
+        }
+
+        let func = self.generating.unwrap();
+        let builder = self.shared_env.builder;
+        let bb_cur = LLVMGetInsertBlock(builder);
+        let bb_dispatch = LLVMAppendBasicBlock(func, cstr0!());
+
+        self.env.dispatch_blocks.insert(clauses.clone(), bb_dispatch);
+
+        match clauses.split_first() {
+            None => {
+                LLVMPositionBuilderAtEnd(builder, bb_dispatch);
+                if self.env.is_main {
+                    self.call_helper("unhandled_exception", vec![]);
+                }
+                // The exception stays pending, so the caller continues to dispatch it
+                let ret_ty = LLVMGetElementType(LLVMGetReturnType(LLVMTypeOf(func)));
+                if LLVMGetTypeKind(ret_ty) == llvm::LLVMTypeKind::LLVMVoidTypeKind {
+                    LLVMBuildRetVoid(builder);
+                } else {
+                    LLVMBuildRet(builder, LLVMConstNull(ret_ty));
+                }
+            }
+            Some((&i, rest)) => {
+                let clause = self.env.exception_clauses[i].clone();
+                let (selector, exception_slot) = self.env.clause_slots[&i];
+                let bb_handler = self.get_basic_block(clause.handler_offset).retrieve();
+                match clause.kind {
+                    ExceptionClauseKind::Catch(_) => {
+                        let bb_next = self.get_dispatch_block(rest.to_vec());
+                        let class_ref = self.env.catch_classes[&i].clone();
+                        let bb_catch = LLVMAppendBasicBlock(func, cstr0!());
+                        LLVMPositionBuilderAtEnd(builder, bb_dispatch);
+                        let exception = self.call_helper("get_exception", vec![]);
+                        let cond = self.build_is_instance(exception, &class_ref.borrow());
+                        LLVMBuildCondBr(builder, cond, bb_catch, bb_next);
+                        LLVMPositionBuilderAtEnd(builder, bb_catch);
+                        let exception = self.call_helper("take_exception", vec![]);
+                        LLVMBuildStore(builder, exception, exception_slot);
+                        LLVMBuildBr(builder, bb_handler);
+                    }
+                    ExceptionClauseKind::Filter { filter_offset } => {
+                        let bb_filter = self.get_basic_block(filter_offset).retrieve();
+                        LLVMPositionBuilderAtEnd(builder, bb_dispatch);
+                        let exception = self.call_helper("take_exception", vec![]);
+                        LLVMBuildStore(builder, exception, exception_slot);
+                        LLVMBuildBr(builder, bb_filter);
+                    }
+                    ExceptionClauseKind::Finally | ExceptionClauseKind::Fault => {
+                        LLVMPositionBuilderAtEnd(builder, bb_dispatch);
+                        let exception = self.call_helper("take_exception", vec![]);
+                        LLVMBuildStore(builder, exception, exception_slot);
+                        LLVMBuildStore(builder, self.llvm_int32(0), selector);
+                        LLVMBuildBr(builder, bb_handler);
+                    }
+                }
             }
-            e => unimplemented!("{:?}", e),
         }
-    }
 
-    unsafe fn gen_instr_ldelem_i1(&mut self, stack: &mut Vec<TypedValue>) {
-        let typed_val = self.gen_instr_general_ldelem(stack);
-        stack.push(typed_val);
+        LLVMPositionBuilderAtEnd(builder, bb_cur);
+
+        bb_dispatch
     }
 
-    unsafe fn gen_instr_ldelem_i4(&mut self, stack: &mut Vec<TypedValue>) {
-        let typed_val = self.gen_instr_general_ldelem(stack);
-        stack.push(typed_val);
+    /// Branches to the dispatcher if the preceding call left an exception pending
+    unsafe fn build_exception_check(&mut self) {
+        let builder = self.shared_env.builder;
+        let pending = self.call_helper("exception_pending", vec![]);
+        let cond = LLVMBuildICmp(
+            builder,
+            llvm::LLVMIntPredicate::LLVMIntNE,
+            pending,
+            LLVMConstNull(LLVMTypeOf(pending)),
+            cstr0!(),
+        );
+        let bb_dispatch = self.get_dispatch_block(self.enclosing_clauses(self.env.pc));
+        let bb_continue = LLVMAppendBasicBlock(self.generating.unwrap(), cstr0!());
+        LLVMBuildCondBr(builder, cond, bb_dispatch, bb_continue);
+        LLVMPositionBuilderAtEnd(builder, bb_continue);
     }
 
-    unsafe fn gen_instr_ldelem_ref(&mut self, stack: &mut Vec<TypedValue>) {
-        let typed_val = self.gen_instr_general_ldelem(stack);
-        stack.push(typed_val)
+    /// Throws a new ``class`` exception if ``cond`` is true
+    unsafe fn build_throw_if(&mut self, cond: LLVMValueRef, class_ref: ClassInfoRef) {
+        let func = self.generating.unwrap();
+        let builder = self.shared_env.builder;
+        let bb_throw = LLVMAppendBasicBlock(func, cstr0!());
+        let bb_continue = LLVMAppendBasicBlock(func, cstr0!());
+        LLVMBuildCondBr(builder, cond, bb_throw, bb_continue);
+
+        LLVMPositionBuilderAtEnd(builder, bb_throw);
+        let class = class_ref.borrow();
+        let llvm_class_ty = self.get_llvm_class_type(&class);
+        let exception = self.typecast(
+            self.call_memory_alloc(self.get_size_of_llvm_class_type(llvm_class_ty)),
+            llvm_class_ty,
+        );
+        let (_, method_table) = self.ensure_all_class_methods_compiled(&class);
+        self.store2element(
+            exception,
+            vec![self.llvm_int32(0), self.llvm_int32(0)],
+            method_table,
+        );
+        let ctor = self
+            .shared_env
+            .methods
+            .get_method(
+                ((&*class).into(): TypePath).with_method_name(".ctor"),
+                &Type::full_method_ty(0x20, Type::void_ty(), &[]),
+            )
+            .unwrap()
+            .llvm_function;
+        self.call_function(ctor, vec![exception]);
+        self.build_throw(exception);
+
+        LLVMPositionBuilderAtEnd(builder, bb_continue);
     }
 
-    unsafe fn gen_instr_general_ldelem(&mut self, stack: &mut Vec<TypedValue>) -> TypedValue {
-        let index = stack.pop().unwrap().val;
-        let TypedValue {
-            ty: arr_ty,
-            val: array,
-        } = stack.pop().unwrap();
-        TypedValue::new(
-            self.shared_env
-                .type_id_with(|e| &e.ty_arena[arr_ty].as_szarray().unwrap().elem_ty),
-            self.load_element(
-                array,
-                vec![LLVMBuildAdd(
-                    self.shared_env.builder,
-                    self.llvm_int32(1),
-                    index,
-                    cstr0!(),
-                )],
-            ),
-        )
+    unsafe fn build_null_check(&mut self, obj: LLVMValueRef) {
+        let cond = LLVMBuildIsNull(self.shared_env.builder, obj, cstr0!());
+        self.build_throw_if(cond, mscorlib_system_null_reference_exception());
     }
 
-    unsafe fn gen_instr_stelem_i1(&mut self, stack: &mut Vec<TypedValue>) {
-        self.gen_instr_general_stelem(stack)
+    unsafe fn build_bounds_check(&mut self, array: LLVMValueRef, index: LLVMValueRef) {
+        self.build_null_check(array);
+        let len = self.load_array_length(array);
+        // Negative indexes are also out of range when compared as unsigned
+        let cond = LLVMBuildICmp(
+            self.shared_env.builder,
+            llvm::LLVMIntPredicate::LLVMIntUGE,
+            index,
+            self.typecast(len, LLVMTypeOf(index)),
+            cstr0!(),
+        );
+        self.build_throw_if(cond, mscorlib_system_index_out_of_range_exception());
     }
 
-    unsafe fn gen_instr_stelem_i4(&mut self, stack: &mut Vec<TypedValue>) {
-        self.gen_instr_general_stelem(stack)
+    unsafe fn build_divisor_check(&mut self, divisor: LLVMValueRef) {
+        if LLVMGetTypeKind(LLVMTypeOf(divisor)) != llvm::LLVMTypeKind::LLVMIntegerTypeKind {
+            return;
+        }
+        let cond = LLVMBuildIsNull(self.shared_env.builder, divisor, cstr0!());
+        self.build_throw_if(cond, mscorlib_system_divide_by_zero_exception());
     }
 
-    unsafe fn gen_instr_stelem_ref(&mut self, stack: &mut Vec<TypedValue>) {
-        self.gen_instr_general_stelem(stack)
+    /// Throws ``OverflowException`` if the quotient is out of range, which is the case only for the
+    /// minimum value divided by -1 (LLVM's sdiv traps on it)
+    unsafe fn build_division_overflow_check(
+        &mut self,
+        dividend: LLVMValueRef,
+        divisor: LLVMValueRef,
+    ) {
+        let ty = LLVMTypeOf(dividend);
+        if LLVMGetTypeKind(ty) != llvm::LLVMTypeKind::LLVMIntegerTypeKind {
+            return;
+        }
+        let builder = self.shared_env.builder;
+        let min = LLVMConstInt(ty, 1 << (LLVMGetIntTypeWidth(ty) - 1), 0);
+        let is_min = LLVMBuildICmp(
+            builder,
+            llvm::LLVMIntPredicate::LLVMIntEQ,
+            dividend,
+            min,
+            cstr0!(),
+        );
+        let is_minus_one = LLVMBuildICmp(
+            builder,
+            llvm::LLVMIntPredicate::LLVMIntEQ,
+            self.typecast(divisor, ty),
+            LLVMConstAllOnes(ty),
+            cstr0!(),
+        );
+        let cond = LLVMBuildAnd(builder, is_min, is_minus_one, cstr0!());
+        self.build_throw_if(cond, mscorlib_system_overflow_exception());
     }
 
-    unsafe fn gen_instr_general_stelem(&mut self, stack: &mut Vec<TypedValue>) {
-        let value = stack.pop().unwrap().val;
-        let index = stack.pop().unwrap().val;
-        let array = stack.pop().unwrap().val;
-        self.store2element(
-            array,
-            vec![LLVMBuildAdd(
-                self.shared_env.builder,
-                self.llvm_int32(1),
-                index,
-                cstr0!(),
-            )],
-            value,
+    /// Returns 1 instead of ``divisor`` if it is -1 because the remainder is 0 either way, whereas
+    /// LLVM's srem traps on the minimum value divided by -1
+    unsafe fn replace_minus_one_divisor(&mut self, divisor: LLVMValueRef) -> LLVMValueRef {
+        let ty = LLVMTypeOf(divisor);
+        if LLVMGetTypeKind(ty) != llvm::LLVMTypeKind::LLVMIntegerTypeKind {
+            return divisor;
+        }
+        let builder = self.shared_env.builder;
+        let is_minus_one = LLVMBuildICmp(
+            builder,
+            llvm::LLVMIntPredicate::LLVMIntEQ,
+            divisor,
+            LLVMConstAllOnes(ty),
+            cstr0!(),
         );
+        let one = LLVMConstInt(ty, 1, 0);
+        LLVMBuildSelect(builder, is_minus_one, one, divisor, cstr0!())
     }
 
-    unsafe fn gen_instr_ldlen(&mut self, stack: &mut Vec<TypedValue>) {
-        let array = self.typecast(
-            stack.pop().unwrap().val,
-            LLVMPointerType(LLVMInt32TypeInContext(self.shared_env.context), 0),
+    /// Throws ``ArithmeticException`` if ``val`` is NaN or an infinity
+    unsafe fn build_finite_check(&mut self, val: LLVMValueRef) {
+        // ``val - val`` is 0 for finite values and NaN otherwise
+        let diff = LLVMBuildFSub(self.shared_env.builder, val, val, cstr0!());
+        let cond = LLVMBuildFCmp(
+            self.shared_env.builder,
+            llvm::LLVMRealPredicate::LLVMRealUNE,
+            diff,
+            LLVMConstReal(LLVMTypeOf(val), 0.0),
+            cstr0!(),
         );
-        let index = self.load_element(array, vec![self.llvm_int32(0)]);
-        stack.push(TypedValue::new(
-            self.shared_env.type_id(&Type::i4_ty()),
-            index,
-        ));
+        self.build_throw_if(cond, mscorlib_system_arithmetic_exception());
     }
 
-    unsafe fn gen_instr_conv_r_un(&mut self, stack: &mut Vec<TypedValue>) {
-        let value = stack.pop().unwrap().val;
-        stack.push(TypedValue::new(
-            self.shared_env.type_id(&Type::r8_ty()),
-            LLVMBuildUIToFP(
-                self.shared_env.builder,
-                value,
-                LLVMDoubleTypeInContext(self.shared_env.context),
-                cstr0!(),
-            ),
-        ))
+    unsafe fn build_throw(&mut self, exception: LLVMValueRef) {
+        self.call_helper("throw_exception", vec![exception]);
+        let bb_dispatch = self.get_dispatch_block(self.enclosing_clauses(self.env.pc));
+        LLVMBuildBr(self.shared_env.builder, bb_dispatch);
     }
 
-    unsafe fn gen_instr_box(&mut self, stack: &mut Vec<TypedValue>, token: Token) {
-        let val = stack.pop().unwrap().val;
-        match self.assembly.image.metadata.get_table_entry(token).unwrap() {
-            Table::TypeRef(_) => {
-                let class_ref = self.assembly.image.get_class(token).unwrap().clone();
-                let class = class_ref.borrow();
-                let llvm_class = self.get_llvm_class_type(&class);
-                let new_obj = self.typecast(
-                    self.call_memory_alloc(self.get_size_of_llvm_class_type(llvm_class)),
-                    llvm_class,
-                );
-                let (_, method_table) = self.ensure_all_class_methods_compiled(&*class);
-                self.store2element(
-                    new_obj,
-                    vec![self.llvm_int32(0), self.llvm_int32(0)],
-                    method_table,
+    unsafe fn build_finally_continuation(&mut self, continuation: FinallyContinuation) {
+        match continuation {
+            FinallyContinuation::Leave { target } => {
+                let bb_target = self.get_basic_block(target).retrieve();
+                LLVMBuildBr(self.shared_env.builder, bb_target);
+            }
+            FinallyContinuation::Finally { clause, selector } => {
+                let (selector_slot, _) = self.env.clause_slots[&clause];
+                let handler_offset = self.env.exception_clauses[clause].handler_offset;
+                let bb_handler = self.get_basic_block(handler_offset).retrieve();
+                LLVMBuildStore(
+                    self.shared_env.builder,
+                    self.llvm_int32(selector),
+                    selector_slot,
                 );
-                self.store2element(new_obj, vec![self.llvm_int32(0), self.llvm_int32(1)], val);
-                stack.push(TypedValue::new(
-                    self.shared_env.type_id(&Type::object_ty()),
-                    new_obj,
-                ));
+                LLVMBuildBr(self.shared_env.builder, bb_handler);
             }
-            e => unimplemented!("newarr: unimplemented: {:?}", e),
         }
     }
 
-    unsafe fn gen_instr_newarr(&mut self, stack: &mut Vec<TypedValue>, token: Token) {
-        unsafe fn newarr_typeref<'a>(
-            compiler: &mut JITCompiler,
-            len: LLVMValueRef,
-            trt: &TypeRefTable,
-        ) -> TypedValue {
-            let TypePath(path) = compiler.assembly.image.get_path_from_type_ref_table(trt);
-            let ty = match (path[0], path[1], path[2]) {
-                ("mscorlib", "System", ty) => ty,
-                _ => unimplemented!(),
-            };
-            let (szarr_ty, sz) = match ty {
-                "Int32" => (Type::i4_szarr_ty(), 4),
-                "Boolean" => (Type::boolean_szarr_ty(), 1),
-                "Object" => (Type::object_szarr_ty(), 8),
-                _ => unimplemented!(),
-            };
-            let llvm_szarr_ty = szarr_ty.to_llvmty(compiler);
-            let new_arr = compiler.typecast(
-                compiler.call_function(
-                    compiler
-                        .shared_env
-                        .methods
-                        .get_helper_function("new_szarray")
-                        .unwrap()
-                        .llvm_function,
-                    vec![compiler.llvm_int32(sz), len],
-                ),
-                llvm_szarr_ty,
-            );
-            TypedValue::new(compiler.shared_env.type_id(&szarr_ty), new_arr)
-        }
-
-        unsafe fn newarr_typedef<'a>(
-            compiler: &mut JITCompiler,
-            len: LLVMValueRef,
-            token: Token,
-        ) -> TypedValue {
-            let elem_ty = Type::new(ElementType::Class(
-                compiler.assembly.image.get_class(token).unwrap().clone(),
-            ));
-            let llvm_elem_ty = elem_ty.to_llvmty(compiler);
-            let szarr_ty = Type::szarr_ty(elem_ty);
-            let llvm_szarr_ty = szarr_ty.to_llvmty(compiler);
-            let new_arr = compiler.typecast(
-                compiler.call_function(
-                    compiler
-                        .shared_env
-                        .methods
-                        .get_helper_function("new_szarray")
-                        .unwrap()
-                        .llvm_function,
-                    vec![compiler.get_size_of_llvm_class_type(llvm_elem_ty), len],
-                ),
-                llvm_szarr_ty,
-            );
-            TypedValue::new(compiler.shared_env.type_id(&szarr_ty), new_arr)
-        }
+    unsafe fn gen_instr_endfinally(&mut self) {
+        let pc = self.env.pc;
+        let clause =
+            self.find_innermost_clause(|c| c.is_finally_or_fault() && c.handler_contains(pc));
+        let (selector_slot, exception_slot) = self.env.clause_slots[&clause];
+        let continuations = self
+            .env
+            .finally_continuations
+            .get(&clause)
+            .cloned()
+            .unwrap_or(vec![]);
+        let func = self.generating.unwrap();
+        let builder = self.shared_env.builder;
 
-        let len = stack.pop().unwrap().val;
+        let bb_rethrow = LLVMAppendBasicBlock(func, cstr0!());
+        let selector = LLVMBuildLoad(builder, selector_slot, cstr0!());
+        let switch = LLVMBuildSwitch(builder, selector, bb_rethrow, continuations.len() as u32);
 
-        match self.assembly.image.metadata.get_table_entry(token).unwrap() {
-            Table::TypeRef(trt) => stack.push(newarr_typeref(self, len, &trt)),
-            Table::TypeDef(_) => stack.push(newarr_typedef(self, len, token)),
-            e => unimplemented!("newarr: unimplemented: {:?}", e),
+        for (i, continuation) in continuations.into_iter().enumerate() {
+            let bb = LLVMAppendBasicBlock(func, cstr0!());
+            LLVMAddCase(switch, self.llvm_int32(i as u64 + 1), bb);
+            LLVMPositionBuilderAtEnd(builder, bb);
+            self.build_finally_continuation(continuation);
         }
-    }
-
-    unsafe fn gen_instr_newobj(&mut self, stack: &mut Vec<TypedValue>, token: Token) {
-        match self.assembly.image.metadata.get_table_entry(token).unwrap() {
-            Table::MemberRef(mrt) => {
-                let class = self
-                    .assembly
-                    .image
-                    .get_class(mrt.class_decoded())
-                    .unwrap()
-                    .clone();
-                let class_borrowed = class.borrow();
-                let method_name = self.assembly.image.get_string(mrt.name);
-                let type_path = (&*class_borrowed).into(): TypePath;
-                let method_ty = self
-                    .assembly
-                    .image
-                    .get_method_ref_type_from_signature(mrt.signature);
-                let (func, method_sig) = if let Some(f) = self
-                    .shared_env
-                    .methods
-                    .get_method(type_path.with_method_name(method_name), &method_ty)
-                {
-                    (f.llvm_function, f.ty.as_fnptr().unwrap().clone())
-                } else {
-                    panic!();
-                };
-                let llvm_class_ty = self.get_llvm_class_type(&*class_borrowed);
-                let new_obj = self.typecast(
-                    self.call_memory_alloc(self.get_size_of_llvm_class_type(llvm_class_ty)),
-                    llvm_class_ty,
-                );
-                let (_, mut args) = get_arg_vals_from_stack(stack, method_sig.params.len(), false);
-                args.insert(0, new_obj);
-
-                self.call_function(func, args);
-
-                let (_, method_table) = self.ensure_all_class_methods_compiled(&*class_borrowed);
-
-                self.store2element(
-                    new_obj,
-                    vec![self.llvm_int32(0), self.llvm_int32(0)],
-                    method_table,
-                );
-
-                stack.push(TypedValue::new(
-                    self.shared_env.type_id(&Type::class_ty(class.clone())),
-                    new_obj,
-                ))
-            } // TODO
-            Table::MethodDef(mdt) => {
-                let method_ref = self.assembly.image.get_method_by_rva(mdt.rva).unwrap();
-                let method_info = method_ref.borrow();
-                let method = method_info.as_mdef();
-                let method_sig = method.ty.as_fnptr().unwrap();
-                let llvm_class_ty = self.get_llvm_class_type(&method.class.borrow());
-                let new_obj = self.typecast(
-                    self.call_memory_alloc(self.get_size_of_llvm_class_type(llvm_class_ty)),
-                    llvm_class_ty,
-                );
-                let (_, mut args) = get_arg_vals_from_stack(stack, method_sig.params.len(), false);
-                args.insert(0, new_obj);
 
-                let func = self.get_function_by_rva(mdt.rva);
-                self.call_function(func, args);
+        // Entered by an exception. Continue to dispatch it.
+        LLVMPositionBuilderAtEnd(builder, bb_rethrow);
+        let exception = LLVMBuildLoad(builder, exception_slot, cstr0!());
+        self.call_helper("throw_exception", vec![exception]);
+        let bb_dispatch = self.get_dispatch_block(self.outer_clauses(clause));
+        LLVMBuildBr(builder, bb_dispatch);
+    }
 
-                let class = method.class.borrow();
-                let (_, method_table) = self.ensure_all_class_methods_compiled(&*class);
+    unsafe fn gen_instr_endfilter(&mut self, val: LLVMValueRef) {
+        let pc = self.env.pc;
+        let clause = self.find_innermost_clause(|c| c.filter_contains(pc));
+        let (_, exception_slot) = self.env.clause_slots[&clause];
+        let handler_offset = self.env.exception_clauses[clause].handler_offset;
+        let bb_handler = self.get_basic_block(handler_offset).retrieve();
+        let bb_reject = LLVMAppendBasicBlock(self.generating.unwrap(), cstr0!());
+        let builder = self.shared_env.builder;
+
+        let cond = LLVMBuildICmp(
+            builder,
+            llvm::LLVMIntPredicate::LLVMIntNE,
+            val,
+            LLVMConstNull(LLVMTypeOf(val)),
+            cstr0!(),
+        );
+        LLVMBuildCondBr(builder, cond, bb_handler, bb_reject);
 
-                self.store2element(
-                    new_obj,
-                    vec![self.llvm_int32(0), self.llvm_int32(0)],
-                    method_table,
-                );
+        LLVMPositionBuilderAtEnd(builder, bb_reject);
+        let exception = LLVMBuildLoad(builder, exception_slot, cstr0!());
+        self.call_helper("throw_exception", vec![exception]);
+        let bb_dispatch = self.get_dispatch_block(self.outer_clauses(clause));
+        LLVMBuildBr(builder, bb_dispatch);
+    }
 
-                stack.push(TypedValue::new(
-                    self.shared_env
-                        .type_id(&Type::class_ty(method.class.clone())),
-                    new_obj,
-                ))
-            }
-            e => unimplemented!("call: unimplemented: {:?}", e),
-        }
+    unsafe fn call_helper(&self, name: &str, args: Vec<LLVMValueRef>) -> LLVMValueRef {
+        self.call_function(
+            self.shared_env
+                .methods
+                .get_helper_function(name)
+                .unwrap()
+                .llvm_function,
+            args,
+        )
     }
 
     unsafe fn call_function(&self, callee: LLVMValueRef, args: Vec<LLVMValueRef>) -> LLVMValueRef {
@@ -1457,11 +4785,7 @@ impl<'a> JITCompiler<'a> {
 
         self.shared_env.class_types.add(class, class_ptr_ty);
 
-        let mut fields_ty = class
-            .fields
-            .iter()
-            .map(|ClassField { ty, .. }| ty.to_llvmty(self))
-            .collect::<Vec<LLVMTypeRef>>();
+        let mut fields_ty = self.get_llvm_fields_type(class);
 
         // method_table always occupies the first field
         fields_ty.insert(
@@ -1477,6 +4801,44 @@ impl<'a> JITCompiler<'a> {
         class_ptr_ty
     }
 
+    /// Returns the layout of the unboxed values of the value type ``class``, which has no method
+    /// table unlike objects
+    unsafe fn get_llvm_value_type(&mut self, class: &ClassInfo) -> LLVMTypeRef {
+        if let Some(ty) = self.shared_env.value_types.get(class.into(): TypePath) {
+            return *ty;
+        }
+
+        let value_ty = LLVMStructCreateNamed(
+            self.shared_env.context,
+            CString::new(format!("{}.value", class.get_full_name()))
+                .unwrap()
+                .as_ptr(),
+        );
+
+        self.shared_env
+            .value_types
+            .add(class.into(): TypePath, value_ty);
+
+        let mut fields_ty = self.get_llvm_fields_type(class);
+        LLVMStructSetBody(value_ty, fields_ty.as_mut_ptr(), fields_ty.len() as u32, 0);
+
+        value_ty
+    }
+
+    /// Returns the types of the instance fields of ``class`` including the inherited ones. A
+    /// value type without fields still occupies a byte.
+    unsafe fn get_llvm_fields_type(&mut self, class: &ClassInfo) -> Vec<LLVMTypeRef> {
+        let mut fields_ty = class
+            .collect_all_fields()
+            .iter()
+            .map(|ClassField { ty, .. }| ty.to_llvmty(self))
+            .collect::<Vec<LLVMTypeRef>>();
+        if fields_ty.is_empty() && class.is_valuetype() {
+            fields_ty.push(LLVMInt8TypeInContext(self.shared_env.context));
+        }
+        fields_ty
+    }
+
     unsafe fn get_size_of_llvm_class_type(&self, class: LLVMTypeRef) -> LLVMValueRef {
         LLVMConstPtrToInt(
             LLVMConstGEP(
@@ -1495,6 +4857,15 @@ impl<'a> JITCompiler<'a> {
             return val;
         }
 
+        // Handles (e.g. ``RuntimeTypeHandle``) are passed to the runtime as the pointers they hold
+        if LLVMGetTypeKind(v_ty) == llvm::LLVMTypeKind::LLVMStructTypeKind
+            && LLVMCountStructElementTypes(v_ty) == 1
+            && LLVMGetTypeKind(to) != llvm::LLVMTypeKind::LLVMStructTypeKind
+        {
+            let elem = LLVMBuildExtractValue(self.shared_env.builder, val, 0, cstr0!());
+            return self.typecast(elem, to);
+        }
+
         match LLVMGetTypeKind(v_ty) {
             llvm::LLVMTypeKind::LLVMIntegerTypeKind => match LLVMGetTypeKind(to) {
                 llvm::LLVMTypeKind::LLVMIntegerTypeKind => {
@@ -1504,7 +4875,7 @@ impl<'a> JITCompiler<'a> {
                         return LLVMBuildZExtOrBitCast(self.shared_env.builder, val, to, cstr0!());
                     }
                 }
-                llvm::LLVMTypeKind::LLVMDoubleTypeKind => {
+                llvm::LLVMTypeKind::LLVMDoubleTypeKind | llvm::LLVMTypeKind::LLVMFloatTypeKind => {
                     return LLVMBuildSIToFP(self.shared_env.builder, val, to, cstr0!());
                 }
                 llvm::LLVMTypeKind::LLVMPointerTypeKind => {
@@ -1513,7 +4884,13 @@ impl<'a> JITCompiler<'a> {
                 _ => {}
             },
             llvm::LLVMTypeKind::LLVMDoubleTypeKind | llvm::LLVMTypeKind::LLVMFloatTypeKind => {
-                return LLVMBuildFPToSI(self.shared_env.builder, val, to, cstr0!());
+                match LLVMGetTypeKind(to) {
+                    llvm::LLVMTypeKind::LLVMDoubleTypeKind
+                    | llvm::LLVMTypeKind::LLVMFloatTypeKind => {
+                        return LLVMBuildFPCast(self.shared_env.builder, val, to, cstr0!());
+                    }
+                    _ => return LLVMBuildFPToSI(self.shared_env.builder, val, to, cstr0!()),
+                }
             }
             llvm::LLVMTypeKind::LLVMVoidTypeKind => return val,
             llvm::LLVMTypeKind::LLVMPointerTypeKind => match LLVMGetTypeKind(to) {
@@ -1549,9 +4926,21 @@ impl<'a> JITCompiler<'a> {
         let llvm_method_table = self.llvm_ptr(method_table_ptr as *mut u8);
         let mut methods = vec![];
 
-        for m in &class.method_table {
-            match &*m.borrow() {
-                MethodInfo::MDef(m) => methods.push(self.get_function_by_rva(m.rva)),
+        for m_ref in &class.method_table {
+            match &*m_ref.borrow() {
+                // Generic methods have no code until they are instantiated
+                MethodInfo::MDef(m) if m.is_runtime_impl() => {
+                    let func = self.get_runtime_method(m_ref);
+                    methods.push(func.unwrap_or_else(|| self.llvm_ptr(0 as *mut u8)))
+                }
+                MethodInfo::MDef(m) if m.is_generic_def() || !m.has_body() => {
+                    methods.push(self.llvm_ptr(0 as *mut u8))
+                }
+                // Virtual calls pass boxed objects to the methods of value types
+                MethodInfo::MDef(_) if class.is_valuetype() => {
+                    methods.push(self.get_unboxing_stub(m_ref))
+                }
+                MethodInfo::MDef(_) => methods.push(self.get_function_by_method(m_ref)),
                 MethodInfo::MRef(m) => {
                     let class = m.class.borrow();
                     methods.push(
@@ -1572,9 +4961,101 @@ impl<'a> JITCompiler<'a> {
             .method_table_map
             .insert(method_table_ptr, (llvm_method_table, methods));
 
+        // Used for type tests at runtime
+        let parent_method_table_ptr = class.parent.as_ref().map(|parent| {
+            let parent = parent.borrow();
+            self.get_llvm_class_type(&parent);
+            self.ensure_all_class_methods_compiled(&parent).0
+        });
+        let interface_maps = class
+            .interface_maps
+            .iter()
+            .map(|map| {
+                let interface = map.interface.borrow();
+                self.get_llvm_class_type(&interface);
+                let interface_method_table_ptr = self.ensure_all_class_methods_compiled(&interface).0;
+                (interface_method_table_ptr, map.slots.clone())
+            })
+            .collect();
+        register_method_table(
+            method_table_ptr,
+            parent_method_table_ptr,
+            interface_maps,
+            class.get_full_name(),
+            class.is_valuetype(),
+            self.shared_env
+                .runtime_assemblies
+                .get(class.resolution_scope.get_name())
+                .cloned(),
+        );
+
+        // Generic virtual methods called so far have no slot for this class to inherit
+        self.shared_env
+            .method_table_classes
+            .push((method_table_ptr, class.clone()));
+        for id in 0..self.shared_env.generic_virtual_method_instances.len() {
+            self.compile_generic_virtual_method_instance(class, method_table_ptr, id);
+        }
+
         (method_table_ptr, llvm_method_table)
     }
 
+    /// Returns the function that calls ``method_ref``, an instance method of a value type, with
+    /// the address of the value held by the boxed ``this``
+    unsafe fn get_unboxing_stub(&mut self, method_ref: &MethodInfoRef) -> LLVMValueRef {
+        let func = self.get_function_by_method(method_ref);
+        let method_info = method_ref.borrow();
+        let method = method_info.as_mdef();
+        let class = method.class.borrow();
+        let boxed_ty = self.get_llvm_class_type(&class);
+        let value_ty = self.get_llvm_value_type(&class);
+
+        let func_ty = LLVMGetElementType(LLVMTypeOf(func));
+        let mut params_ty = vec![0 as LLVMTypeRef; LLVMCountParamTypes(func_ty) as usize];
+        LLVMGetParamTypes(func_ty, params_ty.as_mut_ptr());
+        params_ty[0] = boxed_ty;
+        let ret_ty = LLVMGetReturnType(func_ty);
+        let stub = LLVMAddFunction(
+            self.shared_env.module,
+            CString::new(format!("{}.unbox", method.name))
+                .unwrap()
+                .as_ptr(),
+            LLVMFunctionType(ret_ty, params_ty.as_mut_ptr(), params_ty.len() as u32, 0),
+        );
+
+        let builder = LLVMCreateBuilderInContext(self.shared_env.context);
+        LLVMPositionBuilderAtEnd(
+            builder,
+            LLVMAppendBasicBlockInContext(self.shared_env.context, stub, cstr0!()),
+        );
+        let mut args = (0..params_ty.len())
+            .map(|i| LLVMGetParam(stub, i as u32))
+            .collect::<Vec<LLVMValueRef>>();
+        let value = LLVMBuildGEP(
+            builder,
+            args[0],
+            vec![self.llvm_int32(0), self.llvm_int32(1)].as_mut_ptr(),
+            2,
+            cstr0!(),
+        );
+        args[0] = LLVMBuildBitCast(builder, value, LLVMPointerType(value_ty, 0), cstr0!());
+        let ret = LLVMBuildCall(
+            builder,
+            func,
+            args.as_mut_ptr(),
+            args.len() as u32,
+            cstr0!(),
+        );
+        if LLVMGetTypeKind(ret_ty) == llvm::LLVMTypeKind::LLVMVoidTypeKind {
+            LLVMBuildRetVoid(builder);
+        } else {
+            LLVMBuildRet(builder, ret);
+        }
+        LLVMDisposeBuilder(builder);
+
+        stub
+    }
+
     unsafe fn load_element(&self, obj: LLVMValueRef, mut idx: Vec<LLVMValueRef>) -> LLVMValueRef {
         let gep = LLVMBuildGEP(
             self.shared_env.builder,
@@ -1620,7 +5101,9 @@ impl<'a> JITCompiler<'a> {
 }
 
 unsafe fn cur_bb_has_no_terminator(builder: LLVMBuilderRef) -> bool {
-    LLVMIsATerminatorInst(LLVMGetLastInstruction(LLVMGetInsertBlock(builder))) == ptr::null_mut()
+    // The block may be empty (e.g. the continuation of an exception check)
+    let last = LLVMGetLastInstruction(LLVMGetInsertBlock(builder));
+    last == ptr::null_mut() || LLVMIsATerminatorInst(last) == ptr::null_mut()
 }
 
 pub trait CastIntoLLVMType {
@@ -1634,15 +5117,20 @@ impl CastIntoLLVMType for Type {
             ElementType::Void => LLVMVoidTypeInContext(ctx),
             ElementType::Boolean => LLVMInt8TypeInContext(ctx),
             ElementType::Char => LLVMInt32TypeInContext(ctx),
-            ElementType::I4 => LLVMInt32TypeInContext(ctx),
-            ElementType::U4 => LLVMInt32TypeInContext(ctx),
-            ElementType::I8 => LLVMInt64TypeInContext(ctx),
+            ElementType::I1 | ElementType::U1 => LLVMInt8TypeInContext(ctx),
+            ElementType::I2 | ElementType::U2 => LLVMInt16TypeInContext(ctx),
+            ElementType::I4 | ElementType::U4 => LLVMInt32TypeInContext(ctx),
+            ElementType::I8 | ElementType::U8 => LLVMInt64TypeInContext(ctx),
+            ElementType::R4 => LLVMFloatTypeInContext(ctx),
             ElementType::R8 => LLVMDoubleTypeInContext(ctx),
             ElementType::String => compiler
                 .shared_env
                 .class_types
                 .get(TypePath(vec!["mscorlib", "System", "String"]))
                 .unwrap(),
+            // The rank, the element class, the lengths and the lower bounds precede the elements
+            // (see ``new_array``)
+            ElementType::Array(_) => LLVMPointerType(LLVMInt64TypeInContext(ctx), 0),
             ElementType::SzArray(ref szarr) => {
                 LLVMPointerType(szarr.elem_ty.to_llvmty(compiler), 0)
             }
@@ -1660,11 +5148,43 @@ impl CastIntoLLVMType for Type {
                 if class.is_enum() {
                     LLVMInt32TypeInContext(ctx)
                 } else {
-                    unimplemented!()
+                    compiler.get_llvm_value_type(class)
+                }
+            }
+            ElementType::Ptr(ref elem) | ElementType::ByRef(ref elem) => {
+                LLVMPointerType(elem.to_llvmty(compiler), 0)
+            }
+            ElementType::I | ElementType::U => LLVMInt64TypeInContext(ctx),
+            ElementType::FnPtr(ref sig) => {
+                let this_ty = if sig.has_implicit_this() {
+                    Some(Type::object_ty().to_llvmty(compiler))
+                } else {
+                    None
+                };
+                LLVMPointerType(compiler.get_llvm_function_type(sig, this_ty), 0)
+            }
+            ElementType::GenericInst(ref inst) => {
+                // Only the instantiations nested in other ones are too deep
+                let class = ClassInfo::instantiate(&inst.class, &inst.args)
+                    .expect("generic instance cannot be constructed");
+                let class = &class.borrow();
+                if inst.is_valuetype {
+                    compiler.get_llvm_value_type(class)
+                } else {
+                    compiler.get_llvm_class_type(class)
                 }
             }
-            ElementType::Ptr(ref elem) => LLVMPointerType(elem.to_llvmty(compiler), 0),
-            ElementType::FnPtr(_) => unimplemented!(),
+            ElementType::Var(_) | ElementType::MVar(_) => {
+                unreachable!("type parameters must be instantiated before compiling")
+            }
+            // The address of the value and the method table of its type
+            ElementType::TypedByRef => {
+                let mut elems = [
+                    LLVMPointerType(LLVMInt8TypeInContext(ctx), 0),
+                    LLVMPointerType(LLVMInt8TypeInContext(ctx), 0),
+                ];
+                LLVMStructTypeInContext(ctx, elems.as_mut_ptr(), 2, 0)
+            }
         }
     }
 }
@@ -1697,6 +5217,18 @@ impl CodeEnvironment {
         CodeEnvironment {
             arguments: FxHashMap::default(),
             locals: FxHashMap::default(),
+            pc: 0,
+            is_main: false,
+            exception_clauses: vec![],
+            clause_slots: FxHashMap::default(),
+            catch_classes: FxHashMap::default(),
+            dispatch_blocks: FxHashMap::default(),
+            finally_continuations: FxHashMap::default(),
+            leave_continuations: FxHashMap::default(),
+            generic_context: GenericContext::default(),
+            constrained: None,
+            method_ty: None,
+            arglist: None,
         }
     }
 }
@@ -1724,7 +5256,17 @@ impl SharedEnvironment {
                 pass_mgr,
                 methods: BuiltinFunctions::new(context, module),
                 class_types: ClassTypesNameResolver::new(),
+                static_storages: NameResolver::new(),
+                value_types: NameResolver::new(),
+                delegate_invokes: NameResolver::new(),
+                runtime_assemblies: FxHashMap::default(),
                 method_table_map: FxHashMap::default(),
+                method_table_classes: vec![],
+                generic_virtual_method_ids: FxHashMap::default(),
+                generic_virtual_method_instances: vec![],
+                generic_virtual_methods: vec![],
+                other_image_generic_virtual_methods: vec![],
+                asm_envs: FxHashMap::default(),
                 ty_arena: id_arena::Arena::new(),
             }
         }
@@ -1756,12 +5298,25 @@ impl SharedEnvironment {
 
         self.ty_arena.alloc(ty_cloned)
     }
+
+    /// Returns the handle of ``asm`` in ``LOADED_ASSEMBLIES``, registering it on first use. Its
+    /// name and resources are taken then, since the assembly is in use by the JIT while guest code
+    /// runs.
+    pub fn runtime_assembly(&mut self, asm: &Assembly) -> usize {
+        if let Some(&handle) = self.runtime_assemblies.get(&asm.name) {
+            return handle;
+        }
+        let handle = register_runtime_assembly(RuntimeAssembly::new(asm));
+        self.runtime_assemblies.insert(asm.name.clone(), handle);
+        handle
+    }
 }
 
 impl AssemblyUniqueEnvironment {
     pub fn new() -> Self {
         AssemblyUniqueEnvironment {
             generated: FxHashMap::default(),
+            generated_instances: FxHashMap::default(),
             compile_queue: VecDeque::new(),
         }
     }
@@ -1799,6 +5354,70 @@ impl ClassTypesNameResolver {
     }
 }
 
+/// Returns the minimum and the maximum value of a ``bw``-bit integer
+fn int_range(bw: u32, signed: bool) -> (i128, i128) {
+    if signed {
+        (-(1i128 << (bw - 1)), (1i128 << (bw - 1)) - 1)
+    } else {
+        (0, (1i128 << bw) - 1)
+    }
+}
+
+/// Returns the method of the mscorlib class ``class`` that the runtime implements instead of a
+/// builtin function (e.g. ``Invoke`` of ``System.Action``)
+fn find_runtime_method(class: &ClassInfo, name: &str, ty: &Type) -> Option<MethodInfoRef> {
+    class
+        .methods
+        .iter()
+        .find(|m| match &*m.borrow() {
+            MethodInfo::MDef(m) => m.is_runtime_impl() && m.name == name && m.ty == *ty,
+            MethodInfo::MRef(_) => false,
+        })
+        .cloned()
+}
+
+/// Returns the class of the objects that box values of ``ty``, or None if ``ty`` is a reference
+/// type
+fn get_boxed_class(ty: &Type) -> CResult<Option<ClassInfoRef>> {
+    Ok(match ty.base {
+        ElementType::I4 => Some(mscorlib_system_int32()),
+        ElementType::I8 => Some(mscorlib_system_int64()),
+        ElementType::R8 => Some(mscorlib_system_double()),
+        ElementType::Boolean => Some(mscorlib_system_boolean()),
+        ElementType::Char => Some(mscorlib_system_char()),
+        ElementType::I1
+        | ElementType::U1
+        | ElementType::I2
+        | ElementType::U2
+        | ElementType::U4
+        | ElementType::U8
+        | ElementType::R4
+        | ElementType::I
+        | ElementType::U => ty.get_class(),
+        ElementType::ValueType(ref class) => Some(class.clone()),
+        ElementType::GenericInst(ref inst) if inst.is_valuetype => {
+            ClassInfo::instantiate(&inst.class, &inst.args)
+        }
+        ElementType::Class(_)
+        | ElementType::Object
+        | ElementType::String
+        | ElementType::SzArray(_)
+        | ElementType::Array(_)
+        | ElementType::GenericInst(_) => None,
+        ref e => unsupported!("box: {:?}", e),
+    })
+}
+
+/// Returns the type of ``this`` in the instance methods of ``class``. Methods of value types
+/// take the address of the value.
+fn get_this_ty(class: &ClassInfoRef) -> Type {
+    if class.borrow().is_valuetype() {
+        Type::byref_ty(Type::from_class(class.clone()))
+    } else {
+        Type::class_ty(class.clone())
+    }
+}
+
 fn get_arg_vals_from_stack(
     stack: &mut Vec<TypedValue>,
     params_len: usize,
@@ -1829,3 +5448,37 @@ unsafe fn llvm_const_ptr(ctx: LLVMContextRef, p: *mut u8) -> LLVMValueRef {
     let ptr_as_int = LLVMConstInt(LLVMInt64TypeInContext(ctx), p as u64, 0);
     LLVMConstIntToPtr(ptr_as_int, LLVMPointerType(LLVMInt8TypeInContext(ctx), 0))
 }
+
+#[test]
+fn test_unresolvable_catch_class() {
+    // Catch clauses whose classes can't be resolved make the method fail to compile
+    for table in &[TableKind::TypeRef, TableKind::TypeSpec] {
+        let asm = Assembly::load(std::path::PathBuf::from("./examples/exceptions.exe")).unwrap();
+        let method_ref = asm.borrow_mut().image.get_entry_method().unwrap();
+        if let MethodInfo::MDef(method) = &mut *method_ref.borrow_mut() {
+            let token = encode_token(table.clone().into(), 0xffff);
+            method.exception_clauses[0].kind = ExceptionClauseKind::Catch(token);
+        }
+        let mut shared_env = SharedEnvironment::new();
+        let mut asm = asm.borrow_mut();
+        let mut jit = unsafe { JITCompiler::new(&mut asm, &mut shared_env) };
+        let result = unsafe { jit.generate_method_as_main(&method_ref) };
+        assert!(matches!(result, Err(Error::CouldntCompile(_))));
+    }
+}
+
+#[test]
+fn test_unsupported_call_target() {
+    // A call to something that isn't a method makes the method fail to compile, not panic
+    let asm = Assembly::load(std::path::PathBuf::from("./examples/hello.exe")).unwrap();
+    let method_ref = asm.borrow_mut().image.get_entry_method().unwrap();
+    if let MethodInfo::MDef(method) = &mut *method_ref.borrow_mut() {
+        let token = encode_token(TableKind::TypeRef.into(), 1);
+        method.body.insert(0, Instruction::Call(token));
+    }
+    let mut shared_env = SharedEnvironment::new();
+    let mut asm = asm.borrow_mut();
+    let mut jit = unsafe { JITCompiler::new(&mut asm, &mut shared_env) };
+    let result = unsafe { jit.generate_method_as_main(&method_ref) };
+    assert!(matches!(result, Err(Error::CouldntCompile(_))));
+}
@@ -17,6 +17,26 @@ fn main() {
         .author("uint256_t")
         .about("An ECMA-335 implementation written in Rust")
         .arg(Arg::with_name("file").help("Input file name").index(1))
+        .arg(
+            Arg::with_name("assembly-path")
+                .short("L")
+                .long("assembly-path")
+                .value_name("DIR")
+                .help("Adds a directory to look for referenced assemblies in")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("FILE")
+                .help(
+                    "Reads the directories to look for referenced assemblies in from FILE \
+                     [default: yacht.config next to the input file]",
+                )
+                .takes_value(true),
+        )
         .setting(AppSettings::ArgRequiredElseHelp);
     let app_matches = app.get_matches();
 
@@ -31,10 +51,32 @@ fn main() {
         None => { eprintln!("{}: {}", Colour::Red.bold().paint("error"), $msg); return }
     } }}; }
 
-    let asm = expect!(
-        assembly::Assembly::load(filename),
-        "An error occurred while loading file"
-    );
+    // Referenced assemblies are looked for next to the referencing file, then in the directories
+    // given by -L, YACHT_ASSEMBLY_PATH and the config file in this order
+    let mut resolver = assembly::AssemblyResolver::new();
+    for path in app_matches.values_of("assembly-path").into_iter().flatten() {
+        resolver.add_probing_path(path);
+    }
+    resolver.add_probing_paths_from_env();
+    let config = match app_matches.value_of("config") {
+        Some(config) => Some(PathBuf::from(config)),
+        None => Some(filename.with_file_name("yacht.config")).filter(|config| config.is_file()),
+    };
+    if let Some(config) = config {
+        if let Err(err) = resolver.add_probing_paths_from_config(&config) {
+            let msg = format!("{}: {}", config.display(), err);
+            eprintln!("{}: {}", Colour::Red.bold().paint("error"), msg);
+            return;
+        }
+    }
+
+    let asm = match assembly::Assembly::load_with_resolver(filename, resolver) {
+        Ok(asm) => asm,
+        Err(err) => {
+            eprintln!("{}: {}", Colour::Red.bold().paint("error"), err);
+            return;
+        }
+    };
     let entry_method = expect!(
         asm.borrow_mut().image.get_entry_method(),
         "Entry method not found"
@@ -44,7 +86,13 @@ fn main() {
         let mut asm = asm.borrow_mut();
         let mut shared_env = jit::jit::SharedEnvironment::new();
         let mut jit = jit::jit::JITCompiler::new(&mut *asm, &mut shared_env);
-        let main = jit.generate_method_as_main(&entry_method);
+        let main = match jit.generate_method_as_main(&entry_method) {
+            Ok(main) => main,
+            Err(err) => {
+                eprintln!("{}: {}", Colour::Red.bold().paint("error"), err);
+                return;
+            }
+        };
         jit.run_method(main);
     }
 }
@@ -52,7 +100,10 @@ fn main() {
 #[cfg(test)]
 mod tests {
     use std::{fs, path::PathBuf};
-    use yacht::{exec::jit, metadata::assembly};
+    use yacht::{
+        exec::jit::{self, builtin},
+        metadata::assembly,
+    };
 
     #[test]
     fn exec_examples() {
@@ -65,13 +116,39 @@ mod tests {
             }
             let asm = assembly::Assembly::load(PathBuf::from(filename)).unwrap();
             let method = asm.borrow_mut().image.get_entry_method().unwrap();
+            builtin::start_capturing_console_output();
             unsafe {
                 let mut asm = asm.borrow_mut();
                 let mut shared_env = jit::jit::SharedEnvironment::new();
                 let mut jit = jit::jit::JITCompiler::new(&mut asm, &mut shared_env);
-                let main = jit.generate_method_as_main(&method);
+                let main = jit.generate_method_as_main(&method).unwrap();
                 jit.run_method(main);
             }
+            let output = builtin::stop_capturing_console_output();
+            // Examples with an expected output must print exactly it
+            if let Ok(expected) = fs::read_to_string(path.with_extension("out")) {
+                assert_eq!(output, expected, "{}", filename);
+            } else {
+                print!("{}", output);
+            }
+        }
+    }
+
+    #[test]
+    fn exec_from_bytes() {
+        let bytes = fs::read("./examples/dll_a.exe").unwrap();
+        let resolver = assembly::DependencyResolver::new(|name| {
+            fs::read(PathBuf::from(format!("./examples/{}.dll", name))).ok()
+        });
+        assert!(assembly::Assembly::load_from_bytes(&bytes).is_err());
+        let asm = assembly::Assembly::load_from_bytes_with_resolver(&bytes, resolver).unwrap();
+        let method = asm.borrow_mut().image.get_entry_method().unwrap();
+        unsafe {
+            let mut asm = asm.borrow_mut();
+            let mut shared_env = jit::jit::SharedEnvironment::new();
+            let mut jit = jit::jit::JITCompiler::new(&mut asm, &mut shared_env);
+            let main = jit.generate_method_as_main(&method).unwrap();
+            jit.run_method(main);
         }
     }
 }
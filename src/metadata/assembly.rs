@@ -1,6 +1,12 @@
-use super::{image::*, pe_parser::*};
+use super::{error::*, image::*, metadata::*, pe_parser::*};
+use crate::util::sha1::sha1;
 use rustc_hash::FxHashMap;
-use std::{cell::RefCell, path::PathBuf, rc::Rc};
+use std::{
+    cell::RefCell,
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 pub type AssemblyRef = Rc<RefCell<Assembly>>;
 
@@ -10,9 +16,190 @@ pub struct Assembly {
     pub image: Image,
 }
 
+/// Returns the bytes of a referenced assembly by its name (e.g. ``MyLib``). Used before probing
+/// the file system.
+#[derive(Clone)]
+pub struct DependencyResolver(Rc<dyn Fn(&str) -> Option<Vec<u8>>>);
+
+impl DependencyResolver {
+    pub fn new<F: Fn(&str) -> Option<Vec<u8>> + 'static>(f: F) -> Self {
+        DependencyResolver(Rc::new(f))
+    }
+
+    pub fn resolve(&self, name: &str) -> Option<Vec<u8>> {
+        (self.0)(name)
+    }
+}
+
+impl fmt::Debug for DependencyResolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DependencyResolver")
+    }
+}
+
+/// Environment variable that lists probing paths, separated as ``PATH`` is
+pub const ASSEMBLY_PATH_ENV: &str = "YACHT_ASSEMBLY_PATH";
+
+/// Decides where referenced assemblies are looked for. For a reference to ``MyLib``, the
+/// dependency resolver is asked first. Then ``MyLib.dll`` and ``MyLib.exe`` are looked for in the
+/// directory of the referencing file, and then in each probing path in order.
+#[derive(Debug, Clone, Default)]
+pub struct AssemblyResolver {
+    pub probing_paths: Vec<PathBuf>,
+    pub dependency_resolver: Option<DependencyResolver>,
+}
+
+impl AssemblyResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_probing_path<P: Into<PathBuf>>(&mut self, path: P) -> &mut Self {
+        self.probing_paths.push(path.into());
+        self
+    }
+
+    /// Appends the paths listed in ``YACHT_ASSEMBLY_PATH``
+    pub fn add_probing_paths_from_env(&mut self) -> &mut Self {
+        if let Some(paths) = env::var_os(ASSEMBLY_PATH_ENV) {
+            self.probing_paths.extend(env::split_paths(&paths));
+        }
+        self
+    }
+
+    /// Appends the paths listed in the config file ``path``: one directory per line, relative to
+    /// the directory of the config file. Lines starting with ``#`` are ignored.
+    pub fn add_probing_paths_from_config<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for line in fs::read_to_string(path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.probing_paths.push(base_dir.join(line));
+        }
+        Ok(())
+    }
+
+    /// Returns the files to look for the assembly ``name`` in, in order. ``base_dir`` is the
+    /// directory of the referencing file.
+    pub fn candidates(&self, name: &str, base_dir: Option<&Path>) -> Vec<PathBuf> {
+        base_dir
+            .into_iter()
+            .chain(self.probing_paths.iter().map(|path| path.as_path()))
+            .flat_map(|dir| {
+                vec![
+                    dir.join(format!("{}.dll", name)),
+                    dir.join(format!("{}.exe", name)),
+                ]
+            })
+            .collect()
+    }
+}
+
+impl From<DependencyResolver> for AssemblyResolver {
+    fn from(resolver: DependencyResolver) -> Self {
+        Self {
+            probing_paths: vec![],
+            dependency_resolver: Some(resolver),
+        }
+    }
+}
+
+/// The name, version, culture and public key token of an assembly (II.6.2.1)
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssemblyIdentity {
+    pub name: String,
+    pub version: [u16; 4],
+
+    /// Empty if culture neutral
+    pub culture: String,
+
+    pub public_key_token: Option<[u8; 8]>,
+}
+
+impl AssemblyIdentity {
+    /// Returns true if this assembly can be bound to ``reference``. Newer versions are accepted
+    /// in place of older ones, but not the other way around.
+    pub fn satisfies(&self, reference: &AssemblyIdentity) -> bool {
+        self.name.eq_ignore_ascii_case(&reference.name)
+            && self.version >= reference.version
+            && self.culture.eq_ignore_ascii_case(&reference.culture)
+            && (reference.public_key_token.is_none()
+                || self.public_key_token == reference.public_key_token)
+    }
+}
+
+impl fmt::Display for AssemblyIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [major, minor, build, revision] = self.version;
+        write!(
+            f,
+            "{}, Version={}.{}.{}.{}, Culture={}",
+            self.name,
+            major,
+            minor,
+            build,
+            revision,
+            if self.culture.is_empty() {
+                "neutral"
+            } else {
+                &self.culture
+            }
+        )?;
+        if let Some(token) = self.public_key_token {
+            write!(f, ", PublicKeyToken=")?;
+            for b in &token {
+                write!(f, "{:02x}", b)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the public key token of ``public_key``: the last 8 bytes of its SHA-1 hash in reverse
+/// order (II.6.2.1.3)
+pub fn public_key_token(public_key: &[u8]) -> [u8; 8] {
+    let hash = sha1(public_key);
+    let mut token = [0u8; 8];
+    for (i, b) in hash[12..].iter().rev().enumerate() {
+        token[i] = *b;
+    }
+    token
+}
+
 impl Assembly {
-    pub fn load(filename: PathBuf) -> Option<AssemblyRef> {
-        let mut pe_parser = PEParser::new(filename)?;
+    pub fn load(filename: PathBuf) -> LoadResult<AssemblyRef> {
+        Self::load_from_parser(PEParser::new(filename)?, AssemblyResolver::new())
+    }
+
+    pub fn load_with_resolver<R: Into<AssemblyResolver>>(
+        filename: PathBuf,
+        resolver: R,
+    ) -> LoadResult<AssemblyRef> {
+        Self::load_from_parser(PEParser::new(filename)?, resolver.into())
+    }
+
+    /// Loads an assembly from an in-memory image. Its dependencies can only be found by a resolver.
+    pub fn load_from_bytes(bytes: &[u8]) -> LoadResult<AssemblyRef> {
+        Self::load_from_parser(
+            PEParser::from_bytes(bytes.to_vec()),
+            AssemblyResolver::new(),
+        )
+    }
+
+    pub fn load_from_bytes_with_resolver<R: Into<AssemblyResolver>>(
+        bytes: &[u8],
+        resolver: R,
+    ) -> LoadResult<AssemblyRef> {
+        Self::load_from_parser(PEParser::from_bytes(bytes.to_vec()), resolver.into())
+    }
+
+    fn load_from_parser(
+        mut pe_parser: PEParser,
+        resolver: AssemblyResolver,
+    ) -> LoadResult<AssemblyRef> {
         let asmref = Rc::new(RefCell::new(pe_parser.create_assembly()?));
         let mut loaded = FxHashMap::default();
 
@@ -21,37 +208,251 @@ impl Assembly {
         {
             let mut asm = asmref.borrow_mut();
             asm.image.pe_parser = Some(Rc::new(RefCell::new(pe_parser)));
-            asm.image.setup_all_asmref(&mut loaded);
-            asm.image.define_all_class();
-            asm.image.setup_all_typeref();
-            asm.image.setup_all_class();
+            asm.image.resolver = resolver;
+            asm.image.setup_all_asmref(&mut loaded)?;
+            asm.image.define_all_class()?;
+            asm.image.setup_all_module(&mut loaded)?;
+            asm.image.setup_all_typeref()?;
+            asm.image.setup_all_class()?;
         }
 
         for asm in loaded.values() {
             let mut ok = asm.borrow_mut();
-            ok.image.setup_all_typeref();
-            ok.image.setup_all_class();
+            for module in ok.image.modules.values() {
+                let mut module = module.borrow_mut();
+                module.image.setup_all_typeref()?;
+                module.image.setup_all_class()?;
+            }
+            ok.image.setup_all_typeref()?;
+            ok.image.setup_all_class()?;
         }
 
-        Some(asmref)
+        Ok(asmref)
     }
 
+    /// Loads the dependencies and the modules of ``asm``, created by ``pe_parser``, and defines
+    /// its classes
     pub fn load_exclusive(
-        filename: PathBuf,
+        pe_parser: PEParser,
+        asm: Assembly,
+        resolver: AssemblyResolver,
         loaded: &mut FxHashMap<String, AssemblyRef>,
-    ) -> Option<AssemblyRef> {
-        let mut pe_parser = PEParser::new(filename)?;
-        let asmref = Rc::new(RefCell::new(pe_parser.create_assembly()?));
+    ) -> LoadResult<AssemblyRef> {
+        let asmref = Rc::new(RefCell::new(asm));
 
         loaded.insert(asmref.borrow().name.clone(), asmref.clone());
 
         {
             let mut asm = asmref.borrow_mut();
             asm.image.pe_parser = Some(Rc::new(RefCell::new(pe_parser)));
-            asm.image.setup_all_asmref(loaded);
-            asm.image.define_all_class();
+            asm.image.resolver = resolver;
+            asm.image.setup_all_asmref(loaded)?;
+            asm.image.define_all_class()?;
+            asm.image.setup_all_module(loaded)?;
         }
 
-        Some(asmref)
+        Ok(asmref)
+    }
+
+    /// Returns the display name (e.g. ``MyLib, Version=1.0.0.0, Culture=neutral``)
+    pub fn full_name(&self) -> String {
+        let asm = match self.image.metadata_reader().rows::<AssemblyTable>().next() {
+            Some((_, asm)) => *asm,
+            None => return self.name.clone(),
+        };
+        let culture = self
+            .image
+            .metadata_reader()
+            .get_string(asm.culture)
+            .unwrap_or("");
+        format!(
+            "{}, Version={}.{}.{}.{}, Culture={}",
+            self.name,
+            asm.major_version,
+            asm.minor_version,
+            asm.build_number,
+            asm.revision_number,
+            if culture.is_empty() {
+                "neutral"
+            } else {
+                culture
+            }
+        )
     }
 }
+
+#[test]
+fn test_malformed_images() {
+    let hello = std::fs::read("./examples/hello.exe").unwrap();
+    match Assembly::load_from_bytes(&hello[..0x100]) {
+        Err(LoadError::Malformed { .. }) => {}
+        r => panic!("unexpected result: {:?}", r.map(|_| ())),
+    }
+
+    let dll_a = std::fs::read("./examples/dll_a.exe").unwrap();
+    match Assembly::load_from_bytes(&dll_a) {
+        Err(LoadError::AssemblyNotFound { name, .. }) => assert_eq!(name, "dll_b"),
+        r => panic!("unexpected result: {:?}", r.map(|_| ())),
+    }
+
+    // Reading the rows of a loaded image must not panic either, whatever their bytes are
+    let read_all_rows = |image: &Image| {
+        for kind in 0..NUM_TABLES {
+            for row in image.metadata.get_table(kind) {
+                let _ = match row {
+                    Table::Constant(t) => t.parent_decoded(),
+                    Table::CustomAttribute(t) => t.parent_decoded().and(t.type_decoded()),
+                    Table::DeclSecurity(t) => t.parent_decoded(),
+                    Table::Event(t) => t.event_type_decoded(),
+                    Table::ExportedType(t) => t.implementation_decoded(),
+                    Table::FieldMarshal(t) => t.parent_decoded(),
+                    Table::GenericParam(t) => t.owner_decoded(),
+                    Table::GenericParamConstraint(t) => t.constraint_decoded(),
+                    Table::ImplMap(t) => t.member_forwarded_decoded(),
+                    Table::InterfaceImpl(t) => t.interface_decoded(),
+                    Table::MethodImpl(t) => {
+                        t.method_body_decoded().and(t.method_declaration_decoded())
+                    }
+                    Table::MethodSemantics(t) => t.association_decoded(),
+                    Table::MethodSpec(t) => t.method_decoded(),
+                    Table::TypeDef(t) => t.extends_decoded(),
+                    Table::MemberRef(t) => {
+                        let _ = image.get_string(t.name);
+                        let _ = image.get_method_ref_type_from_signature(t.signature);
+                        t.class_decoded()
+                    }
+                    Table::TypeRef(t) => {
+                        let _ = image.get_path_from_type_ref_table(t);
+                        t.resolution_scope_decoded()
+                    }
+                    _ => None,
+                };
+            }
+        }
+        let _ = image.manifest_resources();
+    };
+
+    // Loading corrupted images must fail or succeed without panicking. Most corruptions hit the
+    // #~ stream, whose offset and size precede its name in the metadata root (BSJB).
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    let mut rand = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed as usize
+    };
+    for entry in std::fs::read_dir("./examples").unwrap() {
+        let path = entry.unwrap().path();
+        let ext = path.extension().and_then(|ext| ext.to_str());
+        if ext != Some("exe") && ext != Some("dll") {
+            continue;
+        }
+        let bytes = std::fs::read(path).unwrap();
+        // Images depending on others fail to load before reaching most of the loader
+        if Assembly::load_from_bytes(&bytes).is_err() {
+            continue;
+        }
+        let find = |pat: &[u8]| bytes.windows(pat.len()).position(|w| w == pat).unwrap();
+        let (root, header) = (find(b"BSJB"), find(b"#~\0\0"));
+        let read_u32 =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let tables = root + read_u32(header - 8) as usize;
+        let tables_size = read_u32(header - 4) as usize;
+        // The identifiers of #Strings, renamed to others of the same length so that methods collide
+        // and resolve as overrides of each other
+        let strings_header = find(b"#Strings\0");
+        let strings = root + read_u32(strings_header - 8) as usize;
+        let strings_size = read_u32(strings_header - 4) as usize;
+        let mut names: Vec<(usize, usize)> = vec![];
+        let mut start = strings + 1;
+        for i in start..strings + strings_size {
+            if bytes[i] == 0 {
+                if i > start {
+                    names.push((start, i - start));
+                }
+                start = i + 1;
+            }
+        }
+        for _ in 0..5000 {
+            let mut bytes = bytes.clone();
+            if rand() % 4 == 0 {
+                bytes.truncate(rand() % bytes.len());
+            } else if rand() % 4 == 0 {
+                let (dst, len) = names[rand() % names.len()];
+                let same_len: Vec<_> = names.iter().filter(|(_, l)| *l == len).collect();
+                let (src, _) = same_len[rand() % same_len.len()];
+                bytes.copy_within(*src..*src + len, dst);
+            } else {
+                for _ in 0..1 + rand() % 8 {
+                    // 0xff in the low byte of a coded index makes a tag that most kinds do not use
+                    let (i, byte) = match rand() % 3 {
+                        0 => (rand() % bytes.len(), rand() as u8),
+                        1 => (tables + rand() % tables_size, rand() as u8),
+                        _ => (tables + rand() % tables_size, 0xff),
+                    };
+                    bytes[i] = byte;
+                }
+            }
+            if let Ok(asm) = Assembly::load_from_bytes(&bytes) {
+                read_all_rows(&asm.borrow().image);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_assembly_binding() {
+    let identity = |version, token| AssemblyIdentity {
+        name: "MyLib".to_string(),
+        version,
+        culture: "".to_string(),
+        public_key_token: token,
+    };
+    let token = Some([1, 2, 3, 4, 5, 6, 7, 8]);
+    assert!(identity([1, 2, 0, 0], token).satisfies(&identity([1, 0, 0, 0], None)));
+    assert!(identity([1, 0, 0, 0], token).satisfies(&identity([1, 0, 0, 0], token)));
+    assert!(!identity([1, 0, 0, 0], token).satisfies(&identity([2, 0, 0, 0], token)));
+    assert!(!identity([1, 0, 0, 0], None).satisfies(&identity([1, 0, 0, 0], token)));
+    assert_eq!(
+        identity([1, 0, 0, 0], token).to_string(),
+        "MyLib, Version=1.0.0.0, Culture=neutral, PublicKeyToken=0102030405060708"
+    );
+
+    // The ECMA standard public key
+    assert_eq!(
+        public_key_token(&[0, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0]),
+        [0xb7, 0x7a, 0x5c, 0x56, 0x19, 0x34, 0xe0, 0x89]
+    );
+
+    // dll_a.exe refers to dll_b, which refers to dll_c and dll_d. Put dll_b in a separate
+    // directory as an .exe and the others in a probing path.
+    let dir = env::temp_dir().join(format!("yacht_test_binding_{}", std::process::id()));
+    let (app_dir, lib_dir) = (dir.join("app"), dir.join("lib"));
+    fs::create_dir_all(&app_dir).unwrap();
+    fs::create_dir_all(&lib_dir).unwrap();
+    fs::copy("./examples/dll_a.exe", app_dir.join("dll_a.exe")).unwrap();
+    fs::copy("./examples/dll_b.dll", lib_dir.join("dll_b.exe")).unwrap();
+    fs::write(dir.join("yacht.config"), "# shared libraries\nlib\n").unwrap();
+
+    match Assembly::load(app_dir.join("dll_a.exe")) {
+        Err(LoadError::AssemblyNotFound { name, probed }) => {
+            assert_eq!(name, "dll_b");
+            assert_eq!(
+                probed,
+                vec![app_dir.join("dll_b.dll"), app_dir.join("dll_b.exe")]
+            );
+        }
+        r => panic!("unexpected result: {:?}", r.map(|_| ())),
+    }
+
+    let mut resolver = AssemblyResolver::new();
+    resolver
+        .add_probing_paths_from_config(dir.join("yacht.config"))
+        .unwrap();
+    resolver.add_probing_path("./examples");
+    let asm = Assembly::load_with_resolver(app_dir.join("dll_a.exe"), resolver);
+    fs::remove_dir_all(&dir).unwrap();
+    let asm = asm.unwrap();
+    let dll_b = asm.borrow().image.asm_refs["dll_b"].clone();
+    assert_eq!(dll_b.borrow().image.filename, lib_dir.join("dll_b.exe"));
+}
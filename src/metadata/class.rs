@@ -1,17 +1,86 @@
-use crate::metadata::{method::*, signature::*};
-use std::{cell::RefCell, fmt, rc::Rc};
+use crate::metadata::{image::mscorlib_system_object, method::*, signature::*};
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    rc::Rc,
+};
 
 pub type ClassInfoRef = Rc<RefCell<ClassInfo>>;
 
+/// Generic types nested deeper than this (e.g. by a field of type ``C<C<T>>`` in ``C<T>``) are
+/// not instantiated
+const MAX_INSTANTIATION_DEPTH: usize = 32;
+
+thread_local! {
+    /// Number of generic instantiations in progress
+    static INSTANTIATION_DEPTH: Cell<usize> = Cell::new(0);
+}
+
 #[derive(Clone)]
 pub struct ClassInfo {
     pub resolution_scope: ResolutionScope,
     pub name: String,
     pub namespace: String,
     pub fields: Vec<ClassField>,
+
+    /// Static fields, which are not part of the object layout
+    pub static_fields: Vec<ClassField>,
+
     pub methods: Vec<MethodInfoRef>,
     pub parent: Option<ClassInfoRef>,
     pub method_table: Vec<MethodInfoRef>,
+
+    /// TypeAttributes (II.23.1.15)
+    pub flags: u32,
+
+    /// Interfaces this class implements directly
+    pub interfaces: Vec<ClassInfoRef>,
+
+    /// Interface maps of all the interfaces this class implements, including the ones inherited
+    /// from the parent classes and the other interfaces
+    pub interface_maps: Vec<InterfaceMap>,
+
+    /// Methods of this class that explicitly implement methods of interfaces
+    pub method_impls: Vec<MethodImpl>,
+
+    /// Names of the type parameters if this is a generic type definition
+    pub generic_params: Vec<String>,
+
+    /// Type arguments if this is an instance of a generic type
+    pub generic_args: Vec<Type>,
+
+    /// Generic type definition this class is instantiated from
+    pub generic_def: Option<ClassInfoRef>,
+
+    /// Instances of this generic type definition created so far
+    pub instances: Vec<ClassInfoRef>,
+}
+
+/// Associates the methods of an interface with the slots of a method table
+#[derive(Clone, Debug)]
+pub struct InterfaceMap {
+    pub interface: ClassInfoRef,
+
+    /// ``slots[i]`` is the index in the method table of the method that implements the i-th
+    /// method of ``interface``
+    pub slots: Vec<usize>,
+}
+
+/// Explicit implementation of an interface method (II.22.27 MethodImpl) such as
+/// ``void IDisposable.Dispose()``
+#[derive(Clone, Debug)]
+pub struct MethodImpl {
+    /// Method of the class that implements ``name``
+    pub body: MethodInfoRef,
+
+    /// Interface that declares ``name``
+    pub interface: ClassInfoRef,
+
+    /// Name of the implemented method
+    pub name: String,
+
+    /// Signature of the implemented method, with the type arguments of ``interface``
+    pub ty: Type,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -48,9 +117,18 @@ impl ClassInfo {
             name: name.to_string(),
             namespace: namespace.to_string(),
             fields,
+            static_fields: vec![],
             methods,
             parent,
             method_table: vec![],
+            flags: 0,
+            interfaces: vec![],
+            interface_maps: vec![],
+            method_impls: vec![],
+            generic_params: vec![],
+            generic_args: vec![],
+            generic_def: None,
+            instances: vec![],
         }
     }
 
@@ -67,9 +145,18 @@ impl ClassInfo {
             name: name.to_string(),
             namespace: namespace.to_string(),
             fields,
+            static_fields: vec![],
             methods,
             parent,
             method_table: vec![],
+            flags: 0,
+            interfaces: vec![],
+            interface_maps: vec![],
+            method_impls: vec![],
+            generic_params: vec![],
+            generic_args: vec![],
+            generic_def: None,
+            instances: vec![],
         }))
     }
 
@@ -79,35 +166,451 @@ impl ClassInfo {
             name: "".to_string(),
             namespace: "".to_string(),
             fields: vec![],
+            static_fields: vec![],
             methods: vec![],
             parent: None,
             method_table: vec![],
+            flags: 0,
+            interfaces: vec![],
+            interface_maps: vec![],
+            method_impls: vec![],
+            generic_params: vec![],
+            generic_args: vec![],
+            generic_def: None,
+            instances: vec![],
         }))
     }
 
-    pub fn get_method_index(&self, name: &str) -> Option<usize> {
-        self.method_table
+    /// Returns the name including the namespace (e.g. System.String)
+    pub fn get_full_name(&self) -> String {
+        if self.namespace.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}.{}", self.namespace, self.name)
+        }
+    }
+
+    pub fn is_interface(&self) -> bool {
+        self.flags & type_attributes_flags::INTERFACE > 0
+    }
+
+    pub fn is_generic_def(&self) -> bool {
+        !self.generic_params.is_empty()
+    }
+
+    /// Returns true if the type initializer may run at any time before the first access to a
+    /// static field. Otherwise it runs exactly at the first access to any static member or the
+    /// first instantiation.
+    pub fn is_before_field_init(&self) -> bool {
+        self.flags & type_attributes_flags::BEFORE_FIELD_INIT > 0
+    }
+
+    /// Returns the type initializer (.cctor) if any
+    pub fn get_type_initializer(&self) -> Option<MethodInfoRef> {
+        self.methods
+            .iter()
+            .find(|m| {
+                let m = m.borrow();
+                m.is_static() && m.get_name() == ".cctor"
+            })
+            .cloned()
+    }
+
+    pub fn get_static_field_index(&self, name: &str) -> Option<usize> {
+        self.static_fields.iter().position(|f| f.name == name)
+    }
+
+    /// Returns the instance of the generic type ``class_ref`` for ``args``, creating it at the
+    /// first request. The fields and methods of the instance have ``Var`` replaced with
+    /// ``args``. Returns None if the instantiations are nested too deeply or the instance does not
+    /// implement its interfaces.
+    pub fn instantiate(class_ref: &ClassInfoRef, args: &[Type]) -> Option<ClassInfoRef> {
+        if let Some(instance) = class_ref
+            .borrow()
+            .instances
+            .iter()
+            .find(|instance| instance.borrow().generic_args.as_slice() == args)
+        {
+            return Some(instance.clone());
+        }
+
+        let depth = INSTANTIATION_DEPTH.with(|depth| depth.get());
+        if depth >= MAX_INSTANTIATION_DEPTH {
+            return None;
+        }
+        INSTANTIATION_DEPTH.with(|d| d.set(depth + 1));
+        let instance_ref = ClassInfo::instantiate_new(class_ref, args);
+        INSTANTIATION_DEPTH.with(|d| d.set(depth));
+        instance_ref
+    }
+
+    fn instantiate_new(class_ref: &ClassInfoRef, args: &[Type]) -> Option<ClassInfoRef> {
+        let (instance_ref, fields, static_fields, methods, parent, interfaces, method_impls) = {
+            let class = class_ref.borrow();
+            let instance_ref = ClassInfo::new_ref(
+                class.resolution_scope.clone(),
+                class.namespace.as_str(),
+                format!("{}<{}>", class.name, type_list_name(args)).as_str(),
+                vec![],
+                vec![],
+                None,
+            );
+            (
+                instance_ref,
+                class.fields.clone(),
+                class.static_fields.clone(),
+                class.methods.clone(),
+                class.parent.clone(),
+                class.interfaces.clone(),
+                class.method_impls.clone(),
+            )
+        };
+
+        // Register the instance first so that the fields referring to the instance itself
+        // (e.g. ``Node<T> next``) find it
+        {
+            let mut instance = instance_ref.borrow_mut();
+            instance.generic_args = args.to_vec();
+            instance.generic_def = Some(class_ref.clone());
+        }
+        class_ref.borrow_mut().instances.push(instance_ref.clone());
+
+        let ctx = GenericContext {
+            class_args: args.to_vec(),
+            method_args: vec![],
+        };
+
+        // A generic parent or interface (e.g. ``Base<T>``) is instantiated with the same arguments
+        let parent = parent.map(|parent| ClassInfo::instantiate_open(&parent, &ctx));
+        let interfaces = interfaces
+            .iter()
+            .map(|interface| ClassInfo::instantiate_open(interface, &ctx))
+            .collect();
+
+        let fields = fields
+            .into_iter()
+            .map(|ClassField { name, ty }| ClassField::new(name, ty.instantiate(&ctx)))
+            .collect();
+        let static_fields = static_fields
+            .into_iter()
+            .map(|ClassField { name, ty }| ClassField::new(name, ty.instantiate(&ctx)))
+            .collect();
+
+        let instance_methods: Vec<MethodInfoRef> = methods
             .iter()
-            .position(|m| m.borrow().get_name() == name)
+            .map(|minfo| match *minfo.borrow() {
+                MethodInfo::MDef(ref mdef) => {
+                    let mut mdef = mdef.clone();
+                    mdef.ty = mdef.ty.instantiate(&ctx);
+                    mdef.locals_ty = mdef
+                        .locals_ty
+                        .iter()
+                        .map(|ty| ty.instantiate(&ctx))
+                        .collect();
+                    mdef.class = instance_ref.clone();
+                    Rc::new(RefCell::new(MethodInfo::MDef(mdef)))
+                }
+                MethodInfo::MRef(_) => minfo.clone(),
+            })
+            .collect();
+
+        let method_impls = method_impls
+            .into_iter()
+            .filter_map(|method_impl| {
+                let idx = methods
+                    .iter()
+                    .position(|m| Rc::ptr_eq(m, &method_impl.body))?;
+                Some(MethodImpl {
+                    body: instance_methods[idx].clone(),
+                    interface: ClassInfo::instantiate_open(&method_impl.interface, &ctx),
+                    name: method_impl.name,
+                    ty: method_impl.ty.instantiate(&ctx),
+                })
+            })
+            .collect();
+
+        {
+            let mut instance = instance_ref.borrow_mut();
+            instance.flags = class_ref.borrow().flags;
+            instance.parent = parent;
+            instance.interfaces = interfaces;
+            instance.fields = fields;
+            instance.static_fields = static_fields;
+            instance.methods = instance_methods;
+            instance.method_impls = method_impls;
+        }
+
+        if ClassInfo::construct_method_table(&instance_ref).is_err() {
+            class_ref
+                .borrow_mut()
+                .instances
+                .retain(|instance| !Rc::ptr_eq(instance, &instance_ref));
+            return None;
+        }
+
+        Some(instance_ref)
+    }
+
+    /// Instantiates ``class_ref`` again with ``ctx`` if it is an instance of a generic type whose
+    /// arguments refer to type parameters (e.g. ``Base<T>``). Otherwise returns ``class_ref``.
+    fn instantiate_open(class_ref: &ClassInfoRef, ctx: &GenericContext) -> ClassInfoRef {
+        let (generic_def, generic_args) = {
+            let class = class_ref.borrow();
+            (class.generic_def.clone(), class.generic_args.clone())
+        };
+        match generic_def {
+            Some(ref generic_def)
+                if generic_args.iter().any(|arg| arg.contains_generic_params()) =>
+            {
+                ClassInfo::instantiate(
+                    generic_def,
+                    &generic_args
+                        .iter()
+                        .map(|arg| arg.instantiate(ctx))
+                        .collect::<Vec<Type>>(),
+                )
+                .unwrap_or_else(|| class_ref.clone())
+            }
+            _ => class_ref.clone(),
+        }
     }
 
+    /// Builds the method table of ``class_ref`` (and its parents) from the method table of the
+    /// parent and the non-static methods of the class. Returns the reason if the class does not
+    /// implement a method of its interfaces.
+    pub fn construct_method_table(class_ref: &ClassInfoRef) -> Result<(), String> {
+        // Signatures may refer to the class itself, so it is not borrowed mutably until the method
+        // table is built
+        let (method_table, interfaces) = {
+            let class = class_ref.borrow();
+
+            // Interfaces have no method table of their own. Their methods are called through the
+            // interface maps of the implementing classes.
+            if class.is_interface() {
+                return Ok(());
+            }
+
+            let mut method_table = match &class.parent {
+                Some(parent) => {
+                    ClassInfo::construct_method_table(parent)?;
+                    parent.borrow().method_table.clone()
+                }
+                // If already borrowed, it means that ``class`` is System::Object.
+                None => mscorlib_system_object()
+                    .try_borrow()
+                    .map(|sys_obj| sys_obj.methods.clone())
+                    .unwrap_or_else(|_| class.methods.clone()),
+            };
+
+            for minforef in &class.methods {
+                let minfo = minforef.borrow();
+
+                if minfo.is_static() {
+                    continue;
+                }
+
+                if let Some(m) = method_table.iter_mut().find(|m| {
+                    let m = m.borrow();
+                    m.get_name() == minfo.get_name() && m.get_ty() == minfo.get_ty()
+                }) {
+                    // Override
+                    *m = minforef.clone()
+                } else {
+                    // New slot
+                    method_table.push(minforef.clone());
+                }
+            }
+
+            let mut interfaces = match &class.parent {
+                Some(parent) => parent
+                    .borrow()
+                    .interface_maps
+                    .iter()
+                    .map(|map| map.interface.clone())
+                    .collect(),
+                None => vec![],
+            };
+            for interface in &class.interfaces {
+                ClassInfo::collect_interfaces(interface, &mut interfaces);
+            }
+            (method_table, interfaces)
+        };
+        class_ref.borrow_mut().method_table = method_table;
+
+        let interface_maps = {
+            let class = class_ref.borrow();
+            interfaces
+                .into_iter()
+                .map(|interface| {
+                    let slots = interface
+                        .borrow()
+                        .get_interface_methods()
+                        .iter()
+                        .map(|m| {
+                            let m = m.borrow();
+                            class
+                                .get_interface_method_impl(&interface.borrow(), &m)
+                                .ok_or_else(|| {
+                                    format!(
+                                        "{}.{} is not implemented",
+                                        interface.borrow().get_full_name(),
+                                        m.get_name()
+                                    )
+                                })
+                        })
+                        .collect::<Result<Vec<usize>, String>>()?;
+                    Ok(InterfaceMap { interface, slots })
+                })
+                .collect::<Result<Vec<InterfaceMap>, String>>()?
+        };
+        class_ref.borrow_mut().interface_maps = interface_maps;
+
+        Ok(())
+    }
+
+    /// Adds ``interface`` and the interfaces it inherits to ``interfaces`` unless already added
+    fn collect_interfaces(interface: &ClassInfoRef, interfaces: &mut Vec<ClassInfoRef>) {
+        if interfaces.iter().any(|i| Rc::ptr_eq(i, interface)) {
+            return;
+        }
+        interfaces.push(interface.clone());
+        for base in &interface.borrow().interfaces {
+            ClassInfo::collect_interfaces(base, interfaces);
+        }
+    }
+
+    /// Returns the index in the method table of the method that implements ``method`` of
+    /// ``interface``. An explicit implementation (MethodImpl) of this class or the parent classes
+    /// takes precedence over a method with the same name and signature (II.12.2).
+    fn get_interface_method_impl(
+        &self,
+        interface: &ClassInfo,
+        method: &MethodInfo,
+    ) -> Option<usize> {
+        let explicit = self
+            .find_method_impl(interface, method.get_name(), method.get_ty())
+            .and_then(|body| self.method_table.iter().position(|m| Rc::ptr_eq(m, &body)));
+        explicit.or_else(|| self.get_method_index(method.get_name(), method.get_ty()))
+    }
+
+    /// Returns the method of this class or the parent classes that explicitly implements the
+    /// method ``name`` with the signature ``ty`` of ``interface``
+    fn find_method_impl(
+        &self,
+        interface: &ClassInfo,
+        name: &str,
+        ty: &Type,
+    ) -> Option<MethodInfoRef> {
+        self.method_impls
+            .iter()
+            .find(|mi| {
+                let mi_interface = mi.interface.borrow();
+                *mi_interface == *interface
+                    && mi_interface.generic_args == interface.generic_args
+                    && mi.name == name
+                    && mi.ty == *ty
+            })
+            .map(|mi| mi.body.clone())
+            .or_else(|| {
+                let parent = self.parent.as_ref()?.borrow();
+                parent.find_method_impl(interface, name, ty)
+            })
+    }
+
+    /// Returns the methods of this interface in the order of the slots of interface maps
+    pub fn get_interface_methods(&self) -> Vec<MethodInfoRef> {
+        self.methods
+            .iter()
+            .filter(|m| !m.borrow().is_static())
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the slot in interface maps of the method ``name`` with the signature ``ty`` of this
+    /// interface
+    pub fn get_interface_method_index(&self, name: &str, ty: &Type) -> Option<usize> {
+        self.get_interface_methods().iter().position(|m| {
+            let m = m.borrow();
+            m.get_name() == name && m.get_ty() == ty
+        })
+    }
+
+    /// Returns the index in the method table of the method ``name`` with the signature ``ty``
+    pub fn get_method_index(&self, name: &str, ty: &Type) -> Option<usize> {
+        self.method_table.iter().position(|m| {
+            let m = m.borrow();
+            m.get_name() == name && m.get_ty() == ty
+        })
+    }
+
+    /// Returns the index of the field named ``name`` in the object layout, which places the
+    /// fields inherited from the parent classes first.
     pub fn get_field_index(&self, name: &str) -> Option<usize> {
-        self.fields.iter().position(|f| f.name == name)
+        self.collect_all_fields()
+            .iter()
+            .rposition(|f| f.name == name)
+    }
+
+    /// Collects the fields of the parent classes and this class, in the order of the object
+    /// layout.
+    pub fn collect_all_fields(&self) -> Vec<ClassField> {
+        let mut fields = match self.parent {
+            Some(ref parent) => parent.borrow().collect_all_fields(),
+            None => vec![],
+        };
+        fields.extend(self.fields.iter().cloned());
+        fields
     }
 
     pub fn is_enum(&self) -> bool {
+        match self.parent {
+            Some(ref parent) => parent.borrow().is_mscorlib_system("Enum"),
+            None => false,
+        }
+    }
+
+    /// Returns true if this class derives from System.ValueType. System.Enum itself is not a
+    /// value type.
+    pub fn is_valuetype(&self) -> bool {
         match self.parent {
             Some(ref parent) => {
                 let parent = parent.borrow();
-                (match parent.resolution_scope {
-                    ResolutionScope::AssemblyRef { ref name } if name == "mscorlib" => true,
-                    _ => false,
-                }) && parent.namespace == "System"
-                    && parent.name == "Enum"
+                parent.is_mscorlib_system("Enum")
+                    || (parent.is_mscorlib_system("ValueType") && !self.is_mscorlib_system("Enum"))
             }
             None => false,
         }
     }
+
+    /// Returns true if this class is a delegate type, which derives from System.MulticastDelegate
+    pub fn is_delegate(&self) -> bool {
+        match self.parent {
+            Some(ref parent) => parent.borrow().is_mscorlib_system("MulticastDelegate"),
+            None => false,
+        }
+    }
+
+    /// Returns true if the instances of this class are instances of ``target``, that is, this
+    /// class is ``target``, derives from it or implements it
+    pub fn is_assignable_to(&self, target: &ClassInfo) -> bool {
+        self == target
+            || self
+                .interfaces
+                .iter()
+                .any(|interface| interface.borrow().is_assignable_to(target))
+            || self
+                .parent
+                .as_ref()
+                .map_or(false, |parent| parent.borrow().is_assignable_to(target))
+    }
+
+    fn is_mscorlib_system(&self, name: &str) -> bool {
+        (match self.resolution_scope {
+            ResolutionScope::AssemblyRef { name: ref asm } if asm == "mscorlib" => true,
+            _ => false,
+        }) && self.namespace == "System"
+            && self.name == name
+    }
 }
 
 impl ClassField {
@@ -147,3 +650,69 @@ impl fmt::Debug for ClassInfo {
         )
     }
 }
+
+#[rustfmt::skip]
+pub mod type_attributes_flags {
+    // TODO: Implement all the flags
+    pub const INTERFACE        : u32 = 0x00000020;
+    pub const ABSTRACT         : u32 = 0x00000080;
+    pub const BEFORE_FIELD_INIT: u32 = 0x00100000;
+}
+
+#[rustfmt::skip]
+pub mod field_attributes_flags {
+    // TODO: Implement all the flags
+    pub const STATIC   : u16 = 0x0010;
+    pub const INIT_ONLY: u16 = 0x0020;
+    pub const LITERAL  : u16 = 0x0040;
+}
+
+#[test]
+fn test_self_referencing_method_table() {
+    // class A { virtual void M(A a) } class B : A { void M(B b) }, and class Shape { virtual
+    // void Visit(Circle c) } class Circle : Shape { override void Visit(Circle c) }
+    let new_class = |name: &str, parent: Option<&ClassInfoRef>| {
+        ClassInfo::new_ref(
+            ResolutionScope::asm_ref("test"),
+            "",
+            name,
+            vec![],
+            vec![],
+            parent.cloned(),
+        )
+    };
+    let set_method = |class: &ClassInfoRef, name: &str, param: &ClassInfoRef| {
+        let ty = Type::full_method_ty(0x20, Type::void_ty(), &[Type::class_ty(param.clone())]);
+        let method = MethodInfo::MRef(MemberRefInfo {
+            name: name.to_string(),
+            class: class.clone(),
+            ty,
+        });
+        class.borrow_mut().methods = vec![Rc::new(RefCell::new(method))];
+    };
+    let method_table_names = |class: &ClassInfoRef| {
+        class
+            .borrow()
+            .method_table
+            .iter()
+            .map(|m| m.borrow().get_class().borrow().name.clone())
+            .collect::<Vec<String>>()
+    };
+    let object_len = mscorlib_system_object().borrow().methods.len();
+
+    let a = new_class("A", None);
+    let b = new_class("B", Some(&a));
+    set_method(&a, "M", &a);
+    set_method(&b, "M", &b);
+    ClassInfo::construct_method_table(&b).unwrap();
+    // B.M(B) overloads A.M(A)
+    assert_eq!(method_table_names(&b)[object_len..], ["A", "B"]);
+
+    let shape = new_class("Shape", None);
+    let circle = new_class("Circle", Some(&shape));
+    set_method(&shape, "Visit", &circle);
+    set_method(&circle, "Visit", &circle);
+    ClassInfo::construct_method_table(&circle).unwrap();
+    // Circle.Visit(Circle) overrides Shape.Visit(Circle)
+    assert_eq!(method_table_names(&circle)[object_len..], ["Circle"]);
+}
@@ -0,0 +1,145 @@
+use super::metadata::TableKind;
+use std::{error, fmt, path::PathBuf};
+
+pub type LoadResult<T> = Result<T, LoadError>;
+
+/// The part of an image that was being read when loading failed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadStage {
+    MSDOSHeader,
+    PEFileHeader,
+    PEOptionalHeader,
+    SectionHeaders,
+    CLIHeader,
+    MetaDataHeader,
+    StreamHeaders,
+    MetaDataTables,
+    Heaps,
+}
+
+/// Why an assembly could not be loaded
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadError {
+    /// The file could not be read
+    Io { path: PathBuf, reason: String },
+
+    /// The headers or the metadata streams are malformed. ``offset`` is the position in the image
+    /// where reading stopped.
+    Malformed {
+        stage: LoadStage,
+        offset: u64,
+        reason: String,
+    },
+
+    /// A row of a metadata table is inconsistent (e.g. refers to a row that does not exist).
+    /// ``row`` is 1-based.
+    InvalidRow {
+        table: TableKind,
+        row: u32,
+        reason: String,
+    },
+
+    /// A referenced assembly was not found. ``probed`` lists the files that were looked for.
+    AssemblyNotFound { name: String, probed: Vec<PathBuf> },
+
+    /// A module listed in the manifest of an assembly was not found. ``path`` is where it was
+    /// looked for.
+    ModuleNotFound { name: String, path: Option<PathBuf> },
+
+    /// A referenced assembly was found but its identity does not satisfy the reference (e.g. its
+    /// version is older). ``path`` is None if the assembly was given by a dependency resolver.
+    AssemblyMismatch {
+        reference: String,
+        found: String,
+        path: Option<PathBuf>,
+    },
+}
+
+impl LoadError {
+    pub fn malformed<S: Into<String>>(stage: LoadStage, offset: u64, reason: S) -> Self {
+        LoadError::Malformed {
+            stage,
+            offset,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn invalid_row<S: Into<String>>(table: TableKind, row: u32, reason: S) -> Self {
+        LoadError::InvalidRow {
+            table,
+            row,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for LoadStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            LoadStage::MSDOSHeader => "MS-DOS header",
+            LoadStage::PEFileHeader => "PE file header",
+            LoadStage::PEOptionalHeader => "PE optional header",
+            LoadStage::SectionHeaders => "section headers",
+            LoadStage::CLIHeader => "CLI header",
+            LoadStage::MetaDataHeader => "metadata root",
+            LoadStage::StreamHeaders => "stream headers",
+            LoadStage::MetaDataTables => "metadata tables",
+            LoadStage::Heaps => "metadata heaps",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io { path, reason } => write!(f, "{}: {}", path.display(), reason),
+            LoadError::Malformed {
+                stage,
+                offset,
+                reason,
+            } => write!(f, "malformed {} at offset {:#x}: {}", stage, offset, reason),
+            LoadError::InvalidRow { table, row, reason } => {
+                write!(f, "invalid {:?} row {}: {}", table, row, reason)
+            }
+            LoadError::AssemblyNotFound { name, probed } => {
+                write!(f, "referenced assembly '{}' not found", name)?;
+                if !probed.is_empty() {
+                    write!(f, " (probed ")?;
+                    for (i, path) in probed.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", path.display())?;
+                    }
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            LoadError::ModuleNotFound { name, path } => {
+                write!(f, "module '{}' not found", name)?;
+                match path {
+                    Some(path) => write!(f, " at {}", path.display()),
+                    None => Ok(()),
+                }
+            }
+            LoadError::AssemblyMismatch {
+                reference,
+                found,
+                path,
+            } => {
+                write!(
+                    f,
+                    "referenced assembly '{}' does not match '{}'",
+                    reference, found
+                )?;
+                match path {
+                    Some(path) => write!(f, " found at {}", path.display()),
+                    None => write!(f, " given by the dependency resolver"),
+                }
+            }
+        }
+    }
+}
+
+impl error::Error for LoadError {}
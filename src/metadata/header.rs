@@ -1,7 +1,7 @@
 #[derive(Debug, Clone, Copy)]
 #[repr(C, packed)]
 pub struct PEFileHeader {
-    /// Always 0x14c
+    /// Target machine (see ``machine_types``). 0x14c for 32-bit and AnyCPU images.
     pub machine: u16,
 
     /// Number of sections; indicates size of the Section Table,
@@ -27,6 +27,9 @@ pub struct PEFileHeader {
 
 #[derive(Debug, Clone)]
 pub struct PEOptionalHeader {
+    /// 0x10b for PE32, 0x20b for PE32+ (see ``optional_header_magic``)
+    pub magic: u16,
+
     /// Size of the code (text) section, or the sum of all code sections
     /// if there are multiple sections.
     pub code_size: u32,
@@ -47,11 +50,11 @@ pub struct PEOptionalHeader {
     /// RVA of the code section.
     pub base_of_code: u32,
 
-    /// RVA of the data section.
+    /// RVA of the data section. Absent (0) in PE32+.
     pub base_of_data: u32,
 
     /// Shall be a multiple of 0x10000.
-    pub image_base: u32,
+    pub image_base: u64,
 
     /// Shall be greater than File Alignment.
     pub section_alignment: u32,
@@ -71,16 +74,16 @@ pub struct PEOptionalHeader {
     pub dll_flags: u16,
 
     /// Should be 0x100000 (1Mb)
-    pub stack_reserve_size: u32,
+    pub stack_reserve_size: u64,
 
     /// Should be 0x1000 (4Kb)
-    pub stack_commit_size: u32,
+    pub stack_commit_size: u64,
 
     /// Should be 0x100000 (1Mb)
-    pub heap_reserve_size: u32,
+    pub heap_reserve_size: u64,
 
     /// Should be 0x1000 (4Kb)
-    pub heap_commit_size: u32,
+    pub heap_commit_size: u64,
 
     /// Shall be 0
     pub loader_flags: u32,
@@ -114,6 +117,9 @@ pub struct PEOptionalHeader {
     pub cli_header_size: u32,
 }
 
+/// Index of the CLI header in the data directories of the optional header
+pub const CLI_HEADER_DATA_DIRECTORY: usize = 14;
+
 #[derive(Debug, Clone)]
 pub struct SectionHeader {
     /// An 8-byte, null-padded ASCII string. There is no terminating null
@@ -157,6 +163,22 @@ pub struct SectionHeader {
     pub characteristics: u32,
 }
 
+impl SectionHeader {
+    /// Returns the file offset of ``rva`` if this section has it on disk
+    pub fn file_offset(&self, rva: u32) -> Option<u64> {
+        let offset = rva.checked_sub(self.virtual_address)?;
+        if offset >= self.size_of_raw_data {
+            return None;
+        }
+        Some(self.pointer_to_raw_data as u64 + offset as u64)
+    }
+}
+
+/// Maps ``rva`` to a file offset through the section that contains it
+pub fn rva_to_file_offset(sections: &[SectionHeader], rva: u32) -> Option<u64> {
+    sections.iter().find_map(|section| section.file_offset(rva))
+}
+
 #[derive(Debug, Clone)]
 pub struct CLIHeader {
     /// Size of the header in bytes
@@ -259,3 +281,47 @@ mod section_characteristics {
     /// Section can be written to.
     pub const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
 }
+
+#[rustfmt::skip]
+pub mod machine_types {
+    pub const I386 : u16 = 0x014c;
+    pub const AMD64: u16 = 0x8664;
+    pub const ARM64: u16 = 0xaa64;
+}
+
+#[rustfmt::skip]
+pub mod optional_header_magic {
+    pub const PE32     : u16 = 0x10b;
+    pub const PE32_PLUS: u16 = 0x20b;
+}
+
+#[test]
+fn test_rva_to_file_offset() {
+    let section =
+        |name: &str, virtual_address, size_of_raw_data, pointer_to_raw_data| SectionHeader {
+            name: name.to_string(),
+            virtual_size: size_of_raw_data,
+            virtual_address,
+            size_of_raw_data,
+            pointer_to_raw_data,
+            pointer_to_relocations: 0,
+            pointer_to_linenumbers: 0,
+            number_of_relocations: 0,
+            number_of_linenumbers: 0,
+            characteristics: 0,
+        };
+    let sections = [
+        section(".text", 0x2000, 0x400, 0x200),
+        section(".sdata", 0x4000, 0x200, 0x600),
+    ];
+    assert_eq!(rva_to_file_offset(&sections, 0x2008), Some(0x208));
+    // In a section other than the first
+    assert_eq!(rva_to_file_offset(&sections, 0x4010), Some(0x610));
+    // Past the raw data of .text
+    assert_eq!(rva_to_file_offset(&sections, 0x2400), None);
+    // Before any section
+    assert_eq!(rva_to_file_offset(&sections, 0x1000), None);
+    // Between the two sections and past the last one
+    assert_eq!(rva_to_file_offset(&sections, 0x3000), None);
+    assert_eq!(rva_to_file_offset(&sections, 0x4200), None);
+}
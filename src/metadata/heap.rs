@@ -0,0 +1,139 @@
+use std::str::from_utf8;
+
+/// II.24.2.3 #Strings heap. A string is decoded on demand from any offset, which may point into
+/// the middle of another string since compilers share common suffixes.
+#[derive(Debug, Clone, Default)]
+pub struct StringHeap {
+    bytes: Vec<u8>,
+}
+
+/// II.24.2.4 #Blob heap
+#[derive(Debug, Clone, Default)]
+pub struct BlobHeap {
+    bytes: Vec<u8>,
+}
+
+/// II.24.2.4 #US heap. Each entry is a blob of UTF-16 characters followed by one terminal byte.
+#[derive(Debug, Clone, Default)]
+pub struct UserStringHeap {
+    blob: BlobHeap,
+}
+
+impl StringHeap {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes }
+    }
+
+    /// Returns the null-terminated string at ``index``
+    pub fn get(&self, index: u32) -> Option<&str> {
+        let bytes = self.bytes.get(index as usize..)?;
+        let len = bytes.iter().position(|&c| c == 0)?;
+        from_utf8(&bytes[..len]).ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+impl BlobHeap {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes }
+    }
+
+    /// Returns the blob at ``index`` without its length prefix. Index 0 is the empty blob even if
+    /// the heap is absent.
+    pub fn get(&self, index: u32) -> Option<&[u8]> {
+        if index == 0 {
+            return Some(&[]);
+        }
+        let bytes = self.bytes.get(index as usize..)?;
+        let (len, len_size) = decompress_blob_length(bytes)?;
+        bytes.get(len_size..len_size + len as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+impl UserStringHeap {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            blob: BlobHeap::new(bytes),
+        }
+    }
+
+    /// Returns the UTF-16 characters of the string at ``index``
+    pub fn get(&self, index: u32) -> Option<Vec<u16>> {
+        let bytes = self.blob.get(index)?;
+        Some(
+            bytes
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect(),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.blob.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blob.is_empty()
+    }
+}
+
+/// II.24.2.4 Decodes the length of a blob. Returns the length and the size of its encoding.
+pub fn decompress_blob_length(bytes: &[u8]) -> Option<(u32, usize)> {
+    let first = *bytes.get(0)? as u32;
+    if first & 0b1000_0000 == 0 {
+        Some((first, 1))
+    } else if first & 0b1100_0000 == 0b1000_0000 {
+        Some((((first & 0b0011_1111) << 8) + *bytes.get(1)? as u32, 2))
+    } else if first & 0b1110_0000 == 0b1100_0000 {
+        let rest = bytes.get(1..4)?;
+        Some((
+            ((first & 0b0001_1111) << 24)
+                + ((rest[0] as u32) << 16)
+                + ((rest[1] as u32) << 8)
+                + rest[2] as u32,
+            4,
+        ))
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_heaps() {
+    let strings = StringHeap::new(b"\0Foo\0BarBaz\0".to_vec());
+    assert_eq!(strings.get(0), Some(""));
+    assert_eq!(strings.get(1), Some("Foo"));
+    assert_eq!(strings.get(5), Some("BarBaz"));
+    assert_eq!(strings.get(8), Some("Baz"));
+    assert_eq!(strings.get(12), None);
+
+    let mut bytes = vec![0, 3, 1, 2, 3, 0x80, 0x80];
+    bytes.extend(vec![7u8; 0x80]);
+    bytes.extend(&[0xc0, 0, 0x40, 0]);
+    bytes.extend(vec![9u8; 0x4000]);
+    let blob = BlobHeap::new(bytes);
+    assert_eq!(blob.get(0), Some(&[][..]));
+    assert_eq!(blob.get(1), Some(&[1, 2, 3][..]));
+    assert_eq!(blob.get(2), Some(&[2][..]));
+    assert_eq!(blob.get(5).map(|b| b.len()), Some(0x80));
+    assert_eq!(blob.get(0x87).map(|b| b.len()), Some(0x4000));
+    assert_eq!(blob.get(0x5000), None);
+
+    let us = UserStringHeap::new(vec![0, 5, b'h', 0, b'i', 0, 0]);
+    assert_eq!(us.get(1), Some(vec![b'h' as u16, b'i' as u16]));
+}
@@ -1,12 +1,12 @@
 use crate::{
     metadata::{
-        assembly::*, class::*, metadata::*, method::*, pe_parser::*, signature::*, token::*,
+        assembly::*, class::*, error::*, metadata::*, method::*, pe_parser::*, reader::*,
+        signature::*, token::*,
     },
     util::{name_path::*, resolver::*},
 };
-use rustc_hash::FxHashMap;
-use std::path;
-use std::{cell::RefCell, rc::Rc};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::{cell::RefCell, fs, path, rc::Rc};
 
 pub type RVA = u32;
 
@@ -21,17 +21,54 @@ pub struct Image {
     /// PE file reader
     pub pe_parser: Option<Rc<RefCell<PEParser>>>,
 
-    /// Cache ``MethodInfoRef`` by RVA
-    pub method_cache: FxHashMap<RVA, MethodInfoRef>,
+    /// Cache ``MethodInfoRef`` by token
+    pub method_cache: FxHashMap<Token, MethodInfoRef>,
+
+    /// Methods in ``method_cache``, to tell the methods defined in this image from the others
+    pub defined_methods: FxHashSet<*const RefCell<MethodInfo>>,
 
     /// Cache ``ClassInfoRef`` by token
     pub class_cache: FxHashMap<Token, ClassInfoRef>,
 
+    /// Cache ``ClassInfoRef`` that each field belongs to by the token of the field
+    pub field_owner_cache: FxHashMap<Token, ClassInfoRef>,
+
     /// Assembly References
     pub asm_refs: FxHashMap<String, AssemblyRef>,
 
-    /// File name from which this image is loaded
+    /// File name from which this image is loaded. Empty if loaded from memory.
     pub filename: path::PathBuf,
+
+    /// Finds referenced assemblies
+    pub resolver: AssemblyResolver,
+
+    /// Assemblies that define the types that TypeRefs refer to through type forwarders
+    pub type_forwards: FxHashMap<TypeRefTable, String>,
+
+    /// Name of the assembly that this image is a module of. None if this image has the manifest.
+    pub parent_assembly: Option<String>,
+
+    /// Modules listed in the manifest (II.22.19 File) by their file names
+    pub modules: FxHashMap<String, AssemblyRef>,
+
+    /// Classes defined in all the modules of the assembly. Empty if it has only one module.
+    pub module_classes: Vec<ClassInfoRef>,
+}
+
+/// Resource listed in the manifest of an assembly (II.22.24)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestResource {
+    pub name: String,
+    pub visibility: ResourceVisibility,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResourceVisibility {
+    /// Exported from the assembly
+    Public,
+    /// Private to the assembly
+    Private,
 }
 
 impl Image {
@@ -46,9 +83,16 @@ impl Image {
             metadata,
             pe_parser,
             method_cache: FxHashMap::default(),
+            defined_methods: FxHashSet::default(),
             class_cache: FxHashMap::default(),
+            field_owner_cache: FxHashMap::default(),
             asm_refs: FxHashMap::default(),
             filename,
+            resolver: AssemblyResolver::new(),
+            type_forwards: FxHashMap::default(),
+            parent_assembly: None,
+            modules: FxHashMap::default(),
+            module_classes: vec![],
         }
     }
 
@@ -60,293 +104,1182 @@ impl Image {
             asms.insert(name.clone(), asm.clone());
             asm.borrow().image.collect_all_reachable_assemblies(asms);
         }
+        for (name, module) in &self.modules {
+            let key = format!("{}/{}", module.borrow().name, name);
+            if asms.contains_key(&key) {
+                continue;
+            }
+            asms.insert(key, module.clone());
+            module.borrow().image.collect_all_reachable_assemblies(asms);
+        }
     }
 
-    pub fn setup_all_asmref(&mut self, loaded: &mut FxHashMap<String, AssemblyRef>) {
-        for asmref_ in self.metadata.get_table(TableKind::AssemblyRef) {
+    /// Loads the referenced assemblies
+    pub fn setup_all_asmref(
+        &mut self,
+        loaded: &mut FxHashMap<String, AssemblyRef>,
+    ) -> LoadResult<()> {
+        for (i, asmref_) in self
+            .metadata
+            .get_table(TableKind::AssemblyRef)
+            .iter()
+            .enumerate()
+        {
             let asmref = retrieve!(asmref_, Table::AssemblyRef);
-            let name = self.get_string(asmref.name);
+            let name = self.metadata.strings.get(asmref.name).ok_or_else(|| {
+                LoadError::invalid_row(TableKind::AssemblyRef, i as u32 + 1, "invalid name")
+            })?;
 
             // TODO: Treat as special
-            if name == "mscorlib" {
+            if self.asm_ref_name(asmref) == Some("mscorlib") {
                 continue;
             }
 
-            // Already loaded
+            let reference = self.asm_ref_identity(asmref).ok_or_else(|| {
+                LoadError::invalid_row(TableKind::AssemblyRef, i as u32 + 1, "invalid identity")
+            })?;
+
+            // Already loaded. The identity of an assembly being set up cannot be checked here.
             if let Some(asm) = loaded.get(name) {
+                if let Ok(asm) = asm.try_borrow() {
+                    check_identity(&asm, &reference)?;
+                }
                 self.asm_refs.insert(name.to_string(), asm.clone());
                 continue;
             }
 
-            let asm = Assembly::load_exclusive(
-                {
-                    let mut path = self.filename.parent().unwrap().to_path_buf();
-                    path.push(name.clone());
-                    path.set_extension("dll");
-                    path
-                },
-                loaded,
-            )
-            .unwrap();
+            let (pe_parser, asm) = self.find_referenced_assembly(&reference)?;
+            let asm = Assembly::load_exclusive(pe_parser, asm, self.resolver.clone(), loaded)?;
 
             loaded.insert(name.to_string(), asm.clone());
 
             self.asm_refs.insert(name.to_string(), asm);
         }
+
+        Ok(())
+    }
+
+    /// Finds the assembly that satisfies ``reference`` as ``resolver`` decides. If only
+    /// assemblies with other identities are found, reports the first of them.
+    fn find_referenced_assembly(
+        &self,
+        reference: &AssemblyIdentity,
+    ) -> LoadResult<(PEParser, Assembly)> {
+        let mut mismatch = None;
+
+        if let Some(resolver) = &self.resolver.dependency_resolver {
+            if let Some(bytes) = resolver.resolve(&reference.name) {
+                let mut pe_parser = PEParser::from_bytes(bytes);
+                let asm = pe_parser.create_assembly()?;
+                match check_identity(&asm, reference) {
+                    Ok(()) => return Ok((pe_parser, asm)),
+                    Err(err) => mismatch = Some(err),
+                }
+            }
+        }
+
+        let probed = self
+            .resolver
+            .candidates(&reference.name, self.filename.parent());
+        for path in &probed {
+            if !path.is_file() {
+                continue;
+            }
+            let mut pe_parser = PEParser::new(path.clone())?;
+            let asm = pe_parser.create_assembly()?;
+            match check_identity(&asm, reference) {
+                Ok(()) => return Ok((pe_parser, asm)),
+                Err(err) => {
+                    mismatch.get_or_insert(err);
+                }
+            }
+        }
+
+        Err(mismatch.unwrap_or_else(|| LoadError::AssemblyNotFound {
+            name: reference.name.clone(),
+            probed,
+        }))
+    }
+
+    /// Loads the other modules of this assembly and lets every module find the classes of the
+    /// others
+    pub fn setup_all_module(
+        &mut self,
+        loaded: &mut FxHashMap<String, AssemblyRef>,
+    ) -> LoadResult<()> {
+        let asm_name = self
+            .get_assembly_name()
+            .ok_or_else(|| LoadError::invalid_row(TableKind::Assembly, 1, "invalid name"))?
+            .to_string();
+
+        let mut names = vec![];
+        for (token, file) in self.metadata_reader().rows::<FileTable>() {
+            if file.flags & file_flags::CONTAINS_NO_META_DATA > 0 {
+                continue;
+            }
+            let name = self.metadata.strings.get(file.name).ok_or_else(|| {
+                LoadError::invalid_row(TableKind::File, decode_token(token).1, "invalid name")
+            })?;
+            names.push(name.to_string());
+        }
+
+        for name in names {
+            let mut pe_parser = self.find_module(&name)?;
+            let mut module = pe_parser.create_module(&asm_name)?;
+            module.image.pe_parser = Some(Rc::new(RefCell::new(pe_parser)));
+            module.image.resolver = self.resolver.clone();
+            module.image.setup_all_asmref(loaded)?;
+            module.image.define_all_class()?;
+            self.modules.insert(name, Rc::new(RefCell::new(module)));
+        }
+
+        if self.modules.is_empty() {
+            return Ok(());
+        }
+
+        let mut classes = self.defined_classes();
+        for module in self.modules.values() {
+            classes.extend(module.borrow().image.defined_classes());
+        }
+        for module in self.modules.values() {
+            module.borrow_mut().image.module_classes = classes.clone();
+        }
+        self.module_classes = classes;
+
+        Ok(())
+    }
+
+    /// Asks ``resolver`` for the module ``name`` first, then looks for it next to this image.
+    /// Modules are files of the assembly, so they are not probed for elsewhere.
+    fn find_module(&self, name: &str) -> LoadResult<PEParser> {
+        if let Some(resolver) = &self.resolver.dependency_resolver {
+            if let Some(bytes) = resolver.resolve(name) {
+                return Ok(PEParser::from_bytes(bytes));
+            }
+        }
+
+        let not_found = |path| LoadError::ModuleNotFound {
+            name: name.to_string(),
+            path,
+        };
+        let path = self.filename.parent().ok_or_else(|| not_found(None))?.join(name);
+        if !path.is_file() {
+            return Err(not_found(Some(path)));
+        }
+        PEParser::new(path)
+    }
+
+    /// Returns the classes defined in this image
+    fn defined_classes(&self) -> Vec<ClassInfoRef> {
+        let typedef = TableKind::TypeDef.into();
+        self.class_cache
+            .iter()
+            .filter(|(token, _)| decode_token(**token).0 == typedef)
+            .map(|(_, class)| class.clone())
+            .collect()
+    }
+
+    /// Returns the class ``namespace.name`` defined in any module of this assembly
+    fn find_module_class(&self, namespace: &str, name: &str) -> Option<ClassInfoRef> {
+        let asm_name = self.get_assembly_name()?;
+        let path = TypePath(vec![asm_name, namespace, name]);
+        self.find_class(path.clone()).or_else(|| {
+            self.module_classes
+                .iter()
+                .find(|class| (&*class.borrow()).into(): TypePath == path)
+                .cloned()
+        })
+    }
+
+    /// Returns the resources listed in the manifest of this assembly. The bytes of a resource in
+    /// another file or assembly are read from there.
+    pub fn manifest_resources(&self) -> LoadResult<Vec<ManifestResource>> {
+        self.metadata_reader()
+            .rows::<ManifestResourceTable>()
+            .map(|(token, res)| self.read_manifest_resource(decode_token(token).1, res))
+            .collect()
+    }
+
+    /// Returns the resource ``name`` listed in the manifest of this assembly
+    pub fn manifest_resource(&self, name: &str) -> LoadResult<Option<ManifestResource>> {
+        for (token, res) in self.metadata_reader().rows::<ManifestResourceTable>() {
+            if self.metadata.strings.get(res.name) == Some(name) {
+                return self
+                    .read_manifest_resource(decode_token(token).1, res)
+                    .map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn read_manifest_resource(
+        &self,
+        row: u32,
+        res: &ManifestResourceTable,
+    ) -> LoadResult<ManifestResource> {
+        let invalid =
+            |reason: &str| LoadError::invalid_row(TableKind::ManifestResource, row, reason);
+        let name = self
+            .metadata
+            .strings
+            .get(res.name)
+            .ok_or_else(|| invalid("invalid name"))?;
+        let visibility = match res.flags & manifest_resource_flags::VISIBILITY_MASK {
+            manifest_resource_flags::PUBLIC => ResourceVisibility::Public,
+            manifest_resource_flags::PRIVATE => ResourceVisibility::Private,
+            _ => return Err(invalid("invalid visibility")),
+        };
+        let out_of_bounds = || invalid("resource out of the Resources directory");
+
+        let bytes = match res.implementation_decoded() {
+            None => self.read_embedded_resource(res.offset).ok_or_else(out_of_bounds)?,
+            Some(token) => match self.metadata.get_table_entry(token) {
+                Some(Table::File(file)) => {
+                    let file_name = self
+                        .metadata
+                        .strings
+                        .get(file.name)
+                        .ok_or_else(|| invalid("invalid file"))?;
+                    match self.modules.get(file_name) {
+                        // Embedded in a module of this assembly
+                        Some(module) => module
+                            .borrow()
+                            .image
+                            .read_embedded_resource(res.offset)
+                            .ok_or_else(out_of_bounds)?,
+                        None => self.read_linked_file(file_name)?,
+                    }
+                }
+                Some(Table::AssemblyRef(asmref)) => {
+                    let asm_name = self
+                        .metadata
+                        .strings
+                        .get(asmref.name)
+                        .ok_or_else(|| invalid("invalid assembly"))?;
+                    let asm = self
+                        .asm_refs
+                        .get(asm_name)
+                        .ok_or_else(|| LoadError::AssemblyNotFound {
+                            name: asm_name.to_string(),
+                            probed: vec![],
+                        })?
+                        .try_borrow()
+                        .map_err(|_| invalid("referenced assembly is in use"))?;
+                    match asm.image.manifest_resource(name)? {
+                        Some(resource) => resource.bytes,
+                        None => return Err(invalid("not found in the referenced assembly")),
+                    }
+                }
+                _ => return Err(invalid("invalid implementation")),
+            },
+        };
+
+        Ok(ManifestResource {
+            name: name.to_string(),
+            visibility,
+            bytes,
+        })
+    }
+
+    /// Reads the resource at ``offset`` in the Resources directory of the CLI header. Returns
+    /// None if it does not fit in the directory.
+    fn read_embedded_resource(&self, offset: u32) -> Option<Vec<u8>> {
+        let header = &self.cli_info.cli_header;
+        let size = header.resources_size as u64;
+        if offset as u64 + 4 > size {
+            return None;
+        }
+        let start = self
+            .cli_info
+            .rva_to_offset(header.resources_rva.checked_add(offset)?)?;
+        let bytes = self
+            .pe_parser
+            .as_ref()?
+            .try_borrow_mut()
+            .ok()?
+            .read_resource(start)?;
+        if offset as u64 + 4 + bytes.len() as u64 > size {
+            return None;
+        }
+        Some(bytes)
+    }
+
+    /// Returns the initial data that FieldRVA gives to the field ``token`` (e.g. the elements of
+    /// an array initializer). Its size is the one of the field's type, which ClassLayout gives
+    /// for a value type. None if the field has no such data.
+    pub fn get_field_rva_data(&self, token: Token) -> Option<Vec<u8>> {
+        let DecodedToken(table, row) = decode_token(token);
+        if table != TableKind::Field.into() {
+            return None;
+        }
+        let reader = self.metadata_reader();
+        let (_, field_rva) = reader
+            .rows::<FieldRVATable>()
+            .find(|(_, field_rva)| field_rva.field == row)?;
+        let field = reader.row::<FieldTable>(row)?;
+        let ty = SignatureParser::new(reader.get_blob(field.signature)?).parse_field_sig(self)?;
+        let size = self.get_size_of_field_data(&ty)?;
+        let offset = self.cli_info.rva_to_offset(field_rva.rva)?;
+        self.pe_parser
+            .as_ref()?
+            .try_borrow_mut()
+            .ok()?
+            .read_bytes_at(offset, size)
+    }
+
+    fn get_size_of_field_data(&self, ty: &Type) -> Option<usize> {
+        Some(match ty.base {
+            ElementType::Boolean | ElementType::I1 | ElementType::U1 => 1,
+            ElementType::Char | ElementType::I2 | ElementType::U2 => 2,
+            ElementType::I4 | ElementType::U4 | ElementType::R4 => 4,
+            ElementType::I8
+            | ElementType::U8
+            | ElementType::R8
+            | ElementType::I
+            | ElementType::U => 8,
+            ElementType::ValueType(ref class) => {
+                let class = class.borrow();
+                let (token, _) = self.class_cache.iter().find(|(token, class_ref)| {
+                    decode_token(**token).0 == TableKind::TypeDef.into()
+                        && *class_ref.borrow() == *class
+                })?;
+                let row = decode_token(*token).1;
+                let (_, layout) = self
+                    .metadata_reader()
+                    .rows::<ClassLayoutTable>()
+                    .find(|(_, layout)| layout.parent == row)?;
+                layout.class_size as usize
+            }
+            _ => return None,
+        })
+    }
+
+    /// Reads the file ``name`` of this assembly that has no metadata (e.g. a linked resource)
+    fn read_linked_file(&self, name: &str) -> LoadResult<Vec<u8>> {
+        if let Some(resolver) = &self.resolver.dependency_resolver {
+            if let Some(bytes) = resolver.resolve(name) {
+                return Ok(bytes);
+            }
+        }
+
+        let path = match self.filename.parent() {
+            Some(dir) => dir.join(name),
+            None => {
+                return Err(LoadError::ModuleNotFound {
+                    name: name.to_string(),
+                    path: None,
+                })
+            }
+        };
+        fs::read(&path).map_err(|err| LoadError::Io {
+            path,
+            reason: err.to_string(),
+        })
+    }
+
+    /// Returns the identity of this assembly
+    pub fn assembly_identity(&self) -> Option<AssemblyIdentity> {
+        let (_, asm) = self.metadata_reader().rows::<AssemblyTable>().next()?;
+        let public_key = self.metadata.blob.get(asm.public_key)?;
+        Some(AssemblyIdentity {
+            name: self.metadata.strings.get(asm.name)?.to_string(),
+            version: [
+                asm.major_version,
+                asm.minor_version,
+                asm.build_number,
+                asm.revision_number,
+            ],
+            culture: self.metadata.strings.get(asm.culture)?.to_string(),
+            public_key_token: if public_key.is_empty() {
+                None
+            } else {
+                Some(public_key_token(public_key))
+            },
+        })
+    }
+
+    /// Returns the identity that ``asmref`` refers to
+    pub fn asm_ref_identity(&self, asmref: &AssemblyRefTable) -> Option<AssemblyIdentity> {
+        let key_or_token = self.metadata.blob.get(asmref.public_key_or_token)?;
+        let public_key_token = match key_or_token.len() {
+            0 => None,
+            _ if asmref.flags & assembly_flags::PUBLIC_KEY > 0 => {
+                Some(public_key_token(key_or_token))
+            }
+            8 => {
+                let mut token = [0u8; 8];
+                token.copy_from_slice(key_or_token);
+                Some(token)
+            }
+            _ => return None,
+        };
+        Some(AssemblyIdentity {
+            name: self.metadata.strings.get(asmref.name)?.to_string(),
+            version: [
+                asmref.major_version,
+                asmref.minor_version,
+                asmref.build_number,
+                asmref.revision_number,
+            ],
+            culture: self.metadata.strings.get(asmref.culture)?.to_string(),
+            public_key_token,
+        })
+    }
+
+    /// Returns the name of the assembly that ``asmref`` refers to, or ``mscorlib`` for the core
+    /// library: its facades and the assemblies signed with the keys of the framework
+    pub fn asm_ref_name(&self, asmref: &AssemblyRefTable) -> Option<&str> {
+        let name = self.metadata.strings.get(asmref.name)?;
+        let token = self.asm_ref_identity(asmref)?.public_key_token;
+        if is_core_library(name) || token.iter().any(|t| FRAMEWORK_PUBLIC_KEY_TOKENS.contains(t)) {
+            Some("mscorlib")
+        } else {
+            Some(name)
+        }
     }
 
-    pub fn setup_all_typeref(&mut self) {
+    pub fn setup_all_typeref(&mut self) -> LoadResult<()> {
+        let mut nested = vec![];
         for (i, typeref) in self
             .metadata
             .get_table(TableKind::TypeRef)
             .iter()
             .enumerate()
         {
-            let token = encode_token(TableKind::TypeRef.into(), i as u32 + 1);
+            let row = i as u32 + 1;
+            let invalid = |reason: &str| LoadError::invalid_row(TableKind::TypeRef, row, reason);
+            let token = encode_token(TableKind::TypeRef.into(), row);
             let tref = retrieve!(typeref, Table::TypeRef);
-            let namespace = self.get_string(tref.type_namespace);
-            let name = self.get_string(tref.type_name);
-            let asm = retrieve!(
-                self.metadata
-                    .get_table_entry(tref.resolution_scope_decoded())
-                    .unwrap(),
-                Table::AssemblyRef
-            );
-            let asm_name = self.get_string(asm.name);
+            let namespace = self
+                .metadata
+                .strings
+                .get(tref.type_namespace)
+                .ok_or_else(|| invalid("invalid namespace"))?;
+            let name = self
+                .metadata
+                .strings
+                .get(tref.type_name)
+                .ok_or_else(|| invalid("invalid name"))?;
+            let scope = decode_coded_index(CodedIndexKind::ResolutionScope, tref.resolution_scope)
+                .and_then(|scope| self.metadata.get_table_entry(scope));
+            let asm = match scope {
+                Some(Table::AssemblyRef(asm)) => asm,
+                // Types defined in this module or another module of this assembly
+                Some(Table::Module(_)) | Some(Table::ModuleRef(_)) => {
+                    let class = self.find_module_class(namespace, name).ok_or_else(|| {
+                        invalid(&format!("{}.{} not found in any module", namespace, name))
+                    })?;
+                    self.class_cache.insert(token, class);
+                    continue;
+                }
+                // Resolved after the types enclosing them
+                Some(Table::TypeRef(_)) => {
+                    nested.push(row);
+                    continue;
+                }
+                _ => return Err(invalid("invalid resolution scope")),
+            };
+            let asm_name = self
+                .asm_ref_name(&asm)
+                .ok_or_else(|| invalid("invalid assembly name"))?;
 
-            // TODO: Treat as special
-            if asm_name == "mscorlib" {
-                if let Some(class) = get_mscorlib().get(TypePath(vec!["mscorlib", namespace, name]))
-                {
-                    self.class_cache.insert(token, class.clone());
+            let found = self.find_referenced_class(asm_name, namespace, name, 0);
+            let (defining_asm, class) = match found {
+                Some(found) => found,
+                // TODO: Types of mscorlib that are not implemented yet
+                None if is_core_library(asm_name) => continue,
+                None => {
+                    return Err(invalid(&format!(
+                        "{}.{} not found in {}",
+                        namespace, name, asm_name
+                    )))
                 }
-                continue;
+            };
+
+            if defining_asm != asm_name {
+                self.type_forwards.insert(*tref, defining_asm);
             }
+            self.class_cache.insert(token, class);
+        }
 
-            let class = self
-                .asm_refs
-                .get(asm_name)
-                .unwrap()
-                .borrow()
-                .image
-                .find_class(TypePath(vec![asm_name, namespace, name]))
-                .unwrap();
+        self.setup_nested_typerefs(nested)
+    }
+
+    /// Resolves the TypeRefs at ``rows``, which refer to nested types, through the types enclosing
+    /// them. Enclosing types may be nested as well, so this repeats while any TypeRef is resolved.
+    fn setup_nested_typerefs(&mut self, mut rows: Vec<u32>) -> LoadResult<()> {
+        while !rows.is_empty() {
+            let mut unresolved = vec![];
+            for &row in &rows {
+                let invalid =
+                    |reason: &str| LoadError::invalid_row(TableKind::TypeRef, row, reason);
+                let tref = *self.metadata_reader().row::<TypeRefTable>(row).unwrap();
+                let enclosing = tref
+                    .resolution_scope_decoded()
+                    .and_then(|scope| self.class_cache.get(&scope.into()));
+                let enclosing = match enclosing {
+                    Some(enclosing) => enclosing.clone(),
+                    None => {
+                        unresolved.push(row);
+                        continue;
+                    }
+                };
+                let name = self
+                    .metadata
+                    .strings
+                    .get(tref.type_name)
+                    .ok_or_else(|| invalid("invalid name"))?;
+                let class = match self.find_nested_class(&enclosing, name) {
+                    Some(class) => class,
+                    // TODO: Types of mscorlib that are not implemented yet
+                    None if is_core_library(enclosing.borrow().resolution_scope.get_name()) => {
+                        continue
+                    }
+                    None => {
+                        let enclosing = enclosing.borrow();
+                        return Err(invalid(&format!(
+                            "{} not found in {}.{}",
+                            name, enclosing.namespace, enclosing.name
+                        )));
+                    }
+                };
+                self.class_cache
+                    .insert(encode_token(TableKind::TypeRef.into(), row), class);
+            }
+
+            if unresolved.len() == rows.len() {
+                // The rest are nested in types of mscorlib that are not implemented yet, or in
+                // each other
+                for row in unresolved {
+                    let tref = self.metadata_reader().row::<TypeRefTable>(row).unwrap();
+                    match self.outermost_type_ref(tref) {
+                        Some((_, Table::AssemblyRef(asmref)))
+                            if self.asm_ref_name(&asmref) == Some("mscorlib") => {}
+                        _ => {
+                            return Err(LoadError::invalid_row(
+                                TableKind::TypeRef,
+                                row,
+                                "enclosing type not found",
+                            ))
+                        }
+                    }
+                }
+                break;
+            }
+            rows = unresolved;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the TypeRef of the outermost type enclosing the type of ``tref`` (``tref`` itself if
+    /// the type is not nested) along with its resolution scope. Returns None if the resolution
+    /// scopes are broken or circular.
+    fn outermost_type_ref(&self, tref: &TypeRefTable) -> Option<(TypeRefTable, Table)> {
+        let mut outer = *tref;
+        for _ in 0..=self.metadata.get_table(TableKind::TypeRef).len() {
+            match self.metadata.get_table_entry(outer.resolution_scope_decoded()?)? {
+                Table::TypeRef(enclosing) => outer = enclosing,
+                scope => return Some((outer, scope)),
+            }
+        }
+        None
+    }
+
+    /// Finds the type named ``name`` nested in ``enclosing``, which is defined in this assembly or
+    /// one referenced by it
+    fn find_nested_class(&self, enclosing: &ClassInfoRef, name: &str) -> Option<ClassInfoRef> {
+        let asm_name = enclosing.borrow().resolution_scope.get_name().to_string();
+        if Some(asm_name.as_str()) == self.get_assembly_name() {
+            return self.find_nested_typedef(enclosing, name);
+        }
+        self.asm_refs
+            .get(&asm_name)?
+            .try_borrow()
+            .ok()?
+            .image
+            .find_nested_typedef(enclosing, name)
+    }
+
+    /// Finds the TypeDef named ``name`` nested in ``enclosing`` (II.22.32 NestedClass)
+    fn find_nested_typedef(&self, enclosing: &ClassInfoRef, name: &str) -> Option<ClassInfoRef> {
+        let typedef = |row| self.get_class(encode_token(TableKind::TypeDef.into(), row));
+        self.metadata_reader()
+            .rows::<NestedClassTable>()
+            .find_map(|(_, nc)| {
+                let nested = typedef(nc.nested_class)?;
+                if Rc::ptr_eq(typedef(nc.enclosing_class)?, enclosing)
+                    && nested.borrow().name == name
+                {
+                    Some(nested.clone())
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Finds ``namespace.name`` in ``asm_name``, which is this assembly or one referenced by it.
+    /// Returns the name of the assembly that defines the class along with it.
+    fn find_referenced_class(
+        &self,
+        asm_name: &str,
+        namespace: &str,
+        name: &str,
+        depth: usize,
+    ) -> Option<(String, ClassInfoRef)> {
+        if is_core_library(asm_name) {
+            let mscorlib = get_mscorlib();
+            let class = mscorlib.get(TypePath(vec!["mscorlib", namespace, name]))?;
+            return Some(("mscorlib".to_string(), class.clone()));
+        }
+
+        // An assembly may refer to its own types through an AssemblyRef
+        if Some(asm_name) == self.get_assembly_name() {
+            return self.find_exported_class(namespace, name, depth);
+        }
+
+        self.asm_refs
+            .get(asm_name)?
+            .try_borrow()
+            .ok()?
+            .image
+            .find_exported_class(namespace, name, depth)
+    }
+
+    /// Finds ``namespace.name`` defined in this assembly, or in another assembly that this one
+    /// forwards the type to (II.22.14 ExportedType)
+    fn find_exported_class(
+        &self,
+        namespace: &str,
+        name: &str,
+        depth: usize,
+    ) -> Option<(String, ClassInfoRef)> {
+        let asm_name = self.get_assembly_name()?;
+        if let Some(class) = self.find_class(TypePath(vec![asm_name, namespace, name])) {
+            return Some((asm_name.to_string(), class));
+        }
+
+        // Forwarders may point to each other
+        if depth >= MAX_TYPE_FORWARDING_DEPTH {
+            return None;
+        }
 
-            self.class_cache.insert(token, class.clone());
+        let strings = &self.metadata.strings;
+        let (_, exported_type) = self
+            .metadata_reader()
+            .rows::<ExportedTypeTable>()
+            .find(|(_, et)| {
+                strings.get(et.type_namespace) == Some(namespace)
+                    && strings.get(et.type_name) == Some(name)
+            })?;
+        let implementation =
+            decode_coded_index(CodedIndexKind::Implementation, exported_type.implementation)?;
+        match self.metadata.get_table_entry(implementation)? {
+            Table::AssemblyRef(asmref) => {
+                let target = self.asm_ref_name(&asmref)?;
+                self.find_referenced_class(target, namespace, name, depth + 1)
+            }
+            Table::File(_) => {
+                let class = self.find_module_class(namespace, name)?;
+                Some((asm_name.to_string(), class))
+            }
+            // TODO: Nested types
+            _ => None,
         }
     }
 
-    pub fn setup_all_class(&mut self) {
+    pub fn setup_all_class(&mut self) -> LoadResult<()> {
         let typedefs = self.metadata.get_table(TableKind::TypeDef);
         let fields = self.metadata.get_table(TableKind::Field);
         let methoddefs = self.metadata.get_table(TableKind::MethodDef);
+        let mut generic_parents = vec![];
 
         for (i, typedef) in typedefs.iter().enumerate() {
+            let row = i as u32 + 1;
+            let invalid = |reason: &str| LoadError::invalid_row(TableKind::TypeDef, row, reason);
             let typedef = retrieve!(typedef, Table::TypeDef);
-            let next_typedef = typedefs.get(i + 1);
-            let (field_range, method_range) = {
-                let field_start = typedef.field_list as usize - 1;
-                let method_start = typedef.method_list as usize - 1;
-                next_typedef.map_or(
-                    (field_start..fields.len(), method_start..methoddefs.len()),
-                    |table| {
-                        let td = retrieve!(table, Table::TypeDef);
-                        (
-                            field_start..(td.field_list as usize - 1),
-                            method_start..(td.method_list as usize - 1),
-                        )
-                    },
-                )
-            };
+            let next_typedef = typedefs.get(i + 1).map(|td| retrieve!(td, Table::TypeDef));
+
+            // Lists run until the start of the next type's. They must be 1-based, ascending and
+            // inside the tables.
+            let field_end = next_typedef.map_or(fields.len() + 1, |td| td.field_list as usize);
+            let method_end =
+                next_typedef.map_or(methoddefs.len() + 1, |td| td.method_list as usize);
+            let field_range = typedef.field_list as usize..field_end;
+            let method_range = typedef.method_list as usize..method_end;
+            if field_range.start == 0
+                || field_range.start > field_range.end
+                || field_range.end > fields.len() + 1
+            {
+                return Err(invalid("invalid field list"));
+            }
+            if method_range.start == 0
+                || method_range.start > method_range.end
+                || method_range.end > methoddefs.len() + 1
+            {
+                return Err(invalid("invalid method list"));
+            }
 
             let class = self
-                .get_class(encode_token(TableKind::TypeDef.into(), i as u32 + 1))
-                .unwrap()
+                .get_class(encode_token(TableKind::TypeDef.into(), row))
+                .ok_or_else(|| invalid("not defined"))?
                 .clone();
 
             // Set class fields
-            class.borrow_mut().fields = self.metadata.get_table(TableKind::Field)[field_range]
-                .iter()
-                .map(|t| {
-                    let ft = retrieve!(t, Table::Field);
-                    let name = self.get_string(ft.name).to_string();
-                    let mut sig = self.get_blob(ft.signature).iter();
-                    assert_eq!(sig.next().unwrap(), &0x06);
-                    let ty = Type::into_type(self, &mut sig).unwrap();
-                    ClassField { name, ty }
-                })
-                .collect();
+            let mut instance_fields = vec![];
+            let mut static_fields = vec![];
+            for i in field_range {
+                let invalid = |reason| LoadError::invalid_row(TableKind::Field, i as u32, reason);
+                let ft = retrieve!(&fields[i - 1], Table::Field);
+                let name = self
+                    .metadata
+                    .strings
+                    .get(ft.name)
+                    .ok_or_else(|| invalid("invalid name"))?
+                    .to_string();
+                let sig = self
+                    .metadata
+                    .blob
+                    .get(ft.signature)
+                    .ok_or_else(|| invalid("invalid signature"))?;
+                let ty = SignatureParser::new(sig)
+                    .parse_field_sig(self)
+                    .ok_or_else(|| invalid("invalid signature"))?;
+                if ft.flags & field_attributes_flags::STATIC > 0 {
+                    static_fields.push(ClassField { name, ty })
+                } else {
+                    instance_fields.push(ClassField { name, ty })
+                }
+                self.field_owner_cache.insert(
+                    encode_token(TableKind::Field.into(), i as u32),
+                    class.clone(),
+                );
+            }
+            class.borrow_mut().fields = instance_fields;
+            class.borrow_mut().static_fields = static_fields;
 
             let pe_parser_ref = self.pe_parser.as_ref().unwrap();
             let mut pe_parser = pe_parser_ref.borrow_mut();
 
             // Set class methods
             let mut methods = vec![];
-            for mdef in &self.metadata.get_table(TableKind::MethodDef)[method_range] {
-                let mdef = retrieve!(mdef, Table::MethodDef);
-                let method = pe_parser.read_method(self, &class, mdef.rva).unwrap();
-                self.method_cache.insert(mdef.rva, method.clone());
+            for i in method_range {
+                let mdef = retrieve!(&methoddefs[i - 1], Table::MethodDef);
+                let method = pe_parser.read_method(self, &class, mdef).ok_or_else(|| {
+                    LoadError::invalid_row(
+                        TableKind::MethodDef,
+                        i as u32,
+                        "malformed method body or signature",
+                    )
+                })?;
+                self.method_cache.insert(
+                    encode_token(TableKind::MethodDef.into(), i as u32),
+                    method.clone(),
+                );
+                self.defined_methods.insert(Rc::as_ptr(&method));
                 methods.push(method)
             }
             class.borrow_mut().methods = methods;
 
             // Set parent class
             if typedef.extends != 0 {
-                let token = decode_typedef_or_ref_token(typedef.extends as u32);
-                let typedef_or_ref = self.metadata.get_table_entry(token).unwrap();
-                match typedef_or_ref {
-                    Table::TypeDef(_) => {
-                        class.borrow_mut().parent = Some(self.get_class(token).unwrap().clone());
+                let token = decode_coded_index(CodedIndexKind::TypeDefOrRef, typedef.extends)
+                    .ok_or_else(|| invalid("invalid parent"))?;
+                match self.metadata.get_table_entry(token) {
+                    Some(Table::TypeDef(_)) | Some(Table::TypeRef(_)) => {
+                        let parent = self
+                            .get_class(token)
+                            .ok_or_else(|| invalid("parent not found"))?;
+                        class.borrow_mut().parent = Some(parent.clone());
                     }
-                    Table::TypeRef(_) => {
-                        class.borrow_mut().parent = Some(self.get_class(token).unwrap().clone());
+                    // The generic parent class is instantiated after all the classes are set up
+                    Some(Table::TypeSpec(tst)) => generic_parents.push((row, class.clone(), tst)),
+                    _ => return Err(invalid("invalid parent")),
+                }
+            }
+        }
+
+        self.setup_all_generic_param()?;
+        self.setup_all_interface_impl()?;
+
+        for (row, class, tst) in generic_parents {
+            let invalid = |reason| LoadError::invalid_row(TableKind::TypeDef, row, reason);
+            let inst = match self.get_type_from_type_spec(&tst).map(|ty| ty.base) {
+                Some(ElementType::GenericInst(inst)) => inst,
+                _ => return Err(invalid("invalid generic parent")),
+            };
+            let parent = ClassInfo::instantiate(&inst.class, &inst.args)
+                .ok_or_else(|| invalid("generic parent nested too deeply"))?;
+            class.borrow_mut().parent = Some(parent);
+        }
+
+        self.setup_all_method_impl()?;
+        self.check_parent_cycles()?;
+        self.setup_all_class_method_table()
+    }
+
+    fn setup_all_generic_param(&mut self) -> LoadResult<()> {
+        for (i, gp) in self
+            .metadata
+            .get_table(TableKind::GenericParam)
+            .iter()
+            .enumerate()
+        {
+            let row = i as u32 + 1;
+            let invalid = |reason| LoadError::invalid_row(TableKind::GenericParam, row, reason);
+            let gp = retrieve!(gp, Table::GenericParam);
+            let name = self
+                .metadata
+                .strings
+                .get(gp.name)
+                .ok_or_else(|| invalid("invalid name"))?
+                .to_string();
+            let owner = decode_coded_index(CodedIndexKind::TypeOrMethodDef, gp.owner)
+                .ok_or_else(|| invalid("invalid owner"))?;
+            match self.metadata.get_table_entry(owner) {
+                Some(Table::TypeDef(_)) => {
+                    let class = self.get_class(owner).ok_or_else(|| invalid("invalid owner"))?;
+                    class.borrow_mut().generic_params.push(name)
+                }
+                Some(Table::MethodDef(_)) => {
+                    let method = self.get_method(owner).ok_or_else(|| invalid("invalid owner"))?;
+                    let mut method = method.borrow_mut();
+                    match *method {
+                        MethodInfo::MDef(ref mut m) => m.generic_params.push(name),
+                        MethodInfo::MRef(_) => return Err(invalid("invalid owner")),
                     }
-                    _ => unreachable!(),
                 }
+                _ => return Err(invalid("invalid owner")),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn setup_all_interface_impl(&mut self) -> LoadResult<()> {
+        let mut generic_interfaces = vec![];
+
+        for (i, iit) in self
+            .metadata
+            .get_table(TableKind::InterfaceImpl)
+            .iter()
+            .enumerate()
+        {
+            let row = i as u32 + 1;
+            let invalid = |reason| LoadError::invalid_row(TableKind::InterfaceImpl, row, reason);
+            let iit = retrieve!(iit, Table::InterfaceImpl);
+            let class = self
+                .get_class(encode_token(TableKind::TypeDef.into(), iit.class as u32))
+                .ok_or_else(|| invalid("invalid class"))?;
+            let token = decode_coded_index(CodedIndexKind::TypeDefOrRef, iit.interface)
+                .ok_or_else(|| invalid("invalid interface"))?;
+            let interface = match self.metadata.get_table_entry(token) {
+                // Instantiated after all the non-generic interfaces are set up
+                Some(Table::TypeSpec(tst)) => {
+                    generic_interfaces.push((row, class.clone(), tst));
+                    continue;
+                }
+                Some(_) => match self.get_class(token) {
+                    Some(interface) => interface.clone(),
+                    // TODO: Interfaces of mscorlib that are not implemented yet
+                    None => continue,
+                },
+                None => return Err(invalid("invalid interface")),
+            };
+            if !interface.borrow().is_interface() {
+                return Err(invalid("not an interface"));
+            }
+            class.borrow_mut().interfaces.push(interface);
+        }
+
+        for (row, class, tst) in generic_interfaces {
+            let inst = match self.get_type_from_type_spec(&tst).map(|ty| ty.base) {
+                Some(ElementType::GenericInst(inst)) => inst,
+                // TODO: Interfaces of mscorlib that are not implemented yet
+                _ => continue,
+            };
+            let interface = ClassInfo::instantiate(&inst.class, &inst.args).ok_or_else(|| {
+                LoadError::invalid_row(TableKind::InterfaceImpl, row, "nested too deeply")
+            })?;
+            if !interface.borrow().is_interface() {
+                return Err(LoadError::invalid_row(
+                    TableKind::InterfaceImpl,
+                    row,
+                    "not an interface",
+                ));
+            }
+            class.borrow_mut().interfaces.push(interface);
+        }
+
+        Ok(())
+    }
+
+    /// Records the explicit implementations of interface methods. The generic interfaces must
+    /// have been instantiated beforehand.
+    fn setup_all_method_impl(&mut self) -> LoadResult<()> {
+        // The methods may have been set up again since recorded
+        for class in self.defined_classes() {
+            class.borrow_mut().method_impls.clear();
+        }
+
+        for (i, mit) in self
+            .metadata
+            .get_table(TableKind::MethodImpl)
+            .iter()
+            .enumerate()
+        {
+            let row = i as u32 + 1;
+            let invalid = |reason| LoadError::invalid_row(TableKind::MethodImpl, row, reason);
+            let mit = retrieve!(mit, Table::MethodImpl);
+            let class = self
+                .get_class(encode_token(TableKind::TypeDef.into(), mit.class))
+                .ok_or_else(|| invalid("invalid class"))?
+                .clone();
+            let body = decode_coded_index(CodedIndexKind::MethodDefOrRef, mit.method_body)
+                .and_then(|token| self.get_method(token))
+                .ok_or_else(|| invalid("invalid method body"))?;
+            let declaration =
+                decode_coded_index(CodedIndexKind::MethodDefOrRef, mit.method_declaration)
+                    .ok_or_else(|| invalid("invalid method declaration"))?;
+            let (interface, name, ty) = match self.metadata.get_table_entry(declaration) {
+                Some(Table::MethodDef(_)) => {
+                    let method = self
+                        .get_method(declaration)
+                        .ok_or_else(|| invalid("invalid method declaration"))?;
+                    let method = method.borrow();
+                    (
+                        method.get_class().clone(),
+                        method.get_name().to_string(),
+                        method.get_ty().clone(),
+                    )
+                }
+                Some(Table::MemberRef(mrt)) => {
+                    let interface = self
+                        .get_method_impl_interface(mrt.class)
+                        .ok_or_else(|| invalid("invalid method declaration"))?;
+                    let name = self
+                        .metadata
+                        .strings
+                        .get(mrt.name)
+                        .ok_or_else(|| invalid("invalid name"))?
+                        .to_string();
+                    let ty = self
+                        .metadata
+                        .blob
+                        .get(mrt.signature)
+                        .and_then(|sig| SignatureParser::new(sig).parse_method_ref_sig(self))
+                        .ok_or_else(|| invalid("invalid signature"))?;
+                    let ctx = GenericContext {
+                        class_args: interface.borrow().generic_args.clone(),
+                        method_args: vec![],
+                    };
+                    (interface, name, ty.instantiate(&ctx))
+                }
+                _ => return Err(invalid("invalid method declaration")),
+            };
+            if !interface.borrow().is_interface() {
+                return Err(invalid("not an interface method"));
             }
+            class.borrow_mut().method_impls.push(MethodImpl {
+                body,
+                interface,
+                name,
+                ty,
+            });
         }
 
-        self.setup_all_class_method_table();
+        Ok(())
+    }
+
+    /// Returns the interface that ``parent``, the MemberRefParent of a method declaration of
+    /// MethodImpl, refers to
+    fn get_method_impl_interface(&self, parent: u32) -> Option<ClassInfoRef> {
+        let token = decode_coded_index(CodedIndexKind::MemberRefParent, parent)?;
+        match self.metadata.get_table_entry(token)? {
+            Table::TypeDef(_) | Table::TypeRef(_) => self.get_class(token).cloned(),
+            Table::TypeSpec(tst) => match self.get_type_from_type_spec(&tst)?.base {
+                ElementType::GenericInst(inst) => {
+                    ClassInfo::instantiate(&inst.class, &inst.args)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
     }
 
-    pub fn define_all_class(&mut self) {
+    pub fn define_all_class(&mut self) -> LoadResult<()> {
+        let asm_name = self
+            .get_assembly_name()
+            .ok_or_else(|| LoadError::invalid_row(TableKind::Assembly, 1, "invalid name"))?
+            .to_string();
         for (i, typedef) in self
             .metadata
             .get_table(TableKind::TypeDef)
             .iter()
             .enumerate()
         {
+            let invalid = |reason| LoadError::invalid_row(TableKind::TypeDef, i as u32 + 1, reason);
             let typedef = retrieve!(typedef, Table::TypeDef);
             let class_info = ClassInfo::new_ref(
                 ResolutionScope::AssemblyRef {
-                    name: self.get_assembly_name().unwrap().to_string(),
+                    name: asm_name.clone(),
                 },
-                self.get_string(typedef.type_namespace),
-                self.get_string(typedef.type_name),
+                self.metadata
+                    .strings
+                    .get(typedef.type_namespace)
+                    .ok_or_else(|| invalid("invalid namespace"))?,
+                self.metadata
+                    .strings
+                    .get(typedef.type_name)
+                    .ok_or_else(|| invalid("invalid name"))?,
                 vec![],
                 vec![],
                 None,
             );
+            class_info.borrow_mut().flags = typedef.flags;
             self.class_cache.insert(
                 encode_token(TableKind::TypeDef.into(), i as u32 + 1),
                 class_info.clone(),
             );
         }
-    }
 
-    fn get_assembly_name(&self) -> Option<&str> {
-        let asm = retrieve!(
-            self.metadata.get_table(TableKind::Assembly).get(0)?,
-            Table::Assembly
-        );
-        Some(self.get_string(asm.name))
+        Ok(())
     }
 
-    fn setup_all_class_method_table(&mut self) {
-        for (_token, class_ref) in &self.class_cache {
-            self.construct_class_method_table(class_ref);
+    /// Returns the name of the assembly that this image is, or is a module of
+    fn get_assembly_name(&self) -> Option<&str> {
+        match self.metadata.get_table(TableKind::Assembly).first() {
+            Some(asm) => self.metadata.strings.get(retrieve!(asm, Table::Assembly).name),
+            None => self.parent_assembly.as_deref(),
         }
     }
 
-    fn construct_class_method_table(&self, class_ref: &ClassInfoRef) {
-        let mut class = class_ref.borrow_mut();
-        let mut method_table = match &class.parent {
-            Some(parent) => {
-                self.construct_class_method_table(parent);
-                parent.borrow().method_table.clone()
-            }
-            // If already borrowed, it means that ``class`` is System::Object.
-            None => mscorlib_system_object()
-                .try_borrow()
-                .map(|sys_obj| sys_obj.methods.clone())
-                .unwrap_or_else(|_| class.methods.clone()),
-        };
-
-        for minforef in &class.methods {
-            let minfo = minforef.borrow();
-
-            if minfo.is_static() {
-                continue;
+    /// Makes sure that no class is its own ancestor
+    fn check_parent_cycles(&self) -> LoadResult<()> {
+        for (token, class_ref) in &self.class_cache {
+            let mut visited = FxHashSet::default();
+            let mut cur = Some(class_ref.clone());
+            while let Some(class) = cur {
+                if !visited.insert(Rc::as_ptr(&class)) {
+                    return Err(Self::invalid_class(*token, "circular inheritance"));
+                }
+                cur = class.borrow().parent.clone();
             }
+        }
+        Ok(())
+    }
 
-            if let Some(m) = method_table
-                .iter_mut()
-                .find(|m| m.borrow().get_name() == minfo.get_name())
-            {
-                // Override
-                *m = minforef.clone()
-            } else {
-                // New slot
-                method_table.push(minforef.clone());
-            }
+    fn setup_all_class_method_table(&mut self) -> LoadResult<()> {
+        for (token, class_ref) in &self.class_cache {
+            ClassInfo::construct_method_table(class_ref)
+                .map_err(|reason| Self::invalid_class(*token, &reason))?;
         }
+        Ok(())
+    }
 
-        class.method_table = method_table;
+    /// Returns an ``InvalidRow`` error for the TypeDef or TypeRef row of ``token``
+    fn invalid_class(token: Token, reason: &str) -> LoadError {
+        let DecodedToken(table, row) = decode_token(token);
+        let table = if table == TableKind::TypeDef.into() {
+            TableKind::TypeDef
+        } else {
+            TableKind::TypeRef
+        };
+        LoadError::invalid_row(table, row, reason)
     }
 
     pub fn get_class<T: Into<Token>>(&self, token: T) -> Option<&ClassInfoRef> {
         self.class_cache.get(&token.into())
     }
 
-    pub fn get_string<T: Into<u32>>(&self, n: T) -> &str {
-        self.metadata.strings.get(&n.into()).unwrap().as_str()
+    pub fn get_field_owner<T: Into<Token>>(&self, token: T) -> Option<&ClassInfoRef> {
+        self.field_owner_cache.get(&token.into())
     }
 
-    pub fn get_user_string<T: Into<u32>>(&self, n: T) -> &Vec<u16> {
-        self.metadata.user_strings.get(&n.into()).unwrap()
+    /// Returns the typed reader of the metadata of this image
+    pub fn metadata_reader(&self) -> MetadataReader {
+        MetadataReader::new(&self.metadata)
     }
 
-    pub fn get_entry_method(&mut self) -> Option<MethodInfoRef> {
-        let method_or_file = self
-            .metadata
-            .get_table_entry(self.cli_info.cli_header.entry_point_token)?;
-        let mdef = match method_or_file {
-            Table::MethodDef(t) => t,
-            // TOOD: File
-            _ => return None,
-        };
-        self.get_method_by_rva(mdef.rva)
+    /// Returns None if ``n`` is out of the #Strings heap or the string is not valid UTF-8
+    pub fn get_string<T: Into<u32>>(&self, n: T) -> Option<&str> {
+        self.metadata.strings.get(n.into())
     }
 
-    pub fn get_method_by_rva(&self, rva: u32) -> Option<MethodInfoRef> {
-        self.method_cache.get(&rva).map(|m| m.clone())
+    pub fn get_user_string<T: Into<u32>>(&self, n: T) -> Option<Vec<u16>> {
+        self.metadata.user_strings.get(n.into())
     }
 
-    pub fn get_method_def_table_by_rva(&self, rva: u32) -> Option<&MethodDefTable> {
-        for method_def in self.metadata.get_table(TableKind::MethodDef) {
-            match method_def {
-                Table::MethodDef(mdt) if mdt.rva == rva => return Some(mdt),
-                Table::MethodDef(_) => continue,
-                _ => return None,
-            }
+    pub fn get_entry_method(&mut self) -> Option<MethodInfoRef> {
+        let entry_point_token = self.cli_info.cli_header.entry_point_token;
+        match self.metadata.get_table_entry(entry_point_token)? {
+            Table::MethodDef(_) => self.get_method(entry_point_token),
+            // TOOD: File
+            _ => None,
         }
-        None
     }
 
-    pub fn get_blob<T: Into<u32>>(&self, n: T) -> &Vec<u8> {
-        self.metadata.blob.get(&n.into()).unwrap()
+    pub fn get_method<T: Into<Token>>(&self, token: T) -> Option<MethodInfoRef> {
+        self.method_cache.get(&token.into()).map(|m| m.clone())
     }
 
-    pub fn get_path_from_type_ref_table<'a>(
-        &'a self,
-        type_ref_table: &TypeRefTable,
-    ) -> TypePath<'a> {
-        let token = type_ref_table.resolution_scope_decoded();
-        let assembly_ref_table = retrieve!(
-            self.metadata.get_table_entry(token).unwrap(),
-            Table::AssemblyRef
-        );
-        let asm_ref_name = self.get_string(assembly_ref_table.name);
-        let ty_namespace = self.get_string(type_ref_table.type_namespace);
-        let ty_name = self.get_string(type_ref_table.type_name);
-        TypePath(vec![asm_ref_name, ty_namespace, ty_name])
+    /// Returns true if ``method`` is defined in this image rather than another assembly or module.
+    /// The methods of the instances of a generic type belong to the image defining the type.
+    pub fn defines_method(&self, method: &MethodInfoRef) -> bool {
+        if self.defined_methods.contains(&Rc::as_ptr(method)) {
+            return true;
+        }
+        let generic_def = match &*method.borrow() {
+            MethodInfo::MDef(m) => m.class.borrow().generic_def.clone(),
+            MethodInfo::MRef(_) => None,
+        };
+        match generic_def {
+            Some(def) => def
+                .borrow()
+                .methods
+                .iter()
+                .any(|m| self.defined_methods.contains(&Rc::as_ptr(m))),
+            None => false,
+        }
+    }
+
+    pub fn get_blob<T: Into<u32>>(&self, n: T) -> Option<&[u8]> {
+        self.metadata.blob.get(n.into())
+    }
+
+    /// Returns None if the row is broken or the resolution scope is not supported. Nested types
+    /// are in the assembly of the outermost type enclosing them.
+    pub fn get_path_from_type_ref_table<'a>(
+        &'a self,
+        type_ref_table: &TypeRefTable,
+    ) -> Option<TypePath<'a>> {
+        let (outer, scope) = self.outermost_type_ref(type_ref_table)?;
+        let asm_ref_name = match scope {
+            // Types in the modules of this assembly
+            Table::Module(_) | Table::ModuleRef(_) => self.get_assembly_name()?,
+            Table::AssemblyRef(assembly_ref_table) => match self.type_forwards.get(&outer) {
+                Some(defining_asm) => defining_asm.as_str(),
+                None => self.asm_ref_name(&assembly_ref_table)?,
+            },
+            _ => return None,
+        };
+        let ty_namespace = self.get_string(type_ref_table.type_namespace)?;
+        let ty_name = self.get_string(type_ref_table.type_name)?;
+        Some(TypePath(vec![asm_ref_name, ty_namespace, ty_name]))
     }
 
-    pub fn get_method_ref_type_from_signature(&self, signature: u16) -> Type {
-        let sig = self.get_blob(signature);
-        SignatureParser::new(sig)
-            .parse_method_ref_sig(self)
-            .unwrap()
+    pub fn get_method_ref_type_from_signature(&self, signature: u32) -> Option<Type> {
+        SignatureParser::new(self.get_blob(signature)?).parse_method_ref_sig(self)
+    }
+
+    pub fn get_type_from_type_spec(&self, type_spec: &TypeSpecTable) -> Option<Type> {
+        let sig = self.metadata.blob.get(type_spec.signature)?;
+        SignatureParser::new(sig).parse_type_spec_sig(self)
+    }
+
+    pub fn get_type_args_from_method_spec(&self, method_spec: &MethodSpecTable) -> Option<Vec<Type>> {
+        let sig = self.metadata.blob.get(method_spec.instantiation)?;
+        SignatureParser::new(sig).parse_method_spec_sig(self)
     }
 
     pub fn find_class<'a, P: Into<TypePath<'a>>>(&self, path_: P) -> Option<ClassInfoRef> {
@@ -360,16 +1293,65 @@ impl Image {
     }
 }
 
+/// Assemblies whose types are provided by the built-in mscorlib. Programs built for .NET Core or
+/// .NET Standard refer to these facades instead of mscorlib.
+const CORE_LIBRARY_FACADES: &[&str] = &[
+    "mscorlib",
+    "netstandard",
+    "System.Private.CoreLib",
+    "System.Runtime",
+    "System.Runtime.Extensions",
+    "System.Console",
+];
+
+/// Public key tokens of the assemblies of .NET Framework (b03f5f7f11d50a3a), .NET Standard
+/// (cc7b13ffcd2ddd51) and .NET Core (7cec85d7bea7798e). Their types are looked up in the built-in
+/// mscorlib whatever the names of the assemblies are.
+const FRAMEWORK_PUBLIC_KEY_TOKENS: &[[u8; 8]] = &[
+    [0xb0, 0x3f, 0x5f, 0x7f, 0x11, 0xd5, 0x0a, 0x3a],
+    [0xcc, 0x7b, 0x13, 0xff, 0xcd, 0x2d, 0xdd, 0x51],
+    [0x7c, 0xec, 0x85, 0xd7, 0xbe, 0xa7, 0x79, 0x8e],
+];
+
+/// Type forwarders followed before giving up on a TypeRef
+const MAX_TYPE_FORWARDING_DEPTH: usize = 8;
+
+pub fn is_core_library(asm_name: &str) -> bool {
+    CORE_LIBRARY_FACADES.contains(&asm_name)
+}
+
+/// Returns an error if ``asm`` cannot be bound to ``reference``
+fn check_identity(asm: &Assembly, reference: &AssemblyIdentity) -> LoadResult<()> {
+    let identity = asm.image.assembly_identity().ok_or_else(|| {
+        LoadError::invalid_row(TableKind::Assembly, 1, "invalid identity")
+    })?;
+    if identity.satisfies(reference) {
+        return Ok(());
+    }
+    Err(LoadError::AssemblyMismatch {
+        reference: reference.to_string(),
+        found: identity.to_string(),
+        path: if asm.image.filename.as_os_str().is_empty() {
+            None
+        } else {
+            Some(asm.image.filename.clone())
+        },
+    })
+}
+
 thread_local! {
     pub static MSCORLIB: Rc<NameResolver<ClassInfoRef>> = {
         #[rustfmt::skip]
         macro_rules! parse_ty {
             (void) => { Type::void_ty() };
+            (bool) => { Type::boolean_ty() };
             (i4  ) => { Type::i4_ty() };
+            (i8  ) => { Type::i8_ty() };
             (r8  ) => { Type::r8_ty() };
             (char) => { Type::char_ty() };
             (obj ) => { Type::object_ty() };
             (str ) => { Type::string_ty() };
+            (u1a ) => { Type::szarr_ty(Type::u1_ty()) };
         }
 
         macro_rules! method {
@@ -391,22 +1373,44 @@ thread_local! {
             )}}}
 
         let class_system_obj_ref = class!(Object, None);
-        let class_system_int32_ref = class!(Int32, Some(class_system_obj_ref.clone()));
-        let class_system_string_ref = class!(String, Some(class_system_obj_ref.clone()));
         let class_system_valuetype_ref = class!(ValueType, Some(class_system_obj_ref.clone()));
+        let class_system_int32_ref = class!(Int32, Some(class_system_valuetype_ref.clone()));
+        let class_system_int64_ref = class!(Int64, Some(class_system_valuetype_ref.clone()));
+        let class_system_double_ref = class!(Double, Some(class_system_valuetype_ref.clone()));
+        let class_system_boolean_ref = class!(Boolean, Some(class_system_valuetype_ref.clone()));
+        let class_system_char_ref = class!(Char, Some(class_system_valuetype_ref.clone()));
+        let class_system_string_ref = class!(String, Some(class_system_obj_ref.clone()));
         let class_system_enum_ref = class!(Enum, Some(class_system_valuetype_ref.clone()));
+        let class_system_exception_ref = class!(Exception, Some(class_system_obj_ref.clone()));
+        let class_system_idisposable_ref = class!(IDisposable, None);
+        let class_system_icomparable_ref = class!(IComparable, None);
 
         {
             let mut class_system_obj = class_system_obj_ref.borrow_mut();
             let mut class_system_int32 = class_system_int32_ref.borrow_mut();
+            let mut class_system_int64 = class_system_int64_ref.borrow_mut();
+            let mut class_system_double = class_system_double_ref.borrow_mut();
+            let mut class_system_boolean = class_system_boolean_ref.borrow_mut();
+            let mut class_system_char = class_system_char_ref.borrow_mut();
             let mut class_system_string = class_system_string_ref.borrow_mut();
             let mut class_system_valuetype = class_system_valuetype_ref.borrow_mut();
             let mut class_system_enum = class_system_enum_ref.borrow_mut();
+            let mut class_system_exception = class_system_exception_ref.borrow_mut();
+            let mut class_system_idisposable = class_system_idisposable_ref.borrow_mut();
+            let mut class_system_icomparable = class_system_icomparable_ref.borrow_mut();
 
             class_system_obj.methods =
                 vec![method!([0x20], str, [], "ToString", class_system_obj_ref)];
             class_system_int32.methods =
                 vec![method!([0x20], str, [], "ToString", class_system_int32_ref)];
+            class_system_int64.methods =
+                vec![method!([0x20], str, [], "ToString", class_system_int64_ref)];
+            class_system_double.methods =
+                vec![method!([0x20], str, [], "ToString", class_system_double_ref)];
+            class_system_boolean.methods =
+                vec![method!([0x20], str, [], "ToString", class_system_boolean_ref)];
+            class_system_char.methods =
+                vec![method!([0x20], str, [], "ToString", class_system_char_ref)];
             class_system_string.methods = vec![
                 method!([0x20], str, [], "ToString", class_system_string_ref),
                 method!([0x20], char, [i4], "get_Chars", class_system_string_ref),
@@ -416,20 +1420,328 @@ thread_local! {
                 vec![method!([0x20], str, [], "ToString", class_system_valuetype_ref)];
             class_system_enum.methods =
                 vec![method!([0x20], str, [], "ToString", class_system_enum_ref)];
+            class_system_exception.methods = vec![
+                method!([0x20], str, [], "ToString", class_system_exception_ref),
+                method!([0x20], str, [], "get_Message", class_system_exception_ref),
+            ];
+            class_system_idisposable.methods =
+                vec![method!([0x20], void, [], "Dispose", class_system_idisposable_ref)];
+            class_system_icomparable.methods =
+                vec![method!([0x20], i4, [obj], "CompareTo", class_system_icomparable_ref)];
+
+            class_system_idisposable.flags =
+                type_attributes_flags::INTERFACE | type_attributes_flags::ABSTRACT;
+            class_system_icomparable.flags =
+                type_attributes_flags::INTERFACE | type_attributes_flags::ABSTRACT;
 
             class_system_obj.method_table = class_system_obj.methods.clone();
             class_system_int32.method_table = class_system_int32.methods.clone();
+            class_system_int64.method_table = class_system_int64.methods.clone();
+            class_system_double.method_table = class_system_double.methods.clone();
+            class_system_boolean.method_table = class_system_boolean.methods.clone();
+            class_system_char.method_table = class_system_char.methods.clone();
             class_system_string.method_table = class_system_string.methods.clone();
             class_system_valuetype.method_table = class_system_valuetype.methods.clone();
             class_system_enum.method_table = class_system_enum.methods.clone();
+            class_system_exception.method_table = class_system_exception.methods.clone();
 
             // class_system_obj.fields = vec![];
             class_system_int32.fields = vec![ClassField::new_ty(Type::i4_ty())];
+            class_system_int64.fields = vec![ClassField::new_ty(Type::i8_ty())];
+            class_system_double.fields = vec![ClassField::new_ty(Type::r8_ty())];
+            class_system_boolean.fields = vec![ClassField::new_ty(Type::boolean_ty())];
+            class_system_char.fields = vec![ClassField::new_ty(Type::char_ty())];
             class_system_string.fields = vec![ClassField::new_ty(Type::ptr_ty(Type::char_ty()))];
+            class_system_exception.fields =
+                vec![ClassField::new("_message".to_string(), Type::string_ty())];
+        }
+
+        // Exceptions thrown by the runtime. They share the members of System.Exception.
+        macro_rules! exception_class { ($name:ident, $parent:expr) => {{
+            let class_ref = class!($name, Some($parent.clone()));
+            class_ref.borrow_mut().method_table =
+                class_system_exception_ref.borrow().method_table.clone();
+            class_ref
+        }}}
+
+        let class_system_system_exception_ref =
+            exception_class!(SystemException, class_system_exception_ref);
+        let class_system_null_reference_exception_ref =
+            exception_class!(NullReferenceException, class_system_system_exception_ref);
+        let class_system_index_out_of_range_exception_ref =
+            exception_class!(IndexOutOfRangeException, class_system_system_exception_ref);
+        let class_system_arithmetic_exception_ref =
+            exception_class!(ArithmeticException, class_system_system_exception_ref);
+        let class_system_divide_by_zero_exception_ref =
+            exception_class!(DivideByZeroException, class_system_arithmetic_exception_ref);
+        let class_system_overflow_exception_ref =
+            exception_class!(OverflowException, class_system_arithmetic_exception_ref);
+        let class_system_invalid_cast_exception_ref =
+            exception_class!(InvalidCastException, class_system_system_exception_ref);
+        let class_system_array_type_mismatch_exception_ref =
+            exception_class!(ArrayTypeMismatchException, class_system_system_exception_ref);
+        let class_system_argument_exception_ref =
+            exception_class!(ArgumentException, class_system_system_exception_ref);
+        let class_system_invalid_program_exception_ref =
+            exception_class!(InvalidProgramException, class_system_system_exception_ref);
+        let class_system_invalid_operation_exception_ref =
+            exception_class!(InvalidOperationException, class_system_system_exception_ref);
+        let class_system_argument_null_exception_ref =
+            exception_class!(ArgumentNullException, class_system_argument_exception_ref);
+        let class_system_bad_image_format_exception_ref =
+            exception_class!(BadImageFormatException, class_system_system_exception_ref);
+        let class_system_argument_out_of_range_exception_ref =
+            exception_class!(ArgumentOutOfRangeException, class_system_argument_exception_ref);
+        let class_system_object_disposed_exception_ref =
+            exception_class!(ObjectDisposedException, class_system_invalid_operation_exception_ref);
+        let class_system_missing_method_exception_ref =
+            exception_class!(MissingMethodException, class_system_system_exception_ref);
+        let class_system_out_of_memory_exception_ref =
+            exception_class!(OutOfMemoryException, class_system_system_exception_ref);
+
+        // Arrays are not instances of this class, which only appears in signatures (e.g.
+        // ``RuntimeHelpers.InitializeArray``)
+        let class_system_array_ref = class!(Array, Some(class_system_obj_ref.clone()));
+
+        // Handles made by ``ldtoken`` and ``arglist`` hold a pointer: the method table of the type,
+        // the initial data of the field, or the arguments passed after the sentinel
+        let class_system_runtime_type_handle_ref =
+            class!(RuntimeTypeHandle, Some(class_system_valuetype_ref.clone()));
+        let class_system_runtime_field_handle_ref =
+            class!(RuntimeFieldHandle, Some(class_system_valuetype_ref.clone()));
+        let class_system_runtime_argument_handle_ref =
+            class!(RuntimeArgumentHandle, Some(class_system_valuetype_ref.clone()));
+        // ArgIterator holds the arguments and the index of the next one
+        let class_system_arg_iterator_ref =
+            class!(ArgIterator, Some(class_system_valuetype_ref.clone()));
+        for class_ref in &[
+            &class_system_runtime_type_handle_ref,
+            &class_system_runtime_field_handle_ref,
+            &class_system_runtime_argument_handle_ref,
+        ] {
+            class_ref.borrow_mut().fields =
+                vec![ClassField::new("_value".to_string(), Type::i_ty())];
+        }
+        class_system_arg_iterator_ref.borrow_mut().fields = vec![
+            ClassField::new("_args".to_string(), Type::i_ty()),
+            ClassField::new("_next".to_string(), Type::i_ty()),
+        ];
+
+        // Type objects hold the method table of the type
+        let class_system_type_ref = class!(Type, Some(class_system_obj_ref.clone()));
+        {
+            let mut class = class_system_type_ref.borrow_mut();
+            class.fields = vec![ClassField::new("_handle".to_string(), Type::i_ty())];
+            class.methods = vec![
+                method!([0x20], str, [], "ToString", class_system_type_ref),
+                method!([0x20], str, [], "get_FullName", class_system_type_ref),
+                method!([0x20], str, [], "get_Name", class_system_type_ref),
+            ];
+            class.method_table = class.methods.clone();
+        }
+
+        // Delegates hold the target object, the method and the invocation list of the combined
+        // delegates (null unless combined)
+        let class_system_delegate_ref = class!(Delegate, Some(class_system_obj_ref.clone()));
+        let class_system_multicast_delegate_ref =
+            class!(MulticastDelegate, Some(class_system_delegate_ref.clone()));
+        {
+            let mut class_system_delegate = class_system_delegate_ref.borrow_mut();
+            class_system_delegate.fields = vec![
+                ClassField::new("_target".to_string(), Type::object_ty()),
+                ClassField::new("_methodPtr".to_string(), Type::i_ty()),
+                ClassField::new("_invocationList".to_string(), Type::object_szarr_ty()),
+            ];
+            class_system_delegate.method_table =
+                class_system_obj_ref.borrow().method_table.clone();
+            class_system_multicast_delegate_ref.borrow_mut().method_table =
+                class_system_obj_ref.borrow().method_table.clone();
+        }
+
+        #[rustfmt::skip]
+        let delegate_classes = [
+            ("Action",  vec![],                                     false),
+            ("Action`1",vec!["T"],                                  false),
+            ("Action`2",vec!["T1", "T2"],                           false),
+            ("Action`3",vec!["T1", "T2", "T3"],                     false),
+            ("Action`4",vec!["T1", "T2", "T3", "T4"],               false),
+            ("Func`1",  vec!["TResult"],                            true ),
+            ("Func`2",  vec!["T", "TResult"],                       true ),
+            ("Func`3",  vec!["T1", "T2", "TResult"],                true ),
+            ("Func`4",  vec!["T1", "T2", "T3", "TResult"],          true ),
+            ("Func`5",  vec!["T1", "T2", "T3", "T4", "TResult"],    true ),
+        ];
+
+        // The rest of the primitive types. A boxed value holds the value as its only field.
+        #[rustfmt::skip]
+        let primitive_classes = [
+            ("SByte",   Type::i1_ty()),
+            ("Byte",    Type::u1_ty()),
+            ("Int16",   Type::i2_ty()),
+            ("UInt16",  Type::u2_ty()),
+            ("UInt32",  Type::u4_ty()),
+            ("UInt64",  Type::u8_ty()),
+            ("Single",  Type::r4_ty()),
+            ("IntPtr",  Type::i_ty()),
+            ("UIntPtr", Type::u_ty()),
+        ];
+
+        // Streams hold the bytes and the position at runtime. Only the streams of manifest
+        // resources are created, so Stream is not abstract.
+        let class_system_io_stream_ref = ClassInfo::new_ref(
+            ResolutionScope::asm_ref("mscorlib"),
+            "System.IO",
+            "Stream",
+            vec![ClassField::new("_handle".to_string(), Type::i_ty())],
+            vec![],
+            Some(class_system_obj_ref.clone()),
+        );
+        let class_system_io_text_reader_ref = ClassInfo::new_ref(
+            ResolutionScope::asm_ref("mscorlib"),
+            "System.IO",
+            "TextReader",
+            vec![],
+            vec![],
+            Some(class_system_obj_ref.clone()),
+        );
+        // StreamReader reads the stream as UTF-8
+        let class_system_io_stream_reader_ref = ClassInfo::new_ref(
+            ResolutionScope::asm_ref("mscorlib"),
+            "System.IO",
+            "StreamReader",
+            vec![ClassField::new(
+                "_stream".to_string(),
+                Type::class_ty(class_system_io_stream_ref.clone()),
+            )],
+            vec![],
+            Some(class_system_io_text_reader_ref.clone()),
+        );
+        {
+            let mut stream = class_system_io_stream_ref.borrow_mut();
+            let mut text_reader = class_system_io_text_reader_ref.borrow_mut();
+            let mut stream_reader = class_system_io_stream_reader_ref.borrow_mut();
+
+            let object_methods = class_system_obj_ref.borrow().method_table.clone();
+            let stream_methods = vec![
+                method!([0x20], i8, [], "get_Length", class_system_io_stream_ref),
+                method!([0x20], i8, [], "get_Position", class_system_io_stream_ref),
+                method!([0x20], i4, [], "ReadByte", class_system_io_stream_ref),
+                method!([0x20], i4, [u1a, i4, i4], "Read", class_system_io_stream_ref),
+                method!([0x20], void, [], "Close", class_system_io_stream_ref),
+                method!([0x20], void, [], "Dispose", class_system_io_stream_ref),
+            ];
+            let text_reader_methods = vec![
+                method!([0x20], i4, [], "Peek", class_system_io_text_reader_ref),
+                method!([0x20], i4, [], "Read", class_system_io_text_reader_ref),
+                method!([0x20], str, [], "ReadLine", class_system_io_text_reader_ref),
+                method!([0x20], str, [], "ReadToEnd", class_system_io_text_reader_ref),
+                method!([0x20], void, [], "Close", class_system_io_text_reader_ref),
+                method!([0x20], void, [], "Dispose", class_system_io_text_reader_ref),
+            ];
+            let stream_reader_methods = vec![method!(
+                [0x20],
+                bool,
+                [],
+                "get_EndOfStream",
+                class_system_io_stream_reader_ref
+            )];
+
+            stream.method_table = [&object_methods[..], &stream_methods[..]].concat();
+            text_reader.method_table = [&object_methods[..], &text_reader_methods[..]].concat();
+            stream_reader.method_table =
+                [&text_reader.method_table[..], &stream_reader_methods[..]].concat();
+            stream.methods = stream_methods;
+            text_reader.methods = text_reader_methods;
+            stream_reader.methods = stream_reader_methods;
+
+            for class in &mut [stream, text_reader, stream_reader] {
+                let dispose = class
+                    .method_table
+                    .iter()
+                    .position(|m| m.borrow().get_name() == "Dispose")
+                    .unwrap();
+                class.interfaces = vec![class_system_idisposable_ref.clone()];
+                class.interface_maps = vec![InterfaceMap {
+                    interface: class_system_idisposable_ref.clone(),
+                    slots: vec![dispose],
+                }];
+            }
+        }
+
+        // Assemblies loaded at runtime are referred to by the index in the list of loaded ones
+        let class_system_reflection_assembly_ref = ClassInfo::new_ref(
+            ResolutionScope::asm_ref("mscorlib"),
+            "System.Reflection",
+            "Assembly",
+            vec![ClassField::new("_handle".to_string(), Type::i_ty())],
+            vec![],
+            Some(class_system_obj_ref.clone()),
+        );
+        {
+            let mut class = class_system_reflection_assembly_ref.borrow_mut();
+            class.methods = vec![
+                method!([0x20], str, [], "ToString", class_system_reflection_assembly_ref),
+                method!([0x20], str, [], "get_FullName", class_system_reflection_assembly_ref),
+                Rc::new(RefCell::new(MethodInfo::MRef(MemberRefInfo {
+                    name: "GetManifestResourceStream".to_string(),
+                    class: class_system_reflection_assembly_ref.clone(),
+                    ty: Type::full_method_ty(
+                        0x20,
+                        Type::class_ty(class_system_io_stream_ref.clone()),
+                        &[Type::string_ty()],
+                    ),
+                }))),
+            ];
+            class.method_table = class.methods.clone();
+        }
+        // ``Type.get_Assembly`` returns an Assembly, which is declared after Type
+        {
+            let mut class = class_system_type_ref.borrow_mut();
+            class.methods.push(Rc::new(RefCell::new(MethodInfo::MRef(MemberRefInfo {
+                name: "get_Assembly".to_string(),
+                class: class_system_type_ref.clone(),
+                ty: Type::full_method_ty(
+                    0x20,
+                    Type::class_ty(class_system_reflection_assembly_ref.clone()),
+                    &[],
+                ),
+            }))));
+            class.method_table = class.methods.clone();
         }
 
         let mut resolver = NameResolver::new();
 
+        for (name, ty) in &primitive_classes {
+            let class_ref = ClassInfo::new_ref(
+                ResolutionScope::asm_ref("mscorlib"),
+                "System",
+                name,
+                vec![],
+                vec![],
+                Some(class_system_valuetype_ref.clone()),
+            );
+            {
+                let mut class = class_ref.borrow_mut();
+                class.methods = vec![method!([0x20], str, [], "ToString", class_ref)];
+                class.method_table = class.methods.clone();
+                class.fields = vec![ClassField::new_ty(ty.clone())];
+            }
+            resolver.add(TypePath(vec!["mscorlib", "System", name]), class_ref);
+        }
+
+        for (name, params, has_ret) in &delegate_classes {
+            resolver.add(
+                TypePath(vec!["mscorlib", "System", name]),
+                new_mscorlib_delegate_class(
+                    name,
+                    params,
+                    *has_ret,
+                    &class_system_obj_ref,
+                    &class_system_multicast_delegate_ref,
+                ),
+            );
+        }
+
         resolver.add(
             TypePath(vec!["mscorlib", "System", "Object"]),
             class_system_obj_ref,
@@ -438,6 +1750,22 @@ thread_local! {
             TypePath(vec!["mscorlib", "System", "Int32"]),
             class_system_int32_ref,
         );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "Int64"]),
+            class_system_int64_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "Double"]),
+            class_system_double_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "Boolean"]),
+            class_system_boolean_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "Char"]),
+            class_system_char_ref,
+        );
         resolver.add(
             TypePath(vec!["mscorlib", "System", "String"]),
             class_system_string_ref,
@@ -450,6 +1778,135 @@ thread_local! {
             TypePath(vec!["mscorlib", "System", "Enum"]),
             class_system_enum_ref,
         );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "Exception"]),
+            class_system_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "IDisposable"]),
+            class_system_idisposable_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "IComparable"]),
+            class_system_icomparable_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "SystemException"]),
+            class_system_system_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "NullReferenceException"]),
+            class_system_null_reference_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "IndexOutOfRangeException"]),
+            class_system_index_out_of_range_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "ArithmeticException"]),
+            class_system_arithmetic_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "DivideByZeroException"]),
+            class_system_divide_by_zero_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "OverflowException"]),
+            class_system_overflow_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "InvalidCastException"]),
+            class_system_invalid_cast_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "ArrayTypeMismatchException"]),
+            class_system_array_type_mismatch_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "ArgumentException"]),
+            class_system_argument_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "InvalidProgramException"]),
+            class_system_invalid_program_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "InvalidOperationException"]),
+            class_system_invalid_operation_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "ArgumentNullException"]),
+            class_system_argument_null_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "BadImageFormatException"]),
+            class_system_bad_image_format_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "ArgumentOutOfRangeException"]),
+            class_system_argument_out_of_range_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "ObjectDisposedException"]),
+            class_system_object_disposed_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "MissingMethodException"]),
+            class_system_missing_method_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "OutOfMemoryException"]),
+            class_system_out_of_memory_exception_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "Array"]),
+            class_system_array_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "RuntimeTypeHandle"]),
+            class_system_runtime_type_handle_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "RuntimeFieldHandle"]),
+            class_system_runtime_field_handle_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "RuntimeArgumentHandle"]),
+            class_system_runtime_argument_handle_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "ArgIterator"]),
+            class_system_arg_iterator_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "Type"]),
+            class_system_type_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "Delegate"]),
+            class_system_delegate_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System", "MulticastDelegate"]),
+            class_system_multicast_delegate_ref,
+        );
+
+        resolver.add(
+            TypePath(vec!["mscorlib", "System.Reflection", "Assembly"]),
+            class_system_reflection_assembly_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System.IO", "Stream"]),
+            class_system_io_stream_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System.IO", "TextReader"]),
+            class_system_io_text_reader_ref,
+        );
+        resolver.add(
+            TypePath(vec!["mscorlib", "System.IO", "StreamReader"]),
+            class_system_io_stream_reader_ref,
+        );
 
         Rc::new(resolver)
     };
@@ -462,6 +1919,14 @@ pub fn mscorlib_system_string() -> ClassInfoRef {
         .clone()
 }
 
+/// Returns ``System.{name}`` of mscorlib
+pub fn mscorlib_system_class(name: &str) -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", name]))
+        .unwrap()
+        .clone()
+}
+
 pub fn mscorlib_system_int32() -> ClassInfoRef {
     get_mscorlib()
         .get(TypePath(vec!["mscorlib", "System", "Int32"]))
@@ -469,6 +1934,34 @@ pub fn mscorlib_system_int32() -> ClassInfoRef {
         .clone()
 }
 
+pub fn mscorlib_system_int64() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "Int64"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_double() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "Double"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_boolean() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "Boolean"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_char() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "Char"]))
+        .unwrap()
+        .clone()
+}
+
 pub fn mscorlib_system_object() -> ClassInfoRef {
     get_mscorlib()
         .get(TypePath(vec!["mscorlib", "System", "Object"]))
@@ -476,6 +1969,307 @@ pub fn mscorlib_system_object() -> ClassInfoRef {
         .clone()
 }
 
+pub fn mscorlib_system_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "Exception"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_null_reference_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "NullReferenceException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_index_out_of_range_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "IndexOutOfRangeException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_arithmetic_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "ArithmeticException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_divide_by_zero_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "DivideByZeroException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_overflow_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "OverflowException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_invalid_cast_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "InvalidCastException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_array_type_mismatch_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "ArrayTypeMismatchException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_argument_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "ArgumentException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_invalid_program_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "InvalidProgramException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_invalid_operation_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "InvalidOperationException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_argument_null_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "ArgumentNullException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_bad_image_format_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "BadImageFormatException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_argument_out_of_range_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "ArgumentOutOfRangeException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_object_disposed_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "ObjectDisposedException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_missing_method_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "MissingMethodException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_out_of_memory_exception() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "OutOfMemoryException"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_type() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "Type"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_delegate() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System", "Delegate"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_reflection_assembly() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System.Reflection", "Assembly"]))
+        .unwrap()
+        .clone()
+}
+
+pub fn mscorlib_system_io_stream() -> ClassInfoRef {
+    get_mscorlib()
+        .get(TypePath(vec!["mscorlib", "System.IO", "Stream"]))
+        .unwrap()
+        .clone()
+}
+
+/// Returns a new delegate type of mscorlib (e.g. ``Func`2``) with the type parameters ``params``.
+/// ``Invoke`` takes the type parameters as arguments, except the last one which is the return
+/// type if ``has_ret``.
+fn new_mscorlib_delegate_class(
+    name: &str,
+    params: &[&str],
+    has_ret: bool,
+    object: &ClassInfoRef,
+    parent: &ClassInfoRef,
+) -> ClassInfoRef {
+    let class_ref = ClassInfo::new_ref(
+        ResolutionScope::asm_ref("mscorlib"),
+        "System",
+        name,
+        vec![],
+        vec![],
+        Some(parent.clone()),
+    );
+    let mut vars = (0..params.len())
+        .map(|i| Type::new(ElementType::Var(i)))
+        .collect::<Vec<Type>>();
+    let ret = if has_ret {
+        vars.pop().unwrap()
+    } else {
+        Type::void_ty()
+    };
+    let runtime_method = |name: &str, flags: u16, ty: Type| {
+        Rc::new(RefCell::new(MethodInfo::MDef(MethodDefInfo {
+            rva: 0,
+            impl_flags: method_impl_attributes_flags::RUNTIME,
+            flags,
+            name: name.to_string(),
+            header_ty: MethodHeaderType::TinyFormat { bytes: 0 },
+            ty,
+            locals_ty: vec![],
+            body: vec![],
+            exception_clauses: vec![],
+            class: class_ref.clone(),
+            generic_params: vec![],
+            generic_args: vec![],
+        })))
+    };
+    let methods = vec![
+        runtime_method(
+            ".ctor",
+            0,
+            Type::full_method_ty(0x20, Type::void_ty(), &[Type::object_ty(), Type::i_ty()]),
+        ),
+        runtime_method(
+            "Invoke",
+            method_attributes_flags::VIRTUAL | method_attributes_flags::NEW_SLOT,
+            Type::full_method_ty(0x20, ret, &vars),
+        ),
+    ];
+
+    {
+        let mut class = class_ref.borrow_mut();
+        class.generic_params = params.iter().map(|param| param.to_string()).collect();
+        // mscorlib is not available yet, so the method table is made here
+        class.method_table = object.borrow().method_table.clone();
+        class.method_table.extend(methods.iter().cloned());
+        class.methods = methods;
+    }
+
+    class_ref
+}
+
 pub fn get_mscorlib() -> Rc<NameResolver<ClassInfoRef>> {
     MSCORLIB.with(|mscorlib| mscorlib.clone())
 }
+
+#[test]
+fn test_framework_assembly_refs() {
+    // hello.exe refers to mscorlib, which is signed with the ECMA key. Renamed to an assembly that
+    // is not a known facade, it is the core library only if signed with a key of the framework.
+    let hello = fs::read("./examples/hello.exe").unwrap();
+    let replace = |bytes: &mut Vec<u8>, from: &[u8], to: &[u8]| {
+        let i = bytes.windows(from.len()).position(|w| w == from).unwrap();
+        bytes[i..i + to.len()].copy_from_slice(to);
+    };
+    let mut renamed = hello.clone();
+    replace(&mut renamed, b"mscorlib", b"fwfacade");
+    match Assembly::load_from_bytes(&renamed) {
+        Err(LoadError::AssemblyNotFound { name, .. }) => assert_eq!(name, "fwfacade"),
+        r => panic!("unexpected result: {:?}", r.map(|_| ())),
+    }
+
+    for token in FRAMEWORK_PUBLIC_KEY_TOKENS {
+        let mut bytes = renamed.clone();
+        replace(&mut bytes, &[0xb7, 0x7a, 0x5c, 0x56, 0x19, 0x34, 0xe0, 0x89], token);
+        let asm = Assembly::load_from_bytes(&bytes).unwrap();
+        let image = &asm.borrow().image;
+        let asmref = image.metadata_reader().row::<AssemblyRefTable>(1).unwrap();
+        assert_eq!(image.get_string(asmref.name), Some("fwfacade"));
+        assert_eq!(image.asm_ref_name(asmref), Some("mscorlib"));
+        let object = image
+            .metadata_reader()
+            .rows::<TypeRefTable>()
+            .find(|(_, tref)| image.get_string(tref.type_name) == Some("Object"))
+            .map(|(token, _)| image.get_class(token).unwrap().clone())
+            .unwrap();
+        assert!(Rc::ptr_eq(&object, &mscorlib_system_object()));
+    }
+}
+
+#[test]
+fn test_nested_type_refs() {
+    // nested.exe refers to Lib.Outer/Inner (row 4) and Lib.Outer/Inner/Deepest (row 3) of
+    // nested_lib.dll, before Lib.Outer itself (row 5)
+    let asm = Assembly::load(path::PathBuf::from("./examples/nested.exe")).unwrap();
+    let asm = asm.borrow();
+    let lib = asm.image.asm_refs["nested_lib"].borrow();
+    for &(row, name) in &[(3, "Deepest"), (4, "Inner")] {
+        let class = asm
+            .image
+            .get_class(encode_token(TableKind::TypeRef.into(), row))
+            .unwrap();
+        let typedef = lib
+            .image
+            .find_class(TypePath(vec!["nested_lib", "", name]))
+            .unwrap();
+        assert!(Rc::ptr_eq(class, &typedef));
+        let tref = asm.image.metadata_reader().row::<TypeRefTable>(row).unwrap();
+        assert_eq!(
+            asm.image.get_path_from_type_ref_table(tref),
+            Some(TypePath(vec!["nested_lib", "", name]))
+        );
+    }
+}
+
+#[test]
+fn test_multi_module_assembly() {
+    // multimodule.exe refers to Shapes.Shape and Shapes.Util of its module through ModuleRefs
+    let asm = Assembly::load(path::PathBuf::from("./examples/multimodule.exe")).unwrap();
+    let asm = asm.borrow();
+    let module = asm.image.modules["multimodule_shapes.netmodule"].borrow();
+    for &(row, name) in &[(2, "Shape"), (3, "Util")] {
+        let class = asm
+            .image
+            .get_class(encode_token(TableKind::TypeRef.into(), row))
+            .unwrap();
+        assert_eq!(class.borrow().name, name);
+        let typedef = module
+            .image
+            .find_class(TypePath(vec!["multimodule", "Shapes", name]))
+            .unwrap();
+        assert!(Rc::ptr_eq(class, &typedef));
+    }
+
+    // Each image defines only its own methods
+    for (image, other) in &[(&asm.image, &module.image), (&module.image, &asm.image)] {
+        for method in image.method_cache.values() {
+            assert!(image.defines_method(method));
+            assert!(!other.defines_method(method));
+        }
+    }
+}
@@ -1,8 +1,8 @@
 use crate::metadata::{
-    header::{CLIHeader, SectionHeader},
+    header::{rva_to_file_offset, CLIHeader, SectionHeader},
+    heap::*,
     token::*,
 };
-use rustc_hash::FxHashMap;
 
 #[derive(Debug, Clone)]
 pub struct CLIInfo {
@@ -10,6 +10,13 @@ pub struct CLIInfo {
     pub sections: Vec<SectionHeader>,
 }
 
+impl CLIInfo {
+    /// Returns the file offset of ``rva``. None if no section has it on disk.
+    pub fn rva_to_offset(&self, rva: u32) -> Option<u64> {
+        rva_to_file_offset(&self.sections, rva)
+    }
+}
+
 /// #~ Stream
 #[derive(Debug, Clone)]
 pub struct MetaDataStream {
@@ -40,9 +47,9 @@ pub struct MetaDataStream {
 #[derive(Debug, Clone)]
 pub struct MetaDataStreams {
     pub metadata_stream: MetaDataStream,
-    pub strings: FxHashMap<u32, String>,
-    pub user_strings: FxHashMap<u32, Vec<u16>>,
-    pub blob: FxHashMap<u32, Vec<u8>>,
+    pub strings: StringHeap,
+    pub user_strings: UserStringHeap,
+    pub blob: BlobHeap,
     pub guid: String,
 }
 
@@ -58,7 +65,9 @@ impl MetaDataStreams {
             return None;
         }
 
-        self.get_table(table as usize)
+        self.metadata_stream
+            .tables
+            .get(table as usize)?
             .get(entry as usize - 1)
             .map(|t| *t)
     }
@@ -66,6 +75,69 @@ impl MetaDataStreams {
 
 pub const NUM_TABLES: usize = 45;
 
+/// II.24.2.6 Sizes in bytes of heap and table indexes. An index takes 4 bytes if the heap or
+/// the tables it refers to are too large for 2 bytes.
+#[derive(Debug, Clone)]
+pub struct IndexSizes {
+    heap_sizes: u8,
+    rows: [u32; NUM_TABLES],
+}
+
+impl IndexSizes {
+    pub fn new(heap_sizes: u8, table_kinds: &[TableKind], rows: &[u32]) -> Self {
+        let mut rows_by_kind = [0; NUM_TABLES];
+        for (kind, num) in table_kinds.iter().zip(rows) {
+            rows_by_kind[kind.into_num()] = *num;
+        }
+        Self {
+            heap_sizes,
+            rows: rows_by_kind,
+        }
+    }
+
+    pub fn string(&self) -> usize {
+        self.heap_index(heap_sizes_flags::STRING)
+    }
+
+    pub fn guid(&self) -> usize {
+        self.heap_index(heap_sizes_flags::GUID)
+    }
+
+    pub fn blob(&self) -> usize {
+        self.heap_index(heap_sizes_flags::BLOB)
+    }
+
+    pub fn table(&self, kind: TableKind) -> usize {
+        if self.rows[kind.into_num()] < 1 << 16 {
+            2
+        } else {
+            4
+        }
+    }
+
+    pub fn coded(&self, kind: CodedIndexKind) -> usize {
+        let max_rows = kind
+            .tables()
+            .iter()
+            .filter_map(|table| Some(self.rows[table.as_ref()?.into_num()]))
+            .max()
+            .unwrap_or(0);
+        if max_rows < 1 << (16 - kind.tag_bits()) {
+            2
+        } else {
+            4
+        }
+    }
+
+    fn heap_index(&self, flag: u8) -> usize {
+        if self.heap_sizes & flag == 0 {
+            2
+        } else {
+            4
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Copy)]
 pub enum TableKind {
     Assembly,
@@ -111,48 +183,118 @@ pub enum TableKind {
 #[derive(Debug, Clone, Copy)]
 pub enum Table {
     Assembly(AssemblyTable),
-    // AssemblyOS,
-    // AssemblyProcessor,
+    AssemblyOS(AssemblyOSTable),
+    AssemblyProcessor(AssemblyProcessorTable),
     AssemblyRef(AssemblyRefTable),
-    // AssemblyRefOS,
-    // AssemblyRefProcessor,
-    // ClassLayout,
+    AssemblyRefOS(AssemblyRefOSTable),
+    AssemblyRefProcessor(AssemblyRefProcessorTable),
+    ClassLayout(ClassLayoutTable),
     Constant(ConstantTable),
     CustomAttribute(CustomAttributeTable),
-    // DeclSecurity,
-    // EventMap,
-    // Event,
-    // ExportedType,
+    DeclSecurity(DeclSecurityTable),
+    EventMap(EventMapTable),
+    Event(EventTable),
+    ExportedType(ExportedTypeTable),
     Field(FieldTable),
-    // FieldLayout,
-    // FieldMarshal,
-    // FieldRVA,
-    // File,
-    // GenericParam,
-    // GenericParamConstraint,
-    // ImplMap,
-    // InterfaceImpl,
-    // ManifestResource,
+    FieldLayout(FieldLayoutTable),
+    FieldMarshal(FieldMarshalTable),
+    FieldRVA(FieldRVATable),
+    File(FileTable),
+    GenericParam(GenericParamTable),
+    GenericParamConstraint(GenericParamConstraintTable),
+    ImplMap(ImplMapTable),
+    InterfaceImpl(InterfaceImplTable),
+    ManifestResource(ManifestResourceTable),
     MemberRef(MemberRefTable),
     MethodDef(MethodDefTable),
-    // MethodImpl,
-    // MethodSemantics,
-    // MethodSpec,
+    MethodImpl(MethodImplTable),
+    MethodSemantics(MethodSemanticsTable),
+    MethodSpec(MethodSpecTable),
     Module(ModuleTable),
-    // ModuleRef,
-    // NestedClass,
+    ModuleRef(ModuleRefTable),
+    NestedClass(NestedClassTable),
     Param(ParamTable),
-    // Property,
-    // PropertyMap,
+    Property(PropertyTable),
+    PropertyMap(PropertyMapTable),
     StandAloneSig(StandAlongSigTable),
     TypeDef(TypeDefTable),
     TypeRef(TypeRefTable),
-    // TypeSpec,
+    TypeSpec(TypeSpecTable),
+}
+
+/// A row of a metadata table
+pub trait TableRow: Sized {
+    const KIND: TableKind;
+
+    fn from_table(table: &Table) -> Option<&Self>;
 }
 
+macro_rules! table_rows {
+    ($($kind:ident($row:ident)),*) => {
+        $(impl TableRow for $row {
+            const KIND: TableKind = TableKind::$kind;
+
+            fn from_table(table: &Table) -> Option<&Self> {
+                match table {
+                    Table::$kind(row) => Some(row),
+                    _ => None,
+                }
+            }
+        })*
+
+        impl Table {
+            pub fn kind(&self) -> TableKind {
+                match self {
+                    $(Table::$kind(_) => TableKind::$kind,)*
+                }
+            }
+        }
+    };
+}
+
+table_rows!(
+    Assembly(AssemblyTable),
+    AssemblyOS(AssemblyOSTable),
+    AssemblyProcessor(AssemblyProcessorTable),
+    AssemblyRef(AssemblyRefTable),
+    AssemblyRefOS(AssemblyRefOSTable),
+    AssemblyRefProcessor(AssemblyRefProcessorTable),
+    ClassLayout(ClassLayoutTable),
+    Constant(ConstantTable),
+    CustomAttribute(CustomAttributeTable),
+    DeclSecurity(DeclSecurityTable),
+    EventMap(EventMapTable),
+    Event(EventTable),
+    ExportedType(ExportedTypeTable),
+    Field(FieldTable),
+    FieldLayout(FieldLayoutTable),
+    FieldMarshal(FieldMarshalTable),
+    FieldRVA(FieldRVATable),
+    File(FileTable),
+    GenericParam(GenericParamTable),
+    GenericParamConstraint(GenericParamConstraintTable),
+    ImplMap(ImplMapTable),
+    InterfaceImpl(InterfaceImplTable),
+    ManifestResource(ManifestResourceTable),
+    MemberRef(MemberRefTable),
+    MethodDef(MethodDefTable),
+    MethodImpl(MethodImplTable),
+    MethodSemantics(MethodSemanticsTable),
+    MethodSpec(MethodSpecTable),
+    Module(ModuleTable),
+    ModuleRef(ModuleRefTable),
+    NestedClass(NestedClassTable),
+    Param(ParamTable),
+    Property(PropertyTable),
+    PropertyMap(PropertyMapTable),
+    StandAloneSig(StandAlongSigTable),
+    TypeDef(TypeDefTable),
+    TypeRef(TypeRefTable),
+    TypeSpec(TypeSpecTable)
+);
+
 /// II.22.2 Assembly
 #[derive(Debug, Clone, PartialEq, Copy)]
-#[repr(C, packed)]
 pub struct AssemblyTable {
     pub hash_alg_id: u32,
     pub major_version: u16,
@@ -160,147 +302,438 @@ pub struct AssemblyTable {
     pub build_number: u16,
     pub revision_number: u16,
     pub flags: u32,
-    pub public_key: u16,
-    pub name: u16,
-    pub culture: u16,
+    pub public_key: u32,
+    pub name: u32,
+    pub culture: u32,
+}
+
+/// II.22.3 AssemblyOS
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct AssemblyOSTable {
+    pub os_platform_id: u32,
+    pub os_major_version: u32,
+    pub os_minor_version: u32,
+}
+
+/// II.22.4 AssemblyProcessor
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct AssemblyProcessorTable {
+    pub processor: u32,
 }
 
 /// II.22.5 AssemblyRef
 #[derive(Debug, Clone, PartialEq, Copy)]
-#[repr(C, packed)]
 pub struct AssemblyRefTable {
     pub major_version: u16,
     pub minor_version: u16,
     pub build_number: u16,
     pub revision_number: u16,
     pub flags: u32,
-    pub public_key_or_token: u16,
-    pub name: u16,
-    pub culture: u16,
-    pub hash_value: u16,
+    pub public_key_or_token: u32,
+    pub name: u32,
+    pub culture: u32,
+    pub hash_value: u32,
+}
+
+/// II.22.6 AssemblyRefOS
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct AssemblyRefOSTable {
+    pub os_platform_id: u32,
+    pub os_major_version: u32,
+    pub os_minor_version: u32,
+    pub assembly_ref: u32,
+}
+
+/// II.22.7 AssemblyRefProcessor
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct AssemblyRefProcessorTable {
+    pub processor: u32,
+    pub assembly_ref: u32,
+}
+
+/// II.22.8 ClassLayout
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct ClassLayoutTable {
+    pub packing_size: u16,
+    pub class_size: u32,
+    pub parent: u32,
 }
 
 /// II.22.9 Constant
 #[derive(Debug, Clone, PartialEq, Copy)]
-#[repr(C, packed)]
 pub struct ConstantTable {
     pub type_: u16,
-    pub parent: u16,
-    pub value: u16,
+    pub parent: u32,
+    pub value: u32,
 }
 
 /// II.22.10 CustomAttribute
 #[derive(Debug, Clone, PartialEq, Copy)]
-#[repr(C, packed)]
 pub struct CustomAttributeTable {
-    pub parent: u16,
-    pub type_: u16,
-    pub value: u16,
+    pub parent: u32,
+    pub type_: u32,
+    pub value: u32,
+}
+
+/// II.22.11 DeclSecurity
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct DeclSecurityTable {
+    pub action: u16,
+    pub parent: u32,
+    pub permission_set: u32,
+}
+
+/// II.22.12 EventMap
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct EventMapTable {
+    pub parent: u32,
+    pub event_list: u32,
+}
+
+/// II.22.13 Event
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct EventTable {
+    pub event_flags: u16,
+    pub name: u32,
+    pub event_type: u32,
+}
+
+/// II.22.14 ExportedType
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct ExportedTypeTable {
+    pub flags: u32,
+    pub type_def_id: u32,
+    pub type_name: u32,
+    pub type_namespace: u32,
+    pub implementation: u32,
 }
 
 /// II.22.15 Field
 #[derive(Debug, Clone, PartialEq, Copy)]
-#[repr(C, packed)]
 pub struct FieldTable {
     pub flags: u16,
-    pub name: u16,
-    pub signature: u16,
+    pub name: u32,
+    pub signature: u32,
+}
+
+/// II.22.16 FieldLayout
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct FieldLayoutTable {
+    pub offset: u32,
+    pub field: u32,
+}
+
+/// II.22.17 FieldMarshal
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct FieldMarshalTable {
+    pub parent: u32,
+    pub native_type: u32,
+}
+
+/// II.22.18 FieldRVA
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct FieldRVATable {
+    pub rva: u32,
+    pub field: u32,
+}
+
+/// II.22.19 File
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct FileTable {
+    pub flags: u32,
+    pub name: u32,
+    pub hash_value: u32,
+}
+
+/// II.22.20 GenericParam
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct GenericParamTable {
+    pub number: u16,
+    pub flags: u16,
+    pub owner: u32,
+    pub name: u32,
+}
+
+/// II.22.21 GenericParamConstraint
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct GenericParamConstraintTable {
+    pub owner: u32,
+    pub constraint: u32,
+}
+
+/// II.22.22 ImplMap
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct ImplMapTable {
+    pub mapping_flags: u16,
+    pub member_forwarded: u32,
+    pub import_name: u32,
+    pub import_scope: u32,
+}
+
+/// II.22.23 InterfaceImpl
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct InterfaceImplTable {
+    pub class: u32,
+    pub interface: u32,
+}
+
+/// II.22.24 ManifestResource
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct ManifestResourceTable {
+    pub offset: u32,
+    pub flags: u32,
+    pub name: u32,
+    pub implementation: u32,
 }
 
 /// II.22.25 MemberRef
 #[derive(Debug, Clone, PartialEq, Copy)]
-#[repr(C, packed)]
 pub struct MemberRefTable {
-    pub class: u16,
-    pub name: u16,
-    pub signature: u16,
+    pub class: u32,
+    pub name: u32,
+    pub signature: u32,
 }
 
 /// II.22.26 MethodDef
 #[derive(Debug, Clone, PartialEq, Copy)]
-#[repr(C, packed)]
 pub struct MethodDefTable {
     pub rva: u32,
     pub impl_flags: u16,
     pub flags: u16,
-    pub name: u16,
-    pub signature: u16,
-    pub param_list: u16,
+    pub name: u32,
+    pub signature: u32,
+    pub param_list: u32,
+}
+
+/// II.22.27 MethodImpl
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct MethodImplTable {
+    pub class: u32,
+    pub method_body: u32,
+    pub method_declaration: u32,
+}
+
+/// II.22.28 MethodSemantics
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct MethodSemanticsTable {
+    pub semantics: u16,
+    pub method: u32,
+    pub association: u32,
+}
+
+/// II.22.29 MethodSpec
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct MethodSpecTable {
+    pub method: u32,
+    pub instantiation: u32,
 }
 
 /// II.22.30 Module
 #[derive(Debug, Clone, PartialEq, Copy)]
-#[repr(C, packed)]
 pub struct ModuleTable {
     pub generation: u16,
-    pub name: u16,
-    pub mvid: u16,
-    pub env_id: u16,
-    pub env_base_id: u16,
+    pub name: u32,
+    pub mvid: u32,
+    pub env_id: u32,
+    pub env_base_id: u32,
+}
+
+/// II.22.31 ModuleRef
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct ModuleRefTable {
+    pub name: u32,
+}
+
+/// II.22.32 NestedClass
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct NestedClassTable {
+    pub nested_class: u32,
+    pub enclosing_class: u32,
 }
 
 /// II.22.33 Param
 #[derive(Debug, Clone, PartialEq, Copy)]
-#[repr(C, packed)]
 pub struct ParamTable {
-    flags: u16,
-    sequence: u16,
-    name: u16,
+    pub flags: u16,
+    pub sequence: u16,
+    pub name: u32,
+}
+
+/// II.22.34 Property
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct PropertyTable {
+    pub flags: u16,
+    pub name: u32,
+    pub type_: u32,
+}
+
+/// II.22.35 PropertyMap
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct PropertyMapTable {
+    pub parent: u32,
+    pub property_list: u32,
 }
 
 /// II.22.36 StandAloneSig
 #[derive(Debug, Clone, PartialEq, Copy)]
-#[repr(C, packed)]
 pub struct StandAlongSigTable {
-    pub signature: u16,
+    pub signature: u32,
 }
 
 /// II.22.37 TypeDef
 #[derive(Debug, Clone, PartialEq, Copy)]
-#[repr(C, packed)]
 pub struct TypeDefTable {
     pub flags: u32,
-    pub type_name: u16,
-    pub type_namespace: u16,
-    pub extends: u16,
-    pub field_list: u16,
-    pub method_list: u16,
+    pub type_name: u32,
+    pub type_namespace: u32,
+    pub extends: u32,
+    pub field_list: u32,
+    pub method_list: u32,
 }
 
 /// II.22.38 TypeRef
-#[derive(Debug, Clone, PartialEq, Copy)]
-#[repr(C, packed)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
 pub struct TypeRefTable {
-    pub resolution_scope: u16,
-    pub type_name: u16,
-    pub type_namespace: u16,
+    pub resolution_scope: u32,
+    pub type_name: u32,
+    pub type_namespace: u32,
+}
+
+/// II.22.39 TypeSpec
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct TypeSpecTable {
+    pub signature: u32,
+}
+
+impl ConstantTable {
+    pub fn parent_decoded(&self) -> Option<DecodedToken> {
+        decode_coded_index(CodedIndexKind::HasConstant, self.parent)
+    }
+}
+
+impl CustomAttributeTable {
+    pub fn parent_decoded(&self) -> Option<DecodedToken> {
+        decode_coded_index(CodedIndexKind::HasCustomAttribute, self.parent)
+    }
+
+    /// Returns the constructor of the attribute
+    pub fn type_decoded(&self) -> Option<DecodedToken> {
+        decode_coded_index(CodedIndexKind::CustomAttributeType, self.type_)
+    }
+}
+
+impl DeclSecurityTable {
+    pub fn parent_decoded(&self) -> Option<DecodedToken> {
+        decode_coded_index(CodedIndexKind::HasDeclSecurity, self.parent)
+    }
+}
+
+impl EventTable {
+    pub fn event_type_decoded(&self) -> Option<DecodedToken> {
+        decode_typedef_or_ref_token(self.event_type)
+    }
+}
+
+impl ExportedTypeTable {
+    pub fn implementation_decoded(&self) -> Option<DecodedToken> {
+        decode_coded_index(CodedIndexKind::Implementation, self.implementation)
+    }
+}
+
+impl FieldMarshalTable {
+    pub fn parent_decoded(&self) -> Option<DecodedToken> {
+        decode_coded_index(CodedIndexKind::HasFieldMarshal, self.parent)
+    }
+}
+
+impl ImplMapTable {
+    pub fn member_forwarded_decoded(&self) -> Option<DecodedToken> {
+        decode_coded_index(CodedIndexKind::MemberForwarded, self.member_forwarded)
+    }
+}
+
+impl ManifestResourceTable {
+    /// Returns None if the resource is in this file
+    pub fn implementation_decoded(&self) -> Option<DecodedToken> {
+        if self.implementation == 0 {
+            return None;
+        }
+        decode_coded_index(CodedIndexKind::Implementation, self.implementation)
+    }
+}
+
+impl MethodImplTable {
+    pub fn method_body_decoded(&self) -> Option<DecodedToken> {
+        decode_method_def_or_ref_token(self.method_body)
+    }
+
+    pub fn method_declaration_decoded(&self) -> Option<DecodedToken> {
+        decode_method_def_or_ref_token(self.method_declaration)
+    }
+}
+
+impl MethodSemanticsTable {
+    pub fn association_decoded(&self) -> Option<DecodedToken> {
+        decode_coded_index(CodedIndexKind::HasSemantics, self.association)
+    }
+}
+
+impl GenericParamTable {
+    pub fn owner_decoded(&self) -> Option<DecodedToken> {
+        decode_type_or_method_def_token(self.owner)
+    }
+}
+
+impl InterfaceImplTable {
+    pub fn interface_decoded(&self) -> Option<DecodedToken> {
+        decode_typedef_or_ref_token(self.interface)
+    }
+}
+
+impl MethodSpecTable {
+    pub fn method_decoded(&self) -> Option<DecodedToken> {
+        decode_method_def_or_ref_token(self.method)
+    }
 }
 
 impl MemberRefTable {
-    pub fn class_decoded(&self) -> DecodedToken {
+    pub fn class_decoded(&self) -> Option<DecodedToken> {
         decode_member_ref_parent_token(self.class)
     }
 
-    pub fn class2token(&self) -> Token {
-        self.class_decoded().into()
+    pub fn class2token(&self) -> Option<Token> {
+        self.class_decoded().map(Into::into)
+    }
+}
+
+impl GenericParamConstraintTable {
+    pub fn constraint_decoded(&self) -> Option<DecodedToken> {
+        decode_typedef_or_ref_token(self.constraint)
+    }
+}
+
+impl TypeDefTable {
+    pub fn extends_decoded(&self) -> Option<DecodedToken> {
+        decode_typedef_or_ref_token(self.extends)
     }
 }
 
 impl TypeRefTable {
-    pub fn resolution_scope_decoded(&self) -> DecodedToken {
+    pub fn resolution_scope_decoded(&self) -> Option<DecodedToken> {
         decode_resolution_scope_token(self.resolution_scope)
     }
 }
 
 impl TableKind {
-    pub fn table_kinds(valid: u64) -> Vec<TableKind> {
+    /// Returns the tables present in ``valid``. Returns None if it has an unknown table.
+    pub fn table_kinds(valid: u64) -> Option<Vec<TableKind>> {
         let mut tables = vec![];
         for i in 0..64 {
             if valid & (1 << i) > 0 {
-                tables.push(TableKind::into_table_kind(i).unwrap())
+                tables.push(TableKind::into_table_kind(i)?)
             }
         }
-        tables
+        Some(tables)
     }
 
     pub fn into_table_kind<I: Into<u8>>(n: I) -> Option<TableKind> {
@@ -403,6 +836,60 @@ impl Into<usize> for TableKind {
     }
 }
 
+/// II.23.1.2 AssemblyFlags
+#[rustfmt::skip]
+pub mod assembly_flags {
+    // TODO: Implement all the flags
+    pub const PUBLIC_KEY: u32 = 0x0001;
+}
+
+/// II.23.1.6 FileAttributes
+#[rustfmt::skip]
+pub mod file_flags {
+    pub const CONTAINS_META_DATA   : u32 = 0x0000;
+    pub const CONTAINS_NO_META_DATA: u32 = 0x0001;
+}
+
+/// II.23.1.9 ManifestResourceAttributes
+#[rustfmt::skip]
+pub mod manifest_resource_flags {
+    pub const VISIBILITY_MASK: u32 = 0x0007;
+    pub const PUBLIC         : u32 = 0x0001;
+    pub const PRIVATE        : u32 = 0x0002;
+}
+
+#[rustfmt::skip]
+pub mod heap_sizes_flags {
+    pub const STRING: u8 = 0x01;
+    pub const GUID  : u8 = 0x02;
+    pub const BLOB  : u8 = 0x04;
+}
+
+#[test]
+fn test_index_sizes() {
+    let sizes = IndexSizes::new(0, &[], &[]);
+    assert_eq!((sizes.string(), sizes.guid(), sizes.blob()), (2, 2, 2));
+    assert_eq!(sizes.table(TableKind::TypeDef), 2);
+    assert_eq!(sizes.coded(CodedIndexKind::HasCustomAttribute), 2);
+
+    let sizes = IndexSizes::new(heap_sizes_flags::STRING | heap_sizes_flags::BLOB, &[], &[]);
+    assert_eq!((sizes.string(), sizes.guid(), sizes.blob()), (4, 2, 4));
+
+    // A table index grows past 0xffff rows, and a coded index when the rows of any of its tables
+    // no longer fit in the bits left by the tag
+    let kinds = [TableKind::TypeRef, TableKind::TypeDef, TableKind::Param];
+    let sizes = IndexSizes::new(0, &kinds, &[0x3fff, 0xffff, 0x7ff]);
+    assert_eq!(sizes.table(TableKind::TypeDef), 2);
+    assert_eq!(sizes.coded(CodedIndexKind::ResolutionScope), 2);
+    assert_eq!(sizes.coded(CodedIndexKind::TypeDefOrRef), 4);
+    assert_eq!(sizes.coded(CodedIndexKind::HasCustomAttribute), 4);
+    let sizes = IndexSizes::new(0, &kinds, &[0x4000, 0x1_0000, 0x7ff]);
+    assert_eq!(sizes.table(TableKind::TypeDef), 4);
+    assert_eq!(sizes.table(TableKind::TypeRef), 2);
+    assert_eq!(sizes.coded(CodedIndexKind::ResolutionScope), 4);
+    assert_eq!(sizes.coded(CodedIndexKind::HasFieldMarshal), 2);
+}
+
 #[test]
 fn test_table_kind() {
     TableKind::into_table_kind(0x20).unwrap();
@@ -1,6 +1,6 @@
 use crate::{
     exec::instruction::Instruction,
-    metadata::{class::*, signature::Type},
+    metadata::{class::*, signature::*, token::Token},
 };
 use std::{cell::RefCell, rc::Rc};
 
@@ -21,6 +21,36 @@ pub enum MethodHeaderType {
     },
 }
 
+impl MethodHeaderType {
+    pub fn has_more_sects(&self) -> bool {
+        match self {
+            MethodHeaderType::TinyFormat { .. } => false,
+            MethodHeaderType::FatFormat { flags, .. } => {
+                flags & method_header_flags::MORE_SECTS > 0
+            }
+        }
+    }
+}
+
+/// II.25.4.6 Exception handling clauses. Offsets and lengths are converted from bytes into
+/// indices of ``MethodDefInfo::body``.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExceptionClause {
+    pub kind: ExceptionClauseKind,
+    pub try_offset: usize,
+    pub try_length: usize,
+    pub handler_offset: usize,
+    pub handler_length: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExceptionClauseKind {
+    Catch(Token),
+    Filter { filter_offset: usize },
+    Finally,
+    Fault,
+}
+
 pub type MethodInfoRef = Rc<RefCell<MethodInfo>>;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -39,7 +69,14 @@ pub struct MethodDefInfo {
     pub ty: Type,
     pub locals_ty: Vec<Type>,
     pub body: Vec<Instruction>,
+    pub exception_clauses: Vec<ExceptionClause>,
     pub class: ClassInfoRef,
+
+    /// Names of the type parameters if this is a generic method definition
+    pub generic_params: Vec<String>,
+
+    /// Type arguments if this is an instance of a generic method
+    pub generic_args: Vec<Type>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -85,6 +122,14 @@ impl MethodInfo {
         }
     }
 
+    /// Returns the signature
+    pub fn get_ty(&self) -> &Type {
+        match self {
+            MethodInfo::MDef(ref m) => &m.ty,
+            MethodInfo::MRef(ref m) => &m.ty,
+        }
+    }
+
     pub fn get_class(&self) -> &ClassInfoRef {
         match self {
             MethodInfo::MDef(ref m) => &m.class,
@@ -117,6 +162,99 @@ impl MethodDefInfo {
     pub fn is_reuse_slot(&self) -> bool {
         self.flags & method_attributes_flags::NEW_SLOT == 0
     }
+
+    /// Returns false if this method is abstract (or implemented by the runtime)
+    pub fn has_body(&self) -> bool {
+        self.rva != 0
+    }
+
+    /// Returns true if the runtime provides the implementation of this method (e.g. ``Invoke``
+    /// of delegates)
+    pub fn is_runtime_impl(&self) -> bool {
+        self.impl_flags & method_impl_attributes_flags::CODE_TYPE_MASK
+            == method_impl_attributes_flags::RUNTIME
+    }
+
+    pub fn is_generic_def(&self) -> bool {
+        !self.generic_params.is_empty()
+    }
+
+    /// Returns true if compiling this method needs type arguments that are not known yet
+    pub fn is_open(&self) -> bool {
+        self.is_generic_def() || self.class.borrow().is_generic_def()
+    }
+
+    /// Returns the type arguments of the class and the method
+    pub fn get_generic_context(&self) -> GenericContext {
+        GenericContext {
+            class_args: self.class.borrow().generic_args.clone(),
+            method_args: self.generic_args.clone(),
+        }
+    }
+
+    /// Returns the instance of this generic method for ``args``
+    pub fn instantiate(&self, args: &[Type]) -> MethodDefInfo {
+        let mut minfo = self.clone();
+        minfo.generic_params = vec![];
+        minfo.generic_args = args.to_vec();
+        let ctx = minfo.get_generic_context();
+        minfo.ty = minfo.ty.instantiate(&ctx);
+        minfo.locals_ty = minfo
+            .locals_ty
+            .iter()
+            .map(|ty| ty.instantiate(&ctx))
+            .collect();
+        minfo
+    }
+}
+
+impl ExceptionClause {
+    pub fn try_contains(&self, pc: usize) -> bool {
+        self.try_offset <= pc && pc < self.try_offset + self.try_length
+    }
+
+    pub fn handler_contains(&self, pc: usize) -> bool {
+        self.handler_offset <= pc && pc < self.handler_offset + self.handler_length
+    }
+
+    /// Returns true if ``pc`` is in the filter block of this clause
+    pub fn filter_contains(&self, pc: usize) -> bool {
+        match self.kind {
+            ExceptionClauseKind::Filter { filter_offset } => {
+                filter_offset <= pc && pc < self.handler_offset
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_finally_or_fault(&self) -> bool {
+        match self.kind {
+            ExceptionClauseKind::Finally | ExceptionClauseKind::Fault => true,
+            _ => false,
+        }
+    }
+}
+
+#[rustfmt::skip]
+pub mod method_header_flags {
+    pub const MORE_SECTS : u16 = 0x08;
+    pub const INIT_LOCALS: u16 = 0x10;
+}
+
+#[rustfmt::skip]
+pub mod method_data_section_flags {
+    pub const EH_TABLE    : u8 = 0x01;
+    pub const OPT_IL_TABLE: u8 = 0x02;
+    pub const FAT_FORMAT  : u8 = 0x40;
+    pub const MORE_SECTS  : u8 = 0x80;
+}
+
+#[rustfmt::skip]
+pub mod exception_clause_flags {
+    pub const EXCEPTION: u32 = 0x0000;
+    pub const FILTER   : u32 = 0x0001;
+    pub const FINALLY  : u32 = 0x0002;
+    pub const FAULT    : u32 = 0x0004;
 }
 
 #[rustfmt::skip]
@@ -127,6 +265,12 @@ pub mod method_attributes_flags {
     pub const NEW_SLOT: u16 = 0x0100;
 }
 
+#[rustfmt::skip]
+pub mod method_impl_attributes_flags {
+    pub const CODE_TYPE_MASK: u16 = 0x0003;
+    pub const RUNTIME       : u16 = 0x0003;
+}
+
 // #[derive(Debug, Clone)]
 // pub struct MemberRef {
 //     name: String,
@@ -1,9 +1,12 @@
 pub mod assembly;
 pub mod class;
+pub mod error;
 pub mod header;
+pub mod heap;
 pub mod image;
 pub mod metadata;
 pub mod method;
 pub mod pe_parser;
+pub mod reader;
 pub mod signature;
 pub mod token;
@@ -2,95 +2,204 @@
 
 use crate::exec::decode::BytesToInstructions;
 use crate::metadata::{
-    assembly::*, class::*, header::*, image::*, metadata::*, method::*, signature::*,
+    assembly::*, class::*, error::*, header::*, heap::*, image::*, metadata::*, method::*,
+    signature::*, token::*,
 };
-use rustc_hash::FxHashMap;
 use std::{cell::RefCell, rc::Rc};
 use std::{
-    fs::File,
-    io::{BufReader, Read, Seek, SeekFrom},
+    fs,
+    io::{Cursor, Read, Seek, SeekFrom},
     iter,
     path::PathBuf,
-    str::from_utf8,
 };
 
 #[derive(Debug)]
 pub struct PEParser {
-    reader: BufReader<File>,
+    reader: Cursor<Vec<u8>>,
+    /// Empty if the image is loaded from memory
     filename: PathBuf,
+    /// The check that made the last read fail
+    failed_check: Option<&'static str>,
 }
 
+/// Exception handling clause as stored in a method data section. Offsets are in bytes.
+#[derive(Debug, Clone)]
+struct RawExceptionClause {
+    flags: u32,
+    try_offset: u32,
+    try_length: u32,
+    handler_offset: u32,
+    handler_length: u32,
+    class_token_or_filter_offset: u32,
+}
+
+impl RawExceptionClause {
+    fn into_exception_clause(self, decoder: &BytesToInstructions) -> Option<ExceptionClause> {
+        let try_offset = decoder.get_instr_index(self.try_offset)?;
+        let try_end = decoder.get_instr_index(self.try_offset.checked_add(self.try_length)?)?;
+        let handler_offset = decoder.get_instr_index(self.handler_offset)?;
+        let handler_end =
+            decoder.get_instr_index(self.handler_offset.checked_add(self.handler_length)?)?;
+        let kind = match self.flags {
+            exception_clause_flags::EXCEPTION => {
+                ExceptionClauseKind::Catch(Token(self.class_token_or_filter_offset))
+            }
+            exception_clause_flags::FILTER => ExceptionClauseKind::Filter {
+                filter_offset: decoder.get_instr_index(self.class_token_or_filter_offset)?,
+            },
+            exception_clause_flags::FINALLY => ExceptionClauseKind::Finally,
+            exception_clause_flags::FAULT => ExceptionClauseKind::Fault,
+            _ => return None,
+        };
+        Some(ExceptionClause {
+            kind,
+            try_offset,
+            try_length: try_end.checked_sub(try_offset)?,
+            handler_offset,
+            handler_length: handler_end.checked_sub(handler_offset)?,
+        })
+    }
+}
+
+/// Returns None if ``$expr`` does not hold. The check is reported as the reason of the error.
 macro_rules! try_eq {
-    ($expr:expr) => {{
+    ($self:expr, $expr:expr) => {{
         if !$expr {
+            $self.failed_check = Some(stringify!($expr));
             return None;
         }
     }};
 }
 
 impl PEParser {
-    pub fn new(filename: PathBuf) -> Option<Self> {
-        Some(Self {
-            reader: BufReader::new(match File::open(&filename) {
-                Ok(file) => file,
-                Err(_) => return None,
+    pub fn new(filename: PathBuf) -> LoadResult<Self> {
+        match fs::read(&filename) {
+            Ok(bytes) => Ok(Self {
+                reader: Cursor::new(bytes),
+                filename,
+                failed_check: None,
             }),
-            filename: filename.to_path_buf(),
+            Err(err) => Err(LoadError::Io {
+                path: filename,
+                reason: err.to_string(),
+            }),
+        }
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self {
+            reader: Cursor::new(bytes),
+            filename: PathBuf::new(),
+            failed_check: None,
+        }
+    }
+
+    /// Current offset in the image
+    pub fn position(&self) -> u64 {
+        self.reader.position()
+    }
+
+    pub fn create_assembly(&mut self) -> LoadResult<Assembly> {
+        let image = self.create_image()?;
+
+        let name = match image.metadata_reader().rows::<AssemblyTable>().next() {
+            Some((_, asm)) => match image.metadata.strings.get(asm.name) {
+                Some(name) => name.to_string(),
+                None => {
+                    return Err(LoadError::invalid_row(
+                        TableKind::Assembly,
+                        1,
+                        "invalid name",
+                    ))
+                }
+            },
+            None => {
+                return Err(LoadError::invalid_row(
+                    TableKind::Assembly,
+                    1,
+                    "no Assembly row",
+                ))
+            }
+        };
+
+        Ok(Assembly { name, image })
+    }
+
+    /// Creates a module (e.g. ``.netmodule``) of the assembly ``assembly_name``. Unlike an
+    /// assembly, it has no manifest (II.6.1).
+    pub fn create_module(&mut self, assembly_name: &str) -> LoadResult<Assembly> {
+        let mut image = self.create_image()?;
+        image.parent_assembly = Some(assembly_name.to_string());
+        Ok(Assembly {
+            name: assembly_name.to_string(),
+            image,
         })
     }
 
-    pub fn create_assembly(&mut self) -> Option<Assembly> {
-        self.read_msdos_header()?;
+    fn create_image(&mut self) -> LoadResult<Image> {
+        self.stage(LoadStage::MSDOSHeader, |p| p.read_msdos_header())?;
 
-        let pe_file_header = self.read_pe_file_header()?;
+        let pe_file_header = self.stage(LoadStage::PEFileHeader, |p| p.read_pe_file_header())?;
 
-        let mut pe_optional_headers = vec![];
-        let pe_optional_header_size = 224;
-        for _ in 0..pe_file_header.optional_header_size / pe_optional_header_size {
-            let pe_optional_header = self.read_pe_optional_header()?;
-            pe_optional_headers.push(pe_optional_header);
-        }
+        // Sections follow the optional header, whose size depends on PE32 or PE32+ and on the
+        // number of data directories
+        let pe_optional_header_offset = self.position();
+        let pe_optional_header =
+            self.stage(LoadStage::PEOptionalHeader, |p| p.read_pe_optional_header())?;
+        self.reader
+            .seek(SeekFrom::Start(
+                pe_optional_header_offset + pe_file_header.optional_header_size as u64,
+            ))
+            .ok();
 
         let mut sections = vec![];
         for _ in 0..pe_file_header.number_of_sections {
-            let section = self.read_section_header()?;
+            let section = self.stage(LoadStage::SectionHeaders, |p| p.read_section_header())?;
             sections.push(section);
         }
 
-        let text_section = || -> Option<&SectionHeader> {
-            for section in &sections {
-                if section.name != ".text" {
-                    continue;
+        let cli_header_offset =
+            match rva_to_file_offset(&sections, pe_optional_header.cli_header_rva) {
+                Some(offset) => offset,
+                None => {
+                    return Err(LoadError::malformed(
+                        LoadStage::SectionHeaders,
+                        self.position(),
+                        "no section contains the CLI header",
+                    ))
                 }
-                return Some(section);
+            };
+        self.reader.seek(SeekFrom::Start(cli_header_offset)).ok();
+
+        let cli_header = self.stage(LoadStage::CLIHeader, |p| p.read_cli_header())?;
+
+        let metadata_offset = match rva_to_file_offset(&sections, cli_header.metadata_rva) {
+            Some(metadata_offset) => metadata_offset,
+            None => {
+                return Err(LoadError::malformed(
+                    LoadStage::CLIHeader,
+                    cli_header_offset,
+                    "no section contains the metadata",
+                ))
             }
-            None
-        }()?;
-
-        let cli_header_offset = text_section.pointer_to_raw_data as u64 + 8; /* CLI loader stub */
-        self.reader.seek(SeekFrom::Start(cli_header_offset)).ok()?;
-
-        let cli_header = self.read_cli_header()?;
-
-        let metadata_offset = (cli_header.metadata_rva - text_section.virtual_address
-            + text_section.pointer_to_raw_data) as u64;
-        self.reader.seek(SeekFrom::Start(metadata_offset)).ok()?;
+        };
+        self.reader.seek(SeekFrom::Start(metadata_offset)).ok();
 
-        let metadata_header = self.read_metadata_header()?;
+        let metadata_header =
+            self.stage(LoadStage::MetaDataHeader, |p| p.read_metadata_header())?;
         dprintln!("MetaData header: {:?}", metadata_header);
 
         let mut stream_headers = vec![];
         for _ in 0..metadata_header.streams {
-            let stream = self.read_stream_header()?;
+            let stream = self.stage(LoadStage::StreamHeaders, |p| p.read_stream_header())?;
             stream_headers.push(stream);
         }
 
         dprintln!("Stream headers: {:?}", stream_headers);
 
-        let metadata_streams =
-            self.read_metadata_streams(metadata_offset, &metadata_header, &stream_headers)?;
+        let metadata_streams = self.read_metadata_streams(metadata_offset, &stream_headers)?;
 
-        let image = Image::new(
+        Ok(Image::new(
             CLIInfo {
                 cli_header,
                 sections,
@@ -98,69 +207,92 @@ impl PEParser {
             metadata_streams,
             self.filename.clone(),
             None,
-        );
+        ))
+    }
 
-        let asm = retrieve!(
-            image.metadata.metadata_stream.tables[TableKind::Assembly.into_num()]
-                .get(0)
-                .unwrap(),
-            Table::Assembly
-        );
-        let name = image.get_string(asm.name).to_string();
+    /// Runs ``read`` and turns its failure into a ``LoadError`` of ``stage``
+    fn stage<T, F: FnOnce(&mut Self) -> Option<T>>(
+        &mut self,
+        stage: LoadStage,
+        read: F,
+    ) -> LoadResult<T> {
+        self.failed_check = None;
+        match read(self) {
+            Some(x) => Ok(x),
+            None => Err(self.malformed(stage)),
+        }
+    }
 
-        Some(Assembly { name, image })
+    fn malformed(&mut self, stage: LoadStage) -> LoadError {
+        let offset = self.position();
+        let reason = match self.failed_check.take() {
+            Some(check) => format!("expected {}", check),
+            None if offset >= self.reader.get_ref().len() as u64 => {
+                "unexpected end of file".to_string()
+            }
+            None => "invalid value".to_string(),
+        };
+        LoadError::malformed(stage, offset, reason)
     }
 
-    pub fn read_method(
-        &mut self,
-        image: &Image,
-        class: &ClassInfoRef,
-        rva: u32,
-    ) -> Option<MethodInfoRef> {
-        let text_section = image
-            .cli_info
-            .sections
-            .iter()
-            .find(|section| section.name == ".text")
-            .unwrap();
-        let start = (rva - text_section.virtual_address + text_section.pointer_to_raw_data) as u64;
-        self.read_method_body(image, class, rva, start)
+    /// Reads the resource at ``offset`` in the image (II.24.2.4): its length followed by the bytes
+    pub fn read_resource(&mut self, offset: u64) -> Option<Vec<u8>> {
+        self.reader.seek(SeekFrom::Start(offset)).ok()?;
+        let len = self.read_u32()?;
+        self.read_bytes_vec(len as usize)
     }
 
-    fn read_method_body(
+    /// Reads ``len`` bytes at ``offset``
+    pub fn read_bytes_at(&mut self, offset: u64, len: usize) -> Option<Vec<u8>> {
+        self.reader.seek(SeekFrom::Start(offset)).ok()?;
+        self.read_bytes_vec(len)
+    }
+
+    pub fn read_method(
         &mut self,
         image: &Image,
         class: &ClassInfoRef,
-        rva: u32,
-        start: u64,
+        mdef: &MethodDefTable,
     ) -> Option<MethodInfoRef> {
-        self.reader.seek(SeekFrom::Start(start)).ok()?;
-
-        let (impl_flags, flags, name, ty) = unsafe {
-            let MethodDefTable {
-                name,
-                signature,
+        let MethodDefTable {
+            rva,
+            name,
+            signature,
+            impl_flags,
+            flags,
+            ..
+        } = *mdef;
+        let name = image.metadata.strings.get(name)?.to_string();
+        let sig = image.metadata.blob.get(signature)?;
+        let ty = SignatureParser::new(sig).parse_method_def_sig(image)?;
+
+        // Abstract methods (including the methods of interfaces) and the methods implemented by
+        // the runtime (e.g. ``Invoke`` of delegates) have no body
+        if rva == 0 {
+            return Some(Rc::new(RefCell::new(MethodInfo::MDef(MethodDefInfo {
+                rva,
                 impl_flags,
                 flags,
-                ..
-            } = image.get_method_def_table_by_rva(rva).unwrap();
-            let sig = image.metadata.blob.get(&(*signature as u32)).unwrap();
-            (
-                *impl_flags,
-                *flags,
-                image.get_string(*name).to_string(),
-                SignatureParser::new(sig)
-                    .parse_method_def_sig(image)
-                    .unwrap(),
-            )
-        };
+                name,
+                header_ty: MethodHeaderType::TinyFormat { bytes: 0 },
+                body: vec![],
+                exception_clauses: vec![],
+                locals_ty: vec![],
+                ty,
+                class: class.clone(),
+                generic_params: vec![],
+                generic_args: vec![],
+            }))));
+        }
+
+        let start = image.cli_info.rva_to_offset(rva)?;
+        self.reader.seek(SeekFrom::Start(start)).ok()?;
 
         let header_ty = self.read_method_header_type()?;
 
         match header_ty {
             MethodHeaderType::TinyFormat { bytes } => {
-                let mut raw_body = vec![0u8; bytes];
-                self.read_bytes(raw_body.as_mut_slice())?;
+                let raw_body = self.read_bytes_vec(bytes)?;
                 let body = BytesToInstructions::new(&raw_body).convert()?;
                 Some(Rc::new(RefCell::new(MethodInfo::MDef(MethodDefInfo {
                     rva,
@@ -169,9 +301,12 @@ impl PEParser {
                     name,
                     header_ty,
                     body,
+                    exception_clauses: vec![],
                     locals_ty: vec![],
                     ty,
                     class: class.clone(),
+                    generic_params: vec![],
+                    generic_args: vec![],
                 }))))
             }
             MethodHeaderType::FatFormat {
@@ -180,29 +315,26 @@ impl PEParser {
                 ..
             } => {
                 let locals_ty = match image.metadata.get_table_entry(local_var_sig_tok) {
-                    Some(sig) => match sig {
-                        Table::StandAloneSig(sast) => {
-                            let mut blob = image
-                                .metadata
-                                .blob
-                                .get(&(sast.signature as u32))
-                                .unwrap()
-                                .iter();
-                            assert_eq!(blob.next()?, &0x07);
-                            let len = *blob.next()? as usize;
-                            use std::iter::repeat_with;
-                            repeat_with(|| Type::into_type(image, &mut blob).unwrap())
-                                .take(len)
-                                .collect()
-                        }
-                        _ => unimplemented!(),
-                    },
+                    Some(Table::StandAloneSig(sast)) => {
+                        let blob = image.metadata.blob.get(sast.signature)?;
+                        SignatureParser::new(blob).parse_local_var_sig(image)?
+                    }
+                    Some(_) => return None,
                     None => vec![],
                 };
 
-                let mut raw_body = vec![0u8; code_size as usize];
-                self.read_bytes(raw_body.as_mut_slice())?;
-                let body = BytesToInstructions::new(&raw_body).convert()?;
+                let raw_body = self.read_bytes_vec(code_size as usize)?;
+                let mut decoder = BytesToInstructions::new(&raw_body);
+                let body = decoder.convert()?;
+
+                let exception_clauses = if header_ty.has_more_sects() {
+                    self.read_method_data_sections()?
+                        .into_iter()
+                        .map(|clause| clause.into_exception_clause(&decoder))
+                        .collect::<Option<Vec<ExceptionClause>>>()?
+                } else {
+                    vec![]
+                };
 
                 Some(Rc::new(RefCell::new(MethodInfo::MDef(MethodDefInfo {
                     rva,
@@ -211,9 +343,12 @@ impl PEParser {
                     name,
                     header_ty,
                     body,
+                    exception_clauses,
                     locals_ty,
                     ty,
                     class: class.clone(),
+                    generic_params: vec![],
+                    generic_args: vec![],
                 }))))
             }
         }
@@ -226,14 +361,14 @@ impl PEParser {
                 bytes: first as usize >> 2,
             }),
             FAT_FORMAT => {
-                // TODO: More flags
+                // Flags occupy the lower 12 bits and size the upper 4 bits
                 let flags_size = self.read_u8()?;
-                let size = flags_size & 0b1111;
+                let size = flags_size >> 4;
                 let max_stack = self.read_u16()?;
                 let code_size = self.read_u32()?;
                 let local_var_sig_tok = self.read_u32()?;
                 Some(MethodHeaderType::FatFormat {
-                    flags: ((first as u16) << 8) + ((flags_size as u16) >> 4),
+                    flags: (((flags_size & 0b1111) as u16) << 8) + first as u16,
                     size,
                     max_stack,
                     code_size,
@@ -244,11 +379,73 @@ impl PEParser {
         }
     }
 
+    /// II.25.4.5 Method data section. Only exception handling tables are collected.
+    fn read_method_data_sections(&mut self) -> Option<Vec<RawExceptionClause>> {
+        let mut clauses = vec![];
+
+        loop {
+            // Sections begin on a 4-byte boundary
+            let pos = self.reader.seek(SeekFrom::Current(0)).ok()?;
+            self.reader.seek(SeekFrom::Start((pos + 3) & !3)).ok()?;
+
+            let kind = self.read_u8()?;
+            let is_fat = kind & method_data_section_flags::FAT_FORMAT > 0;
+            let data_size = if is_fat {
+                let x = self.read_u8()? as u32;
+                let y = self.read_u8()? as u32;
+                let z = self.read_u8()? as u32;
+                (z << 16) + (y << 8) + x
+            } else {
+                let data_size = self.read_u8()? as u32;
+                let _reserved = self.read_u16()?;
+                data_size
+            };
+            // The size includes the header of the section
+            try_eq!(self, data_size >= 4);
+
+            if kind & method_data_section_flags::EH_TABLE > 0 {
+                let clause_size = if is_fat { 24 } else { 12 };
+                for _ in 0..(data_size.saturating_sub(4) / clause_size) {
+                    clauses.push(if is_fat {
+                        RawExceptionClause {
+                            flags: self.read_u32()?,
+                            try_offset: self.read_u32()?,
+                            try_length: self.read_u32()?,
+                            handler_offset: self.read_u32()?,
+                            handler_length: self.read_u32()?,
+                            class_token_or_filter_offset: self.read_u32()?,
+                        }
+                    } else {
+                        RawExceptionClause {
+                            flags: self.read_u16()? as u32,
+                            try_offset: self.read_u16()? as u32,
+                            try_length: self.read_u8()? as u32,
+                            handler_offset: self.read_u16()? as u32,
+                            handler_length: self.read_u8()? as u32,
+                            class_token_or_filter_offset: self.read_u32()?,
+                        }
+                    })
+                }
+            } else {
+                self.reader
+                    .seek(SeekFrom::Current(data_size as i64 - 4))
+                    .ok()?;
+            }
+
+            if kind & method_data_section_flags::MORE_SECTS == 0 {
+                break;
+            }
+        }
+
+        Some(clauses)
+    }
+
     fn read_msdos_header(&mut self) -> Option<()> {
         let mut first = [0u8; 60];
         self.read_bytes(&mut first)?;
 
         try_eq!(
+            self,
             &first[..]
                 == &[
                     0x4d, 0x5a, 0x90, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0xFF,
@@ -259,12 +456,13 @@ impl PEParser {
                 ][..]
         );
 
-        let _lfanew = self.read_u32()?;
+        let lfanew = self.read_u32()?;
 
         let mut latter = [0u8; 64];
         self.read_bytes(&mut latter)?;
 
         try_eq!(
+            self,
             &latter[..]
                 == &[
                     0x0e, 0x1f, 0xba, 0x0e, 0x00, 0xb4, 0x09, 0xcd, 0x21, 0xb8, 0x01, 0x4c, 0xcd,
@@ -275,33 +473,54 @@ impl PEParser {
                 ][..]
         );
 
+        self.reader.seek(SeekFrom::Start(lfanew as u64)).ok()?;
+
         Some(())
     }
 
     fn read_pe_file_header(&mut self) -> Option<PEFileHeader> {
         let mut pe_signature = [0u8; 4];
         self.read_bytes(&mut pe_signature)?;
-        try_eq!(&pe_signature[..] == &['P' as u8, 'E' as u8, 0, 0][..]);
+        try_eq!(self, &pe_signature[..] == &['P' as u8, 'E' as u8, 0, 0][..]);
 
         let pe_file_header = self.read_struct::<PEFileHeader>()?;
-        try_eq!(pe_file_header.machine == 0x14c);
-        try_eq!(pe_file_header.pointer_to_symbol_table == 0);
-        try_eq!(pe_file_header.number_of_symbols == 0);
+        let machine = pe_file_header.machine;
+        try_eq!(
+            self,
+            machine == machine_types::I386
+                || machine == machine_types::AMD64
+                || machine == machine_types::ARM64
+        );
+        try_eq!(self, pe_file_header.pointer_to_symbol_table == 0);
+        try_eq!(self, pe_file_header.number_of_symbols == 0);
 
         Some(pe_file_header)
     }
 
     fn read_pe_optional_header(&mut self) -> Option<PEOptionalHeader> {
         let magic = self.read_u16()?;
-        try_eq!(magic == 0x10b);
+        try_eq!(
+            self,
+            magic == optional_header_magic::PE32 || magic == optional_header_magic::PE32_PLUS
+        );
+        let pe32_plus = magic == optional_header_magic::PE32_PLUS;
+
+        // Fields that are 8 bytes long in PE32+
+        let read_word = |p: &mut Self| {
+            if pe32_plus {
+                p.read_u64()
+            } else {
+                p.read_u32().map(|x| x as u64)
+            }
+        };
 
         let _lmajor = self.read_u8()?;
         // println!("{}", lmajor);
-        // try_eq!(lmajor == 6);
+        // try_eq!(self, lmajor == 6);
 
         let lminor = self.read_u8()?;
         // println!("{}", lminor);
-        try_eq!(lminor == 0);
+        try_eq!(self, lminor == 0);
 
         let code_size = self.read_u32()?;
 
@@ -313,112 +532,79 @@ impl PEParser {
 
         let base_of_code = self.read_u32()?;
 
-        let base_of_data = self.read_u32()?;
+        let base_of_data = if pe32_plus { 0 } else { self.read_u32()? };
 
-        let image_base = self.read_u32()?;
+        let image_base = read_word(self)?;
 
         let section_alignment = self.read_u32()?;
 
         let file_alignment = self.read_u32()?;
-        try_eq!(file_alignment == 0x200);
+        try_eq!(self, file_alignment == 0x200);
 
         let os_major = self.read_u16()?;
-        try_eq!(os_major == 5 || os_major == 4);
+        try_eq!(self, os_major == 5 || os_major == 4);
 
         let os_minor = self.read_u16()?;
-        try_eq!(os_minor == 0);
+        try_eq!(self, os_minor == 0);
 
         let user_major = self.read_u16()?;
-        try_eq!(user_major == 0);
+        try_eq!(self, user_major == 0);
 
         let user_minor = self.read_u16()?;
-        try_eq!(user_minor == 0);
+        try_eq!(self, user_minor == 0);
 
         let subsys_major = self.read_u16()?;
-        try_eq!(subsys_major == 5 || subsys_major == 4);
+        try_eq!(self, subsys_major == 5 || subsys_major == 4);
 
         let subsys_minor = self.read_u16()?;
-        try_eq!(subsys_minor == 0);
+        try_eq!(self, subsys_minor == 0);
 
         let reserved = self.read_u32()?;
-        try_eq!(reserved == 0);
+        try_eq!(self, reserved == 0);
 
         let image_size = self.read_u32()?;
 
         let header_size = self.read_u32()?;
 
-        let file_checksum = self.read_u32()?;
-        try_eq!(file_checksum == 0);
+        let _file_checksum = self.read_u32()?;
 
         let sub_system = self.read_u16()?;
 
         let dll_flags = self.read_u16()?;
 
-        let stack_reserve_size = self.read_u32()?;
+        let stack_reserve_size = read_word(self)?;
 
-        let stack_commit_size = self.read_u32()?;
+        let stack_commit_size = read_word(self)?;
 
-        let heap_reserve_size = self.read_u32()?;
+        let heap_reserve_size = read_word(self)?;
 
-        let heap_commit_size = self.read_u32()?;
+        let heap_commit_size = read_word(self)?;
 
         let loader_flags = self.read_u32()?;
 
         let number_of_data_directories = self.read_u32()?;
 
-        let export_table = self.read_u64()?;
-        try_eq!(export_table == 0);
-
-        let import_table_rva = self.read_u32()?;
-
-        let import_table_size = self.read_u32()?;
-
-        let _resource_table = self.read_u64()?;
-        // try_eq!(resource_table == 0);
-
-        let exception_table = self.read_u64()?;
-        try_eq!(exception_table == 0);
-
-        let certification_table = self.read_u64()?;
-        try_eq!(certification_table == 0);
-
-        let base_relocation_table_rva = self.read_u32()?;
-
-        let base_relocation_table_size = self.read_u32()?;
-
-        let debug = self.read_u64()?;
-        try_eq!(debug == 0);
-
-        let copyright = self.read_u64()?;
-        try_eq!(copyright == 0);
-
-        let global_ptr = self.read_u64()?;
-        try_eq!(global_ptr == 0);
-
-        let tls_table = self.read_u64()?;
-        try_eq!(tls_table == 0);
-
-        let load_config_table = self.read_u64()?;
-        try_eq!(load_config_table == 0);
-
-        let bound_import = self.read_u64()?;
-        try_eq!(bound_import == 0);
-
-        let iat_rva = self.read_u32()?;
-
-        let iat_size = self.read_u32()?;
+        // Only the directories that the CLI uses are kept. The others (e.g. debug, certificate)
+        // may be present but are ignored.
+        let mut data_directories = vec![];
+        for _ in 0..number_of_data_directories.min(16) {
+            let rva = self.read_u32()?;
+            let size = self.read_u32()?;
+            data_directories.push((rva, size));
+        }
+        let data_directory = |i: usize| data_directories.get(i).cloned().unwrap_or((0, 0));
 
-        let delay_import_descriptor = self.read_u64()?;
-        try_eq!(delay_import_descriptor == 0);
+        let (import_table_rva, import_table_size) = data_directory(1);
 
-        let cli_header_rva = self.read_u32()?;
+        let (base_relocation_table_rva, base_relocation_table_size) = data_directory(5);
 
-        let cli_header_size = self.read_u32()?;
+        let (iat_rva, iat_size) = data_directory(12);
 
-        let reserved = self.read_u64()?;
-        try_eq!(reserved == 0);
+        let (cli_header_rva, cli_header_size) = data_directory(CLI_HEADER_DATA_DIRECTORY);
+        try_eq!(self, cli_header_rva != 0);
 
         Some(PEOptionalHeader {
+            magic,
             code_size,
             initialized_data_size,
             uninitialized_data_size,
@@ -513,7 +699,7 @@ impl PEParser {
         let strong_name_signature_version = self.read_u32()?;
 
         let code_manager_table = self.read_u64()?;
-        try_eq!(code_manager_table == 0);
+        try_eq!(self, code_manager_table == 0);
 
         let vtable_fixups_virtual_address = self.read_u32()?;
 
@@ -541,18 +727,17 @@ impl PEParser {
 
     fn read_metadata_header(&mut self) -> Option<MetaDataHeader> {
         let signature = self.read_u32()?;
-        try_eq!(signature == 0x424A_5342);
+        try_eq!(self, signature == 0x424A_5342);
 
         let _major_version = self.read_u16()?;
         let _minor_version = self.read_u16()?;
 
         let reserved = self.read_u32()?;
-        try_eq!(reserved == 0);
+        try_eq!(self, reserved == 0);
 
         let length = self.read_u32()?;
 
-        let mut version_raw = vec![0u8; length as usize];
-        self.read_bytes(version_raw.as_mut_slice())?;
+        let version_raw = self.read_bytes_vec(length as usize)?;
         let version = version_raw
             .iter()
             .take_while(|b| **b != 0)
@@ -560,7 +745,7 @@ impl PEParser {
             .collect::<String>();
 
         let flags = self.read_u16()?;
-        try_eq!(flags == 0);
+        try_eq!(self, flags == 0);
 
         let streams = self.read_u16()?;
 
@@ -592,7 +777,7 @@ impl PEParser {
 
     fn read_metadata_stream(&mut self) -> Option<MetaDataStream> {
         let reserved = self.read_u32()?;
-        try_eq!(reserved == 0);
+        try_eq!(self, reserved == 0);
 
         let major_version = self.read_u8()?;
 
@@ -601,7 +786,7 @@ impl PEParser {
         let heap_sizes = self.read_u8()?;
 
         let _reserved = self.read_u8()?;
-        // try_eq!(reserved == 1);
+        // try_eq!(self, reserved == 1);
 
         let valid = self.read_u64()?;
 
@@ -615,8 +800,15 @@ impl PEParser {
             rows.push(row);
         }
 
-        let table_kinds = TableKind::table_kinds(valid);
-        let tables = self.read_metadata_tables(&table_kinds, &rows)?;
+        let table_kinds = match TableKind::table_kinds(valid) {
+            Some(table_kinds) => table_kinds,
+            None => {
+                self.failed_check = Some("no unknown tables in valid");
+                return None;
+            }
+        };
+        let sizes = IndexSizes::new(heap_sizes, &table_kinds, &rows);
+        let tables = self.read_metadata_tables(&table_kinds, &rows, &sizes)?;
 
         Some(MetaDataStream {
             major_version,
@@ -633,6 +825,7 @@ impl PEParser {
         &mut self,
         table_kinds: &[TableKind],
         rows: &[u32],
+        sizes: &IndexSizes,
     ) -> Option<Vec<Vec<Table>>> {
         let mut tables: Vec<Vec<Table>> = iter::repeat_with(|| vec![]).take(NUM_TABLES).collect();
 
@@ -640,25 +833,217 @@ impl PEParser {
             let num = rows[i];
             for _ in 0..num {
                 tables[kind.into_num()].push(match kind {
-                    TableKind::Module => Table::Module(self.read_struct::<ModuleTable>()?),
-                    TableKind::TypeRef => Table::TypeRef(self.read_struct::<TypeRefTable>()?),
-                    TableKind::TypeDef => Table::TypeDef(self.read_struct::<TypeDefTable>()?),
-                    TableKind::MethodDef => Table::MethodDef(self.read_struct::<MethodDefTable>()?),
-                    TableKind::MemberRef => Table::MemberRef(self.read_struct::<MemberRefTable>()?),
-                    TableKind::CustomAttribute => {
-                        Table::CustomAttribute(self.read_struct::<CustomAttributeTable>()?)
+                    TableKind::Assembly => Table::Assembly(AssemblyTable {
+                        hash_alg_id: self.read_u32()?,
+                        major_version: self.read_u16()?,
+                        minor_version: self.read_u16()?,
+                        build_number: self.read_u16()?,
+                        revision_number: self.read_u16()?,
+                        flags: self.read_u32()?,
+                        public_key: self.read_index(sizes.blob())?,
+                        name: self.read_index(sizes.string())?,
+                        culture: self.read_index(sizes.string())?,
+                    }),
+                    TableKind::AssemblyOS => Table::AssemblyOS(AssemblyOSTable {
+                        os_platform_id: self.read_u32()?,
+                        os_major_version: self.read_u32()?,
+                        os_minor_version: self.read_u32()?,
+                    }),
+                    TableKind::AssemblyProcessor => {
+                        Table::AssemblyProcessor(AssemblyProcessorTable {
+                            processor: self.read_u32()?,
+                        })
                     }
-                    TableKind::Assembly => Table::Assembly(self.read_struct::<AssemblyTable>()?),
-                    TableKind::AssemblyRef => {
-                        Table::AssemblyRef(self.read_struct::<AssemblyRefTable>()?)
+                    TableKind::AssemblyRef => Table::AssemblyRef(AssemblyRefTable {
+                        major_version: self.read_u16()?,
+                        minor_version: self.read_u16()?,
+                        build_number: self.read_u16()?,
+                        revision_number: self.read_u16()?,
+                        flags: self.read_u32()?,
+                        public_key_or_token: self.read_index(sizes.blob())?,
+                        name: self.read_index(sizes.string())?,
+                        culture: self.read_index(sizes.string())?,
+                        hash_value: self.read_index(sizes.blob())?,
+                    }),
+                    TableKind::AssemblyRefOS => Table::AssemblyRefOS(AssemblyRefOSTable {
+                        os_platform_id: self.read_u32()?,
+                        os_major_version: self.read_u32()?,
+                        os_minor_version: self.read_u32()?,
+                        assembly_ref: self.read_index(sizes.table(TableKind::AssemblyRef))?,
+                    }),
+                    TableKind::AssemblyRefProcessor => {
+                        Table::AssemblyRefProcessor(AssemblyRefProcessorTable {
+                            processor: self.read_u32()?,
+                            assembly_ref: self.read_index(sizes.table(TableKind::AssemblyRef))?,
+                        })
                     }
-                    TableKind::Constant => Table::Constant(self.read_struct::<ConstantTable>()?),
-                    TableKind::Param => Table::Param(self.read_struct::<ParamTable>()?),
-                    TableKind::StandAloneSig => {
-                        Table::StandAloneSig(self.read_struct::<StandAlongSigTable>()?)
+                    TableKind::ClassLayout => Table::ClassLayout(ClassLayoutTable {
+                        packing_size: self.read_u16()?,
+                        class_size: self.read_u32()?,
+                        parent: self.read_index(sizes.table(TableKind::TypeDef))?,
+                    }),
+                    TableKind::Constant => Table::Constant(ConstantTable {
+                        type_: self.read_u16()?,
+                        parent: self.read_index(sizes.coded(CodedIndexKind::HasConstant))?,
+                        value: self.read_index(sizes.blob())?,
+                    }),
+                    TableKind::CustomAttribute => Table::CustomAttribute(CustomAttributeTable {
+                        parent: self.read_index(sizes.coded(CodedIndexKind::HasCustomAttribute))?,
+                        type_: self.read_index(sizes.coded(CodedIndexKind::CustomAttributeType))?,
+                        value: self.read_index(sizes.blob())?,
+                    }),
+                    TableKind::DeclSecurity => Table::DeclSecurity(DeclSecurityTable {
+                        action: self.read_u16()?,
+                        parent: self.read_index(sizes.coded(CodedIndexKind::HasDeclSecurity))?,
+                        permission_set: self.read_index(sizes.blob())?,
+                    }),
+                    TableKind::EventMap => Table::EventMap(EventMapTable {
+                        parent: self.read_index(sizes.table(TableKind::TypeDef))?,
+                        event_list: self.read_index(sizes.table(TableKind::Event))?,
+                    }),
+                    TableKind::Event => Table::Event(EventTable {
+                        event_flags: self.read_u16()?,
+                        name: self.read_index(sizes.string())?,
+                        event_type: self.read_index(sizes.coded(CodedIndexKind::TypeDefOrRef))?,
+                    }),
+                    TableKind::ExportedType => Table::ExportedType(ExportedTypeTable {
+                        flags: self.read_u32()?,
+                        type_def_id: self.read_u32()?,
+                        type_name: self.read_index(sizes.string())?,
+                        type_namespace: self.read_index(sizes.string())?,
+                        implementation: self
+                            .read_index(sizes.coded(CodedIndexKind::Implementation))?,
+                    }),
+                    TableKind::Field => Table::Field(FieldTable {
+                        flags: self.read_u16()?,
+                        name: self.read_index(sizes.string())?,
+                        signature: self.read_index(sizes.blob())?,
+                    }),
+                    TableKind::FieldLayout => Table::FieldLayout(FieldLayoutTable {
+                        offset: self.read_u32()?,
+                        field: self.read_index(sizes.table(TableKind::Field))?,
+                    }),
+                    TableKind::FieldMarshal => Table::FieldMarshal(FieldMarshalTable {
+                        parent: self.read_index(sizes.coded(CodedIndexKind::HasFieldMarshal))?,
+                        native_type: self.read_index(sizes.blob())?,
+                    }),
+                    TableKind::FieldRVA => Table::FieldRVA(FieldRVATable {
+                        rva: self.read_u32()?,
+                        field: self.read_index(sizes.table(TableKind::Field))?,
+                    }),
+                    TableKind::File => Table::File(FileTable {
+                        flags: self.read_u32()?,
+                        name: self.read_index(sizes.string())?,
+                        hash_value: self.read_index(sizes.blob())?,
+                    }),
+                    TableKind::GenericParam => Table::GenericParam(GenericParamTable {
+                        number: self.read_u16()?,
+                        flags: self.read_u16()?,
+                        owner: self.read_index(sizes.coded(CodedIndexKind::TypeOrMethodDef))?,
+                        name: self.read_index(sizes.string())?,
+                    }),
+                    TableKind::GenericParamConstraint => {
+                        Table::GenericParamConstraint(GenericParamConstraintTable {
+                            owner: self.read_index(sizes.table(TableKind::GenericParam))?,
+                            constraint: self
+                                .read_index(sizes.coded(CodedIndexKind::TypeDefOrRef))?,
+                        })
                     }
-                    TableKind::Field => Table::Field(self.read_struct::<FieldTable>()?),
-                    e => unimplemented!("{:?}", e),
+                    TableKind::ImplMap => Table::ImplMap(ImplMapTable {
+                        mapping_flags: self.read_u16()?,
+                        member_forwarded: self
+                            .read_index(sizes.coded(CodedIndexKind::MemberForwarded))?,
+                        import_name: self.read_index(sizes.string())?,
+                        import_scope: self.read_index(sizes.table(TableKind::ModuleRef))?,
+                    }),
+                    TableKind::InterfaceImpl => Table::InterfaceImpl(InterfaceImplTable {
+                        class: self.read_index(sizes.table(TableKind::TypeDef))?,
+                        interface: self.read_index(sizes.coded(CodedIndexKind::TypeDefOrRef))?,
+                    }),
+                    TableKind::ManifestResource => Table::ManifestResource(ManifestResourceTable {
+                        offset: self.read_u32()?,
+                        flags: self.read_u32()?,
+                        name: self.read_index(sizes.string())?,
+                        implementation: self
+                            .read_index(sizes.coded(CodedIndexKind::Implementation))?,
+                    }),
+                    TableKind::MemberRef => Table::MemberRef(MemberRefTable {
+                        class: self.read_index(sizes.coded(CodedIndexKind::MemberRefParent))?,
+                        name: self.read_index(sizes.string())?,
+                        signature: self.read_index(sizes.blob())?,
+                    }),
+                    TableKind::MethodDef => Table::MethodDef(MethodDefTable {
+                        rva: self.read_u32()?,
+                        impl_flags: self.read_u16()?,
+                        flags: self.read_u16()?,
+                        name: self.read_index(sizes.string())?,
+                        signature: self.read_index(sizes.blob())?,
+                        param_list: self.read_index(sizes.table(TableKind::Param))?,
+                    }),
+                    TableKind::MethodImpl => Table::MethodImpl(MethodImplTable {
+                        class: self.read_index(sizes.table(TableKind::TypeDef))?,
+                        method_body: self
+                            .read_index(sizes.coded(CodedIndexKind::MethodDefOrRef))?,
+                        method_declaration: self
+                            .read_index(sizes.coded(CodedIndexKind::MethodDefOrRef))?,
+                    }),
+                    TableKind::MethodSemantics => Table::MethodSemantics(MethodSemanticsTable {
+                        semantics: self.read_u16()?,
+                        method: self.read_index(sizes.table(TableKind::MethodDef))?,
+                        association: self.read_index(sizes.coded(CodedIndexKind::HasSemantics))?,
+                    }),
+                    TableKind::MethodSpec => Table::MethodSpec(MethodSpecTable {
+                        method: self.read_index(sizes.coded(CodedIndexKind::MethodDefOrRef))?,
+                        instantiation: self.read_index(sizes.blob())?,
+                    }),
+                    TableKind::Module => Table::Module(ModuleTable {
+                        generation: self.read_u16()?,
+                        name: self.read_index(sizes.string())?,
+                        mvid: self.read_index(sizes.guid())?,
+                        env_id: self.read_index(sizes.guid())?,
+                        env_base_id: self.read_index(sizes.guid())?,
+                    }),
+                    TableKind::ModuleRef => Table::ModuleRef(ModuleRefTable {
+                        name: self.read_index(sizes.string())?,
+                    }),
+                    TableKind::NestedClass => Table::NestedClass(NestedClassTable {
+                        nested_class: self.read_index(sizes.table(TableKind::TypeDef))?,
+                        enclosing_class: self.read_index(sizes.table(TableKind::TypeDef))?,
+                    }),
+                    TableKind::Param => Table::Param(ParamTable {
+                        flags: self.read_u16()?,
+                        sequence: self.read_u16()?,
+                        name: self.read_index(sizes.string())?,
+                    }),
+                    TableKind::Property => Table::Property(PropertyTable {
+                        flags: self.read_u16()?,
+                        name: self.read_index(sizes.string())?,
+                        type_: self.read_index(sizes.blob())?,
+                    }),
+                    TableKind::PropertyMap => Table::PropertyMap(PropertyMapTable {
+                        parent: self.read_index(sizes.table(TableKind::TypeDef))?,
+                        property_list: self.read_index(sizes.table(TableKind::Property))?,
+                    }),
+                    TableKind::StandAloneSig => Table::StandAloneSig(StandAlongSigTable {
+                        signature: self.read_index(sizes.blob())?,
+                    }),
+                    TableKind::TypeDef => Table::TypeDef(TypeDefTable {
+                        flags: self.read_u32()?,
+                        type_name: self.read_index(sizes.string())?,
+                        type_namespace: self.read_index(sizes.string())?,
+                        extends: self.read_index(sizes.coded(CodedIndexKind::TypeDefOrRef))?,
+                        field_list: self.read_index(sizes.table(TableKind::Field))?,
+                        method_list: self.read_index(sizes.table(TableKind::MethodDef))?,
+                    }),
+                    TableKind::TypeRef => Table::TypeRef(TypeRefTable {
+                        resolution_scope: self
+                            .read_index(sizes.coded(CodedIndexKind::ResolutionScope))?,
+                        type_name: self.read_index(sizes.string())?,
+                        type_namespace: self.read_index(sizes.string())?,
+                    }),
+                    TableKind::TypeSpec => Table::TypeSpec(TypeSpecTable {
+                        signature: self.read_index(sizes.blob())?,
+                    }),
                 })
             }
         }
@@ -666,29 +1051,19 @@ impl PEParser {
         Some(tables)
     }
 
-    // Return (length, read bytes)
-    fn read_blob_length(&mut self) -> Option<(u32, u32)> {
-        let first = self.read_u8()? as u32;
-        if first & 0b1000_0000 == 0 {
-            Some((first & 0b0111_1111, 1))
-        } else if first & 0b1000_0000 > 0 {
-            Some((((first & 0b0111_1111) << 8) + self.read_u8()? as u32, 2))
-        } else if first & 0b1100_0000 > 0 {
-            let x = self.read_u8()? as u32;
-            let y = self.read_u8()? as u32;
-            let z = self.read_u8()? as u32;
-            Some((((first & 0b0011_1111) << 24) + (x << 16) + (y << 8) + z, 4))
-        } else {
-            None
+    /// Reads a heap or table index of ``size`` bytes
+    fn read_index(&mut self, size: usize) -> Option<u32> {
+        match size {
+            2 => Some(self.read_u16()? as u32),
+            _ => self.read_u32(),
         }
     }
 
     fn read_metadata_streams(
         &mut self,
         metadata_offset: u64,
-        metadata_header: &MetaDataHeader,
         stream_headers: &[StreamHeader],
-    ) -> Option<MetaDataStreams> {
+    ) -> LoadResult<MetaDataStreams> {
         // Some of followings are not streams but heaps.
         let mut metadata_stream = None;
         let mut strings = None;
@@ -696,28 +1071,55 @@ impl PEParser {
         let mut blob = None;
         let mut guid = None;
 
-        for i in 0..metadata_header.streams as usize {
+        for sh in stream_headers {
             self.reader
-                .seek(SeekFrom::Start(
-                    metadata_offset + stream_headers[i].offset as u64,
-                ))
-                .ok()?;
-            match stream_headers[i].name.as_str() {
-                "#~" => metadata_stream = self.read_metadata_streams_metadata_stream(),
-                "#Strings" => strings = self.read_metadata_streams_strings(&stream_headers[i]),
-                "#US" => user_strings = self.read_metadata_streams_user_strings(&stream_headers[i]),
-                "#Blob" => blob = self.read_metadata_streams_blob(&stream_headers[i]),
-                "#GUID" => guid = self.read_metadata_streams_guid(),
-                _ => unreachable!(),
+                .seek(SeekFrom::Start(metadata_offset + sh.offset as u64))
+                .ok();
+            match sh.name.as_str() {
+                "#~" => {
+                    metadata_stream = Some(self.stage(LoadStage::MetaDataTables, |p| {
+                        p.read_metadata_streams_metadata_stream()
+                    })?)
+                }
+                "#Strings" => {
+                    strings =
+                        Some(self.stage(LoadStage::Heaps, |p| p.read_metadata_streams_strings(sh))?)
+                }
+                "#US" => {
+                    user_strings = Some(self.stage(LoadStage::Heaps, |p| {
+                        p.read_metadata_streams_user_strings(sh)
+                    })?)
+                }
+                "#Blob" => {
+                    blob = Some(self.stage(LoadStage::Heaps, |p| p.read_metadata_streams_blob(sh))?)
+                }
+                "#GUID" => {
+                    guid = Some(self.stage(LoadStage::Heaps, |p| p.read_metadata_streams_guid())?)
+                }
+                name => {
+                    return Err(LoadError::malformed(
+                        LoadStage::StreamHeaders,
+                        metadata_offset + sh.offset as u64,
+                        format!("unknown stream {}", name),
+                    ))
+                }
             }
         }
 
-        Some(MetaDataStreams {
-            metadata_stream: metadata_stream?,
-            strings: strings?,
-            user_strings: user_strings?,
-            blob: blob?,
-            guid: guid?,
+        let missing = |name: &str| {
+            LoadError::malformed(
+                LoadStage::StreamHeaders,
+                metadata_offset,
+                format!("no {} stream", name),
+            )
+        };
+
+        Ok(MetaDataStreams {
+            metadata_stream: metadata_stream.ok_or_else(|| missing("#~"))?,
+            strings: strings.unwrap_or_default(),
+            user_strings: user_strings.unwrap_or_default(),
+            blob: blob.unwrap_or_default(),
+            guid: guid.unwrap_or_default(),
         })
     }
 
@@ -727,93 +1129,29 @@ impl PEParser {
         Some(stream)
     }
 
-    fn read_metadata_streams_strings(
-        &mut self,
-        sh: &StreamHeader,
-    ) -> Option<FxHashMap<u32, String>> {
-        let mut strings = FxHashMap::default();
-        let mut bytes = vec![];
-        let mut bgn = 0;
-
-        for i in 0..sh.size {
-            let c = self.read_u8()?;
-            if c == 0 {
-                strings.insert(bgn, from_utf8(&bytes).unwrap().to_string());
-                bytes.clear();
-                bgn = i + 1;
-            } else {
-                bytes.push(c);
-            }
-        }
-
-        when_debug!(for (i, s) in &strings {
-            dprintln!("#Strings({:02X}): '{}'", i, s)
-        });
-
+    fn read_metadata_streams_strings(&mut self, sh: &StreamHeader) -> Option<StringHeap> {
+        let strings = StringHeap::new(self.read_heap(sh)?);
+        dprintln!("#Strings: {} bytes", strings.len());
         Some(strings)
     }
 
-    fn read_metadata_streams_user_strings(
-        &mut self,
-        sh: &StreamHeader,
-    ) -> Option<FxHashMap<u32, Vec<u16>>> {
-        let mut user_strings = FxHashMap::default();
-        let mut bytes = vec![];
-        let mut count = 0;
-
-        while count < sh.size {
-            let bgn = count;
-            let (len, read_bytes) = self.read_blob_length()?;
-            count += read_bytes;
-
-            /* read 2 bytes each so divide len by 2 */
-            for _ in 0..len / 2 {
-                bytes.push(self.read_u16()?);
-            }
-
-            if len % 2 == 1 {
-                // Consume additional one byte
-                self.read_u8()?; // TODO
-            }
-
-            user_strings.insert(bgn as u32, bytes.clone());
-            bytes.clear();
-            count += len as u32;
-        }
-
-        when_debug!(for (i, us) in &user_strings {
-            dprintln!("#US({:02X}): '{}'", i, String::from_utf16(&us).ok()?)
-        });
-
+    fn read_metadata_streams_user_strings(&mut self, sh: &StreamHeader) -> Option<UserStringHeap> {
+        let user_strings = UserStringHeap::new(self.read_heap(sh)?);
+        dprintln!("#US: {} bytes", user_strings.len());
         Some(user_strings)
     }
 
-    fn read_metadata_streams_blob(&mut self, sh: &StreamHeader) -> Option<FxHashMap<u32, Vec<u8>>> {
-        let mut blob = FxHashMap::default();
-        let mut bytes = vec![];
-        let mut count = 0;
-
-        while count < sh.size {
-            let bgn = count;
-            let (len, read_bytes) = self.read_blob_length()?;
-            count += read_bytes;
-
-            for _ in 0..len {
-                bytes.push(self.read_u8()?);
-            }
-
-            blob.insert(bgn as u32, bytes.clone());
-            bytes.clear();
-            count += len as u32;
-        }
-
-        when_debug!(for (i, b) in &blob {
-            dprintln!("#Blob({:02X}): {:?}", i, b)
-        });
-
+    fn read_metadata_streams_blob(&mut self, sh: &StreamHeader) -> Option<BlobHeap> {
+        let blob = BlobHeap::new(self.read_heap(sh)?);
+        dprintln!("#Blob: {} bytes", blob.len());
         Some(blob)
     }
 
+    /// Reads the whole heap. Entries are decoded on demand.
+    fn read_heap(&mut self, sh: &StreamHeader) -> Option<Vec<u8>> {
+        self.read_bytes_vec(sh.size as usize)
+    }
+
     fn read_metadata_streams_guid(&mut self) -> Option<String> {
         let data1 = self.read_u32()?;
         let data2 = self.read_u16()?;
@@ -854,6 +1192,17 @@ impl PEParser {
 }
 
 impl PEParser {
+    /// Reads ``len`` bytes. Fails without allocating if the image is shorter.
+    fn read_bytes_vec(&mut self, len: usize) -> Option<Vec<u8>> {
+        let remaining = (self.reader.get_ref().len() as u64).saturating_sub(self.position());
+        if len as u64 > remaining {
+            return None;
+        }
+        let mut buf = vec![0u8; len];
+        self.read_bytes(&mut buf)?;
+        Some(buf)
+    }
+
     fn read_bytes(&mut self, buf: &mut [u8]) -> Option<()> {
         match self.reader.read_exact(buf) {
             Ok(()) => Some(()),
@@ -907,3 +1256,77 @@ impl PEParser {
         }
     }
 }
+
+#[test]
+fn test_wide_indexes() {
+    // A #~ stream with 4-byte heap indexes and enough TypeRefs for 4-byte ResolutionScope and
+    // TypeDefOrRef indexes, while the indexes of the empty Field and MethodDef tables stay 2 bytes
+    const TYPE_REFS: u32 = 0x4000;
+    let mut bytes = vec![0, 0, 0, 0, 2, 0, heap_sizes_flags::STRING | heap_sizes_flags::GUID, 1];
+    bytes.extend(&0b111u64.to_le_bytes());
+    bytes.extend(&0u64.to_le_bytes());
+    for rows in &[1, TYPE_REFS, 1] {
+        bytes.extend(&rows.to_le_bytes());
+    }
+    bytes.extend(&[0, 0]);
+    for index in &[0x1_0000u32, 1, 0, 0] {
+        bytes.extend(&index.to_le_bytes());
+    }
+    for i in 0..TYPE_REFS {
+        for index in &[0x6u32, 0x1_0000 + i, 0] {
+            bytes.extend(&index.to_le_bytes());
+        }
+    }
+    for index in &[0x1u32, 0x2_0000, 0, TYPE_REFS << 2 | 1] {
+        bytes.extend(&index.to_le_bytes());
+    }
+    bytes.extend(&[1, 0, 1, 0]);
+
+    let mut parser = PEParser::from_bytes(bytes);
+    let stream = parser.read_metadata_stream().unwrap();
+    assert_eq!(stream.rows, vec![1, TYPE_REFS, 1]);
+    match stream.tables[TableKind::Module.into_num()][..] {
+        [Table::Module(module)] => assert_eq!((module.name, module.mvid), (0x1_0000, 1)),
+        ref e => panic!("unexpected rows: {:?}", e),
+    }
+    match stream.tables[TableKind::TypeRef.into_num()].last() {
+        Some(Table::TypeRef(type_ref)) => {
+            assert_eq!(type_ref.resolution_scope, 0x6);
+            assert_eq!(type_ref.type_name, 0x1_0000 + TYPE_REFS - 1);
+        }
+        e => panic!("unexpected row: {:?}", e),
+    }
+    match stream.tables[TableKind::TypeDef.into_num()][..] {
+        [Table::TypeDef(type_def)] => {
+            assert_eq!(type_def.type_name, 0x2_0000);
+            assert_eq!(type_def.extends, TYPE_REFS << 2 | 1);
+            assert_eq!((type_def.field_list, type_def.method_list), (1, 1));
+        }
+        ref e => panic!("unexpected rows: {:?}", e),
+    }
+
+    // One byte less fails to read the last row
+    let mut bytes = parser.reader.into_inner();
+    bytes.pop();
+    assert!(PEParser::from_bytes(bytes).read_metadata_stream().is_none());
+}
+
+#[test]
+fn test_pe32_plus() {
+    // x64.exe has a PE32+ optional header, and its CLI header and metadata are in .sdata while
+    // the method bodies are in .text
+    let bytes = fs::read("./examples/x64.exe").unwrap();
+    let pe_offset = u32::from_le_bytes([bytes[0x3c], bytes[0x3d], bytes[0x3e], bytes[0x3f]]);
+    let magic_offset = pe_offset as usize + 24;
+    let magic = u16::from_le_bytes([bytes[magic_offset], bytes[magic_offset + 1]]);
+    assert_eq!(magic, optional_header_magic::PE32_PLUS);
+
+    let asm = Assembly::load(PathBuf::from("./examples/x64.exe")).unwrap();
+    let mut asm = asm.borrow_mut();
+    let cli_info = &asm.image.cli_info;
+    let names: Vec<&str> = cli_info.sections.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, [".text", ".sdata"]);
+    assert!(cli_info.cli_header.metadata_rva >= cli_info.sections[1].virtual_address);
+    let method = asm.image.get_entry_method().unwrap();
+    assert!(!method.borrow().as_mdef().body.is_empty());
+}
@@ -0,0 +1,139 @@
+use crate::metadata::{metadata::*, token::*};
+
+/// Typed read-only access to the metadata tables and heaps of an image
+#[derive(Debug, Clone, Copy)]
+pub struct MetadataReader<'a> {
+    metadata: &'a MetaDataStreams,
+}
+
+impl<'a> MetadataReader<'a> {
+    pub fn new(metadata: &'a MetaDataStreams) -> Self {
+        Self { metadata }
+    }
+
+    pub fn row_count(&self, kind: TableKind) -> u32 {
+        self.metadata.get_table(kind).len() as u32
+    }
+
+    /// Returns the rows of the table ``T`` with their tokens
+    pub fn rows<T: TableRow + 'a>(&self) -> impl Iterator<Item = (Token, &'a T)> + 'a {
+        self.metadata
+            .get_table(T::KIND)
+            .iter()
+            .enumerate()
+            .filter_map(|(i, table)| {
+                let token = encode_token(T::KIND.into(), i as u32 + 1);
+                Some((token, T::from_table(table)?))
+            })
+    }
+
+    /// Returns the row of the table ``T`` at ``entry`` (1-based)
+    pub fn row<T: TableRow>(&self, entry: u32) -> Option<&'a T> {
+        let table = self
+            .metadata
+            .get_table(T::KIND)
+            .get((entry as usize).checked_sub(1)?)?;
+        T::from_table(table)
+    }
+
+    /// Returns the row ``token`` refers to
+    pub fn get<T: Into<Token>>(&self, token: T) -> Option<&'a Table> {
+        let DecodedToken(table, entry) = decode_token(token.into());
+        TableKind::into_table_kind(table as u8)?;
+        self.metadata
+            .get_table(table as usize)
+            .get((entry as usize).checked_sub(1)?)
+    }
+
+    /// Decodes a coded index of ``kind`` into a token. Returns None for a null index.
+    pub fn decode(&self, kind: CodedIndexKind, index: u32) -> Option<Token> {
+        let decoded = decode_coded_index(kind, index)?;
+        if decoded.1 == 0 {
+            return None;
+        }
+        Some(decoded.into())
+    }
+
+    /// Returns the row a coded index of ``kind`` refers to
+    pub fn resolve(&self, kind: CodedIndexKind, index: u32) -> Option<&'a Table> {
+        self.get(self.decode(kind, index)?)
+    }
+
+    pub fn get_string(&self, index: u32) -> Option<&'a str> {
+        self.metadata.strings.get(index)
+    }
+
+    pub fn get_blob(&self, index: u32) -> Option<&'a [u8]> {
+        self.metadata.blob.get(index)
+    }
+
+    pub fn get_user_string(&self, index: u32) -> Option<Vec<u16>> {
+        self.metadata.user_strings.get(index)
+    }
+}
+
+#[test]
+fn test_metadata_reader() {
+    use crate::metadata::assembly::Assembly;
+
+    // opcodes.exe has nested value types with explicit sizes whose fields have initial data
+    let bytes = std::fs::read("./examples/opcodes.exe").unwrap();
+    let asm = Assembly::load_from_bytes(&bytes).unwrap();
+    let asm = asm.borrow();
+    let reader = asm.image.metadata_reader();
+
+    let type_names: Vec<&str> = reader
+        .rows::<TypeDefTable>()
+        .map(|(_, row)| reader.get_string(row.type_name).unwrap())
+        .collect();
+    assert_eq!(
+        type_names,
+        vec![
+            "<Module>",
+            "Program",
+            "<PrivateImplementationDetails>",
+            "__StaticArrayInitTypeSize=20",
+            "__StaticArrayInitTypeSize=10"
+        ]
+    );
+    assert_eq!(reader.row_count(TableKind::TypeDef), 5);
+    let (token, _) = reader.rows::<TypeDefTable>().nth(1).unwrap();
+    assert_eq!(token, Token(0x0200_0002));
+    assert!(reader.row::<TypeDefTable>(0).is_none());
+    assert!(reader.row::<TypeDefTable>(6).is_none());
+    assert!(reader.get(Token(0x0200_0006)).is_none());
+    assert!(reader.get(Token(0x7f00_0001)).is_none());
+
+    let nested: Vec<(u32, u32)> = reader
+        .rows::<NestedClassTable>()
+        .map(|(_, row)| (row.nested_class, row.enclosing_class))
+        .collect();
+    assert_eq!(nested, vec![(4, 3), (5, 3)]);
+    let sizes: Vec<(u32, u32)> = reader
+        .rows::<ClassLayoutTable>()
+        .map(|(_, row)| (row.parent, row.class_size))
+        .collect();
+    assert_eq!(sizes, vec![(4, 20), (5, 10)]);
+    assert_eq!(reader.row_count(TableKind::FieldRVA), 2);
+
+    // Types from mscorlib are scoped by an AssemblyRef
+    let (_, object) = reader.rows::<TypeRefTable>().next().unwrap();
+    match reader.resolve(CodedIndexKind::ResolutionScope, object.resolution_scope) {
+        Some(Table::AssemblyRef(asm_ref)) => {
+            assert_eq!(reader.get_string(asm_ref.name), Some("mscorlib"))
+        }
+        e => panic!("unexpected row: {:?}", e),
+    }
+
+    // The call site of a vararg method is a MemberRef whose parent is the MethodDef
+    let vararg_sites: Vec<&str> = reader
+        .rows::<MemberRefTable>()
+        .filter(|(_, row)| match reader.resolve(CodedIndexKind::MemberRefParent, row.class) {
+            Some(Table::MethodDef(_)) => true,
+            _ => false,
+        })
+        .map(|(_, row)| reader.get_string(row.name).unwrap())
+        .collect();
+    assert_eq!(vararg_sites, vec!["Sum"]);
+    assert_eq!(reader.decode(CodedIndexKind::MemberRefParent, 0), None);
+}
@@ -11,17 +11,34 @@ pub enum ElementType {
     Void,
     Boolean,
     Char,
+    I1,
+    U1,
+    I2,
+    U2,
     I4,
     U4,
     I8,
+    U8,
+    R4,
     R8,
+    /// Native int (System.IntPtr)
+    I,
+    /// Native unsigned int (System.UIntPtr)
+    U,
     String,
     Class(ClassInfoRef),
     SzArray(Box<SzArrayInfo>),
+    Array(Box<ArrayInfo>),
     FnPtr(Box<MethodSignature>),
     Ptr(Box<Type>),
+    ByRef(Box<Type>),
     Object,
     ValueType(ClassInfoRef),
+    Var(usize),
+    MVar(usize),
+    GenericInst(Box<GenericInstInfo>),
+    /// System.TypedReference
+    TypedByRef,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -34,6 +51,9 @@ pub struct MethodSignature {
 
     /// Parameters' types
     pub params: Vec<Type>,
+
+    /// Index in ``params`` of the first argument passed after ``SENTINEL`` to a vararg method
+    pub sentinel: Option<usize>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -42,11 +62,52 @@ pub struct SzArrayInfo {
     pub elem_ty: Type,
 }
 
+/// Multidimensional array (II.23.2.13 ArrayShape)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayInfo {
+    /// Array's element type
+    pub elem_ty: Type,
+
+    /// Number of dimensions
+    pub rank: usize,
+
+    /// Sizes of the leading dimensions, which the signature may omit
+    pub sizes: Vec<u32>,
+
+    /// Lower bounds of the leading dimensions, which the signature may omit
+    pub lo_bounds: Vec<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenericInstInfo {
+    /// Generic type definition
+    pub class: ClassInfoRef,
+
+    /// Type arguments
+    pub args: Vec<Type>,
+
+    /// True if the signature instantiates the type as ``VALUETYPE`` rather than ``CLASS``
+    pub is_valuetype: bool,
+}
+
+/// Type arguments that replace ``Var`` (of the class) and ``MVar`` (of the method)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GenericContext {
+    pub class_args: Vec<Type>,
+    pub method_args: Vec<Type>,
+}
+
 #[derive(Debug, Clone)]
 pub struct SignatureParser<'a> {
     sig: Iter<'a, u8>,
+    /// Nesting depth of the signature being parsed
+    depth: usize,
 }
 
+/// Types nested deeper than this are rejected so that malformed signatures cannot exhaust the
+/// stack
+const MAX_SIGNATURE_DEPTH: usize = 64;
+
 impl Type {
     pub fn new(base: ElementType) -> Self {
         Self { base }
@@ -68,6 +129,22 @@ impl Type {
         Self::new(ElementType::Char)
     }
 
+    pub fn i1_ty() -> Self {
+        Self::new(ElementType::I1)
+    }
+
+    pub fn u1_ty() -> Self {
+        Self::new(ElementType::U1)
+    }
+
+    pub fn i2_ty() -> Self {
+        Self::new(ElementType::I2)
+    }
+
+    pub fn u2_ty() -> Self {
+        Self::new(ElementType::U2)
+    }
+
     pub fn i4_ty() -> Self {
         Self::new(ElementType::I4)
     }
@@ -76,10 +153,26 @@ impl Type {
         Self::new(ElementType::U4)
     }
 
+    pub fn i_ty() -> Self {
+        Self::new(ElementType::I)
+    }
+
     pub fn i8_ty() -> Self {
         Self::new(ElementType::I8)
     }
 
+    pub fn u8_ty() -> Self {
+        Self::new(ElementType::U8)
+    }
+
+    pub fn r4_ty() -> Self {
+        Self::new(ElementType::R4)
+    }
+
+    pub fn u_ty() -> Self {
+        Self::new(ElementType::U)
+    }
+
     pub fn r8_ty() -> Self {
         Self::new(ElementType::R8)
     }
@@ -89,6 +182,7 @@ impl Type {
             info: flags,
             ret,
             params: params.to_vec(),
+            sentinel: None,
         })))
     }
 
@@ -118,6 +212,15 @@ impl Type {
         Self::new(ElementType::Class(class))
     }
 
+    /// Returns the type of the instances of ``class``
+    pub fn from_class(class: ClassInfoRef) -> Self {
+        if class.borrow().is_valuetype() {
+            Self::new(ElementType::ValueType(class))
+        } else {
+            Self::new(ElementType::Class(class))
+        }
+    }
+
     pub fn object_ty() -> Self {
         Self::new(ElementType::Object)
     }
@@ -126,46 +229,249 @@ impl Type {
         Self::new(ElementType::Ptr(Box::new(elem)))
     }
 
+    pub fn byref_ty(elem: Type) -> Self {
+        Self::new(ElementType::ByRef(Box::new(elem)))
+    }
+
     pub fn into_type<'a>(image: &Image, sig: &mut Iter<'a, u8>) -> Option<Self> {
+        Type::into_type_nested(image, sig, 0)
+    }
+
+    fn into_type_nested<'a>(image: &Image, sig: &mut Iter<'a, u8>, depth: usize) -> Option<Self> {
+        if depth > MAX_SIGNATURE_DEPTH {
+            return None;
+        }
+        let depth = depth + 1;
         match sig.next()? {
             0x01 => Some(Type::new(ElementType::Void)),
             0x02 => Some(Type::new(ElementType::Boolean)),
             0x03 => Some(Type::new(ElementType::Char)),
+            0x04 => Some(Type::new(ElementType::I1)),
+            0x05 => Some(Type::new(ElementType::U1)),
+            0x06 => Some(Type::new(ElementType::I2)),
+            0x07 => Some(Type::new(ElementType::U2)),
             0x08 => Some(Type::new(ElementType::I4)),
             0x09 => Some(Type::new(ElementType::U4)),
             0x0a => Some(Type::new(ElementType::I8)),
+            0x0b => Some(Type::new(ElementType::U8)),
+            0x0c => Some(Type::new(ElementType::R4)),
             0x0d => Some(Type::new(ElementType::R8)),
             0x0e => Some(Type::new(ElementType::String)),
+            0x18 => Some(Type::new(ElementType::I)),
+            0x19 => Some(Type::new(ElementType::U)),
+            0x1b => {
+                let mut parser = SignatureParser {
+                    sig: sig.clone(),
+                    depth,
+                };
+                let ty = parser.parse_method_ref_sig(image)?;
+                *sig = parser.sig;
+                Some(ty)
+            }
+            0x0f => Some(Type::ptr_ty(Type::into_type_nested(image, sig, depth)?)),
+            0x10 => Some(Type::byref_ty(Type::into_type_nested(image, sig, depth)?)),
             0x12 => Type::class_into_type(image, sig),
             0x1c => Some(Type::new(ElementType::Object)),
             0x1d => Some(Type::new(ElementType::SzArray(Box::new(SzArrayInfo {
-                elem_ty: Type::into_type(image, sig)?,
+                elem_ty: Type::into_type_nested(image, sig, depth)?,
             })))),
+            0x14 => Type::array_into_type(image, sig, depth),
             0x11 => Type::valuetype_into_type(image, sig),
-            // TODO
-            e => unimplemented!("{:?}", e),
-            // _ => None,
+            0x13 => Some(Type::new(ElementType::Var(decompress_uint(sig)? as usize))),
+            0x15 => Type::generic_inst_into_type(image, sig, depth),
+            0x1e => Some(Type::new(ElementType::MVar(decompress_uint(sig)? as usize))),
+            0x16 => Some(Type::new(ElementType::TypedByRef)),
+            // CMOD_REQD and CMOD_OPT (e.g. ``modreq(IsReadOnly)`` of ``in`` parameters) do not
+            // change how values are passed. The modifier type is not resolved because it may
+            // live in an assembly that is not loaded.
+            0x1f | 0x20 => {
+                decode_coded_index(CodedIndexKind::TypeDefOrRef, decompress_uint(sig)?)?;
+                Type::into_type_nested(image, sig, depth)
+            }
+            // PINNED locals are never moved by the GC anyway
+            0x45 => Type::into_type_nested(image, sig, depth),
+            _ => None,
         }
     }
 
     fn class_into_type<'a>(image: &Image, sig: &mut Iter<'a, u8>) -> Option<Self> {
-        let token = decompress_uint(sig).unwrap();
-        let class_ref = image
-            .class_cache
-            .get(&decode_typedef_or_ref_token(token).into())
-            .unwrap();
+        let token = decode_coded_index(CodedIndexKind::TypeDefOrRef, decompress_uint(sig)?)?;
+        let class_ref = image.class_cache.get(&token.into())?;
         Some(Type::new(ElementType::Class(class_ref.clone())))
     }
 
     fn valuetype_into_type<'a>(image: &Image, sig: &mut Iter<'a, u8>) -> Option<Self> {
-        let token = decompress_uint(sig).unwrap();
-        let class_ref = image
-            .class_cache
-            .get(&decode_typedef_or_ref_token(token).into())
-            .unwrap();
+        let token = decode_coded_index(CodedIndexKind::TypeDefOrRef, decompress_uint(sig)?)?;
+        let class_ref = image.class_cache.get(&token.into())?;
         Some(Type::new(ElementType::ValueType(class_ref.clone())))
     }
 
+    fn array_into_type<'a>(image: &Image, sig: &mut Iter<'a, u8>, depth: usize) -> Option<Self> {
+        let elem_ty = Type::into_type_nested(image, sig, depth)?;
+        let rank = decompress_uint(sig)? as usize;
+        if rank == 0 {
+            return None;
+        }
+        let num_sizes = decompress_uint(sig)?;
+        let sizes = repeat_with(|| decompress_uint(sig))
+            .take(num_sizes as usize)
+            .collect::<Option<Vec<u32>>>()?;
+        let num_lo_bounds = decompress_uint(sig)?;
+        let lo_bounds = repeat_with(|| decompress_int(sig))
+            .take(num_lo_bounds as usize)
+            .collect::<Option<Vec<i32>>>()?;
+        Some(Type::new(ElementType::Array(Box::new(ArrayInfo {
+            elem_ty,
+            rank,
+            sizes,
+            lo_bounds,
+        }))))
+    }
+
+    fn generic_inst_into_type<'a>(
+        image: &Image,
+        sig: &mut Iter<'a, u8>,
+        depth: usize,
+    ) -> Option<Self> {
+        let is_valuetype = match sig.next()? {
+            0x11 => true,
+            0x12 => false,
+            _ => return None,
+        };
+        let token = decode_coded_index(CodedIndexKind::TypeDefOrRef, decompress_uint(sig)?)?;
+        let class = image.class_cache.get(&token.into())?.clone();
+        let arg_count = decompress_uint(sig)?;
+        let args = repeat_with(|| Type::into_type_nested(image, sig, depth))
+            .take(arg_count as usize)
+            .collect::<Option<Vec<Type>>>()?;
+        Some(Type::new(ElementType::GenericInst(Box::new(
+            GenericInstInfo {
+                class,
+                args,
+                is_valuetype,
+            },
+        ))))
+    }
+
+    /// Replaces ``Var`` and ``MVar`` with the type arguments in ``ctx``, and instantiates
+    /// the generic types whose arguments are all known. Type parameters not given by ``ctx``
+    /// are left as they are.
+    pub fn instantiate(&self, ctx: &GenericContext) -> Type {
+        match self.base {
+            ElementType::Var(n) => ctx
+                .class_args
+                .get(n)
+                .cloned()
+                .unwrap_or_else(|| self.clone()),
+            ElementType::MVar(n) => ctx
+                .method_args
+                .get(n)
+                .cloned()
+                .unwrap_or_else(|| self.clone()),
+            ElementType::SzArray(ref szarr) => Type::szarr_ty(szarr.elem_ty.instantiate(ctx)),
+            ElementType::Array(ref arr) => Type::new(ElementType::Array(Box::new(ArrayInfo {
+                elem_ty: arr.elem_ty.instantiate(ctx),
+                ..(**arr).clone()
+            }))),
+            ElementType::Ptr(ref elem) => Type::ptr_ty(elem.instantiate(ctx)),
+            ElementType::ByRef(ref elem) => Type::byref_ty(elem.instantiate(ctx)),
+            ElementType::FnPtr(ref msig) => Type::full_method_ty(
+                msig.info,
+                msig.ret.instantiate(ctx),
+                &msig
+                    .params
+                    .iter()
+                    .map(|p| p.instantiate(ctx))
+                    .collect::<Vec<Type>>(),
+            ),
+            ElementType::GenericInst(ref inst) => {
+                let args = inst
+                    .args
+                    .iter()
+                    .map(|arg| arg.instantiate(ctx))
+                    .collect::<Vec<Type>>();
+                let instance = if args.iter().all(|arg| !arg.contains_generic_params()) {
+                    ClassInfo::instantiate(&inst.class, &args)
+                } else {
+                    None
+                };
+                match instance {
+                    Some(instance) if inst.is_valuetype => {
+                        Type::new(ElementType::ValueType(instance))
+                    }
+                    Some(instance) => Type::class_ty(instance),
+                    None => Type::new(ElementType::GenericInst(Box::new(GenericInstInfo {
+                        class: inst.class.clone(),
+                        args,
+                        is_valuetype: inst.is_valuetype,
+                    }))),
+                }
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// Returns true if this type refers to any type parameter
+    pub fn contains_generic_params(&self) -> bool {
+        match self.base {
+            ElementType::Var(_) | ElementType::MVar(_) => true,
+            ElementType::SzArray(ref szarr) => szarr.elem_ty.contains_generic_params(),
+            ElementType::Array(ref arr) => arr.elem_ty.contains_generic_params(),
+            ElementType::Ptr(ref elem) | ElementType::ByRef(ref elem) => {
+                elem.contains_generic_params()
+            }
+            ElementType::FnPtr(ref msig) => {
+                msig.ret.contains_generic_params()
+                    || msig.params.iter().any(|p| p.contains_generic_params())
+            }
+            ElementType::GenericInst(ref inst) => {
+                inst.args.iter().any(|arg| arg.contains_generic_params())
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the full name of this type (e.g. System.Int32)
+    pub fn get_name(&self) -> String {
+        match self.base {
+            ElementType::Void => "System.Void".to_string(),
+            ElementType::Boolean => "System.Boolean".to_string(),
+            ElementType::Char => "System.Char".to_string(),
+            ElementType::I1 => "System.SByte".to_string(),
+            ElementType::U1 => "System.Byte".to_string(),
+            ElementType::I2 => "System.Int16".to_string(),
+            ElementType::U2 => "System.UInt16".to_string(),
+            ElementType::I4 => "System.Int32".to_string(),
+            ElementType::U4 => "System.UInt32".to_string(),
+            ElementType::I8 => "System.Int64".to_string(),
+            ElementType::U8 => "System.UInt64".to_string(),
+            ElementType::R4 => "System.Single".to_string(),
+            ElementType::R8 => "System.Double".to_string(),
+            ElementType::I => "System.IntPtr".to_string(),
+            ElementType::U => "System.UIntPtr".to_string(),
+            ElementType::String => "System.String".to_string(),
+            ElementType::Object => "System.Object".to_string(),
+            ElementType::Class(ref class) | ElementType::ValueType(ref class) => {
+                class.borrow().get_full_name()
+            }
+            ElementType::SzArray(ref szarr) => format!("{}[]", szarr.elem_ty.get_name()),
+            ElementType::Array(ref arr) => {
+                format!("{}[{}]", arr.elem_ty.get_name(), ",".repeat(arr.rank - 1))
+            }
+            ElementType::Ptr(ref elem) => format!("{}*", elem.get_name()),
+            ElementType::ByRef(ref elem) => format!("{}&", elem.get_name()),
+            ElementType::FnPtr(_) => "method".to_string(),
+            ElementType::TypedByRef => "System.TypedReference".to_string(),
+            ElementType::Var(n) => format!("!{}", n),
+            ElementType::MVar(n) => format!("!!{}", n),
+            ElementType::GenericInst(ref inst) => format!(
+                "{}<{}>",
+                inst.class.borrow().get_full_name(),
+                type_list_name(&inst.args)
+            ),
+        }
+    }
+
     pub fn as_fnptr(&self) -> Option<&MethodSignature> {
         match self.base {
             ElementType::FnPtr(ref fnptr) => Some(fnptr),
@@ -180,6 +486,23 @@ impl Type {
         }
     }
 
+    /// Returns the class of a user-defined value type other than enums, which are treated as
+    /// their underlying integer type
+    pub fn as_valuetype(&self) -> Option<&ClassInfoRef> {
+        match self.base {
+            ElementType::ValueType(ref class) if !class.borrow().is_enum() => Some(class),
+            _ => None,
+        }
+    }
+
+    /// Returns the type that a managed or unmanaged pointer points to
+    pub fn as_pointee(&self) -> Option<&Type> {
+        match self.base {
+            ElementType::Ptr(ref elem) | ElementType::ByRef(ref elem) => Some(elem),
+            _ => None,
+        }
+    }
+
     pub fn as_szarray(&self) -> Option<&SzArrayInfo> {
         match self.base {
             ElementType::SzArray(ref szarr) => Some(szarr),
@@ -187,6 +510,13 @@ impl Type {
         }
     }
 
+    pub fn as_array(&self) -> Option<&ArrayInfo> {
+        match self.base {
+            ElementType::Array(ref arr) => Some(arr),
+            _ => None,
+        }
+    }
+
     pub fn equal_method(&self, ret: ElementType, params: &[ElementType]) -> bool {
         match self.base {
             ElementType::FnPtr(ref ms) => {
@@ -196,6 +526,85 @@ impl Type {
         }
     }
 
+    /// Returns true if values of this type are not objects and need boxing to be treated as
+    /// objects
+    pub fn is_valuetype(&self) -> bool {
+        match self.base {
+            ElementType::Boolean
+            | ElementType::Char
+            | ElementType::I1
+            | ElementType::U1
+            | ElementType::I2
+            | ElementType::U2
+            | ElementType::I4
+            | ElementType::U4
+            | ElementType::I8
+            | ElementType::U8
+            | ElementType::R4
+            | ElementType::R8
+            | ElementType::I
+            | ElementType::U
+            | ElementType::ValueType(_) => true,
+            ElementType::GenericInst(ref inst) => inst.is_valuetype,
+            _ => false,
+        }
+    }
+
+    /// Returns the class of the objects of this type, or the class of the boxed values for value
+    /// types. Arrays and pointers have no such class.
+    pub fn get_class(&self) -> Option<ClassInfoRef> {
+        match self.base {
+            ElementType::Object => Some(mscorlib_system_object()),
+            ElementType::String => Some(mscorlib_system_string()),
+            ElementType::I4 => Some(mscorlib_system_int32()),
+            ElementType::I8 => Some(mscorlib_system_int64()),
+            ElementType::R8 => Some(mscorlib_system_double()),
+            ElementType::Boolean => Some(mscorlib_system_boolean()),
+            ElementType::Char => Some(mscorlib_system_char()),
+            ElementType::I1 => Some(mscorlib_system_class("SByte")),
+            ElementType::U1 => Some(mscorlib_system_class("Byte")),
+            ElementType::I2 => Some(mscorlib_system_class("Int16")),
+            ElementType::U2 => Some(mscorlib_system_class("UInt16")),
+            ElementType::U4 => Some(mscorlib_system_class("UInt32")),
+            ElementType::U8 => Some(mscorlib_system_class("UInt64")),
+            ElementType::R4 => Some(mscorlib_system_class("Single")),
+            ElementType::I => Some(mscorlib_system_class("IntPtr")),
+            ElementType::U => Some(mscorlib_system_class("UIntPtr")),
+            ElementType::Class(ref class) | ElementType::ValueType(ref class) => {
+                Some(class.clone())
+            }
+            ElementType::GenericInst(ref inst) => ClassInfo::instantiate(&inst.class, &inst.args),
+            _ => None,
+        }
+    }
+
+    /// Returns true if a value of this type can be stored in a location of ``target`` as it is,
+    /// or by boxing it if this is a value type (II.12.1.1 and I.8.7)
+    pub fn is_assignable_to(&self, target: &Type) -> bool {
+        if self == target {
+            return true;
+        }
+        match (&self.base, &target.base) {
+            // Every type, including boxed value types, derives from System.Object
+            (_, ElementType::Object) => true,
+            // Arrays are covariant only in reference types
+            (ElementType::SzArray(arr), ElementType::SzArray(target_arr)) => {
+                !arr.elem_ty.is_valuetype() && arr.elem_ty.is_assignable_to(&target_arr.elem_ty)
+            }
+            // The shapes in signatures may differ only in the sizes and the lower bounds
+            (ElementType::Array(arr), ElementType::Array(target_arr)) => {
+                arr.rank == target_arr.rank
+                    && (arr.elem_ty == target_arr.elem_ty
+                        || !arr.elem_ty.is_valuetype()
+                            && arr.elem_ty.is_assignable_to(&target_arr.elem_ty))
+            }
+            _ => match (self.get_class(), target.get_class()) {
+                (Some(class), Some(target)) => class.borrow().is_assignable_to(&target.borrow()),
+                _ => false,
+            },
+        }
+    }
+
     pub fn is_void(&self) -> bool {
         self.base == ElementType::Void
     }
@@ -203,7 +612,17 @@ impl Type {
     pub fn is_int(&self) -> bool {
         match self.base {
             // Is Bool int?
-            ElementType::Char | ElementType::I4 | ElementType::I8 | ElementType::U4 => true,
+            ElementType::Char
+            | ElementType::I1
+            | ElementType::U1
+            | ElementType::I2
+            | ElementType::U2
+            | ElementType::I4
+            | ElementType::U4
+            | ElementType::I8
+            | ElementType::U8
+            | ElementType::I
+            | ElementType::U => true,
             ElementType::ValueType(ref c) if c.borrow().is_enum() => true,
             _ => false,
         }
@@ -211,7 +630,7 @@ impl Type {
 
     pub fn is_float(&self) -> bool {
         match self.base {
-            ElementType::R8 => true,
+            ElementType::R4 | ElementType::R8 => true,
             _ => false,
         }
     }
@@ -219,63 +638,146 @@ impl Type {
 
 impl<'a> SignatureParser<'a> {
     pub fn new(sig: &'a [u8]) -> Self {
-        Self { sig: sig.iter() }
+        Self {
+            sig: sig.iter(),
+            depth: 0,
+        }
+    }
+
+    fn parse_type(&mut self, image: &Image) -> Option<Type> {
+        Type::into_type_nested(image, &mut self.sig, self.depth)
     }
 
+    /// II.23.2.2 MethodRefSig. Also used for StandAloneMethodSig (II.23.2.3).
     pub fn parse_method_ref_sig(&mut self, image: &Image) -> Option<Type> {
-        let first = *self.sig.next()?;
-        let _has_this = first & 0x20;
-        let _explicit_this = first & 0x40;
-        let _var_arg = first & 0x5;
+        self.parse_method_sig(image, true)
+    }
 
-        let param_count = decompress_uint(&mut self.sig)?;
-        let ret = Type::into_type(image, &mut self.sig)?;
+    /// II.23.2.1 MethodDefSig
+    pub fn parse_method_def_sig(&mut self, image: &Image) -> Option<Type> {
+        self.parse_method_sig(image, false)
+    }
 
-        let params = repeat_with(|| Type::into_type(image, &mut self.sig).unwrap())
-            .take(param_count as usize)
-            .collect();
+    fn parse_method_sig(&mut self, image: &Image, allow_sentinel: bool) -> Option<Type> {
+        let first = *self.sig.next()?;
+        if first & signature_flags::KIND_MASK > signature_flags::VARARG
+            || first & signature_flags::EXPLICITTHIS > 0 && first & signature_flags::HASTHIS == 0
+        {
+            return None;
+        }
 
-        let _var_arg_params = if let Some(0x41) = self.sig.next() {
-            repeat_with(|| Type::into_type(image, &mut self.sig).unwrap())
-                .take(param_count as usize)
-                .collect()
-        } else {
-            vec![]
-        };
+        if first & signature_flags::GENERIC > 0 {
+            let _generic_param_count = decompress_uint(&mut self.sig)?;
+        }
+
+        let param_count = decompress_uint(&mut self.sig)? as usize;
+        let ret = self.parse_type(image)?;
+
+        // The arguments of a vararg call follow SENTINEL, which may come before any parameter
+        let mut sentinel = None;
+        let mut params = vec![];
+        while params.len() < param_count {
+            if allow_sentinel && sentinel.is_none() && self.sig.as_slice().first() == Some(&0x41) {
+                self.sig.next();
+                sentinel = Some(params.len());
+                continue;
+            }
+            params.push(self.parse_type(image)?);
+        }
 
         Some(Type::new(ElementType::FnPtr(Box::new(MethodSignature {
             info: first,
             ret,
             params,
+            sentinel,
         }))))
     }
 
-    pub fn parse_method_def_sig(&mut self, image: &Image) -> Option<Type> {
+    /// II.23.2.4 FieldSig
+    pub fn parse_field_sig(&mut self, image: &Image) -> Option<Type> {
+        if *self.sig.next()? != signature_flags::FIELD {
+            return None;
+        }
+        self.parse_type(image)
+    }
+
+    /// II.23.2.5 PropertySig. Returns the getter's signature, whose return type is the type of
+    /// the property and whose parameters are the indexes.
+    pub fn parse_property_sig(&mut self, image: &Image) -> Option<Type> {
         let first = *self.sig.next()?;
-        let _default = first == 0;
-        let _has_this = first & 0x20;
-        let _explicit_this = first & 0x40;
-        let _var_arg = first & 0x5;
-        let _generic = first & 0x10;
+        if first & !signature_flags::HASTHIS != signature_flags::PROPERTY {
+            return None;
+        }
 
         let param_count = decompress_uint(&mut self.sig)?;
-        let ret = Type::into_type(image, &mut self.sig)?;
+        let ret = self.parse_type(image)?;
 
-        let params = repeat_with(|| Type::into_type(image, &mut self.sig).unwrap())
+        let params = repeat_with(|| self.parse_type(image))
             .take(param_count as usize)
-            .collect();
+            .collect::<Option<Vec<Type>>>()?;
 
         Some(Type::new(ElementType::FnPtr(Box::new(MethodSignature {
             info: first,
             ret,
             params,
+            sentinel: None,
         }))))
     }
+
+    /// II.23.2.6 LocalVarSig
+    pub fn parse_local_var_sig(&mut self, image: &Image) -> Option<Vec<Type>> {
+        if *self.sig.next()? != signature_flags::LOCAL_SIG {
+            return None;
+        }
+
+        let count = decompress_uint(&mut self.sig)?;
+
+        repeat_with(|| self.parse_type(image))
+            .take(count as usize)
+            .collect()
+    }
+
+    /// II.23.2.15 MethodSpec. Returns the type arguments.
+    pub fn parse_method_spec_sig(&mut self, image: &Image) -> Option<Vec<Type>> {
+        let first = *self.sig.next()?;
+        if first != signature_flags::GENERICINST {
+            return None;
+        }
+
+        let arg_count = decompress_uint(&mut self.sig)?;
+
+        repeat_with(|| self.parse_type(image))
+            .take(arg_count as usize)
+            .collect()
+    }
+
+    /// II.23.2.14 TypeSpec
+    pub fn parse_type_spec_sig(&mut self, image: &Image) -> Option<Type> {
+        self.parse_type(image)
+    }
 }
 
 impl MethodSignature {
     pub fn has_this(&self) -> bool {
-        self.info & 0x20 > 0
+        self.info & signature_flags::HASTHIS > 0
+    }
+
+    /// True if ``this`` is passed explicitly as the first of ``params``
+    pub fn has_explicit_this(&self) -> bool {
+        self.info & signature_flags::EXPLICITTHIS > 0
+    }
+
+    /// True if ``this`` is passed in addition to ``params``
+    pub fn has_implicit_this(&self) -> bool {
+        self.has_this() && !self.has_explicit_this()
+    }
+
+    pub fn is_generic(&self) -> bool {
+        self.info & signature_flags::GENERIC > 0
+    }
+
+    pub fn is_vararg(&self) -> bool {
+        self.info & signature_flags::KIND_MASK == signature_flags::VARARG
     }
 }
 
@@ -289,35 +791,178 @@ impl fmt::Debug for ElementType {
                 ElementType::Void => "Void".to_string(),
                 ElementType::Boolean => "Boolean".to_string(),
                 ElementType::Char => "Char".to_string(),
+                ElementType::I1 => "I1".to_string(),
+                ElementType::U1 => "U1".to_string(),
+                ElementType::I2 => "I2".to_string(),
+                ElementType::U2 => "U2".to_string(),
                 ElementType::I4 => "I4".to_string(),
                 ElementType::U4 => "U4".to_string(),
                 ElementType::I8 => "I8".to_string(),
+                ElementType::U8 => "U8".to_string(),
+                ElementType::R4 => "R4".to_string(),
                 ElementType::R8 => "R8".to_string(),
+                ElementType::I => "I".to_string(),
+                ElementType::U => "U".to_string(),
                 ElementType::String => "String".to_string(),
                 ElementType::SzArray(s) => format!("SzArray({:?})", s),
+                ElementType::Array(a) => format!("Array({:?})", a),
                 ElementType::FnPtr(f) => format!("FnPtr({:?})", f),
                 ElementType::Ptr(e) => format!("Ptr({:?})", e),
+                ElementType::ByRef(e) => format!("ByRef({:?})", e),
                 ElementType::Object => format!("Object"),
                 ElementType::ValueType(c) => format!("ValueType({})", c.borrow().name),
+                ElementType::Var(n) => format!("Var({})", n),
+                ElementType::MVar(n) => format!("MVar({})", n),
+                ElementType::GenericInst(i) =>
+                    format!("GenericInst({}, {:?})", i.class.borrow().name, i.args),
+                ElementType::TypedByRef => "TypedByRef".to_string(),
             }
         )
     }
 }
 
+/// Joins the names of ``types`` with commas (e.g. System.Int32,System.String)
+pub fn type_list_name(types: &[Type]) -> String {
+    types
+        .iter()
+        .map(|ty| ty.get_name())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Decompresses a signed integer, whose sign bit is rotated to the least significant bit
+/// (II.23.2)
+pub fn decompress_int<'a>(sig: &mut Iter<'a, u8>) -> Option<i32> {
+    let bits = match *sig.clone().next()? {
+        x if x & 0b1000_0000 == 0 => 7,
+        x if x & 0b0100_0000 == 0 => 14,
+        _ => 29,
+    };
+    let x = decompress_uint(sig)? as i32;
+    Some(if x & 1 == 0 {
+        x >> 1
+    } else {
+        (x >> 1) - (1 << (bits - 1))
+    })
+}
+
+/// Decompresses an unsigned integer (II.23.2). Returns None for the invalid first bytes
+/// 0xe0-0xff.
 pub fn decompress_uint<'a>(sig: &mut Iter<'a, u8>) -> Option<u32> {
     let x = *sig.next()? as u32;
     if x & 0b1000_0000 == 0 {
         // 1 byte
         Some(x)
-    } else if x & 0b1000_0000 > 0 {
+    } else if x & 0b1100_0000 == 0b1000_0000 {
         // 2 bytes
         let y = *sig.next()? as u32;
         Some(((x & 0b0011_1111) << 8) + y)
-    } else {
+    } else if x & 0b1110_0000 == 0b1100_0000 {
         // 4 bytes
         let y = *sig.next()? as u32;
         let z = *sig.next()? as u32;
         let u = *sig.next()? as u32;
         Some(((x & 0b0001_1111) << 24) + (y << 16) + (z << 8) + u)
+    } else {
+        None
     }
 }
+
+/// II.23.2.1-II.23.2.6 and II.23.2.15. The low 4 bits of the first byte of a method signature
+/// are its calling convention kind.
+#[rustfmt::skip]
+pub mod signature_flags {
+    pub const DEFAULT     : u8 = 0x00;
+    pub const C           : u8 = 0x01;
+    pub const STDCALL     : u8 = 0x02;
+    pub const THISCALL    : u8 = 0x03;
+    pub const FASTCALL    : u8 = 0x04;
+    pub const VARARG      : u8 = 0x05;
+    pub const FIELD       : u8 = 0x06;
+    pub const LOCAL_SIG   : u8 = 0x07;
+    pub const PROPERTY    : u8 = 0x08;
+    pub const GENERICINST : u8 = 0x0a;
+    pub const KIND_MASK   : u8 = 0x0f;
+    pub const GENERIC     : u8 = 0x10;
+    pub const HASTHIS     : u8 = 0x20;
+    pub const EXPLICITTHIS: u8 = 0x40;
+}
+
+#[test]
+fn test_decompress() {
+    let uint = |bytes: &[u8]| decompress_uint(&mut bytes.iter());
+    assert_eq!(uint(&[0x03]), Some(0x03));
+    assert_eq!(uint(&[0x7f]), Some(0x7f));
+    assert_eq!(uint(&[0x80, 0x80]), Some(0x80));
+    assert_eq!(uint(&[0xbf, 0xff]), Some(0x3fff));
+    assert_eq!(uint(&[0xc0, 0x00, 0x40, 0x00]), Some(0x4000));
+    assert_eq!(uint(&[0xdf, 0xff, 0xff, 0xff]), Some(0x1fff_ffff));
+    assert_eq!(uint(&[0xc0, 0x00]), None);
+    assert_eq!(uint(&[0xff]), None);
+
+    let int = |bytes: &[u8]| decompress_int(&mut bytes.iter());
+    assert_eq!(int(&[0x06]), Some(3));
+    assert_eq!(int(&[0x7b]), Some(-3));
+    assert_eq!(int(&[0x80, 0x80]), Some(64));
+    assert_eq!(int(&[0x01]), Some(-64));
+    assert_eq!(int(&[0xc0, 0x00, 0x40, 0x00]), Some(8192));
+    assert_eq!(int(&[0x80, 0x01]), Some(-8192));
+    assert_eq!(int(&[0xdf, 0xff, 0xff, 0xfe]), Some(268435455));
+    assert_eq!(int(&[0xc0, 0x00, 0x00, 0x01]), Some(-268435456));
+}
+
+#[test]
+fn test_signatures() {
+    use crate::metadata::assembly::Assembly;
+    use std::path::PathBuf;
+
+    let asm = Assembly::load(PathBuf::from("./examples/hello.exe")).unwrap();
+    let image = &asm.borrow().image;
+    let method = |sig: &[u8]| SignatureParser::new(sig).parse_method_ref_sig(image);
+    let msig = |ty: Option<Type>| ty.unwrap().as_fnptr().unwrap().clone();
+
+    // void M(in int) with modreq(IsReadOnly) on the parameter
+    let m = msig(method(&[0x20, 0x01, 0x01, 0x10, 0x1f, 0x05, 0x08]));
+    assert!(m.has_implicit_this());
+    assert_eq!(m.params, vec![Type::byref_ty(Type::i4_ty())]);
+
+    // Explicit this is one of the parameters
+    let m = msig(method(&[0x60, 0x01, 0x01, 0x1c]));
+    assert!(m.has_this() && !m.has_implicit_this());
+    assert_eq!(m.params.len(), 1);
+
+    // vararg call with a string after the sentinel
+    let m = msig(method(&[0x05, 0x02, 0x01, 0x08, 0x41, 0x0e]));
+    assert!(m.is_vararg());
+    assert_eq!(m.params, vec![Type::i4_ty(), Type::string_ty()]);
+    assert_eq!(m.sentinel, Some(1));
+    assert!(SignatureParser::new(&[0x05, 0x01, 0x01, 0x41, 0x08])
+        .parse_method_def_sig(image)
+        .is_none());
+
+    // Unknown calling convention and a truncated signature
+    assert!(method(&[0x0b, 0x00, 0x01]).is_none());
+    assert!(method(&[0x00, 0x02, 0x01, 0x08]).is_none());
+
+    let field = SignatureParser::new(&[0x06, 0x20, 0x05, 0x0e]).parse_field_sig(image);
+    assert_eq!(field, Some(Type::string_ty()));
+    assert!(SignatureParser::new(&[0x07, 0x0e])
+        .parse_field_sig(image)
+        .is_none());
+
+    let property = SignatureParser::new(&[0x28, 0x01, 0x08, 0x0e]).parse_property_sig(image);
+    let property = msig(property);
+    assert_eq!(property.ret, Type::i4_ty());
+    assert_eq!(property.params, vec![Type::string_ty()]);
+
+    let locals = SignatureParser::new(&[0x07, 0x02, 0x45, 0x0f, 0x08, 0x16])
+        .parse_local_var_sig(image)
+        .unwrap();
+    assert_eq!(
+        locals,
+        vec![
+            Type::ptr_ty(Type::i4_ty()),
+            Type::new(ElementType::TypedByRef)
+        ]
+    );
+}
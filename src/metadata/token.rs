@@ -27,50 +27,118 @@ impl Into<Token> for DecodedToken {
     }
 }
 
-pub fn decode_resolution_scope_token<T: Into<Token>>(token: T) -> DecodedToken {
-    let Token(raw_token) = token.into();
-    let tag = raw_token & 0b11;
-    let table: u32 = match tag {
-        0 => TableKind::Module,
-        1 => TableKind::ModuleRef,
-        2 => TableKind::AssemblyRef,
-        3 => TableKind::TypeRef,
-        _ => unreachable!(),
+/// II.24.2.6 Kinds of coded indexes. A coded index stores the table in its low ``tag_bits()``
+/// bits and the 1-based row in the rest.
+#[derive(Debug, Clone, PartialEq, Eq, Copy)]
+pub enum CodedIndexKind {
+    TypeDefOrRef,
+    HasConstant,
+    HasCustomAttribute,
+    HasFieldMarshal,
+    HasDeclSecurity,
+    MemberRefParent,
+    HasSemantics,
+    MethodDefOrRef,
+    MemberForwarded,
+    Implementation,
+    CustomAttributeType,
+    ResolutionScope,
+    TypeOrMethodDef,
+}
+
+impl CodedIndexKind {
+    /// Returns the tables indexed by each tag. ``None`` is a tag that is not used.
+    pub fn tables(self) -> &'static [Option<TableKind>] {
+        use TableKind::*;
+        match self {
+            CodedIndexKind::TypeDefOrRef => &[Some(TypeDef), Some(TypeRef), Some(TypeSpec)],
+            CodedIndexKind::HasConstant => &[Some(Field), Some(Param), Some(Property)],
+            CodedIndexKind::HasCustomAttribute => &[
+                Some(MethodDef),
+                Some(Field),
+                Some(TypeRef),
+                Some(TypeDef),
+                Some(Param),
+                Some(InterfaceImpl),
+                Some(MemberRef),
+                Some(Module),
+                Some(DeclSecurity),
+                Some(Property),
+                Some(Event),
+                Some(StandAloneSig),
+                Some(ModuleRef),
+                Some(TypeSpec),
+                Some(Assembly),
+                Some(AssemblyRef),
+                Some(File),
+                Some(ExportedType),
+                Some(ManifestResource),
+                Some(GenericParam),
+                Some(GenericParamConstraint),
+                Some(MethodSpec),
+            ],
+            CodedIndexKind::HasFieldMarshal => &[Some(Field), Some(Param)],
+            CodedIndexKind::HasDeclSecurity => &[Some(TypeDef), Some(MethodDef), Some(Assembly)],
+            CodedIndexKind::MemberRefParent => &[
+                Some(TypeDef),
+                Some(TypeRef),
+                Some(ModuleRef),
+                Some(MethodDef),
+                Some(TypeSpec),
+            ],
+            CodedIndexKind::HasSemantics => &[Some(Event), Some(Property)],
+            CodedIndexKind::MethodDefOrRef => &[Some(MethodDef), Some(MemberRef)],
+            CodedIndexKind::MemberForwarded => &[Some(Field), Some(MethodDef)],
+            CodedIndexKind::Implementation => &[Some(File), Some(AssemblyRef), Some(ExportedType)],
+            CodedIndexKind::CustomAttributeType => {
+                &[None, None, Some(MethodDef), Some(MemberRef), None]
+            }
+            CodedIndexKind::ResolutionScope => &[
+                Some(Module),
+                Some(ModuleRef),
+                Some(AssemblyRef),
+                Some(TypeRef),
+            ],
+            CodedIndexKind::TypeOrMethodDef => &[Some(TypeDef), Some(MethodDef)],
+        }
+    }
+
+    pub fn tag_bits(self) -> u32 {
+        (self.tables().len() as u32)
+            .next_power_of_two()
+            .trailing_zeros()
     }
-    .into();
-    let entry = raw_token >> 2;
-    DecodedToken(table, entry)
 }
 
-pub fn decode_typedef_or_ref_token<T: Into<Token>>(token: T) -> DecodedToken {
+/// Decodes a coded index of ``kind``. Returns None if the tag is not used by ``kind``.
+pub fn decode_coded_index<T: Into<Token>>(kind: CodedIndexKind, token: T) -> Option<DecodedToken> {
     let Token(raw_token) = token.into();
-    let tag = raw_token & 0b11;
-    let idx = raw_token >> 2;
-    DecodedToken(
-        match tag {
-            0 => TableKind::TypeDef.into(),
-            1 => TableKind::TypeRef.into(),
-            2 => TableKind::TypeSpec.into(),
-            _ => unreachable!(),
-        },
-        idx,
-    )
+    let tag_bits = kind.tag_bits();
+    let tag = raw_token & ((1 << tag_bits) - 1);
+    let table = (*kind.tables().get(tag as usize)?)?;
+    Some(DecodedToken(table.into(), raw_token >> tag_bits))
 }
 
-pub fn decode_member_ref_parent_token<T: Into<Token>>(token: T) -> DecodedToken {
-    let Token(raw_token) = token.into();
-    let tag = raw_token & 0b111;
-    let table: u32 = match tag {
-        0 => TableKind::TypeDef,
-        1 => TableKind::TypeRef,
-        2 => TableKind::ModuleRef,
-        3 => TableKind::MethodDef,
-        4 => TableKind::TypeSpec,
-        _ => unreachable!(),
-    }
-    .into();
-    let entry = raw_token >> 3;
-    DecodedToken(table, entry)
+// The decoders of particular coded indexes return None for tags that the index does not use
+
+pub fn decode_resolution_scope_token<T: Into<Token>>(token: T) -> Option<DecodedToken> {
+    decode_coded_index(CodedIndexKind::ResolutionScope, token)
+}
+
+pub fn decode_typedef_or_ref_token<T: Into<Token>>(token: T) -> Option<DecodedToken> {
+    decode_coded_index(CodedIndexKind::TypeDefOrRef, token)
+}
+
+pub fn decode_member_ref_parent_token<T: Into<Token>>(token: T) -> Option<DecodedToken> {
+    decode_coded_index(CodedIndexKind::MemberRefParent, token)
+}
+
+pub fn decode_type_or_method_def_token<T: Into<Token>>(token: T) -> Option<DecodedToken> {
+    decode_coded_index(CodedIndexKind::TypeOrMethodDef, token)
+}
+
+pub fn decode_method_def_or_ref_token<T: Into<Token>>(token: T) -> Option<DecodedToken> {
+    decode_coded_index(CodedIndexKind::MethodDefOrRef, token)
 }
 
 pub fn encode_token(table: u32, entry: u32) -> Token {
@@ -83,3 +151,56 @@ pub fn decode_token<T: Into<Token>>(token: T) -> DecodedToken {
     let entry = raw_token & 0x00ff_ffff;
     DecodedToken(table, entry)
 }
+
+#[test]
+fn test_coded_indexes() {
+    use CodedIndexKind::*;
+    assert_eq!(TypeDefOrRef.tag_bits(), 2);
+    assert_eq!(HasCustomAttribute.tag_bits(), 5);
+    assert_eq!(CustomAttributeType.tag_bits(), 3);
+    assert_eq!(MethodDefOrRef.tag_bits(), 1);
+
+    let table = |kind: TableKind| kind.into_num() as u32;
+    assert_eq!(
+        decode_coded_index(TypeDefOrRef, 0x0d_u32),
+        Some(DecodedToken(table(TableKind::TypeRef), 3))
+    );
+    assert_eq!(
+        decode_coded_index(HasConstant, 0x0a_u32),
+        Some(DecodedToken(table(TableKind::Property), 2))
+    );
+    assert_eq!(
+        decode_coded_index(HasCustomAttribute, 0x2e_u32),
+        Some(DecodedToken(table(TableKind::Assembly), 1))
+    );
+    assert_eq!(
+        decode_coded_index(MemberRefParent, 0x23_u32),
+        Some(DecodedToken(table(TableKind::MethodDef), 4))
+    );
+    assert_eq!(
+        decode_coded_index(Implementation, 0x06_u32),
+        Some(DecodedToken(table(TableKind::ExportedType), 1))
+    );
+    assert_eq!(
+        decode_coded_index(CustomAttributeType, 0x0b_u32),
+        Some(DecodedToken(table(TableKind::MemberRef), 1))
+    );
+    assert_eq!(
+        decode_coded_index(ResolutionScope, 0x06_u32),
+        Some(DecodedToken(table(TableKind::AssemblyRef), 1))
+    );
+    assert_eq!(
+        decode_coded_index(TypeOrMethodDef, 0x03_u32),
+        Some(DecodedToken(table(TableKind::MethodDef), 1))
+    );
+
+    // Unused and out-of-range tags
+    assert_eq!(decode_coded_index(CustomAttributeType, 0x08_u32), None);
+    assert_eq!(decode_coded_index(TypeDefOrRef, 0x03_u32), None);
+    assert_eq!(decode_coded_index(HasCustomAttribute, 0x1f_u32), None);
+
+    assert_eq!(
+        decode_token(encode_token(table(TableKind::Field), 0x12_3456)),
+        DecodedToken(table(TableKind::Field), 0x12_3456)
+    );
+}
@@ -1,2 +1,3 @@
 pub mod name_path;
 pub mod resolver;
+pub mod sha1;